@@ -0,0 +1,81 @@
+//! Times one crowded intersection ticking on one thread and on several, as
+//! `--threads` shares out each tick's following and entry checks.
+//!
+//! The world is made a few windows across so its roads hold a crowd far
+//! bigger than the default one does, and every approach is fed as fast as
+//! the arrivals allow. Each thread count must end in the very same state.
+//!
+//! Run it with `cargo run --release --example crowd_bench`.
+
+use road_intersection::config::Config;
+use road_intersection::simulation::TrafficSimulation;
+use std::time::{Duration, Instant};
+
+/// Side of the square world, in pixels.
+const SIDE: u32 = 4_000;
+
+/// Thread counts timed; the first is the one the others are measured by.
+const THREADS: &[usize] = &[1, 2, 4, 8];
+
+/// Simulated seconds each run lasts.
+const SECONDS: f64 = 90.0;
+
+/// Runs per thread count; the fastest is reported.
+const RUNS: usize = 3;
+
+fn run(threads: usize) -> (Duration, u64, usize) {
+    let mut simulation = TrafficSimulation::new(Config {
+        seed: Some(1),
+        auto_spawn: true,
+        arrival_rate: 6.0,
+        threads,
+        width: SIDE,
+        height: SIDE,
+        ..Config::default()
+    });
+    let started = Instant::now();
+    let mut most = 0;
+    while simulation.simulated_seconds() < SECONDS {
+        simulation.update();
+        most = most.max(simulation.vehicles.len());
+    }
+    (started.elapsed(), simulation.state_hash(), most)
+}
+
+fn main() {
+    println!(
+        "{}x{} world, {}s simulated, {} core(s)",
+        SIDE,
+        SIDE,
+        SECONDS,
+        std::thread::available_parallelism().map_or(1, |cores| cores.get())
+    );
+    println!(
+        "{:>8} {:>10} {:>8} {:>10}",
+        "threads", "time", "speedup", "most"
+    );
+    let mut baseline = None;
+    for &threads in THREADS {
+        let mut best = Duration::MAX;
+        let mut outcome = None;
+        for _ in 0..RUNS {
+            let (elapsed, hash, most) = run(threads);
+            best = best.min(elapsed);
+            outcome = Some((hash, most));
+        }
+        let (hash, most) = outcome.expect("at least one run");
+        let (single_time, single_hash) = *baseline.get_or_insert((best, hash));
+        assert_eq!(
+            hash, single_hash,
+            "{} threads ended somewhere else",
+            threads
+        );
+        println!(
+            "{:>8} {:>8.0}ms {:>7.1}x {:>10}",
+            threads,
+            best.as_secs_f64() * 1e3,
+            single_time.as_secs_f64() / best.as_secs_f64(),
+            most
+        );
+    }
+}
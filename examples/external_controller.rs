@@ -0,0 +1,71 @@
+//! The lights driven from outside the simulation, one tick at a time, the
+//! way a learning agent would drive them: each step sends an action and gets
+//! back an observation and a reward.
+//!
+//! The action space is `PhaseCommand`: `Hold`, or `Switch(p)` for any phase
+//! `p` of the light plan. The observation is the queue on each approach, the
+//! vehicles that exited during the tick, the current phase and stage, and
+//! whether the action was accepted. The reward is minus the delay picked up
+//! during the tick.
+//!
+//! This agent greens whichever phase serves the longest queue. Run it with
+//! `cargo run --example external_controller`.
+
+use road_intersection::config::Config;
+use road_intersection::direction::Direction;
+use road_intersection::lights::{ControllerKind, LightPlan, PhaseCommand};
+use road_intersection::simulation::TrafficSimulation;
+
+/// Simulated seconds to run.
+const DURATION: f64 = 120.0;
+
+/// Ticks between spawns on each approach.
+const SPAWN_EVERY: u64 = 180;
+
+fn main() {
+    let plan = LightPlan::default();
+    let config = Config {
+        seed: Some(7),
+        signal_controller: ControllerKind::External,
+        light_plan: plan.clone(),
+        ..Config::default()
+    };
+    let mut simulation = TrafficSimulation::new(config);
+
+    let mut action = PhaseCommand::Hold;
+    let mut total_reward = 0.0;
+    let mut exited = 0;
+    let mut switches = 0;
+    while simulation.simulated_seconds() < DURATION {
+        if simulation.tick.is_multiple_of(SPAWN_EVERY) {
+            for direction in Direction::ALL {
+                simulation.spawn_vehicle(direction);
+            }
+        }
+        let observation = simulation.step(action);
+        total_reward += observation.reward;
+        exited += observation.exited;
+        if observation.accepted {
+            switches += 1;
+        }
+
+        let served = |phase: usize| -> usize {
+            Direction::ALL
+                .into_iter()
+                .filter(|direction| plan.phases[phase].green[direction.index()])
+                .map(|direction| observation.queues[direction.index()])
+                .sum()
+        };
+        let best = (0..plan.phases.len()).max_by_key(|&phase| served(phase));
+        action = match best {
+            Some(phase) if served(phase) > served(observation.phase) => PhaseCommand::Switch(phase),
+            _ => PhaseCommand::Hold,
+        };
+    }
+
+    println!(
+        "{} vehicles exited, {} phase switches, total reward {:.1}",
+        exited, switches, total_reward
+    );
+    simulation.print_summary();
+}
@@ -0,0 +1,89 @@
+//! Times a busy map of intersections ticking on one thread and on several,
+//! as `--threads` spreads them.
+//!
+//! Every way onto the map is fed as fast as the arrivals allow, the way a
+//! long soak loads it. Each thread count must end in the very same state.
+//!
+//! Run it with `cargo run --release --example grid_bench`.
+
+use road_intersection::config::Config;
+use road_intersection::network::Network;
+use road_intersection::simulation::TrafficSimulation;
+use std::time::{Duration, Instant};
+
+/// Columns and rows of the map, the largest `--grid` takes.
+const GRID: (usize, usize) = (4, 4);
+
+/// Thread counts timed; the first is the one the others are measured by.
+const THREADS: &[usize] = &[1, 2, 4, 8];
+
+/// Simulated seconds each run lasts.
+const SECONDS: f64 = 60.0;
+
+/// Runs per thread count; the fastest is reported.
+const RUNS: usize = 3;
+
+fn run(threads: usize) -> (Duration, Vec<u64>, usize) {
+    let (columns, rows) = GRID;
+    let mut network = Network::new(
+        Config {
+            seed: Some(1),
+            grid: Some(GRID),
+            auto_spawn: true,
+            arrival_rate: 4.0,
+            threads,
+            ..Config::default()
+        },
+        columns,
+        rows,
+    );
+    let started = Instant::now();
+    while network.intersections[0].simulated_seconds() < SECONDS {
+        network.update();
+    }
+    let elapsed = started.elapsed();
+    let hashes = network
+        .intersections
+        .iter()
+        .map(TrafficSimulation::state_hash)
+        .collect();
+    (elapsed, hashes, network.completed)
+}
+
+fn main() {
+    println!(
+        "{}x{} intersections, {}s simulated, {} core(s)",
+        GRID.0,
+        GRID.1,
+        SECONDS,
+        std::thread::available_parallelism().map_or(1, |cores| cores.get())
+    );
+    println!(
+        "{:>8} {:>10} {:>8} {:>10}",
+        "threads", "time", "speedup", "completed"
+    );
+    let mut baseline = None;
+    for &threads in THREADS {
+        let mut best = Duration::MAX;
+        let mut outcome = None;
+        for _ in 0..RUNS {
+            let (elapsed, hashes, completed) = run(threads);
+            best = best.min(elapsed);
+            outcome = Some((hashes, completed));
+        }
+        let (hashes, completed) = outcome.expect("at least one run");
+        let (single_time, single_hashes) = baseline.get_or_insert((best, hashes.clone()));
+        assert_eq!(
+            &hashes, single_hashes,
+            "{} threads ended somewhere else",
+            threads
+        );
+        println!(
+            "{:>8} {:>8.0}ms {:>7.1}x {:>10}",
+            threads,
+            best.as_secs_f64() * 1e3,
+            single_time.as_secs_f64() / best.as_secs_f64(),
+            completed
+        );
+    }
+}
@@ -0,0 +1,73 @@
+//! Times collision detection over crowds of vehicles, comparing every pair
+//! against the spatial grid the simulation buckets vehicles into.
+//!
+//! The crowds are scattered at random over a region a few windows across:
+//! far more than the roads hold, the way a very high arrival rate over a
+//! bigger network would load the checks. Both ways must find the same
+//! overlaps.
+//!
+//! Run it with `cargo run --release --example neighbour_bench`.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use road_intersection::config::ColorMode;
+use road_intersection::direction::{Direction, Route};
+use road_intersection::grid::{overlapping_pairs, overlapping_pairs_naive};
+use road_intersection::vehicle::Vehicle;
+use std::time::{Duration, Instant};
+
+/// Vehicles in each crowd timed.
+const CROWDS: &[usize] = &[100, 500, 2_000, 5_000];
+
+/// Side of the square the vehicles are scattered over, in pixels, for
+/// 2,000 of them; other crowds keep the same density.
+const SIDE: f32 = 4_000.0;
+
+/// Runs of each way per crowd; the fastest is reported.
+const RUNS: usize = 5;
+
+fn crowd(count: usize, rng: &mut StdRng) -> Vec<Vehicle> {
+    let side = SIDE * (count as f32 / 2_000.0).sqrt();
+    (0..count as u64)
+        .map(|id| {
+            let direction = Direction::ALL[rng.random_range(0..4)];
+            let mut vehicle = Vehicle::new(id, direction, Route::Straight, ColorMode::ById, rng);
+            vehicle.x = rng.random_range(0.0..side);
+            vehicle.y = rng.random_range(0.0..side);
+            vehicle
+        })
+        .collect()
+}
+
+fn fastest(run: impl Fn() -> Vec<(usize, usize)>) -> (Duration, Vec<(usize, usize)>) {
+    let mut best = Duration::MAX;
+    let mut pairs = Vec::new();
+    for _ in 0..RUNS {
+        let started = Instant::now();
+        pairs = run();
+        best = best.min(started.elapsed());
+    }
+    (best, pairs)
+}
+
+fn main() {
+    let mut rng = StdRng::seed_from_u64(1);
+    println!(
+        "{:>8} {:>12} {:>12} {:>8} {:>9}",
+        "vehicles", "naive", "grid", "speedup", "overlaps"
+    );
+    for &count in CROWDS {
+        let vehicles = crowd(count, &mut rng);
+        let (naive_time, naive) = fastest(|| overlapping_pairs_naive(&vehicles));
+        let (grid_time, indexed) = fastest(|| overlapping_pairs(&vehicles));
+        assert_eq!(naive, indexed, "the grid missed or invented an overlap");
+        println!(
+            "{:>8} {:>10.0}us {:>10.0}us {:>7.1}x {:>9}",
+            count,
+            naive_time.as_secs_f64() * 1e6,
+            grid_time.as_secs_f64() * 1e6,
+            naive_time.as_secs_f64() / grid_time.as_secs_f64(),
+            naive.len()
+        );
+    }
+}
@@ -0,0 +1,48 @@
+//! Sweeps the light timings without a window and prints how each one copes
+//! with saturated traffic, one row per timing.
+//!
+//! Every run starts from the same seed and the default plan, retimed by the
+//! given seconds of green and yellow, and is fed as fast as each spawn point
+//! clears, as `--soak` feeds it. The simulation never needs a canvas to
+//! step, so this is just a loop over `TrafficSimulation::soak`.
+//!
+//! Run it with `cargo run --release --example sweep`.
+
+use road_intersection::config::Config;
+use road_intersection::simulation::TrafficSimulation;
+
+/// Simulated seconds each run lasts.
+const SECONDS: f64 = 300.0;
+
+/// Seconds added to every green phase, one run each.
+const GREEN_CHANGES: &[f32] = &[-2.0, 0.0, 2.0, 4.0, 8.0];
+
+/// Seconds added to the yellow, one run each per green change.
+const YELLOW_CHANGES: &[f32] = &[0.0, 1.0];
+
+fn main() {
+    println!("green  yellow  exited  per min  collisions  mean delay");
+    for &green in GREEN_CHANGES {
+        for &yellow in YELLOW_CHANGES {
+            let mut simulation = TrafficSimulation::new(Config {
+                seed: Some(0),
+                ..Config::default()
+            });
+            simulation.retime_lights(green, yellow);
+            let failures = simulation.soak(SECONDS);
+            let stats = simulation.stats();
+            println!(
+                "{:>+5.1}  {:>+6.1}  {:>6}  {:>7.1}  {:>10}  {:>9.2}s",
+                green,
+                yellow,
+                stats.exited,
+                stats.exited as f64 * 60.0 / stats.simulated_seconds,
+                stats.collisions,
+                simulation.mean_delay()
+            );
+            for failure in failures {
+                println!("    {}", failure);
+            }
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use crate::lights::Stage;
+use crate::simulation::TrafficSimulation;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Vehicles on the road at which the engine hum is at its loudest.
+const FULL_HUM: usize = 40;
+
+/// The sounds there are, one WAV each in the assets directory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sound {
+    /// Looped for as long as the run lasts, louder the more vehicles.
+    Engine,
+    Horn,
+    Click,
+}
+
+impl Sound {
+    pub const ALL: [Sound; 3] = [Sound::Engine, Sound::Horn, Sound::Click];
+
+    #[cfg(feature = "audio")]
+    fn file_name(self) -> &'static str {
+        match self {
+            Sound::Engine => "engine.wav",
+            Sound::Horn => "horn.wav",
+            Sound::Click => "click.wav",
+        }
+    }
+}
+
+/// What to play after a frame's ticks.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Cues {
+    /// The hum's loudness, from 0 for an empty road to 1.
+    pub engine: f32,
+    /// Vehicles that have just been stopped `horn_after` seconds.
+    pub horns: usize,
+    /// Whether the lights changed stage.
+    pub click: bool,
+}
+
+/// Works out from the simulation what should be heard, with no audio of
+/// its own, so it runs the same whether or not sound is built in.
+#[derive(Default)]
+pub struct Soundscape {
+    /// The tick each vehicle came to a halt on, by id, while it is stopped,
+    /// and whether it has sounded its horn since.
+    stopped_since: HashMap<u64, (u64, bool)>,
+    /// The phase and stage the lights were last heard in.
+    lights: Option<(usize, Stage)>,
+}
+
+impl Soundscape {
+    /// Each vehicle sounds its horn once per stop, when it has been
+    /// stopped `horn_after` seconds. The lights click on every change of
+    /// stage, never on the first call.
+    pub fn listen(&mut self, simulation: &TrafficSimulation) -> Cues {
+        let config = &simulation.config;
+        let horn_ticks = (config.horn_after * config.tick_rate).round() as u64;
+        let tick = simulation.tick;
+        let mut horns = 0;
+        let mut stopped = HashMap::new();
+        for vehicle in &simulation.vehicles {
+            if vehicle.velocity > 0.0 {
+                continue;
+            }
+            let (since, mut honked) = self
+                .stopped_since
+                .get(&vehicle.id)
+                .copied()
+                .unwrap_or((tick, false));
+            // Frames may run several ticks, so the horn goes on the first
+            // call past the threshold rather than on the exact tick.
+            if !honked && tick - since >= horn_ticks {
+                horns += 1;
+                honked = true;
+            }
+            stopped.insert(vehicle.id, (since, honked));
+        }
+        self.stopped_since = stopped;
+
+        let mut click = false;
+        if config.signalled() {
+            let lights = simulation.lights();
+            let now = (lights.phase(), lights.stage());
+            click = self.lights.is_some_and(|last| last != now);
+            self.lights = Some(now);
+        }
+        Cues {
+            engine: (simulation.vehicles.len() as f32 / FULL_HUM as f32).min(1.0),
+            horns,
+            click,
+        }
+    }
+}
+
+/// Plays the cues through SDL_mixer, built with the `audio` feature.
+pub struct Audio {
+    pub muted: bool,
+    #[cfg(feature = "audio")]
+    chunks: Vec<(Sound, sdl2::mixer::Chunk)>,
+    #[cfg(feature = "audio")]
+    engine: Option<sdl2::mixer::Channel>,
+    #[cfg(feature = "audio")]
+    _subsystem: sdl2::AudioSubsystem,
+}
+
+impl Audio {
+    /// Opens the audio device and loads every sound from `dir`, failing if
+    /// any is missing.
+    #[cfg(feature = "audio")]
+    pub fn open(sdl: &sdl2::Sdl, dir: &Path) -> Result<Self, String> {
+        use sdl2::mixer::{self, Channel, Chunk};
+
+        let subsystem = sdl
+            .audio()
+            .map_err(|e| format!("Could not initialize audio subsystem: {}", e))?;
+        mixer::open_audio(
+            mixer::DEFAULT_FREQUENCY,
+            mixer::DEFAULT_FORMAT,
+            mixer::DEFAULT_CHANNELS,
+            1024,
+        )
+        .map_err(|e| format!("Could not open the audio device: {}", e))?;
+        mixer::allocate_channels(16);
+        let mut chunks = Vec::new();
+        for sound in Sound::ALL {
+            let path = dir.join(sound.file_name());
+            let chunk = Chunk::from_file(&path)
+                .map_err(|e| format!("Could not load {}: {}", path.display(), e))?;
+            chunks.push((sound, chunk));
+        }
+        let mut audio = Self {
+            muted: false,
+            chunks,
+            engine: None,
+            _subsystem: subsystem,
+        };
+        // Looped forever, at no volume until there is traffic.
+        let engine = Channel::all().play(audio.chunk(Sound::Engine), -1)?;
+        engine.set_volume(0);
+        audio.engine = Some(engine);
+        Ok(audio)
+    }
+
+    #[cfg(not(feature = "audio"))]
+    pub fn open(_sdl: &sdl2::Sdl, _dir: &Path) -> Result<Self, String> {
+        Err("Built without the audio feature".to_string())
+    }
+
+    #[cfg(feature = "audio")]
+    fn chunk(&self, sound: Sound) -> &sdl2::mixer::Chunk {
+        let (_, chunk) = self
+            .chunks
+            .iter()
+            .find(|(loaded, _)| *loaded == sound)
+            .expect("every sound is loaded by open");
+        chunk
+    }
+
+    /// Sets the hum and plays any horns and click. Muted, the hum is
+    /// silenced and nothing new starts.
+    #[cfg(feature = "audio")]
+    pub fn play(&mut self, cues: Cues) {
+        use sdl2::mixer::{Channel, MAX_VOLUME};
+
+        let hum = if self.muted { 0.0 } else { cues.engine };
+        if let Some(engine) = self.engine {
+            // Quiet under the effects even when the road is full.
+            engine.set_volume((hum * MAX_VOLUME as f32 / 3.0).round() as i32);
+        }
+        if self.muted {
+            return;
+        }
+        let effects = [
+            (Sound::Horn, cues.horns.min(3)),
+            (Sound::Click, cues.click as usize),
+        ];
+        for (sound, count) in effects {
+            for _ in 0..count {
+                // Every channel busy means the sound is dropped.
+                if let Err(e) = Channel::all().play(self.chunk(sound), 0) {
+                    warn!("Could not play {:?}: {}", sound, e);
+                }
+            }
+        }
+    }
+
+    #[cfg(not(feature = "audio"))]
+    pub fn play(&mut self, _cues: Cues) {}
+}
+
+#[cfg(feature = "audio")]
+impl Drop for Audio {
+    fn drop(&mut self) {
+        // The chunks must go before the device they play on.
+        self.chunks.clear();
+        sdl2::mixer::close_audio();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::direction::Direction;
+
+    #[test]
+    fn a_stopped_vehicle_honks_once_and_the_lights_click_on_each_change() {
+        let mut simulation = TrafficSimulation::new(Config {
+            horn_after: 2.0,
+            ..Config::default()
+        });
+        let mut soundscape = Soundscape::default();
+        assert_eq!(soundscape.listen(&simulation), Cues::default());
+
+        // The cross street starts on red, so this one waits at the line.
+        simulation.spawn_vehicle(Direction::Right);
+        let (mut horns, mut clicks) = (0, 0);
+        let mut first_horn = None;
+        for _ in 0..60 * 6 {
+            simulation.update();
+            let cues = soundscape.listen(&simulation);
+            assert!(cues.engine > 0.0 && cues.engine <= 1.0);
+            horns += cues.horns;
+            clicks += cues.click as usize;
+            if cues.horns > 0 && first_horn.is_none() {
+                first_horn = Some(simulation.tick);
+            }
+        }
+        assert_eq!(horns, 1);
+        let stopped = simulation.vehicles[0].stopped_at_line.unwrap();
+        assert_eq!(first_horn, Some(stopped + 2 * 60));
+        // Six seconds in, the first green has only just gone to yellow.
+        assert_eq!(clicks, 1);
+    }
+}
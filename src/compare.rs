@@ -0,0 +1,366 @@
+use crate::config::Config;
+use crate::digits;
+use crate::direction::{Direction, Route};
+use crate::geometry::World;
+use crate::lights::ControllerKind;
+use crate::render::{Inset, Renderer};
+use crate::simulation::{
+    DebugOverlay, TrafficSimulation, drawn_kind, permitted_routes, weighted_approach,
+};
+use crate::vehicle::Vehicle;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use sdl2::pixels::Color;
+use std::collections::VecDeque;
+
+// Each run is drawn at half size, side by side, centred vertically.
+const SCALE: f32 = 0.5;
+
+// Where the top of each run is drawn in a window the size of `world`.
+fn run_top(world: World) -> i32 {
+    (world.height as f32 * (1.0 - SCALE) / 2.0) as i32
+}
+
+/// Two runs of the same traffic under different signal controllers. Every
+/// arrival is made once, from the comparison's own seeded generator, and
+/// handed to both runs, so they differ only in how the lights treat it.
+pub struct Comparison {
+    /// The configured controller on the left, `compare` on the right.
+    pub runs: [TrafficSimulation; 2],
+    rng: StdRng,
+    // Arrivals per approach that a run has yet to admit because its spawn
+    // point was occupied, oldest first.
+    waiting: [[VecDeque<Vehicle>; 4]; 2],
+    last_spawn_time: [Option<u128>; 4],
+    // Key presses made while their approach was cooling down, with the
+    // route asked for, oldest first.
+    held: [VecDeque<Option<Route>>; 4],
+    next_id: u64,
+    // Automatic arrivals are drawn here for both runs, never by the runs.
+    auto_spawn: bool,
+}
+
+impl Comparison {
+    /// Both runs share `config`, the seed included, except that the right
+    /// one uses `other`. An unseeded config gets a random seed, still
+    /// shared.
+    pub fn new(config: Config, other: ControllerKind) -> Self {
+        let seed = config.seed.unwrap_or_else(rand::random);
+        let auto_spawn = config.auto_spawn;
+        let left = Config {
+            seed: Some(seed),
+            auto_spawn: false,
+            ..config
+        };
+        let right = Config {
+            signal_controller: other,
+            ..left.clone()
+        };
+        Self {
+            runs: [TrafficSimulation::new(left), TrafficSimulation::new(right)],
+            rng: StdRng::seed_from_u64(seed),
+            waiting: Default::default(),
+            last_spawn_time: [None; 4],
+            held: Default::default(),
+            next_id: 0,
+            auto_spawn,
+        }
+    }
+
+    fn config(&self) -> &Config {
+        &self.runs[0].config
+    }
+
+    // A new arrival on `direction`, drawn the way a single run draws its
+    // spawns.
+    fn arrival(&mut self, direction: Direction, route: Route) -> Vehicle {
+        let config = &self.runs[0].config;
+        let kind = drawn_kind(config, &mut self.rng);
+        let mut vehicle = Vehicle::new(
+            self.next_id,
+            direction,
+            route,
+            config.color_mode,
+            &mut self.rng,
+        );
+        vehicle.kind = kind;
+        vehicle.tie_break = self.rng.random();
+        self.next_id += 1;
+        vehicle
+    }
+
+    fn queue(&mut self, direction: Direction, route: Option<Route>) {
+        let routes = permitted_routes(self.config(), direction);
+        let route = route.unwrap_or_else(|| routes[self.rng.random_range(0..routes.len())]);
+        let vehicle = self.arrival(direction, route);
+        for waiting in &mut self.waiting {
+            waiting[direction.index()].push_back(vehicle.clone());
+        }
+    }
+
+    /// A key-press arrival for both runs, on `route` if one is given. Each
+    /// run spawns it as soon as its own spawn point is clear. A press made
+    /// while the approach is still cooling down is held, up to
+    /// `spawn_buffer` of them, until `release_spawns` can make it.
+    pub fn request_spawn(
+        &mut self,
+        direction: Direction,
+        route: Option<Route>,
+        wall_millis: u128,
+    ) -> bool {
+        if !self.runs[0].spawning_enabled() || !self.config().layout.accepts_approach(direction) {
+            return false;
+        }
+        if let Some(route) = route
+            && !permitted_routes(self.config(), direction).contains(&route)
+        {
+            warn!("The {} approach may not take that route", direction);
+            return false;
+        }
+        self.release_spawns(wall_millis);
+        let index = direction.index();
+        if self.held[index].is_empty() && !self.cooling_down(direction, wall_millis) {
+            self.queue(direction, route);
+            self.last_spawn_time[index] = Some(self.runs[0].cooldown_millis(wall_millis));
+            return true;
+        }
+        if self.held[index].len() < self.config().spawn_buffer {
+            self.held[index].push_back(route);
+        }
+        false
+    }
+
+    /// Makes the oldest held key press on each approach that has finished
+    /// cooling down.
+    pub fn release_spawns(&mut self, wall_millis: u128) {
+        for direction in Direction::ALL {
+            let index = direction.index();
+            if !self.held[index].is_empty() && !self.cooling_down(direction, wall_millis) {
+                let route = self.held[index].pop_front().expect("checked above");
+                self.queue(direction, route);
+                self.last_spawn_time[index] = Some(self.runs[0].cooldown_millis(wall_millis));
+            }
+        }
+    }
+
+    fn cooling_down(&self, direction: Direction, wall_millis: u128) -> bool {
+        let now = self.runs[0].cooldown_millis(wall_millis);
+        let cooldown = self.config().spawn_cooldown_millis();
+        self.last_spawn_time[direction.index()].is_some_and(|last| now - last <= cooldown)
+    }
+
+    /// A key-press arrival from an approach picked by `approach_weights`.
+    pub fn request_random_spawn(&mut self, wall_millis: u128) -> bool {
+        let direction = weighted_approach(&self.runs[0].config, &mut self.rng);
+        self.request_spawn(direction, None, wall_millis)
+    }
+
+    /// `burst_size` arrivals on every approach, skipping the cooldown.
+    pub fn queue_burst(&mut self) {
+        if !self.runs[0].spawning_enabled() {
+            return;
+        }
+        for direction in Direction::ALL {
+            if self.config().layout.accepts_approach(direction) {
+                for _ in 0..self.config().burst_size {
+                    self.queue(direction, None);
+                }
+            }
+        }
+    }
+
+    /// Starts or stops the random arrivals of `auto_spawn`, for both runs.
+    pub fn toggle_auto_spawn(&mut self) {
+        self.auto_spawn = !self.auto_spawn;
+    }
+
+    /// Drains both runs and drops the arrivals still waiting.
+    pub fn start_drain(&mut self) {
+        for run in &mut self.runs {
+            run.start_drain();
+        }
+        self.waiting = Default::default();
+        self.held = Default::default();
+    }
+
+    /// Draws this tick's automatic arrival, if any, spawns what each run
+    /// can of its waiting arrivals, one per approach, then advances both by
+    /// a tick.
+    pub fn update(&mut self) {
+        let config = &self.runs[0].config;
+        if self.auto_spawn
+            && self.runs[0].spawning_enabled()
+            && self.rng.random::<f32>() < config.arrival_rate * config.tick_seconds()
+        {
+            let direction = weighted_approach(config, &mut self.rng);
+            self.queue(direction, None);
+        }
+        for (run, waiting) in self.runs.iter_mut().zip(&mut self.waiting) {
+            for queue in waiting.iter_mut() {
+                if let Some(arrival) = queue.front()
+                    && run.admit(arrival).is_some()
+                {
+                    queue.pop_front();
+                }
+            }
+            run.update();
+        }
+    }
+
+    /// Arrivals a run has been given but not yet spawned.
+    pub fn waiting(&self, run: usize) -> usize {
+        self.waiting[run].iter().map(VecDeque::len).sum()
+    }
+
+    /// Maps a window point onto the world of whichever half it falls in.
+    pub fn to_world(&self, x: f32, y: f32) -> (f32, f32) {
+        let world = self.config().world();
+        let half = world.width as f32 * SCALE;
+        let x = if x < half { x } else { x - half };
+        self.config()
+            .bend()
+            .straighten(x / SCALE, (y - run_top(world) as f32) / SCALE)
+    }
+
+    /// Draws the left run in the left half and the right run in the right
+    /// half, then under each a readout of vehicles exited, mean delay,
+    /// vehicles queued or waiting, and collisions. The run doing better on
+    /// a line shows it in green, the other in red.
+    pub fn render(
+        &self,
+        canvas: &mut dyn Renderer,
+        alpha: f32,
+        debug: &DebugOverlay,
+    ) -> Result<(), String> {
+        canvas.set_draw_color(Color::RGB(20, 20, 20));
+        canvas.clear();
+        let world = self.config().world();
+        let half = (world.width as f32 * SCALE) as i32;
+        for (side, run) in self.runs.iter().enumerate() {
+            run.render(
+                &mut Inset::new(canvas, world, SCALE, (side as i32 * half, run_top(world))),
+                alpha,
+                debug,
+            )?;
+        }
+        canvas.set_draw_color(Color::RGB(255, 255, 255));
+        canvas.draw_line((half, 0), (half, world.height as i32))?;
+
+        // Each line pairs a score, negated where less is better, with its text.
+        let readouts = [0, 1].map(|side| {
+            let stats = self.runs[side].stats();
+            let queued = stats.queues.iter().sum::<usize>() + self.waiting(side);
+            [
+                (stats.exited as f64, format!("{}", stats.exited)),
+                (
+                    -self.runs[side].mean_delay(),
+                    format!("{:.1}", self.runs[side].mean_delay()),
+                ),
+                (-(queued as f64), queued.to_string()),
+                (-(stats.collisions as f64), stats.collisions.to_string()),
+            ]
+        });
+        let top = run_top(world) + (world.height as f32 * SCALE) as i32 + 12;
+        for line in 0..readouts[0].len() {
+            let (left, right) = (readouts[0][line].0, readouts[1][line].0);
+            for (side, readout) in readouts.iter().enumerate() {
+                let (score, text) = &readout[line];
+                let other = if side == 0 { right } else { left };
+                let color = if score > &other {
+                    Color::RGB(0, 255, 0)
+                } else if score < &other {
+                    Color::RGB(255, 60, 60)
+                } else {
+                    Color::RGB(255, 255, 255)
+                };
+                let y = top + line as i32 * (digits::DIGIT_HEIGHT as i32 + 6);
+                digits::draw_text(canvas, side as i32 * half + 12, y, text, color)?;
+            }
+        }
+
+        canvas.present();
+        Ok(())
+    }
+
+    pub fn print_summary(&self) {
+        for run in &self.runs {
+            println!("== {} ==", run.config.signal_controller.name());
+            run.print_summary();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::WINDOW_WIDTH;
+    use crate::render::{DrawCall, RecordingRenderer};
+    use crate::vehicle::VehicleKind;
+    use sdl2::rect::Rect;
+
+    #[test]
+    fn both_runs_get_the_same_arrivals() {
+        let config = Config {
+            seed: Some(5),
+            truck_share: 0.3,
+            ..Config::default()
+        };
+        let mut comparison = Comparison::new(config, ControllerKind::MaxPressure);
+        assert_eq!(comparison.runs[1].config.seed, Some(5));
+        comparison.queue_burst();
+        let mut seen: [Vec<(Direction, Route, VehicleKind, u64)>; 2] = Default::default();
+        for _ in 0..3600 {
+            comparison.update();
+            for (side, run) in comparison.runs.iter().enumerate() {
+                for vehicle in &run.vehicles {
+                    let entry = (
+                        vehicle.initial_direction,
+                        vehicle.route,
+                        vehicle.kind,
+                        vehicle.tie_break,
+                    );
+                    if !seen[side].contains(&entry) {
+                        seen[side].push(entry);
+                    }
+                }
+            }
+        }
+        assert_eq!(comparison.waiting(0) + comparison.waiting(1), 0);
+        assert_eq!(seen[0].len(), 4 * comparison.config().burst_size);
+        let sorted = |entries: &[(Direction, Route, VehicleKind, u64)]| {
+            let mut entries = entries.to_vec();
+            entries.sort_by_key(|&(direction, _, _, tie)| (direction.index(), tie));
+            entries
+        };
+        assert_eq!(sorted(&seen[0]), sorted(&seen[1]));
+    }
+
+    #[test]
+    fn each_run_draws_into_its_own_half() {
+        let comparison = Comparison::new(Config::default(), ControllerKind::MaxPressure);
+        let mut recorder = RecordingRenderer::default();
+        comparison
+            .render(&mut recorder, 1.0, &DebugOverlay::default())
+            .unwrap();
+        assert!(matches!(recorder.calls[0], DrawCall::Clear(_)));
+        assert_eq!(
+            recorder
+                .calls
+                .iter()
+                .filter(|call| matches!(call, DrawCall::Present))
+                .count(),
+            1
+        );
+        let half = WINDOW_WIDTH as i32 / 2;
+        let backgrounds: Vec<Rect> = recorder
+            .calls
+            .iter()
+            .filter_map(|call| match call {
+                DrawCall::FillRect(rect, _) if rect.width() == half as u32 => Some(*rect),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(backgrounds[0].x(), 0);
+        assert!(backgrounds.iter().any(|rect| rect.x() == half));
+    }
+}
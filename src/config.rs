@@ -0,0 +1,1841 @@
+use crate::conflicts::{ConflictMatrix, Movement};
+use crate::direction::{Direction, Route};
+use crate::environment::{Traction, Weather};
+use crate::geometry::{
+    Bend, CROSSWALK_WIDTH, LANE_WIDTH, Layout, ROAD_WIDTH, TICK_RATE, VEHICLE_SIZE, VEHICLE_SPEED,
+    WINDOW_HEIGHT, WINDOW_WIDTH, World,
+};
+use crate::lights::{ControllerKind, LightPlan, SignalTiming};
+use crate::remote::{self, Value};
+use crate::vehicle::check_route_geometry;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Pixels per second; the highest speed limit accepted, and the default
+/// for approaches without one.
+pub const MAX_SPEED_LIMIT: f32 = 600.0;
+
+/// Pixels; the smallest `base_gap` accepted. A queue packed any tighter
+/// stops with its vehicles touching.
+pub const MIN_BASE_GAP: f32 = 1.0;
+
+/// Lane widths across the narrowest road accepted, which just fits a lane
+/// each way with its lines.
+pub const MIN_ROAD_LANES: u32 = 3;
+
+// Pixels; the narrowest lane accepted, which still draws a roundabout's
+// ring outside its island.
+const MIN_LANE_WIDTH: u32 = 20;
+
+// Smallest vehicle length or width, in pixels, that still leaves room for
+// the markings drawn inside a footprint.
+const MIN_VEHICLE_SIZE: f32 = 20.0;
+
+// Most intersections a grid may have. Each is a whole simulation, drawn in
+// its share of the one window.
+const MAX_GRID_INTERSECTIONS: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum CollisionPolicy {
+    #[default]
+    LogOnly,
+    Stop,
+    Remove,
+}
+
+impl CollisionPolicy {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "log" | "log-only" => Ok(CollisionPolicy::LogOnly),
+            "stop" => Ok(CollisionPolicy::Stop),
+            "remove" => Ok(CollisionPolicy::Remove),
+            _ => Err(format!("Unknown collision policy: {}", value)),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            CollisionPolicy::LogOnly => "log-only",
+            CollisionPolicy::Stop => "stop",
+            CollisionPolicy::Remove => "remove",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ColorMode {
+    #[default]
+    ByRoute,
+    ByDirection,
+    Random,
+    /// A stable hue per vehicle id, so one car can be followed through
+    /// the traffic.
+    ById,
+}
+
+impl ColorMode {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "route" => Ok(ColorMode::ByRoute),
+            "direction" => Ok(ColorMode::ByDirection),
+            "random" => Ok(ColorMode::Random),
+            "id" => Ok(ColorMode::ById),
+            _ => Err(format!("Unknown color mode: {}", value)),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            ColorMode::ByRoute => "route",
+            ColorMode::ByDirection => "direction",
+            ColorMode::Random => "random",
+            ColorMode::ById => "id",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum WindowMode {
+    #[default]
+    Windowed,
+    Borderless,
+    /// Covers the desktop at its current resolution; the scene is scaled to
+    /// fit and `scale` is ignored.
+    Fullscreen,
+}
+
+impl WindowMode {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "windowed" => Ok(WindowMode::Windowed),
+            "borderless" => Ok(WindowMode::Borderless),
+            "fullscreen" => Ok(WindowMode::Fullscreen),
+            _ => Err(format!("Unknown window mode: {}", value)),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            WindowMode::Windowed => "windowed",
+            WindowMode::Borderless => "borderless",
+            WindowMode::Fullscreen => "fullscreen",
+        }
+    }
+}
+
+/// How roads are drawn: white outlines on black, or asphalt on grass.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum RoadStyle {
+    #[default]
+    Schematic,
+    Satellite,
+}
+
+impl RoadStyle {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "schematic" => Ok(RoadStyle::Schematic),
+            "satellite" => Ok(RoadStyle::Satellite),
+            _ => Err(format!("Unknown road style: {}", value)),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            RoadStyle::Schematic => "schematic",
+            RoadStyle::Satellite => "satellite",
+        }
+    }
+
+    pub fn toggled(self) -> Self {
+        match self {
+            RoadStyle::Schematic => RoadStyle::Satellite,
+            RoadStyle::Satellite => RoadStyle::Schematic,
+        }
+    }
+}
+
+/// A reason for a headless run to end before its time is up.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopCondition {
+    /// This many vehicles have driven off the screen.
+    Exited(usize),
+    /// This many seconds have been simulated.
+    Seconds(f64),
+    /// Any collision.
+    Collision,
+    /// The intersection is gridlocked.
+    Gridlock,
+}
+
+impl StopCondition {
+    /// Reads `exited:N`, `seconds:S`, `collision` or `gridlock`.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let (name, count) = match value.split_once(':') {
+            Some((name, count)) => (name, Some(count)),
+            None => (value, None),
+        };
+        match (name, count) {
+            ("exited", Some(count)) => Ok(StopCondition::Exited(parse("exited", count)?)),
+            ("seconds", Some(seconds)) => Ok(StopCondition::Seconds(parse("seconds", seconds)?)),
+            ("collision", None) => Ok(StopCondition::Collision),
+            ("gridlock", None) => Ok(StopCondition::Gridlock),
+            _ => Err(format!("Unknown stop condition: {}", value)),
+        }
+    }
+
+    /// The form `parse` reads.
+    pub fn name(self) -> String {
+        match self {
+            StopCondition::Exited(count) => format!("exited:{}", count),
+            StopCondition::Seconds(seconds) => format!("seconds:{}", seconds),
+            StopCondition::Collision => "collision".to_string(),
+            StopCondition::Gridlock => "gridlock".to_string(),
+        }
+    }
+}
+
+/// What decides when a vehicle may cross its stop line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ControlMode {
+    /// The traffic lights.
+    #[default]
+    Lights,
+    /// No signals: vehicles give way to conflicting traffic in the box and
+    /// to conflicting traffic arriving on their right.
+    Uncontrolled,
+    /// Every vehicle stops at the line, then conflicting vehicles go in the
+    /// order they stopped.
+    AllWayStop,
+}
+
+impl ControlMode {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "lights" => Ok(ControlMode::Lights),
+            "uncontrolled" => Ok(ControlMode::Uncontrolled),
+            "all-way-stop" => Ok(ControlMode::AllWayStop),
+            _ => Err(format!("Unknown control mode: {}", value)),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            ControlMode::Lights => "lights",
+            ControlMode::Uncontrolled => "uncontrolled",
+            ControlMode::AllWayStop => "all-way-stop",
+        }
+    }
+}
+
+/// Parses per-approach numbers such as `up:80,left:70` for `key`.
+/// Approaches left out keep `default`.
+fn parse_per_approach(key: &str, value: &str, default: f32) -> Result<[f32; 4], String> {
+    let mut values = [default; 4];
+    for entry in value.split(',') {
+        let (approach, number) = entry
+            .split_once(':')
+            .ok_or_else(|| format!("{} needs approach:value entries: {}", key, entry))?;
+        values[Direction::parse(approach.trim())?.index()] = parse(key, number.trim())?;
+    }
+    Ok(values)
+}
+
+/// Writes `values` in the form `parse_per_approach` reads.
+fn format_per_approach(values: [f32; 4]) -> String {
+    Direction::ALL
+        .iter()
+        .map(|approach| format!("{}:{:?}", approach, values[approach.index()]))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Parses `COLUMNSxROWS`, such as `3x2`.
+fn parse_grid(value: &str) -> Result<(usize, usize), String> {
+    let (columns, rows) = value
+        .split_once('x')
+        .ok_or_else(|| format!("Grid must be COLUMNSxROWS: {}", value))?;
+    Ok((parse("grid", columns.trim())?, parse("grid", rows.trim())?))
+}
+
+/// Parses `centered` or an `x,y` screen position.
+fn parse_position(value: &str) -> Result<Option<(i32, i32)>, String> {
+    if value == "centered" {
+        return Ok(None);
+    }
+    let (x, y) = value
+        .split_once(',')
+        .ok_or_else(|| format!("Window position must be x,y or centered: {}", value))?;
+    Ok(Some((
+        parse("window_position", x.trim())?,
+        parse("window_position", y.trim())?,
+    )))
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub collision_policy: CollisionPolicy,
+    /// Undo any move that would make two vehicles overlap, stopping them
+    /// where they were, so no collision is ever shown.
+    pub rollback_collisions: bool,
+    /// Pause the window on the tick a collision starts.
+    pub pause_on_collision: bool,
+    pub tick_rate: f32,
+    /// How many simulated seconds pass per wall-clock second. The tick
+    /// length in simulated time stays `1 / tick_rate` whatever the scale.
+    pub time_scale: f32,
+    pub interpolate: bool,
+    /// Remove vehicles, counted as exits, as soon as they clear the
+    /// intersection box instead of at the screen edge.
+    pub despawn_at_box_exit: bool,
+    /// Ease the drawn heading round turns; off draws the exact direction.
+    pub smooth_headings: bool,
+    /// Draw turning vehicles along a quarter circle from the entry lane to
+    /// the exit lane; off draws them turning on the spot.
+    pub curved_turns: bool,
+    pub light_plan: LightPlan,
+    /// Decides which plan phase follows the current one.
+    pub signal_controller: ControllerKind,
+    /// Lights, or one of the unsignalled modes that ignore them.
+    pub control_mode: ControlMode,
+    /// The plan phase the run starts in, counted from 0.
+    pub initial_phase: usize,
+    /// Start a random, seeded number of seconds further into the cycle, so
+    /// repeated runs don't all begin with the same approach green.
+    pub random_phase_offset: bool,
+    /// Seconds into its cycle the plan starts.
+    pub phase_offset: f32,
+    /// Distance in pixels from the first intersection of a corridor. A
+    /// non-zero distance adds the offset that keeps a platoon at cruising
+    /// speed in the green wave.
+    pub wave_distance: f32,
+    pub starvation_warning: f32,
+    pub color_mode: ColorMode,
+    pub road_style: RoadStyle,
+    /// Draw vehicles as flat rectangles even where sprites are available.
+    pub simple_render: bool,
+    /// Directory the vehicle sprites are loaded from, one PNG per kind,
+    /// and the sounds, one WAV each.
+    pub assets: String,
+    /// Play the engine hum, horns and light clicks; needs a build with the
+    /// `audio` feature.
+    pub audio: bool,
+    /// Seconds a vehicle is stopped before it sounds its horn.
+    pub horn_after: f32,
+    pub weather: Weather,
+    /// Share of their usual top speed vehicles drive at in the rain.
+    pub rain_speed_factor: f32,
+    /// Share of their usual deceleration vehicles brake at in the rain, so
+    /// stopping takes them 1 / this as far.
+    pub rain_braking_factor: f32,
+    /// Hour of the day the run starts at, from 0 to 24.
+    pub time_of_day: f32,
+    /// Simulated seconds a whole day and night take; 0 keeps it
+    /// `time_of_day` throughout.
+    pub day_length: f32,
+    /// Seeds the spawn stream: routes, truck draws, random colours, the
+    /// random-spawn key and the random phase offset. `None` seeds from the
+    /// OS.
+    pub seed: Option<u64>,
+    /// Seeds the tie-break stream, which only settles which of two
+    /// vehicles with an equal claim goes first, so demand can change
+    /// without changing how conflicts resolve. `None` falls back to
+    /// `seed`.
+    pub tie_break_seed: Option<u64>,
+    pub layout: Layout,
+    pub print_hashes: bool,
+    /// Print the movement conflict matrix for the layout and exit.
+    pub print_conflicts: bool,
+    /// Print how long each stage of a tick takes, averaged over this many
+    /// ticks; 0 turns profiling off and costs nothing.
+    pub profile_every: u64,
+    /// Run this many simulated seconds of saturated traffic without a
+    /// window, then exit, failing if anything went wrong. Unseeded runs use
+    /// seed 0.
+    pub soak: Option<f64>,
+    /// `soak` counted in ticks instead, as a benchmark counts them: run this
+    /// many without a window, then print the statistics and exit.
+    pub headless: Option<u64>,
+    /// Conditions that end a soak early, checked after every tick; the
+    /// first to hold is reported in the summary.
+    pub stop_when: Vec<StopCondition>,
+    /// After the soak, stop spawning and run until the road is empty,
+    /// reporting how long it took to clear.
+    pub drain: bool,
+    /// Address, such as `127.0.0.1:9100`, to serve Prometheus metrics on at
+    /// `/metrics`; `None` serves nothing.
+    pub serve: Option<String>,
+    /// Address, or just a port on 127.0.0.1, to take JSON commands on and
+    /// stream telemetry from, as `remote::Remote` describes; `None` takes none.
+    pub remote: Option<String>,
+    /// Run a second simulation beside the first, fed exactly the same
+    /// arrivals but with this controller, and show the two side by side.
+    pub compare: Option<ControllerKind>,
+    /// Columns and rows of a map of intersections, each with its own
+    /// lights, that vehicles cross several of on their way to an edge;
+    /// `None` runs the single intersection.
+    pub grid: Option<(usize, usize)>,
+    /// Seconds a vehicle takes on the road between two intersections of
+    /// the `grid`, from leaving one to reaching the next one's spawn point.
+    pub segment_seconds: f32,
+    /// Threads the intersections of the `grid` tick on, each thread taking
+    /// a share of them; 0 uses one per core. A crowded intersection also
+    /// shares each tick's following and entry checks among them. The run is
+    /// the same however many there are.
+    pub threads: usize,
+    /// Write every spawn to this file when the run ends, for `replay`. An
+    /// unseeded run is given a random seed so it can be replayed.
+    pub record: Option<String>,
+    /// Take every spawn from a file written by `record` rather than from
+    /// keys or random arrivals. With the same settings the run is the
+    /// recorded one, tick for tick; with another controller it is the same
+    /// traffic, each arrival waiting as long as its spawn point is taken.
+    pub replay: Option<String>,
+    /// Start from a snapshot saved with F5 rather than an empty road. F5
+    /// saves to this file and F9 restores from it; without it they use
+    /// `snapshot.txt`.
+    pub load: Option<String>,
+    /// CSV or JSON file, by its extension, to write per-approach exits,
+    /// throughput, waits and queues to when the run ends.
+    pub metrics_out: Option<String>,
+    /// Also sample the metrics every this many simulated seconds, writing
+    /// the file again each time; 0 samples only at the end.
+    pub metrics_every: f32,
+    /// Bumper-to-bumper gap, in pixels, kept behind a leader when stopped;
+    /// at least `MIN_BASE_GAP`.
+    pub base_gap: f32,
+    /// Extra gap per pixel-per-second of the follower's speed.
+    pub gap_per_speed: f32,
+    /// Vehicles queued per approach by a spawn burst.
+    pub burst_size: usize,
+    /// Most vehicles each approach may have short of its stop line; spawns
+    /// past that are dropped. `None` queues until the spawn point is taken.
+    pub queue_cap: Option<usize>,
+    /// Spawn traffic on its own, arriving at random at `arrival_rate`.
+    pub auto_spawn: bool,
+    /// Vehicles per second arriving at the whole intersection while
+    /// `auto_spawn` is on, split between approaches by `approach_weights`.
+    pub arrival_rate: f32,
+    /// Pedestrians per second arriving at the crosswalks, spread evenly
+    /// over the arms; 0 leaves the crosswalks off the road altogether.
+    pub pedestrian_rate: f32,
+    /// Whether a detected gridlock makes the lights drain the intersection
+    /// one approach at a time.
+    pub gridlock_recovery: bool,
+    /// Seconds the vehicles in the intersection must all be stuck before it
+    /// counts as gridlocked.
+    pub gridlock_timeout: f32,
+    /// All-red seconds between recovery greens.
+    pub recovery_clearance: f32,
+    /// Green seconds each approach gets during recovery.
+    pub recovery_green: f32,
+    /// Time spawn cooldowns in simulation ticks rather than wall-clock
+    /// time, so runs don't depend on how fast frames are drawn.
+    pub tick_cooldown: bool,
+    /// Window pixels per simulation unit; the simulation itself is unaffected.
+    pub scale: f32,
+    /// Seconds; light phases shorter than this are stretched to it.
+    pub min_phase_duration: f32,
+    /// Seconds; phases that give anyone green last at least this long.
+    pub min_green: f32,
+    /// Seconds of yellow for approaches losing green; 0 disables it.
+    pub yellow: f32,
+    /// Seconds of all-red before a conflicting green; 0 disables it.
+    pub all_red: f32,
+    /// Seconds the max-pressure controller may keep an approach with
+    /// vehicles waiting red before it must give it green.
+    pub max_wait: f32,
+    /// Seconds; the longest a green phase may be retimed to at runtime.
+    pub max_green: f32,
+    /// Seconds; the longest yellow may be retimed to at runtime.
+    pub max_yellow: f32,
+    /// Fraction of spawned vehicles that are trucks, between 0 and 1.
+    pub truck_share: f32,
+    /// Fraction that are motorcycles; with `truck_share`, at most 1. The
+    /// rest are cars.
+    pub motorcycle_share: f32,
+    /// Seconds a stopped vehicle takes to pull away once it is free to.
+    pub reaction_time: f32,
+    /// Pixels per second cubed by which a vehicle's acceleration may change,
+    /// so speed changes ramp in and out; 0 lets it change at once.
+    pub max_jerk: f32,
+    /// Most aggressive a driver may be, from 0 to 1, each drawn evenly up
+    /// to it. Aggressive drivers go on through a yellow from further back
+    /// and leave less of the gap that grows with speed; 0 makes every
+    /// driver careful.
+    pub aggressiveness: f32,
+    /// Radius in pixels of each turn, which sets how far vehicles slow for
+    /// it; 0 lets them turn at full speed.
+    pub left_turn_radius: f32,
+    pub right_turn_radius: f32,
+    /// A car's size in pixels, along and across its direction of travel.
+    /// Other kinds are sized from it.
+    pub vehicle_length: f32,
+    pub vehicle_width: f32,
+    pub window_mode: WindowMode,
+    /// Top-left corner on the desktop; `None` centres the window.
+    pub window_position: Option<(i32, i32)>,
+    /// Pixels across and down the world, which the window shows before
+    /// `scale`; the roads cross at its centre.
+    pub width: u32,
+    pub height: u32,
+    /// Pixels across each road. The lanes keep their width, so a wider
+    /// road leaves more room beside them and makes the box bigger.
+    pub road_width: u32,
+    /// Pixels across each lane, which is also how far its centre runs from
+    /// the road's centre line.
+    pub lane_width: u32,
+    /// How far each approach's stop line sits from the centre, indexed by
+    /// `Direction::index`.
+    pub stop_lines: [f32; 4],
+    /// Pixels the approach roads swing sideways by the window edge, curving
+    /// gently from a car length beyond the furthest stop line; 0 keeps them
+    /// straight. Vehicles are drawn along the curve, but distances are still
+    /// measured along the arm.
+    pub approach_bend: f32,
+    /// How often the random-spawn key picks each approach, relative to the
+    /// others; indexed by `Direction::index`.
+    pub approach_weights: [f32; 4],
+    /// Pixels per second vehicles spawn and cruise at, unless a speed limit
+    /// holds them lower.
+    pub cruise_speed: f32,
+    /// Seconds before an approach takes another key-press spawn.
+    pub spawn_cooldown: f32,
+    /// Key presses each approach holds on to while it cools down, its
+    /// spawn point is taken or its queue is full, each spawned as soon as
+    /// it can be; presses past that many are dropped. 0 drops them all.
+    pub spawn_buffer: usize,
+    /// Pixels per second vehicles may drive in each approach's lane, and
+    /// after turning into the lane heading the same way; indexed by
+    /// `Direction::index`.
+    pub speed_limits: [f32; 4],
+    /// Movements vehicles may not make, such as `up-left` for a "no left
+    /// turn" sign on the up approach.
+    pub banned_movements: Vec<Movement>,
+    /// Lets vehicles make U-turns, which are otherwise never drawn.
+    pub u_turns: bool,
+    /// Check the simulation's invariants after every tick. Off by default
+    /// because the checks compare every pair of vehicles.
+    pub check_invariants: bool,
+    /// Panic on the first broken invariant instead of logging it.
+    pub invariant_panic: bool,
+    /// How much each part of `TrafficSimulation::cost` counts: per second
+    /// of average delay, per exit per minute (which lowers the cost), per
+    /// vehicle in the longest queue and per collision.
+    pub cost_delay_weight: f64,
+    pub cost_throughput_weight: f64,
+    pub cost_queue_weight: f64,
+    pub cost_collision_weight: f64,
+    /// Warn about vehicles that stray from every lane line for their
+    /// direction, once per vehicle.
+    pub check_lanes: bool,
+    /// Pixels a vehicle may be off a lane line before `check_lanes` warns.
+    pub lane_tolerance: f32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            collision_policy: CollisionPolicy::default(),
+            rollback_collisions: false,
+            pause_on_collision: false,
+            tick_rate: TICK_RATE,
+            time_scale: 1.0,
+            interpolate: true,
+            smooth_headings: true,
+            curved_turns: true,
+            despawn_at_box_exit: false,
+            light_plan: LightPlan::default(),
+            signal_controller: ControllerKind::default(),
+            control_mode: ControlMode::default(),
+            initial_phase: 0,
+            random_phase_offset: false,
+            phase_offset: 0.0,
+            wave_distance: 0.0,
+            starvation_warning: 30.0,
+            color_mode: ColorMode::default(),
+            road_style: RoadStyle::default(),
+            simple_render: false,
+            assets: "assets".to_string(),
+            audio: false,
+            horn_after: 10.0,
+            weather: Weather::default(),
+            rain_speed_factor: 0.75,
+            rain_braking_factor: 0.6,
+            time_of_day: 12.0,
+            day_length: 0.0,
+            seed: None,
+            tie_break_seed: None,
+            serve: None,
+            remote: None,
+            compare: None,
+            grid: None,
+            segment_seconds: 1.0,
+            threads: 1,
+            record: None,
+            replay: None,
+            load: None,
+            metrics_out: None,
+            metrics_every: 0.0,
+            profile_every: 0,
+            soak: None,
+            headless: None,
+            stop_when: Vec::new(),
+            drain: false,
+            layout: Layout::default(),
+            print_hashes: false,
+            print_conflicts: false,
+            base_gap: 10.0,
+            gap_per_speed: 0.25,
+            burst_size: 5,
+            auto_spawn: false,
+            arrival_rate: 0.5,
+            pedestrian_rate: 0.0,
+            queue_cap: None,
+            gridlock_recovery: false,
+            gridlock_timeout: 5.0,
+            recovery_clearance: 1.0,
+            recovery_green: 3.0,
+            tick_cooldown: false,
+            scale: 1.0,
+            min_phase_duration: 0.5,
+            min_green: 0.0,
+            yellow: 0.0,
+            all_red: 0.0,
+            max_wait: 20.0,
+            max_green: 30.0,
+            max_yellow: 5.0,
+            truck_share: 0.0,
+            motorcycle_share: 0.0,
+            reaction_time: 0.0,
+            max_jerk: 0.0,
+            aggressiveness: 0.0,
+            left_turn_radius: 0.0,
+            right_turn_radius: 0.0,
+            vehicle_length: VEHICLE_SIZE as f32,
+            vehicle_width: VEHICLE_SIZE as f32,
+            window_mode: WindowMode::default(),
+            window_position: None,
+            width: WINDOW_WIDTH,
+            height: WINDOW_HEIGHT,
+            road_width: ROAD_WIDTH,
+            lane_width: LANE_WIDTH,
+            stop_lines: [World::default().default_stop_setback(); 4],
+            approach_bend: 0.0,
+            approach_weights: [1.0; 4],
+            cruise_speed: VEHICLE_SPEED * TICK_RATE,
+            spawn_cooldown: 1.0,
+            spawn_buffer: 5,
+            speed_limits: [MAX_SPEED_LIMIT; 4],
+            banned_movements: Vec::new(),
+            u_turns: false,
+            check_invariants: false,
+            invariant_panic: false,
+            cost_delay_weight: 1.0,
+            cost_throughput_weight: 1.0,
+            cost_queue_weight: 0.5,
+            cost_collision_weight: 100.0,
+            check_lanes: false,
+            lane_tolerance: 3.0,
+        }
+    }
+}
+
+fn parse<T: FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value for {}: {}", key, value))
+}
+
+impl Config {
+    /// Every CLI flag `--some-option VALUE` sets the config key `some_option`,
+    /// so flags and config files share one list of settings. `--config PATH`
+    /// loads a file; the other flags override it wherever they stand.
+    pub fn from_args<I: Iterator<Item = String>>(args: I) -> Result<Self, String> {
+        Self::from_args_or_file(args, None)
+    }
+
+    /// As `from_args`, but without `--config` the settings start from
+    /// `fallback` when that file exists, such as the one saved last time.
+    pub fn from_args_or_file<I: Iterator<Item = String>>(
+        mut args: I,
+        fallback: Option<&Path>,
+    ) -> Result<Self, String> {
+        let mut file = None;
+        let mut settings = Vec::new();
+
+        while let Some(arg) = args.next() {
+            let setting = match arg.as_str() {
+                "--no-interpolation" => ("interpolate", "false"),
+                "--exact-headings" => ("smooth_headings", "false"),
+                "--square-turns" => ("curved_turns", "false"),
+                "--print-hashes" => ("print_hashes", "true"),
+                "--print-conflicts" => ("print_conflicts", "true"),
+                "--check-invariants" => ("check_invariants", "true"),
+                "--drain" => ("drain", "true"),
+                "--auto" => ("auto_spawn", "true"),
+                "--pause-on-collision" => ("pause_on_collision", "true"),
+                "--check-lanes" => ("check_lanes", "true"),
+                "--simple-render" => ("simple_render", "true"),
+                "--audio" => ("audio", "true"),
+                "--u-turns" => ("u_turns", "true"),
+                flag => {
+                    let key = flag
+                        .strip_prefix("--")
+                        .ok_or_else(|| format!("Unknown argument: {}", arg))?
+                        .replace('-', "_");
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("{} expects a value", flag))?;
+                    if key != "config" {
+                        settings.push((key, value));
+                    } else if file.replace(value).is_some() {
+                        return Err("--config may only be given once".to_string());
+                    }
+                    continue;
+                }
+            };
+            settings.push((setting.0.to_string(), setting.1.to_string()));
+        }
+
+        // The file and the flags are checked together, once merged, so a
+        // flag can mend what the file alone gets wrong.
+        let mut config = match (&file, fallback) {
+            (Some(path), _) => Config::read_file(Path::new(path))?,
+            (None, Some(path)) if path.exists() => {
+                info!("Loading {}", path.display());
+                Config::read_file(path)?
+            }
+            (None, _) => Config::default(),
+        };
+        for (key, value) in settings {
+            config.set(&key, &value)?;
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Reads the `key = value` subset of TOML written by `Config::save`, or
+    /// a `.json` file holding one flat object of the same keys. Missing keys
+    /// keep their defaults.
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let config = Self::read_file(path)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    // `from_file` before the settings are checked.
+    fn read_file(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        let mut config = Config::default();
+
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            let fields = remote::parse_object(&contents)
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+            for (key, value) in fields {
+                let value = match value {
+                    Value::Str(text) => text,
+                    Value::Number(number) => number.to_string(),
+                    Value::Bool(flag) => flag.to_string(),
+                    Value::Null => continue,
+                };
+                config
+                    .set(&key, &value)
+                    .map_err(|e| format!("{}: {}", path.display(), e))?;
+            }
+            return Ok(config);
+        }
+
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or_else(|| {
+                format!("{}:{}: expected key = value", path.display(), number + 1)
+            })?;
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|value| value.strip_suffix('"'))
+                .unwrap_or(value);
+            config
+                .set(key.trim(), value)
+                .map_err(|e| format!("{}:{}: {}", path.display(), number + 1, e))?;
+        }
+
+        Ok(config)
+    }
+
+    /// Writes the current settings, including any changed at runtime, in a
+    /// form `Config::from_file` reads back. Only what tunes the simulation
+    /// is written: how a run is made, such as `serve`, `compare`, `grid`,
+    /// `drain`, `audio` or the debugging checks and printouts, is left to
+    /// each run's flags, since the saved file is loaded on every start.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.to_toml())
+            .map_err(|e| format!("Could not write {}: {}", path.display(), e))
+    }
+
+    /// Simulated seconds covered by one tick. Every timing setting is in
+    /// simulated seconds and is converted to ticks through this.
+    pub fn tick_seconds(&self) -> f32 {
+        1.0 / self.tick_rate
+    }
+
+    /// The first flag set that serves, records or exports a single run, which
+    /// a comparison or a grid has more than one of.
+    fn single_run_flag(&self) -> Option<&'static str> {
+        [
+            ("--serve", self.serve.is_some()),
+            ("--remote", self.remote.is_some()),
+            ("--record", self.record.is_some()),
+            ("--metrics-out", self.metrics_out.is_some()),
+        ]
+        .into_iter()
+        .find_map(|(flag, set)| set.then_some(flag))
+    }
+
+    /// Simulated seconds a run without a window lasts, from `soak` or
+    /// `headless`; `None` opens the window.
+    pub fn soak_seconds(&self) -> Option<f64> {
+        self.soak
+            .or(self.headless.map(|ticks| self.ticks_to_seconds(ticks)))
+    }
+
+    /// Simulated seconds covered by `ticks` ticks.
+    pub fn ticks_to_seconds(&self, ticks: u64) -> f64 {
+        ticks as f64 / self.tick_rate as f64
+    }
+
+    /// Whether traffic lights decide who enters. A roundabout has none,
+    /// whatever `control_mode` says.
+    pub fn signalled(&self) -> bool {
+        self.control_mode == ControlMode::Lights && self.layout != Layout::Roundabout
+    }
+
+    /// What decides entry, for summaries: `roundabout`, or the control
+    /// mode's name.
+    pub fn control_name(&self) -> &'static str {
+        if self.layout == Layout::Roundabout {
+            "roundabout"
+        } else {
+            self.control_mode.name()
+        }
+    }
+
+    /// The world vehicles drive across.
+    pub fn world(&self) -> World {
+        World {
+            width: self.width,
+            height: self.height,
+            road_width: self.road_width,
+            lane_width: self.lane_width,
+        }
+    }
+
+    /// What the weather leaves vehicles of their speed and braking.
+    pub fn traction(&self) -> Traction {
+        match self.weather {
+            Weather::Clear => Traction::DRY,
+            Weather::Rain => Traction {
+                speed: self.rain_speed_factor,
+                braking: self.rain_braking_factor,
+            },
+        }
+    }
+
+    /// Whether pedestrians use the crosswalks.
+    pub fn has_pedestrians(&self) -> bool {
+        self.pedestrian_rate > 0.0
+    }
+
+    /// How far `approach`'s vehicles stop from the centre: at the
+    /// configured stop line, moved back behind the crosswalk while there
+    /// are pedestrians.
+    pub fn stop_setback(&self, approach: Direction) -> f32 {
+        let setback = self.stop_lines[approach.index()];
+        if self.has_pedestrians() {
+            setback.max(self.world().half_road() + CROSSWALK_WIDTH as f32)
+        } else {
+            setback
+        }
+    }
+
+    /// The curve of the approach roads, starting a car length beyond the
+    /// furthest stop line.
+    pub fn bend(&self) -> Bend {
+        let furthest = Direction::ALL
+            .into_iter()
+            .map(|approach| self.stop_setback(approach))
+            .fold(0.0, f32::max);
+        Bend {
+            amount: self.approach_bend,
+            start: furthest + VEHICLE_SIZE as f32,
+            world: self.world(),
+        }
+    }
+
+    /// The radius of `route`'s turn; 0 for going straight. A U-turn's two
+    /// turns are each as tight as a left turn.
+    pub fn turn_radius(&self, route: Route) -> f32 {
+        match route {
+            Route::Straight => 0.0,
+            Route::TurnLeft | Route::UTurn => self.left_turn_radius,
+            Route::TurnRight => self.right_turn_radius,
+        }
+    }
+
+    /// A phase that greens one route alone shows it as an arrow, which
+    /// promises that nothing crossing its path has green at the same time.
+    fn check_arrows(&self) -> Result<(), String> {
+        let arrows = self
+            .light_plan
+            .phases
+            .iter()
+            .any(|phase| phase.only.iter().any(Option::is_some));
+        if !self.signalled() || !arrows {
+            return Ok(());
+        }
+        let movements: Vec<Movement> = Movement::all()
+            .filter(|&movement| self.permits(movement))
+            .collect();
+        let matrix = ConflictMatrix::compute();
+        for (index, phase) in self.light_plan.phases.iter().enumerate() {
+            let green = |movement: &&Movement| phase.allows(movement.approach, movement.route);
+            for arrow in movements.iter().filter(green) {
+                if phase.only[arrow.approach.index()].is_none() {
+                    continue;
+                }
+                if let Some(other) = movements
+                    .iter()
+                    .filter(green)
+                    .find(|&&other| matrix.conflicts(*arrow, other))
+                {
+                    return Err(format!(
+                        "Phase {} shows {} an arrow but also greens {}, which crosses it",
+                        index,
+                        arrow.name(),
+                        other.name()
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether vehicles may make `movement`: the layout has both its arms,
+    /// it isn't banned, and it isn't a U-turn unless `u_turns` allows them.
+    pub fn permits(&self, movement: Movement) -> bool {
+        movement.exists_in(self.layout)
+            && !self.banned_movements.contains(&movement)
+            && (self.u_turns || movement.route != Route::UTurn)
+    }
+
+    /// Whole ticks covering `seconds` of simulated time, rounded to nearest.
+    pub fn seconds_to_ticks(&self, seconds: f32) -> u32 {
+        (seconds / self.tick_seconds()).round() as u32
+    }
+
+    /// `threads`, with 0 resolved to the cores there are.
+    pub fn worker_threads(&self) -> usize {
+        match self.threads {
+            0 => std::thread::available_parallelism().map_or(1, |cores| cores.get()),
+            threads => threads,
+        }
+    }
+
+    /// `spawn_cooldown` in milliseconds, the unit key presses are timed in.
+    pub fn spawn_cooldown_millis(&self) -> u128 {
+        (self.spawn_cooldown * 1000.0).round() as u128
+    }
+
+    /// Wall-clock time between ticks at the configured time scale.
+    pub fn tick_interval(&self) -> Duration {
+        Duration::from_secs_f32(self.tick_seconds() / self.time_scale)
+    }
+
+    pub fn signal_timing(&self) -> SignalTiming {
+        SignalTiming {
+            min_phase: self.min_phase_duration,
+            min_green: self.min_green,
+            yellow: self.yellow,
+            all_red: self.all_red,
+            max_wait: self.max_wait,
+        }
+    }
+
+    fn to_toml(&self) -> String {
+        let mut lines = vec![
+            format!("collision_policy = \"{}\"", self.collision_policy.name()),
+            format!("rollback_collisions = {}", self.rollback_collisions),
+            format!("pause_on_collision = {}", self.pause_on_collision),
+            format!("tick_rate = {:?}", self.tick_rate),
+            format!("time_scale = {:?}", self.time_scale),
+            format!("interpolate = {}", self.interpolate),
+            format!("smooth_headings = {}", self.smooth_headings),
+            format!("curved_turns = {}", self.curved_turns),
+            format!("despawn_at_box_exit = {}", self.despawn_at_box_exit),
+            format!("light_plan = \"{}\"", self.light_plan),
+            format!("signal_controller = \"{}\"", self.signal_controller.name()),
+            format!("control_mode = \"{}\"", self.control_mode.name()),
+            format!("initial_phase = {}", self.initial_phase),
+            format!("random_phase_offset = {}", self.random_phase_offset),
+            format!("phase_offset = {:?}", self.phase_offset),
+            format!("wave_distance = {:?}", self.wave_distance),
+            format!("starvation_warning = {:?}", self.starvation_warning),
+            format!("color_mode = \"{}\"", self.color_mode.name()),
+            format!("road_style = \"{}\"", self.road_style.name()),
+            format!("simple_render = {}", self.simple_render),
+            format!("assets = \"{}\"", self.assets),
+            format!("horn_after = {:?}", self.horn_after),
+            format!("weather = \"{}\"", self.weather.name()),
+            format!("rain_speed_factor = {:?}", self.rain_speed_factor),
+            format!("rain_braking_factor = {:?}", self.rain_braking_factor),
+            format!("time_of_day = {:?}", self.time_of_day),
+            format!("day_length = {:?}", self.day_length),
+        ];
+        if let Some(seed) = self.seed {
+            lines.push(format!("seed = {}", seed));
+        }
+        if let Some(seed) = self.tie_break_seed {
+            lines.push(format!("tie_break_seed = {}", seed));
+        }
+        lines.push(format!("segment_seconds = {:?}", self.segment_seconds));
+        lines.push(format!("threads = {}", self.threads));
+        if let Some(cap) = self.queue_cap {
+            lines.push(format!("queue_cap = {}", cap));
+        }
+        lines.extend([
+            format!("layout = \"{}\"", self.layout),
+            format!("metrics_every = {:?}", self.metrics_every),
+            format!("base_gap = {:?}", self.base_gap),
+            format!("gap_per_speed = {:?}", self.gap_per_speed),
+            format!("burst_size = {}", self.burst_size),
+            format!("auto_spawn = {}", self.auto_spawn),
+            format!("arrival_rate = {:?}", self.arrival_rate),
+            format!("pedestrian_rate = {:?}", self.pedestrian_rate),
+            format!("gridlock_recovery = {}", self.gridlock_recovery),
+            format!("gridlock_timeout = {:?}", self.gridlock_timeout),
+            format!("recovery_clearance = {:?}", self.recovery_clearance),
+            format!("recovery_green = {:?}", self.recovery_green),
+            format!("tick_cooldown = {}", self.tick_cooldown),
+            format!("scale = {:?}", self.scale),
+            format!("min_phase_duration = {:?}", self.min_phase_duration),
+            format!("min_green = {:?}", self.min_green),
+            format!("yellow = {:?}", self.yellow),
+            format!("all_red = {:?}", self.all_red),
+            format!("max_wait = {:?}", self.max_wait),
+            format!("max_green = {:?}", self.max_green),
+            format!("max_yellow = {:?}", self.max_yellow),
+            format!("truck_share = {:?}", self.truck_share),
+            format!("motorcycle_share = {:?}", self.motorcycle_share),
+            format!("reaction_time = {:?}", self.reaction_time),
+            format!("max_jerk = {:?}", self.max_jerk),
+            format!("aggressiveness = {:?}", self.aggressiveness),
+            format!("left_turn_radius = {:?}", self.left_turn_radius),
+            format!("right_turn_radius = {:?}", self.right_turn_radius),
+            format!("vehicle_length = {:?}", self.vehicle_length),
+            format!("vehicle_width = {:?}", self.vehicle_width),
+            format!("window_mode = \"{}\"", self.window_mode.name()),
+            match self.window_position {
+                Some((x, y)) => format!("window_position = \"{},{}\"", x, y),
+                None => "window_position = \"centered\"".to_string(),
+            },
+            format!("width = {}", self.width),
+            format!("height = {}", self.height),
+            format!("road_width = {}", self.road_width),
+            format!("lane_width = {}", self.lane_width),
+            format!("stop_lines = \"{}\"", format_per_approach(self.stop_lines)),
+            format!("approach_bend = {:?}", self.approach_bend),
+            format!(
+                "approach_weights = \"{}\"",
+                format_per_approach(self.approach_weights)
+            ),
+            format!("cruise_speed = {:?}", self.cruise_speed),
+            format!("spawn_cooldown = {:?}", self.spawn_cooldown),
+            format!("spawn_buffer = {}", self.spawn_buffer),
+            format!(
+                "speed_limits = \"{}\"",
+                format_per_approach(self.speed_limits)
+            ),
+            format!(
+                "banned_movements = \"{}\"",
+                self.banned_movements
+                    .iter()
+                    .map(|movement| movement.name())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+            format!("u_turns = {}", self.u_turns),
+            format!("cost_delay_weight = {:?}", self.cost_delay_weight),
+            format!("cost_throughput_weight = {:?}", self.cost_throughput_weight),
+            format!("cost_queue_weight = {:?}", self.cost_queue_weight),
+            format!("cost_collision_weight = {:?}", self.cost_collision_weight),
+            format!("lane_tolerance = {:?}", self.lane_tolerance),
+        ]);
+
+        let mut toml = lines.join("\n");
+        toml.push('\n');
+        toml
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "collision_policy" => self.collision_policy = CollisionPolicy::parse(value)?,
+            "rollback_collisions" => self.rollback_collisions = parse(key, value)?,
+            "pause_on_collision" => self.pause_on_collision = parse(key, value)?,
+            "tick_rate" => self.tick_rate = parse(key, value)?,
+            "time_scale" => self.time_scale = parse(key, value)?,
+            "interpolate" => self.interpolate = parse(key, value)?,
+            "smooth_headings" => self.smooth_headings = parse(key, value)?,
+            "curved_turns" => self.curved_turns = parse(key, value)?,
+            "despawn_at_box_exit" => self.despawn_at_box_exit = parse(key, value)?,
+            "light_plan" => self.light_plan = LightPlan::parse(value)?,
+            "signal_controller" => self.signal_controller = ControllerKind::parse(value)?,
+            "control_mode" => self.control_mode = ControlMode::parse(value)?,
+            "initial_phase" => self.initial_phase = parse(key, value)?,
+            "random_phase_offset" => self.random_phase_offset = parse(key, value)?,
+            "phase_offset" => self.phase_offset = parse(key, value)?,
+            "wave_distance" => self.wave_distance = parse(key, value)?,
+            "starvation_warning" => self.starvation_warning = parse(key, value)?,
+            "color_mode" => self.color_mode = ColorMode::parse(value)?,
+            "road_style" => self.road_style = RoadStyle::parse(value)?,
+            "simple_render" => self.simple_render = parse(key, value)?,
+            "assets" => self.assets = value.to_string(),
+            "audio" => self.audio = parse(key, value)?,
+            "horn_after" => self.horn_after = parse(key, value)?,
+            "weather" => self.weather = Weather::parse(value)?,
+            "rain_speed_factor" => self.rain_speed_factor = parse(key, value)?,
+            "rain_braking_factor" => self.rain_braking_factor = parse(key, value)?,
+            "time_of_day" => self.time_of_day = parse(key, value)?,
+            "day_length" => self.day_length = parse(key, value)?,
+            "seed" => self.seed = Some(parse(key, value)?),
+            "tie_break_seed" => self.tie_break_seed = Some(parse(key, value)?),
+            "serve" => self.serve = Some(value.to_string()),
+            "remote" => self.remote = Some(value.to_string()),
+            "compare" => self.compare = Some(ControllerKind::parse(value)?),
+            "grid" => self.grid = Some(parse_grid(value)?),
+            "segment_seconds" => self.segment_seconds = parse(key, value)?,
+            "threads" => self.threads = parse(key, value)?,
+            "record" => self.record = Some(value.to_string()),
+            "replay" => self.replay = Some(value.to_string()),
+            "load" => self.load = Some(value.to_string()),
+            "metrics_out" => self.metrics_out = Some(value.to_string()),
+            "metrics_every" => self.metrics_every = parse(key, value)?,
+            "soak" => self.soak = Some(parse(key, value)?),
+            "headless" => self.headless = Some(parse(key, value)?),
+            "drain" => self.drain = parse(key, value)?,
+            "stop_when" => {
+                self.stop_when = value
+                    .split(',')
+                    .filter(|name| !name.trim().is_empty())
+                    .map(|name| StopCondition::parse(name.trim()))
+                    .collect::<Result<_, _>>()?
+            }
+            "layout" => self.layout = Layout::parse(value)?,
+            "print_hashes" => self.print_hashes = parse(key, value)?,
+            "print_conflicts" => self.print_conflicts = parse(key, value)?,
+            "profile_every" => self.profile_every = parse(key, value)?,
+            "base_gap" => self.base_gap = parse(key, value)?,
+            "gap_per_speed" => self.gap_per_speed = parse(key, value)?,
+            "burst_size" => self.burst_size = parse(key, value)?,
+            "queue_cap" => self.queue_cap = Some(parse(key, value)?),
+            "auto_spawn" => self.auto_spawn = parse(key, value)?,
+            "arrival_rate" => self.arrival_rate = parse(key, value)?,
+            "pedestrian_rate" => self.pedestrian_rate = parse(key, value)?,
+            "gridlock_recovery" => self.gridlock_recovery = parse(key, value)?,
+            "gridlock_timeout" => self.gridlock_timeout = parse(key, value)?,
+            "recovery_clearance" => self.recovery_clearance = parse(key, value)?,
+            "recovery_green" => self.recovery_green = parse(key, value)?,
+            "tick_cooldown" => self.tick_cooldown = parse(key, value)?,
+            "scale" => self.scale = parse(key, value)?,
+            "min_phase_duration" => self.min_phase_duration = parse(key, value)?,
+            "min_green" => self.min_green = parse(key, value)?,
+            "yellow" => self.yellow = parse(key, value)?,
+            "all_red" => self.all_red = parse(key, value)?,
+            "max_wait" => self.max_wait = parse(key, value)?,
+            "max_green" => self.max_green = parse(key, value)?,
+            "max_yellow" => self.max_yellow = parse(key, value)?,
+            "truck_share" => self.truck_share = parse(key, value)?,
+            "motorcycle_share" => self.motorcycle_share = parse(key, value)?,
+            "reaction_time" => self.reaction_time = parse(key, value)?,
+            "max_jerk" => self.max_jerk = parse(key, value)?,
+            "aggressiveness" => self.aggressiveness = parse(key, value)?,
+            "left_turn_radius" => self.left_turn_radius = parse(key, value)?,
+            "right_turn_radius" => self.right_turn_radius = parse(key, value)?,
+            "vehicle_length" => self.vehicle_length = parse(key, value)?,
+            "vehicle_width" => self.vehicle_width = parse(key, value)?,
+            "window_mode" => self.window_mode = WindowMode::parse(value)?,
+            "window_position" => self.window_position = parse_position(value)?,
+            "width" => self.width = parse(key, value)?,
+            "height" => self.height = parse(key, value)?,
+            "road_width" => {
+                let edge = self.world().default_stop_setback();
+                self.road_width = parse(key, value)?;
+                // Stop lines left at the edge of the box move with it.
+                let setback = self.world().default_stop_setback();
+                for line in self.stop_lines.iter_mut().filter(|line| **line == edge) {
+                    *line = setback;
+                }
+            }
+            "lane_width" => self.lane_width = parse(key, value)?,
+            "stop_lines" => {
+                let setback = self.world().default_stop_setback();
+                self.stop_lines = parse_per_approach(key, value, setback)?
+            }
+            "approach_bend" => self.approach_bend = parse(key, value)?,
+            "approach_weights" => self.approach_weights = parse_per_approach(key, value, 1.0)?,
+            "cruise_speed" => self.cruise_speed = parse(key, value)?,
+            "spawn_cooldown" => self.spawn_cooldown = parse(key, value)?,
+            "spawn_buffer" => self.spawn_buffer = parse(key, value)?,
+            "speed_limits" => self.speed_limits = parse_per_approach(key, value, MAX_SPEED_LIMIT)?,
+            "banned_movements" => {
+                self.banned_movements = value
+                    .split(',')
+                    .filter(|name| !name.trim().is_empty())
+                    .map(|name| Movement::parse(name.trim()))
+                    .collect::<Result<_, _>>()?
+            }
+            "u_turns" => self.u_turns = parse(key, value)?,
+            "check_invariants" => self.check_invariants = parse(key, value)?,
+            "invariant_panic" => self.invariant_panic = parse(key, value)?,
+            "cost_delay_weight" => self.cost_delay_weight = parse(key, value)?,
+            "cost_throughput_weight" => self.cost_throughput_weight = parse(key, value)?,
+            "cost_queue_weight" => self.cost_queue_weight = parse(key, value)?,
+            "cost_collision_weight" => self.cost_collision_weight = parse(key, value)?,
+            "check_lanes" => self.check_lanes = parse(key, value)?,
+            "lane_tolerance" => self.lane_tolerance = parse(key, value)?,
+            _ => return Err(format!("Unknown setting: {}", key)),
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.soak.is_some() && self.headless.is_some() {
+            return Err("Give either --soak or --headless, not both".to_string());
+        }
+        // Nothing would command an external controller in a comparison.
+        if self.compare.is_some()
+            && (self.signal_controller == ControllerKind::External
+                || self.compare == Some(ControllerKind::External))
+        {
+            return Err("The external controller cannot be compared".to_string());
+        }
+        if self.compare.is_some() && (self.record.is_some() || self.replay.is_some()) {
+            return Err("A comparison cannot be recorded or replayed".to_string());
+        }
+        if self.compare.is_some()
+            && let Some(flag) = self.single_run_flag()
+        {
+            return Err(format!("{} cannot be used with --compare", flag));
+        }
+        if self.load.is_some()
+            && (self.compare.is_some()
+                || self.grid.is_some()
+                || self.record.is_some()
+                || self.replay.is_some())
+        {
+            return Err(
+                "A snapshot cannot be loaded into a comparison, a grid, a recording or a replay"
+                    .to_string(),
+            );
+        }
+        if let Some((columns, rows)) = self.grid {
+            if columns == 0 || rows == 0 || columns * rows > MAX_GRID_INTERSECTIONS {
+                return Err(format!(
+                    "A grid needs between 1 and {} intersections: {}x{}",
+                    MAX_GRID_INTERSECTIONS, columns, rows
+                ));
+            }
+            // Every intersection joins its neighbours on all four arms.
+            if !Direction::ALL
+                .into_iter()
+                .all(|arm| self.layout.has_arm(arm))
+            {
+                return Err("A grid is made of four-way intersections only".to_string());
+            }
+            if self.compare.is_some() || self.replay.is_some() {
+                return Err("A grid cannot be compared or replayed".to_string());
+            }
+            if let Some(flag) = self.single_run_flag() {
+                return Err(format!("{} cannot be used with --grid", flag));
+            }
+        }
+        if !(self.segment_seconds >= 0.0 && self.segment_seconds.is_finite()) {
+            return Err(format!(
+                "Segment time must not be negative: {}",
+                self.segment_seconds
+            ));
+        }
+        if self.layout == Layout::Roundabout && self.control_mode == ControlMode::AllWayStop {
+            return Err("A roundabout has no stop signs; vehicles give way instead".to_string());
+        }
+        if let Some(path) = &self.metrics_out
+            && !(path.ends_with(".csv") || path.ends_with(".json"))
+        {
+            return Err(format!(
+                "Metrics output must be a .csv or .json file: {}",
+                path
+            ));
+        }
+        if self.metrics_every < 0.0 {
+            return Err(format!(
+                "Metrics interval must not be negative: {}",
+                self.metrics_every
+            ));
+        }
+        if self.tick_rate <= 0.0 {
+            return Err(format!("Tick rate must be positive: {}", self.tick_rate));
+        }
+        if self.time_scale <= 0.0 {
+            return Err(format!("Time scale must be positive: {}", self.time_scale));
+        }
+        if self.horn_after <= 0.0 {
+            return Err(format!(
+                "Horn threshold must be positive: {}",
+                self.horn_after
+            ));
+        }
+        for (name, factor) in [
+            ("speed", self.rain_speed_factor),
+            ("braking", self.rain_braking_factor),
+        ] {
+            if !(factor > 0.0 && factor <= 1.0) {
+                return Err(format!(
+                    "Rain {} factor must be above 0 and at most 1: {}",
+                    name, factor
+                ));
+            }
+        }
+        if !(0.0..24.0).contains(&self.time_of_day) {
+            return Err(format!(
+                "Time of day must be an hour from 0 up to 24: {}",
+                self.time_of_day
+            ));
+        }
+        if !(self.day_length >= 0.0 && self.day_length.is_finite()) {
+            return Err(format!(
+                "Day length must not be negative: {}",
+                self.day_length
+            ));
+        }
+        if self.queue_cap == Some(0) {
+            return Err("Queue cap must be at least 1".to_string());
+        }
+        // Arrivals are drawn at most one a tick.
+        if !(self.arrival_rate > 0.0 && self.arrival_rate <= self.tick_rate) {
+            return Err(format!(
+                "Arrival rate must be positive and at most the tick rate: {}",
+                self.arrival_rate
+            ));
+        }
+        if !(self.pedestrian_rate >= 0.0 && self.pedestrian_rate <= self.tick_rate) {
+            return Err(format!(
+                "Pedestrian rate must be between 0 and the tick rate: {}",
+                self.pedestrian_rate
+            ));
+        }
+        if !(self.base_gap >= MIN_BASE_GAP && self.base_gap.is_finite()) {
+            return Err(format!(
+                "Base gap must be at least {}px: {}",
+                MIN_BASE_GAP, self.base_gap
+            ));
+        }
+        if !(self.gap_per_speed >= 0.0 && self.gap_per_speed.is_finite()) {
+            return Err(format!(
+                "Gap per speed must not be negative: {}",
+                self.gap_per_speed
+            ));
+        }
+        if self.min_phase_duration <= 0.0 {
+            return Err(format!(
+                "Minimum phase duration must be positive: {}",
+                self.min_phase_duration
+            ));
+        }
+        if self.min_green < 0.0 || self.yellow < 0.0 || self.all_red < 0.0 {
+            return Err("Signal intervals must not be negative".to_string());
+        }
+        if self.max_wait.is_nan() || self.max_wait <= 0.0 {
+            return Err(format!("Maximum wait must be positive: {}", self.max_wait));
+        }
+        if self.max_green < self.min_green.max(self.min_phase_duration) || self.max_yellow < 0.0 {
+            return Err(format!(
+                "Retiming limits must allow the minimum green: max_green {}, max_yellow {}",
+                self.max_green, self.max_yellow
+            ));
+        }
+        if self.initial_phase >= self.light_plan.phases.len() {
+            return Err(format!(
+                "Initial phase {} is past the end of the {}-phase light plan",
+                self.initial_phase,
+                self.light_plan.phases.len()
+            ));
+        }
+        let weights = [
+            self.cost_delay_weight,
+            self.cost_throughput_weight,
+            self.cost_queue_weight,
+            self.cost_collision_weight,
+        ];
+        if weights.iter().any(|w| !w.is_finite() || *w < 0.0) {
+            return Err(format!(
+                "Cost weights must be finite and not negative: {:?}",
+                weights
+            ));
+        }
+        if self.lane_tolerance < 0.0 {
+            return Err(format!(
+                "Lane tolerance must not be negative: {}",
+                self.lane_tolerance
+            ));
+        }
+        if self.phase_offset < 0.0 || self.wave_distance < 0.0 {
+            return Err("Phase offsets must not be negative".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.truck_share) {
+            return Err(format!(
+                "Truck share must be between 0 and 1: {}",
+                self.truck_share
+            ));
+        }
+        if self.motorcycle_share < 0.0 || self.truck_share + self.motorcycle_share > 1.0 {
+            return Err(format!(
+                "Motorcycle share must be between 0 and {}: {}",
+                1.0 - self.truck_share,
+                self.motorcycle_share
+            ));
+        }
+        if self.reaction_time < 0.0 {
+            return Err(format!(
+                "Reaction time must not be negative: {}",
+                self.reaction_time
+            ));
+        }
+        if !(self.max_jerk >= 0.0 && self.max_jerk.is_finite()) {
+            return Err(format!(
+                "Maximum jerk must be zero or positive: {}",
+                self.max_jerk
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.aggressiveness) {
+            return Err(format!(
+                "Aggressiveness must be between 0 and 1: {}",
+                self.aggressiveness
+            ));
+        }
+        // Two car lengths must fit between the box and each edge, as they
+        // must behind a stop line.
+        let world = self.world();
+        let widest_road = (2.0 * world.max_stop_setback()).max(0.0);
+        if self.lane_width < MIN_LANE_WIDTH {
+            return Err(format!(
+                "Lane width must be at least {}: {}",
+                MIN_LANE_WIDTH, self.lane_width
+            ));
+        }
+        let narrowest_road = MIN_ROAD_LANES * self.lane_width;
+        if !(narrowest_road as f32..=widest_road).contains(&(self.road_width as f32)) {
+            return Err(format!(
+                "Road width must be between {} and {} in a {}x{} world with {}px lanes: {}",
+                narrowest_road,
+                widest_road,
+                self.width,
+                self.height,
+                self.lane_width,
+                self.road_width
+            ));
+        }
+        // A turn wider than the road would swing its arc off the roadway
+        // instead of joining the exit lane.
+        for radius in [self.left_turn_radius, self.right_turn_radius] {
+            if !(0.0..=self.road_width as f32).contains(&radius) {
+                return Err(format!(
+                    "Turn radius must be between 0 and {}: {}",
+                    self.road_width, radius
+                ));
+            }
+        }
+        // Trucks and coloured glyphs are drawn inset into the footprint, and
+        // vehicles wider than the space between lane centres would scrape
+        // oncoming traffic.
+        let max_width = 2.0 * self.lane_width as f32;
+        if self.vehicle_length < MIN_VEHICLE_SIZE {
+            return Err(format!(
+                "Vehicle length must be at least {}: {}",
+                MIN_VEHICLE_SIZE, self.vehicle_length
+            ));
+        }
+        if !(MIN_VEHICLE_SIZE..=max_width).contains(&self.vehicle_width) {
+            return Err(format!(
+                "Vehicle width must be between {} and {}: {}",
+                MIN_VEHICLE_SIZE, max_width, self.vehicle_width
+            ));
+        }
+        if self.scale <= 0.0 {
+            return Err(format!("Scale must be positive: {}", self.scale));
+        }
+        if self.recovery_clearance <= 0.0 || self.recovery_green <= 0.0 {
+            return Err("Recovery phases must have positive durations".to_string());
+        }
+        for approach in Direction::ALL {
+            let has_route = Route::ALL
+                .into_iter()
+                .any(|route| self.permits(Movement { approach, route }));
+            if self.layout.accepts_approach(approach) && !has_route {
+                return Err(format!(
+                    "Every route from the {} approach is banned",
+                    approach
+                ));
+            }
+        }
+        for movement in Movement::all().filter(|&movement| self.permits(movement)) {
+            check_route_geometry(
+                movement.approach,
+                movement.route,
+                self.stop_setback(movement.approach),
+                world,
+            )?;
+        }
+        self.check_arrows()?;
+        if self
+            .approach_weights
+            .iter()
+            .any(|weight| !weight.is_finite() || *weight < 0.0)
+        {
+            return Err("Approach weights must not be negative".to_string());
+        }
+        if !(self.cruise_speed > 0.0 && self.cruise_speed <= MAX_SPEED_LIMIT) {
+            return Err(format!(
+                "Cruise speed must be positive and at most {}: {}",
+                MAX_SPEED_LIMIT, self.cruise_speed
+            ));
+        }
+        if !(self.spawn_cooldown >= 0.0 && self.spawn_cooldown.is_finite()) {
+            return Err(format!(
+                "Spawn cooldown must not be negative: {}",
+                self.spawn_cooldown
+            ));
+        }
+        if let Some(limit) = self
+            .speed_limits
+            .iter()
+            .find(|&&limit| limit.is_nan() || limit <= 0.0 || limit > MAX_SPEED_LIMIT)
+        {
+            return Err(format!(
+                "Speed limits must be positive and at most {}: {}",
+                MAX_SPEED_LIMIT, limit
+            ));
+        }
+        let open_weight: f32 = Direction::ALL
+            .into_iter()
+            .filter(|&approach| self.layout.accepts_approach(approach))
+            .map(|approach| self.approach_weights[approach.index()])
+            .sum();
+        if open_weight <= 0.0 {
+            return Err("At least one open approach needs a positive weight".to_string());
+        }
+        // A stop line inside the box would hold vehicles in the crossing traffic.
+        for approach in Direction::ALL {
+            let setback = self.stop_lines[approach.index()];
+            let (nearest, furthest) = (world.default_stop_setback(), world.max_stop_setback());
+            if !(nearest..=furthest).contains(&setback) {
+                return Err(format!(
+                    "The {} stop line must be between {} and {} from the centre: {}",
+                    approach, nearest, furthest, setback
+                ));
+            }
+        }
+        // Gentle means the road never runs more sideways than along, which
+        // also keeps every arm well inside the window.
+        let curve = world.center_x().min(world.center_y()) as f32 - self.bend().start;
+        if !(self.approach_bend >= 0.0 && self.approach_bend <= curve / 2.0) {
+            return Err(format!(
+                "Approach bend must be between 0 and {} with these stop lines: {}",
+                (curve / 2.0).max(0.0),
+                self.approach_bend
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_config_loads_back_unchanged() {
+        let config = Config::from_args(
+            [
+                "--collision-policy",
+                "stop",
+                "--light-plan",
+                "up+down:4.5,none:1,right:3,left:3",
+                "--layout",
+                "three-way:left",
+                "--seed",
+                "9",
+                "--no-interpolation",
+                "--base-gap",
+                "12.5",
+                "--window-mode",
+                "borderless",
+                "--window-position",
+                "-20,64",
+                "--signal-controller",
+                "max-pressure",
+                "--stop-lines",
+                "up:90,left:75.5",
+                "--banned-movements",
+                "right-left",
+                "--stop-when",
+                "exited:200, collision",
+                "--compare",
+                "fixed",
+                "--weather",
+                "rain",
+                "--day-length",
+                "600",
+                "--threads",
+                "4",
+                "--aggressiveness",
+                "0.5",
+                "--drain",
+                "--audio",
+                "--check-invariants",
+            ]
+            .into_iter()
+            .map(String::from),
+        )
+        .unwrap();
+
+        let path = std::env::temp_dir().join("road_intersection_config_roundtrip.toml");
+        config.save(&path).unwrap();
+        let loaded = Config::from_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.to_toml(), config.to_toml());
+        assert_eq!(loaded.collision_policy, CollisionPolicy::Stop);
+        assert_eq!(loaded.seed, Some(9));
+        assert!(!loaded.interpolate);
+        assert_eq!(loaded.window_mode, WindowMode::Borderless);
+        assert_eq!(loaded.window_position, Some((-20, 64)));
+        assert_eq!(loaded.signal_controller, ControllerKind::MaxPressure);
+        // How the run was made stays with its flags.
+        assert_eq!(config.compare, Some(ControllerKind::Fixed));
+        assert_eq!(loaded.compare, None);
+        assert!(config.drain && config.audio && config.check_invariants);
+        assert!(!loaded.drain && !loaded.audio && !loaded.check_invariants);
+        assert!(loaded.stop_when.is_empty());
+        assert_eq!(loaded.weather, Weather::Rain);
+        assert_eq!(loaded.day_length, 600.0);
+        assert_eq!(loaded.stop_lines, [90.0, 60.0, 60.0, 75.5]);
+        assert_eq!(
+            loaded.banned_movements,
+            [Movement::parse("right-left").unwrap()]
+        );
+        assert_eq!(
+            config.stop_when,
+            [StopCondition::Exited(200), StopCondition::Collision]
+        );
+        for bad in ["gridlock:3", "exited", "exited:many", "forever"] {
+            assert!(StopCondition::parse(bad).is_err(), "{}", bad);
+        }
+        assert!(
+            Config::from_args(
+                ["--banned-movements", "up-straight,up-left,up-right"]
+                    .into_iter()
+                    .map(String::from)
+            )
+            .is_err()
+        );
+        assert!(
+            Config::from_args(["--stop-lines", "down:20"].into_iter().map(String::from)).is_err()
+        );
+        for gap in ["0", "0.5", "NaN"] {
+            let args = ["--base-gap", gap].into_iter().map(String::from);
+            assert!(Config::from_args(args).is_err(), "{}", gap);
+        }
+        let grid = |args: &[&str]| Config::from_args(args.iter().map(|arg| arg.to_string()));
+        assert_eq!(grid(&["--grid", "3x2"]).unwrap().grid, Some((3, 2)));
+        for bad in [
+            &["--grid", "5x5"][..],
+            &["--grid", "3"],
+            &["--grid", "2x2", "--layout", "t-junction"],
+            &["--grid", "2x2", "--serve", "9100"],
+            &["--grid", "2x2", "--remote", "9200"],
+            &["--grid", "2x2", "--record", "run.txt"],
+            &["--grid", "2x2", "--metrics-out", "run.json"],
+        ] {
+            assert!(grid(bad).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn turns_must_be_reachable_after_spawning() {
+        let sized = |width, height| World {
+            width,
+            height,
+            ..World::default()
+        };
+        for movement in Movement::all() {
+            let (approach, route) = (movement.approach, movement.route);
+            assert!(check_route_geometry(approach, route, 60.0, sized(1000, 800)).is_ok());
+            assert!(check_route_geometry(approach, route, 60.0, sized(200, 200)).is_ok());
+        }
+        let tiny = check_route_geometry(Direction::Up, Route::TurnLeft, 60.0, sized(100, 100));
+        assert!(tiny.unwrap_err().contains("up-left"));
+        assert!(
+            check_route_geometry(Direction::Left, Route::Straight, 60.0, sized(60, 60)).is_ok()
+        );
+    }
+
+    #[test]
+    fn the_world_and_its_roads_can_be_resized() {
+        let parsed = |args: &[&str]| Config::from_args(args.iter().map(|arg| arg.to_string()));
+        let config =
+            parsed(&["--width", "1400", "--height", "900", "--road-width", "160"]).unwrap();
+        let world = config.world();
+        assert_eq!(
+            (world.width, world.height, world.road_width),
+            (1400, 900, 160)
+        );
+        assert_eq!(world.centre(), (700.0, 450.0));
+        // Stop lines left at the box edge move out with it; others stay.
+        assert_eq!(config.stop_lines, [80.0; 4]);
+        let config = parsed(&["--stop-lines", "up:100", "--road-width", "160"]).unwrap();
+        assert_eq!(config.stop_lines, [100.0, 80.0, 80.0, 80.0]);
+
+        let path = std::env::temp_dir().join("road_intersection_world_roundtrip.toml");
+        config.save(&path).unwrap();
+        let loaded = Config::from_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.world(), config.world());
+        assert_eq!(loaded.stop_lines, config.stop_lines);
+
+        let config = parsed(&["--road-width", "160", "--lane-width", "45"]).unwrap();
+        assert_eq!(config.world().lane_width, 45);
+        let path = std::env::temp_dir().join("road_intersection_lane_roundtrip.toml");
+        config.save(&path).unwrap();
+        assert_eq!(Config::from_file(&path).unwrap().lane_width, 45);
+        fs::remove_file(&path).unwrap();
+
+        for bad in [
+            &["--road-width", "60"][..],
+            &["--lane-width", "45"],
+            &["--lane-width", "15", "--vehicle-width", "20"],
+            &["--lane-width", "20", "--vehicle-width", "45"],
+            &["--road-width", "700"],
+            &["--width", "240", "--height", "240"],
+            &["--road-width", "100", "--left-turn-radius", "110"],
+        ] {
+            assert!(parsed(bad).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn flags_override_a_config_file_wherever_they_stand() {
+        let path = std::env::temp_dir().join("road_intersection_flags_over_file.toml");
+        fs::write(&path, "seed = 4\nyellow = 2.5\n").unwrap();
+        let file = path.to_str().unwrap();
+        let parsed =
+            |args: &[&str]| Config::from_args_or_file(args.iter().map(|arg| arg.to_string()), None);
+        for args in [
+            &["--seed", "7", "--config", file][..],
+            &["--config", file, "--seed", "7"],
+        ] {
+            let config = parsed(args).unwrap();
+            assert_eq!((config.seed, config.yellow), (Some(7), 2.5), "{:?}", args);
+        }
+        assert!(parsed(&["--config", file, "--config", file]).is_err());
+
+        // Only the merged settings are checked: a flag may mend the file.
+        fs::write(&path, "road_width = 40\n").unwrap();
+        assert!(Config::from_file(&path).is_err());
+        let mended = parsed(&["--config", file, "--road-width", "160"]).unwrap();
+        assert_eq!(mended.road_width, 160);
+        assert!(parsed(&["--config", file]).is_err());
+        fs::write(&path, "seed = 4\nyellow = 2.5\n").unwrap();
+
+        // Without `--config`, a fallback that exists is read; one that does
+        // not leaves the defaults.
+        let fallback = |path: &Path| {
+            Config::from_args_or_file(["--seed", "7"].map(String::from).into_iter(), Some(path))
+                .unwrap()
+        };
+        assert_eq!(fallback(&path).yellow, 2.5);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(fallback(&path).yellow, Config::default().yellow);
+    }
+
+    #[test]
+    fn a_comparison_refuses_flags_for_a_single_run() {
+        let parsed = |args: &[&str]| Config::from_args(args.iter().map(|arg| arg.to_string()));
+        for (flag, value) in [
+            ("--serve", "9100"),
+            ("--remote", "9200"),
+            ("--metrics-out", "run.csv"),
+        ] {
+            let refused = parsed(&["--compare", "fixed", flag, value]).unwrap_err();
+            assert!(refused.contains(flag), "{}", refused);
+            assert!(parsed(&[flag, value]).is_ok());
+        }
+        assert!(parsed(&["--compare", "fixed", "--record", "run.txt"]).is_err());
+    }
+
+    #[test]
+    fn a_json_config_file_sets_the_same_keys() {
+        let path = std::env::temp_dir().join("road_intersection_config.json");
+        fs::write(
+            &path,
+            "{\n  \"seed\": 4,\n  \"yellow\": 2.5,\n  \"auto_spawn\": true,\n  \"stop_lines\": \"up:90\",\n  \"replay\": null\n}\n",
+        )
+        .unwrap();
+        let config = Config::from_file(&path).unwrap();
+        assert_eq!((config.seed, config.yellow), (Some(4), 2.5));
+        assert!(config.auto_spawn);
+        assert_eq!(config.stop_lines[Direction::Up.index()], 90.0);
+
+        fs::write(&path, "{\"yellow\": [2.5]}").unwrap();
+        let nested = Config::from_file(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert!(nested.contains("Nested"), "{}", nested);
+    }
+
+    #[test]
+    fn an_arrow_must_not_green_anything_crossing_it() {
+        let plan =
+            |plan: &str| Config::from_args(["--light-plan", plan].map(String::from).into_iter());
+        assert!(plan("protected-lefts").is_ok());
+        assert!(plan("up.left+down.left:3,up+down:5").is_ok());
+        let error = plan("up.left+down:3,none:1").err().unwrap();
+        assert!(
+            error.contains("up-left") && error.contains("down-straight"),
+            "{}",
+            error
+        );
+        // Without the lights nothing is shown, so nothing is promised.
+        assert!(
+            Config::from_args(
+                [
+                    "--light-plan",
+                    "up.left+down:3",
+                    "--control-mode",
+                    "uncontrolled"
+                ]
+                .map(String::from)
+                .into_iter()
+            )
+            .is_ok()
+        );
+    }
+}
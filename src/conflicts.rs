@@ -0,0 +1,185 @@
+use crate::config::ColorMode;
+use crate::direction::{Direction, Route};
+use crate::geometry::Layout;
+use crate::vehicle::Vehicle;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+/// One way through the intersection: where a vehicle comes from and what it
+/// does there.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Movement {
+    pub approach: Direction,
+    pub route: Route,
+}
+
+impl Movement {
+    const COUNT: usize = Direction::ALL.len() * Route::ALL.len();
+
+    pub fn all() -> impl Iterator<Item = Movement> {
+        Direction::ALL.into_iter().flat_map(|approach| {
+            Route::ALL
+                .into_iter()
+                .map(move |route| Movement { approach, route })
+        })
+    }
+
+    fn index(self) -> usize {
+        self.approach.index() * Route::ALL.len() + self.route as usize
+    }
+
+    pub fn name(self) -> String {
+        format!("{}-{}", self.approach, self.route.name())
+    }
+
+    /// Reads the form `name` writes, such as `up-left`.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let (approach, route) = value
+            .split_once('-')
+            .ok_or_else(|| format!("Movement must be approach-route: {}", value))?;
+        Ok(Movement {
+            approach: Direction::parse(approach)?,
+            route: Route::parse(route)?,
+        })
+    }
+
+    pub fn exists_in(self, layout: Layout) -> bool {
+        layout.accepts_approach(self.approach)
+            && layout.has_arm(self.route.exit_direction(self.approach))
+    }
+}
+
+/// Which pairs of movements from different approaches sweep over the same
+/// part of the intersection box. Movements from one approach share a lane
+/// and are kept apart by car following, so they never conflict here.
+pub struct ConflictMatrix {
+    conflicts: [[bool; Movement::COUNT]; Movement::COUNT],
+}
+
+impl ConflictMatrix {
+    /// Derived from the paths vehicles actually drive, so it follows any
+    /// change to the turn geometry.
+    pub fn compute() -> Self {
+        let mut rng = StdRng::seed_from_u64(0);
+        let paths: Vec<Vec<Vehicle>> = Movement::all()
+            .map(|movement| {
+                let vehicle = Vehicle::new(
+                    0,
+                    movement.approach,
+                    movement.route,
+                    ColorMode::ByRoute,
+                    &mut rng,
+                );
+                vehicle
+                    .projected_path()
+                    .into_iter()
+                    .filter(Vehicle::overlaps_intersection)
+                    .collect()
+            })
+            .collect();
+
+        let mut conflicts = [[false; Movement::COUNT]; Movement::COUNT];
+        for a in Movement::all() {
+            for b in Movement::all() {
+                if a.approach == b.approach {
+                    continue;
+                }
+                conflicts[a.index()][b.index()] = paths[a.index()]
+                    .iter()
+                    .any(|ghost| paths[b.index()].iter().any(|other| ghost.overlaps(other)));
+            }
+        }
+        Self { conflicts }
+    }
+
+    pub fn conflicts(&self, a: Movement, b: Movement) -> bool {
+        self.conflicts[a.index()][b.index()]
+    }
+
+    /// A text table of the movements `permits` accepts, `X` marking a
+    /// conflict.
+    pub fn table(&self, permits: impl Fn(Movement) -> bool) -> String {
+        let movements: Vec<Movement> = Movement::all()
+            .filter(|&movement| permits(movement))
+            .collect();
+        let width = movements
+            .iter()
+            .map(|movement| movement.name().len())
+            .max()
+            .unwrap_or(0);
+
+        let mut table = format!("{:width$}", "");
+        for (column, _) in movements.iter().enumerate() {
+            table.push_str(&format!(" {:>2}", column));
+        }
+        table.push('\n');
+        for (row, &a) in movements.iter().enumerate() {
+            table.push_str(&format!("{:width$}", a.name()));
+            for &b in &movements {
+                let mark = if self.conflicts(a, b) { "X" } else { "." };
+                table.push_str(&format!(" {:>2}", mark));
+            }
+            table.push_str(&format!("  {}\n", row));
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn movement(approach: Direction, route: Route) -> Movement {
+        Movement { approach, route }
+    }
+
+    #[test]
+    fn conflicts_match_the_lane_geometry() {
+        let matrix = ConflictMatrix::compute();
+        let up = |route| movement(Direction::Up, route);
+        let down = |route| movement(Direction::Down, route);
+        let right = |route| movement(Direction::Right, route);
+
+        // Crossing straights always meet; opposing straights keep to their lanes.
+        assert!(matrix.conflicts(up(Route::Straight), right(Route::Straight)));
+        assert!(!matrix.conflicts(up(Route::Straight), down(Route::Straight)));
+        // Opposing left-turners turn short of each other, but each crosses
+        // the oncoming straight.
+        assert!(!matrix.conflicts(up(Route::TurnLeft), down(Route::TurnLeft)));
+        assert!(matrix.conflicts(up(Route::TurnLeft), down(Route::Straight)));
+        assert!(!matrix.conflicts(up(Route::Straight), up(Route::TurnLeft)));
+        // A U-turn crosses the oncoming lane as a left turn does, and the
+        // lane from the right it borrows, but stays clear of the oncoming
+        // U-turn on the far side of the box.
+        assert!(matrix.conflicts(up(Route::UTurn), down(Route::Straight)));
+        assert!(matrix.conflicts(up(Route::UTurn), right(Route::Straight)));
+        assert!(!matrix.conflicts(up(Route::UTurn), down(Route::UTurn)));
+
+        for a in Movement::all() {
+            for b in Movement::all() {
+                assert_eq!(matrix.conflicts(a, b), matrix.conflicts(b, a));
+            }
+        }
+    }
+
+    #[test]
+    fn table_lists_only_movements_the_layout_allows() {
+        let matrix = ConflictMatrix::compute();
+        let layout = Layout::ThreeWay {
+            missing: Direction::Up,
+        };
+        let table = matrix.table(|movement| movement.exists_in(layout));
+        // Two ways on from each of the three arms, and a U-turn back.
+        assert_eq!(table.lines().count(), 1 + 6 + 3);
+        assert!(!table.contains("up-straight"));
+        assert!(table.contains("right-straight"));
+    }
+
+    #[test]
+    fn movements_parse_back_from_their_names() {
+        for movement in Movement::all() {
+            assert_eq!(Movement::parse(&movement.name()), Ok(movement));
+        }
+        assert!(Movement::parse("up-u-turn").is_err());
+    }
+}
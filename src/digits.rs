@@ -0,0 +1,97 @@
+use crate::render::Renderer;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+
+pub const DIGIT_WIDTH: u32 = 8;
+pub const DIGIT_HEIGHT: u32 = 14;
+const STROKE: u32 = 2;
+const SPACING: i32 = 4;
+
+// Segments a-g of a seven-segment display, one bit each.
+const SEGMENTS: [u8; 10] = [
+    0b0111111, 0b0000110, 0b1011011, 0b1001111, 0b1100110, 0b1101101, 0b1111101, 0b0000111,
+    0b1111111, 0b1101111,
+];
+
+fn segment_rects(x: i32, y: i32) -> [Rect; 7] {
+    let w = DIGIT_WIDTH as i32;
+    let h = DIGIT_HEIGHT as i32;
+    let s = STROKE as i32;
+    let mid = (h - s) / 2;
+    [
+        Rect::new(x, y, DIGIT_WIDTH, STROKE),
+        Rect::new(x + w - s, y, STROKE, (mid + s) as u32),
+        Rect::new(x + w - s, y + mid, STROKE, (h - mid) as u32),
+        Rect::new(x, y + h - s, DIGIT_WIDTH, STROKE),
+        Rect::new(x, y + mid, STROKE, (h - mid) as u32),
+        Rect::new(x, y, STROKE, (mid + s) as u32),
+        Rect::new(x, y + mid, DIGIT_WIDTH, STROKE),
+    ]
+}
+
+/// Draws digits and decimal points with plain rectangles, so readouts work
+/// without a font.
+pub fn draw_text(
+    canvas: &mut dyn Renderer,
+    x: i32,
+    y: i32,
+    text: &str,
+    color: Color,
+) -> Result<(), String> {
+    canvas.set_draw_color(color);
+    let mut cursor = x;
+
+    for ch in text.chars() {
+        if ch == '.' {
+            canvas.fill_rect(Rect::new(
+                cursor,
+                y + (DIGIT_HEIGHT - STROKE) as i32,
+                STROKE,
+                STROKE,
+            ))?;
+            cursor += STROKE as i32 + SPACING;
+        } else if let Some(value) = ch.to_digit(10) {
+            let segments = SEGMENTS[value as usize];
+            for (bit, rect) in segment_rects(cursor, y).iter().enumerate() {
+                if segments & (1 << bit) != 0 {
+                    canvas.fill_rect(*rect)?;
+                }
+            }
+            cursor += DIGIT_WIDTH as i32 + SPACING;
+        } else {
+            cursor += DIGIT_WIDTH as i32 + SPACING;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::{DrawCall, RecordingRenderer};
+
+    #[test]
+    fn counters_draw_without_a_font() {
+        let mut recorder = RecordingRenderer::default();
+        let white = Color::RGB(255, 255, 255);
+        draw_text(&mut recorder, 0, 0, "12.5", white).unwrap();
+        // Two segments for 1, five for 2, the point, five for 5.
+        assert_eq!(recorder.calls.len(), 2 + 5 + 1 + 5);
+        assert!(
+            recorder
+                .calls
+                .iter()
+                .all(|call| matches!(call, DrawCall::FillRect(_, color) if *color == white))
+        );
+
+        // Characters the digit font lacks leave a gap instead of failing.
+        let mut recorder = RecordingRenderer::default();
+        draw_text(&mut recorder, 0, 0, "q-7", white).unwrap();
+        assert_eq!(recorder.calls.len(), 3);
+        let DrawCall::FillRect(first, _) = recorder.calls[0] else {
+            unreachable!()
+        };
+        assert_eq!(first.x(), 2 * (DIGIT_WIDTH as i32 + SPACING));
+    }
+}
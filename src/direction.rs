@@ -0,0 +1,147 @@
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Up,
+    Down,
+    Right,
+    Left,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 4] = [
+        Direction::Up,
+        Direction::Down,
+        Direction::Right,
+        Direction::Left,
+    ];
+
+    /// Position in `ALL`, for per-approach arrays.
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Direction::Up => "up",
+            Direction::Down => "down",
+            Direction::Right => "right",
+            Direction::Left => "left",
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Self, String> {
+        Direction::ALL
+            .into_iter()
+            .find(|direction| direction.name() == value)
+            .ok_or_else(|| format!("Unknown direction: {}", value))
+    }
+
+    /// One screen pixel in this direction; y grows downwards.
+    pub fn delta(self) -> (i32, i32) {
+        match self {
+            Direction::Up => (0, -1),
+            Direction::Down => (0, 1),
+            Direction::Right => (1, 0),
+            Direction::Left => (-1, 0),
+        }
+    }
+
+    /// Heading in radians, clockwise on screen from pointing right.
+    pub fn angle(self) -> f32 {
+        use std::f32::consts::{FRAC_PI_2, PI};
+        match self {
+            Direction::Right => 0.0,
+            Direction::Down => FRAC_PI_2,
+            Direction::Left => PI,
+            Direction::Up => -FRAC_PI_2,
+        }
+    }
+
+    pub fn opposite(self) -> Self {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Right => Direction::Left,
+            Direction::Left => Direction::Right,
+        }
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Route {
+    Straight,
+    TurnLeft,
+    TurnRight,
+    /// Round the end of the median and back out along the road it came in
+    /// on, in the lane heading the other way.
+    UTurn,
+}
+
+impl Route {
+    pub const ALL: [Route; 4] = [
+        Route::Straight,
+        Route::TurnLeft,
+        Route::TurnRight,
+        Route::UTurn,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Route::Straight => "straight",
+            Route::TurnLeft => "left",
+            Route::TurnRight => "right",
+            Route::UTurn => "uturn",
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Self, String> {
+        Route::ALL
+            .into_iter()
+            .find(|route| route.name() == value)
+            .ok_or_else(|| format!("Unknown route: {}", value))
+    }
+
+    /// The direction a vehicle that set out heading `initial` travels once
+    /// it has completed this route.
+    pub fn exit_direction(self, initial: Direction) -> Direction {
+        match (initial, self) {
+            (_, Route::Straight) => initial,
+            (_, Route::UTurn) => initial.opposite(),
+            (Direction::Up, Route::TurnLeft) | (Direction::Down, Route::TurnRight) => {
+                Direction::Right
+            }
+            (Direction::Up, Route::TurnRight) | (Direction::Down, Route::TurnLeft) => {
+                Direction::Left
+            }
+            (Direction::Right, Route::TurnLeft) | (Direction::Left, Route::TurnRight) => {
+                Direction::Up
+            }
+            (Direction::Right, Route::TurnRight) | (Direction::Left, Route::TurnLeft) => {
+                Direction::Down
+            }
+        }
+    }
+
+    /// The directions a vehicle that set out heading `initial` travels in
+    /// along this route, a leg at a time. A U-turn takes two turns: the
+    /// left turn across the box, then another onto the far side of its own
+    /// road.
+    pub fn headings(self, initial: Direction) -> Vec<Direction> {
+        match self {
+            Route::Straight => vec![initial],
+            Route::TurnLeft | Route::TurnRight => vec![initial, self.exit_direction(initial)],
+            Route::UTurn => vec![
+                initial,
+                Route::TurnLeft.exit_direction(initial),
+                initial.opposite(),
+            ],
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use crate::geometry::World;
+use crate::render::Renderer;
+use sdl2::pixels::Color;
+
+/// Hours it takes the light to come up at dawn and to go at dusk.
+const TWILIGHT: f32 = 2.0;
+/// When dawn and dusk start.
+const DAWN: f32 = 5.0;
+const DUSK: f32 = 18.0;
+
+/// Most opaque the night is drawn over the world, at midnight.
+const NIGHT_ALPHA: f32 = 170.0;
+
+/// Raindrops drawn over the window, and pixels each falls per tick.
+const RAINDROPS: u64 = 160;
+const RAIN_FALL: u64 = 9;
+const RAIN_STREAK: i32 = 12;
+
+/// What the sky is doing, which sets how well vehicles grip the road.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Weather {
+    #[default]
+    Clear,
+    /// Slower driving and longer braking, by `rain_speed_factor` and
+    /// `rain_braking_factor`.
+    Rain,
+}
+
+impl Weather {
+    pub const ALL: [Weather; 2] = [Weather::Clear, Weather::Rain];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Weather::Clear => "clear",
+            Weather::Rain => "rain",
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Self, String> {
+        Weather::ALL
+            .into_iter()
+            .find(|weather| weather.name() == value)
+            .ok_or_else(|| format!("Unknown weather: {}", value))
+    }
+
+    pub fn toggled(self) -> Self {
+        match self {
+            Weather::Clear => Weather::Rain,
+            Weather::Rain => Weather::Clear,
+        }
+    }
+}
+
+/// How much of what a vehicle could do on a dry road the weather leaves
+/// it: its top speed and its braking, as shares.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Traction {
+    pub speed: f32,
+    pub braking: f32,
+}
+
+impl Traction {
+    pub const DRY: Traction = Traction {
+        speed: 1.0,
+        braking: 1.0,
+    };
+}
+
+impl Default for Traction {
+    fn default() -> Self {
+        Traction::DRY
+    }
+}
+
+/// The hour of the day, from 0 to 24, `seconds` into a run that started at
+/// `start` with a whole day every `day_length` seconds; a length of 0
+/// keeps it at `start`.
+pub fn hour_of_day(start: f32, day_length: f32, seconds: f64) -> f32 {
+    if day_length <= 0.0 {
+        return start;
+    }
+    let hours = seconds / day_length as f64 * 24.0;
+    (start as f64 + hours).rem_euclid(24.0) as f32
+}
+
+/// How light it is at `hour`, from 0 through the night to 1 through the
+/// day, rising over dawn and falling over dusk.
+pub fn daylight(hour: f32) -> f32 {
+    let dawn = (hour - DAWN) / TWILIGHT;
+    let dusk = (DUSK + TWILIGHT - hour) / TWILIGHT;
+    dawn.min(dusk).clamp(0.0, 1.0)
+}
+
+/// Darkens the whole of `world` towards a night blue the less `daylight`
+/// there is. Nothing is drawn in full daylight.
+pub fn draw_night(canvas: &mut dyn Renderer, world: World, daylight: f32) -> Result<(), String> {
+    let alpha = (NIGHT_ALPHA * (1.0 - daylight)).round() as u8;
+    if alpha == 0 {
+        return Ok(());
+    }
+    canvas.set_draw_color(Color::RGBA(5, 10, 35, alpha));
+    canvas.fill_rect(world.bounds())
+}
+
+/// Streaks of rain across `world`, falling a little further each
+/// tick. Where each drop falls is worked out from its number rather than
+/// drawn from a random stream, so drawing leaves the run unchanged.
+pub fn draw_rain(canvas: &mut dyn Renderer, world: World, tick: u64) -> Result<(), String> {
+    let (width, height) = (world.width as u64, world.height as u64);
+    canvas.set_draw_color(Color::RGBA(170, 190, 230, 110));
+    for drop in 0..RAINDROPS {
+        // Knuth's multiplicative hash spreads the drops evenly enough.
+        let scatter = drop.wrapping_mul(2_654_435_761);
+        let x = (scatter % width) as i32;
+        let y = ((scatter / width + tick * RAIN_FALL) % height) as i32;
+        canvas.draw_line((x, y), (x - RAIN_STREAK / 3, y + RAIN_STREAK))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_clock_turns_through_dawn_and_dusk() {
+        assert_eq!(hour_of_day(9.0, 0.0, 500.0), 9.0);
+        assert_eq!(hour_of_day(18.0, 240.0, 90.0), 3.0);
+        assert_eq!(daylight(12.0), 1.0);
+        assert_eq!(daylight(0.0), 0.0);
+        assert_eq!(daylight(22.0), 0.0);
+        assert_eq!(daylight(DAWN + TWILIGHT / 2.0), 0.5);
+        assert_eq!(daylight(DUSK + TWILIGHT / 4.0), 0.75);
+    }
+}
@@ -0,0 +1,487 @@
+use crate::direction::Direction;
+use sdl2::rect::Rect;
+use std::fmt;
+
+/// The world's size, and so the window's before scaling, unless configured
+/// otherwise.
+pub const WINDOW_WIDTH: u32 = 1000;
+pub const WINDOW_HEIGHT: u32 = 800;
+pub const VEHICLE_SIZE: u32 = 40;
+pub const VEHICLE_SPEED: f32 = 2.0;
+/// Ticks per simulated second unless configured otherwise; `VEHICLE_SPEED`
+/// is in pixels per one of them.
+pub const TICK_RATE: f32 = 60.0;
+/// How wide each road is unless configured otherwise.
+pub const ROAD_WIDTH: u32 = 120;
+/// How wide each lane is unless configured otherwise; its centre runs
+/// this far from the road's centre line.
+pub const LANE_WIDTH: u32 = 30;
+pub const LIGHT_SIZE: u32 = 30;
+/// Depth of the crosswalk on each arm, which runs from the edge of the
+/// intersection box.
+pub const CROSSWALK_WIDTH: u32 = 16;
+/// Radius of the island in the middle of a roundabout.
+pub const ISLAND_RADIUS: i32 = 22;
+
+/// The part of the world vehicles drive across, spawning at its edges and
+/// leaving over them, and the two roads that cross at its centre. The
+/// window shows all of it before scaling; the camera can still zoom out
+/// past it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct World {
+    pub width: u32,
+    pub height: u32,
+    pub road_width: u32,
+    pub lane_width: u32,
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self {
+            width: WINDOW_WIDTH,
+            height: WINDOW_HEIGHT,
+            road_width: ROAD_WIDTH,
+            lane_width: LANE_WIDTH,
+        }
+    }
+}
+
+impl World {
+    pub fn center_x(self) -> i32 {
+        (self.width / 2) as i32
+    }
+
+    pub fn center_y(self) -> i32 {
+        (self.height / 2) as i32
+    }
+
+    /// Where the roads cross.
+    pub fn centre(self) -> (f32, f32) {
+        (self.center_x() as f32, self.center_y() as f32)
+    }
+
+    pub fn bounds(self) -> Rect {
+        Rect::new(0, 0, self.width, self.height)
+    }
+
+    /// Distance from the centre to each side of the intersection box.
+    pub fn half_road(self) -> f32 {
+        self.road_width as f32 / 2.0
+    }
+
+    /// Radius of the ring vehicles are drawn circulating on at a roundabout,
+    /// through the four points where the lanes cross.
+    pub fn roundabout_radius(self) -> f32 {
+        self.lane_width as f32 * std::f32::consts::SQRT_2
+    }
+
+    /// Distance from the centre to each stop line unless configured
+    /// otherwise: right at the edge of the intersection box.
+    pub fn default_stop_setback(self) -> f32 {
+        self.half_road()
+    }
+
+    /// The furthest a stop line may sit from the centre, leaving room for
+    /// two car lengths between it and the nearest edge.
+    pub fn max_stop_setback(self) -> f32 {
+        self.center_x().min(self.center_y()) as f32 - 2.0 * VEHICLE_SIZE as f32
+    }
+
+    /// Whether a point lies inside the central box where the two roads
+    /// cross.
+    pub fn in_intersection_box(self, x: f32, y: f32) -> bool {
+        let (center_x, center_y) = self.centre();
+        let half_road = self.half_road();
+        (x - center_x).abs() < half_road && (y - center_y).abs() < half_road
+    }
+
+    /// The approach whose incoming arm contains the point, if any.
+    /// Vehicles entering from the bottom arm travel up, and so on.
+    pub fn approach_at(self, x: f32, y: f32) -> Option<Direction> {
+        let half_road = self.half_road();
+        let dx = x - self.center_x() as f32;
+        let dy = y - self.center_y() as f32;
+        if dx.abs() < half_road && dy >= half_road {
+            Some(Direction::Up)
+        } else if dx.abs() < half_road && dy <= -half_road {
+            Some(Direction::Down)
+        } else if dy.abs() < half_road && dx <= -half_road {
+            Some(Direction::Right)
+        } else if dy.abs() < half_road && dx >= half_road {
+            Some(Direction::Left)
+        } else {
+            None
+        }
+    }
+}
+
+/// How the scene sits in the window: drawn `scale` times larger, with its
+/// top-left corner `offset_x`, `offset_y` window pixels in. The offset
+/// letterboxes the scene when the window's shape doesn't match it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    pub scale: f32,
+    pub offset_x: i32,
+    pub offset_y: i32,
+}
+
+impl Viewport {
+    pub fn scaled(scale: f32) -> Self {
+        Self {
+            scale,
+            offset_x: 0,
+            offset_y: 0,
+        }
+    }
+
+    /// The largest scale at which the whole of `world` fits a window of the
+    /// given size, centred in it.
+    pub fn fit(world: World, width: u32, height: u32) -> Self {
+        let (scene_width, scene_height) = (world.width as f32, world.height as f32);
+        let scale = (width as f32 / scene_width).min(height as f32 / scene_height);
+        Self {
+            scale,
+            offset_x: ((width as f32 - scene_width * scale) / 2.0) as i32,
+            offset_y: ((height as f32 - scene_height * scale) / 2.0) as i32,
+        }
+    }
+
+    /// Converts a window pixel to simulation coordinates.
+    pub fn to_logical(self, x: i32, y: i32) -> (f32, f32) {
+        (
+            (x - self.offset_x) as f32 / self.scale,
+            (y - self.offset_y) as f32 / self.scale,
+        )
+    }
+}
+
+/// How much of the way to its target the camera moves each frame, so a
+/// followed vehicle is tracked without the view jumping with every step.
+const CAMERA_EASE: f32 = 0.15;
+
+/// How far in and out the camera zooms.
+pub const MIN_ZOOM: f32 = 0.5;
+pub const MAX_ZOOM: f32 = 8.0;
+
+/// The world point shown at the centre of the window, and how many window
+/// pixels a world pixel is drawn as. It rests on `home`, the intersection
+/// until the view is panned; following a vehicle moves it. The window
+/// shows as much as `world` before zooming.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    pub x: f32,
+    pub y: f32,
+    pub zoom: f32,
+    pub home: (f32, f32),
+    pub world: World,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self::new(World::default())
+    }
+}
+
+impl Camera {
+    /// Looking straight down on the intersection of `world`.
+    pub fn new(world: World) -> Self {
+        let centre = world.centre();
+        Self {
+            x: centre.0,
+            y: centre.1,
+            zoom: 1.0,
+            home: centre,
+            world,
+        }
+    }
+
+    /// Moves part of the way towards centring `(x, y)`, snapping once within
+    /// half a pixel.
+    pub fn ease_towards(&mut self, x: f32, y: f32) {
+        let (dx, dy) = (x - self.x, y - self.y);
+        if dx.abs() < 0.5 && dy.abs() < 0.5 {
+            (self.x, self.y) = (x, y);
+        } else {
+            self.x += dx * CAMERA_EASE;
+            self.y += dy * CAMERA_EASE;
+        }
+    }
+
+    /// What to add to world coordinates, once multiplied by `zoom`, to
+    /// draw them.
+    pub fn offset(self) -> (i32, i32) {
+        let (center_x, center_y) = self.world.centre();
+        (
+            (center_x - self.x * self.zoom).round() as i32,
+            (center_y - self.y * self.zoom).round() as i32,
+        )
+    }
+
+    /// Where a world point is drawn in the window.
+    pub fn to_screen(self, x: f32, y: f32) -> (i32, i32) {
+        let (dx, dy) = self.offset();
+        (
+            (x * self.zoom).round() as i32 + dx,
+            (y * self.zoom).round() as i32 + dy,
+        )
+    }
+
+    /// Converts a point in simulation coordinates, as `Viewport::to_logical`
+    /// gives, to the world point drawn there.
+    pub fn to_world(self, x: f32, y: f32) -> (f32, f32) {
+        let (dx, dy) = self.offset();
+        ((x - dx as f32) / self.zoom, (y - dy as f32) / self.zoom)
+    }
+
+    /// The part of the world the window shows.
+    pub fn visible(self) -> Rect {
+        let (left, top) = self.to_world(0.0, 0.0);
+        let (right, bottom) = self.to_world(self.world.width as f32, self.world.height as f32);
+        Rect::new(
+            left.floor() as i32,
+            top.floor() as i32,
+            (right - left).ceil() as u32,
+            (bottom - top).ceil() as u32,
+        )
+    }
+
+    /// Moves the view by `dx`, `dy` window pixels, so the world follows
+    /// the mouse, and makes where it ends up the new home.
+    pub fn pan(&mut self, dx: f32, dy: f32) {
+        self.x -= dx / self.zoom;
+        self.y -= dy / self.zoom;
+        self.home = (self.x, self.y);
+    }
+
+    /// Zooms in by `factor`, or out for one below 1, keeping the world
+    /// point under the window point `(x, y)` where it is.
+    pub fn zoom_about(&mut self, factor: f32, (x, y): (f32, f32)) {
+        let (world_x, world_y) = self.to_world(x, y);
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        let (center_x, center_y) = self.world.centre();
+        self.x = world_x - (x - center_x) / self.zoom;
+        self.y = world_y - (y - center_y) / self.zoom;
+        self.home = (self.x, self.y);
+    }
+}
+
+/// How the approach roads curve: each arm runs straight out of the box to
+/// `start` pixels from the centre, then swings sideways along a parabola
+/// until it is `amount` pixels over at the edge of `world`. The arms swing
+/// clockwise, top arm to the right, so they never bend into each other.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct Bend {
+    pub amount: f32,
+    pub start: f32,
+    pub world: World,
+}
+
+impl Bend {
+    pub fn is_straight(self) -> bool {
+        self.amount == 0.0
+    }
+
+    /// How far the road at a point of the straight layout is drawn moved.
+    /// Only distance along an arm matters, so both lanes of an arm, and
+    /// anything side by side on it, move together.
+    pub fn offset(self, x: f32, y: f32) -> (f32, f32) {
+        let half_road = self.world.half_road();
+        let swing = |distance: f32, length: f32| {
+            let t = ((distance - self.start) / (length - self.start)).max(0.0);
+            self.amount * t * t
+        };
+        let (center_x, center_y) = self.world.centre();
+        let dx = x - center_x;
+        let dy = y - center_y;
+        if dy <= -half_road {
+            (swing(-dy, center_y), 0.0)
+        } else if dy >= half_road {
+            (-swing(dy, center_y), 0.0)
+        } else if dx <= -half_road {
+            (0.0, -swing(-dx, center_x))
+        } else if dx >= half_road {
+            (0.0, swing(dx, center_x))
+        } else {
+            (0.0, 0.0)
+        }
+    }
+
+    /// The point of the straight layout drawn at `x`, `y`.
+    pub fn straighten(self, x: f32, y: f32) -> (f32, f32) {
+        let (dx, dy) = self.offset(x, y);
+        (x - dx, y - dy)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Layout {
+    #[default]
+    Plus,
+    /// A three-way junction; `missing` is the arm you would reach by
+    /// travelling in that direction from the center.
+    ThreeWay { missing: Direction },
+    /// All four arms meeting at a ring round a central island. There are no
+    /// lights: vehicles give way to traffic already on the ring, and to
+    /// traffic about to join it from their left.
+    Roundabout,
+}
+
+/// Writes the layout in the same form `Layout::parse` reads.
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Layout::Plus => f.write_str("plus"),
+            Layout::Roundabout => f.write_str("roundabout"),
+            Layout::ThreeWay { missing } => write!(f, "three-way:{}", missing),
+        }
+    }
+}
+
+impl Layout {
+    /// Accepts `plus`, `roundabout`, `t-junction` (no top arm) or
+    /// `three-way:<arm>`.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "plus" => Ok(Layout::Plus),
+            "roundabout" => Ok(Layout::Roundabout),
+            "t-junction" => Ok(Layout::ThreeWay {
+                missing: Direction::Up,
+            }),
+            _ => match value.strip_prefix("three-way:") {
+                Some(arm) => Ok(Layout::ThreeWay {
+                    missing: Direction::parse(arm)?,
+                }),
+                None => Err(format!("Unknown layout: {}", value)),
+            },
+        }
+    }
+
+    pub fn has_arm(self, arm: Direction) -> bool {
+        match self {
+            Layout::Plus | Layout::Roundabout => true,
+            Layout::ThreeWay { missing } => arm != missing,
+        }
+    }
+
+    /// Vehicles heading `direction` enter from the opposite arm.
+    pub fn accepts_approach(self, direction: Direction) -> bool {
+        self.has_arm(direction.opposite())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intersection_box_edges() {
+        let world = World::default();
+        let half_road = ROAD_WIDTH as f32 / 2.0;
+        let (cx, cy) = world.centre();
+        let epsilon = 0.5;
+
+        for (dx, dy) in [(1.0, 0.0), (-1.0, 0.0), (0.0, 1.0), (0.0, -1.0)] {
+            let inside = half_road - epsilon;
+            let outside = half_road + epsilon;
+            assert!(world.in_intersection_box(cx + dx * inside, cy + dy * inside));
+            assert!(!world.in_intersection_box(cx + dx * outside, cy + dy * outside));
+        }
+        assert!(!world.in_intersection_box(cx + half_road, cy));
+    }
+
+    #[test]
+    fn scaled_clicks_map_to_the_arm_under_the_cursor() {
+        // A plain 2x scale, and a 4K display letterboxing the scene.
+        let world = World::default();
+        let fit = Viewport::fit(world, 3840, 2160);
+        for viewport in [Viewport::scaled(2.0), fit] {
+            assert_clicks_map(world, viewport);
+        }
+        assert_eq!(fit.offset_y, 0);
+        assert!(fit.offset_x > 0);
+
+        // A wider world than the default fills the width instead.
+        let wide = World {
+            width: 2000,
+            ..world
+        };
+        let fit = Viewport::fit(wide, 3840, 2160);
+        assert_clicks_map(wide, fit);
+        assert_eq!(fit.offset_x, 0);
+        assert!(fit.offset_y > 0);
+    }
+
+    fn assert_clicks_map(world: World, viewport: Viewport) {
+        let scale = viewport.scale;
+        let (center_x, center_y) = (world.center_x(), world.center_y());
+        for (x, y, approach) in [
+            (center_x, world.height as i32 - 10, Some(Direction::Up)),
+            (center_x, 10, Some(Direction::Down)),
+            (10, center_y, Some(Direction::Right)),
+            (world.width as i32 - 10, center_y, Some(Direction::Left)),
+            (center_x, center_y, None),
+            (10, 10, None),
+        ] {
+            let (lx, ly) = viewport.to_logical(
+                (x as f32 * scale) as i32 + viewport.offset_x,
+                (y as f32 * scale) as i32 + viewport.offset_y,
+            );
+            assert_eq!(world.approach_at(lx, ly), approach);
+        }
+    }
+
+    #[test]
+    fn zooming_keeps_the_point_under_the_cursor_in_place() {
+        let mut camera = Camera::default();
+        assert_eq!(camera.to_screen(120.0, 75.0), (120, 75));
+        assert_eq!(
+            camera.visible(),
+            Rect::new(0, 0, WINDOW_WIDTH, WINDOW_HEIGHT)
+        );
+
+        let cursor = (700.0, 250.0);
+        let under = camera.to_world(cursor.0, cursor.1);
+        camera.zoom_about(2.0, cursor);
+        assert_eq!(camera.zoom, 2.0);
+        let (x, y) = camera.to_screen(under.0, under.1);
+        assert!((x as f32 - cursor.0).abs() <= 1.0 && (y as f32 - cursor.1).abs() <= 1.0);
+        let (x, y) = camera.to_world(cursor.0, cursor.1);
+        assert!((x - under.0).abs() < 0.5 && (y - under.1).abs() < 0.5);
+        assert_eq!(camera.visible().width(), WINDOW_WIDTH / 2);
+        assert_eq!(camera.home, (camera.x, camera.y));
+
+        // Dragging the world 100 pixels right at 2x moves the view 50 left.
+        let before = camera.x;
+        camera.pan(100.0, 0.0);
+        assert_eq!(camera.x, before - 50.0);
+
+        for _ in 0..20 {
+            camera.zoom_about(2.0, cursor);
+        }
+        assert_eq!(camera.zoom, MAX_ZOOM);
+        for _ in 0..20 {
+            camera.zoom_about(0.5, cursor);
+        }
+        assert_eq!(camera.zoom, MIN_ZOOM);
+    }
+
+    #[test]
+    fn bent_arms_swing_clockwise_from_the_start_of_the_curve() {
+        let bend = Bend {
+            amount: 80.0,
+            start: 100.0,
+            world: World::default(),
+        };
+        let (cx, cy) = bend.world.centre();
+        // Straight up to the start, then the full amount at the window edge.
+        assert_eq!(bend.offset(cx - 20.0, cy - 100.0), (0.0, 0.0));
+        assert_eq!(bend.offset(cx + 20.0, 0.0), (80.0, 0.0));
+        assert_eq!(bend.offset(cx, WINDOW_HEIGHT as f32), (-80.0, 0.0));
+        assert_eq!(bend.offset(0.0, cy), (0.0, -80.0));
+        assert_eq!(bend.offset(WINDOW_WIDTH as f32, cy + 20.0), (0.0, 80.0));
+        assert_eq!(bend.offset(cx, cy), (0.0, 0.0));
+        // Halfway along the curve it is a quarter of the way over.
+        assert_eq!(bend.offset(cx, cy - 250.0), (20.0, 0.0));
+        assert_eq!(bend.straighten(cx + 20.0, cy - 250.0), (cx, cy - 250.0));
+    }
+}
@@ -0,0 +1,103 @@
+use crate::vehicle::Vehicle;
+use std::collections::HashMap;
+
+/// Side of a grid cell in pixels, a little over a car length, so a vehicle
+/// touches at most four cells.
+pub const CELL_SIZE: f32 = 64.0;
+
+/// Vehicles bucketed by index into the cells of a uniform grid that their
+/// footprints touch, rebuilt each tick, so neighbour queries only compare
+/// vehicles that share a cell rather than every pair.
+pub struct SpatialGrid {
+    cells: HashMap<(i32, i32), Vec<usize>>,
+}
+
+impl SpatialGrid {
+    /// Buckets each vehicle into every cell its footprint, grown by
+    /// `margin` on each side, touches.
+    pub fn new(vehicles: &[Vehicle], margin: f32) -> Self {
+        let mut cells: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+        let cell = |value: f32| (value / CELL_SIZE).floor() as i32;
+        for (index, vehicle) in vehicles.iter().enumerate() {
+            let (half_width, half_height) = vehicle.half_extents();
+            let (half_width, half_height) = (half_width + margin, half_height + margin);
+            for column in cell(vehicle.x - half_width)..=cell(vehicle.x + half_width) {
+                for row in cell(vehicle.y - half_height)..=cell(vehicle.y + half_height) {
+                    cells.entry((column, row)).or_default().push(index);
+                }
+            }
+        }
+        Self { cells }
+    }
+
+    /// Every pair of vehicles sharing a cell, by index with the lower
+    /// first, each once and in ascending order, whatever order the cells
+    /// are visited in.
+    pub fn candidate_pairs(&self) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+        for members in self.cells.values() {
+            for (place, &i) in members.iter().enumerate() {
+                for &j in &members[place + 1..] {
+                    pairs.push((i.min(j), i.max(j)));
+                }
+            }
+        }
+        pairs.sort_unstable();
+        pairs.dedup();
+        pairs
+    }
+}
+
+/// Pairs of vehicles whose footprints overlap, by index with the lower
+/// first, in ascending order.
+pub fn overlapping_pairs(vehicles: &[Vehicle]) -> Vec<(usize, usize)> {
+    SpatialGrid::new(vehicles, 0.0)
+        .candidate_pairs()
+        .into_iter()
+        .filter(|&(i, j)| vehicles[i].overlaps(&vehicles[j]))
+        .collect()
+}
+
+/// `overlapping_pairs` by comparing every pair, to measure and check the
+/// grid against.
+pub fn overlapping_pairs_naive(vehicles: &[Vehicle]) -> Vec<(usize, usize)> {
+    let mut pairs = Vec::new();
+    for i in 0..vehicles.len() {
+        for j in (i + 1)..vehicles.len() {
+            if vehicles[i].overlaps(&vehicles[j]) {
+                pairs.push((i, j));
+            }
+        }
+    }
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ColorMode;
+    use crate::direction::{Direction, Route};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn the_grid_finds_the_same_overlaps_as_every_pair() {
+        let mut rng = StdRng::seed_from_u64(4);
+        let vehicles: Vec<Vehicle> = (0..500)
+            .map(|id| {
+                let direction = Direction::ALL[rng.random_range(0..4)];
+                let mut vehicle =
+                    Vehicle::new(id, direction, Route::Straight, ColorMode::ById, &mut rng);
+                // Negative coordinates too, where cells round down.
+                vehicle.x = rng.random_range(-200.0..1200.0);
+                vehicle.y = rng.random_range(-200.0..1000.0);
+                vehicle
+            })
+            .collect();
+        let naive = overlapping_pairs_naive(&vehicles);
+        assert!(naive.len() > 50, "{}", naive.len());
+        assert_eq!(overlapping_pairs(&vehicles), naive);
+        let checked = SpatialGrid::new(&vehicles, 0.0).candidate_pairs().len();
+        assert!(checked < 500 * 499 / 20, "{}", checked);
+    }
+}
@@ -0,0 +1,200 @@
+use crate::config::Config;
+use crate::direction::{Direction, Route};
+use crate::simulation::TrafficSimulation;
+use crate::vehicle::VehicleKind;
+use std::collections::BTreeSet;
+
+/// A vehicle booked onto an approach on a tick of a scripted run.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScriptedSpawn {
+    pub tick: u64,
+    pub approach: Direction,
+    pub route: Route,
+}
+
+/// Drives a simulation without a window from a script of spawns, checking
+/// after every tick that no two vehicles overlap and that none crossed its
+/// stop line on red. Every vehicle seen on the road is remembered, and
+/// every one that exits, so a test can ask whether all of them got out.
+pub struct Harness {
+    pub simulation: TrafficSimulation,
+    /// Spawns yet to be made, soonest last. One whose spawn point is taken
+    /// is tried again the next tick.
+    script: Vec<ScriptedSpawn>,
+    spawned: BTreeSet<u64>,
+    exited: BTreeSet<u64>,
+    failures: Vec<String>,
+}
+
+impl Harness {
+    pub fn new(config: Config) -> Self {
+        let mut simulation = TrafficSimulation::new(config);
+        simulation.keep_exits();
+        Self {
+            simulation,
+            script: Vec::new(),
+            spawned: BTreeSet::new(),
+            exited: BTreeSet::new(),
+            failures: Vec::new(),
+        }
+    }
+
+    /// Books a vehicle onto `approach` on `tick`, or as soon after as its
+    /// spawn point is clear.
+    pub fn spawn_at(mut self, tick: u64, approach: Direction, route: Route) -> Self {
+        // Behind everything booked for the same tick.
+        let at = self.script.partition_point(|spawn| spawn.tick > tick);
+        self.script.insert(
+            at,
+            ScriptedSpawn {
+                tick,
+                approach,
+                route,
+            },
+        );
+        self
+    }
+
+    /// Runs `ticks` ticks, making the spawns that fall due and checking
+    /// each tick.
+    pub fn step(&mut self, ticks: u64) -> Result<(), String> {
+        for _ in 0..ticks {
+            self.spawn_due()?;
+            self.tick();
+        }
+        Ok(())
+    }
+
+    /// Steps until the script is used up and the road is empty, for at
+    /// most `limit` ticks. Returns whether it emptied in time.
+    pub fn run_until_clear(&mut self, limit: u64) -> Result<bool, String> {
+        for _ in 0..limit {
+            if self.script.is_empty() && self.simulation.vehicles.is_empty() {
+                return Ok(true);
+            }
+            self.step(1)?;
+        }
+        Ok(self.script.is_empty() && self.simulation.vehicles.is_empty())
+    }
+
+    /// Where vehicle `id` is, while it is on the road.
+    pub fn position(&self, id: u64) -> Option<(f32, f32)> {
+        self.simulation
+            .vehicle(id)
+            .map(|vehicle| (vehicle.x, vehicle.y))
+    }
+
+    /// Every vehicle seen on the road, whether put there by the script or
+    /// by the simulation's own arrivals.
+    pub fn spawned(&self) -> &BTreeSet<u64> {
+        &self.spawned
+    }
+
+    pub fn exited(&self) -> &BTreeSet<u64> {
+        &self.exited
+    }
+
+    /// Spawned vehicles that have not exited.
+    pub fn unfinished(&self) -> Vec<u64> {
+        self.spawned.difference(&self.exited).copied().collect()
+    }
+
+    /// What the checks caught, each with the tick it happened on.
+    pub fn failures(&self) -> &[String] {
+        &self.failures
+    }
+
+    fn spawn_due(&mut self) -> Result<(), String> {
+        let tick = self.simulation.tick;
+        let mut waiting = Vec::new();
+        while let Some(spawn) = self.script.pop_if(|spawn| spawn.tick <= tick) {
+            let speed = self.simulation.config.cruise_speed / self.simulation.config.tick_rate;
+            let placed =
+                self.simulation
+                    .spawn_vehicle_with(spawn.approach, speed, Some(spawn.route))?;
+            if !placed {
+                waiting.push(spawn);
+            }
+        }
+        // Refused spawns keep their order and go first next tick.
+        self.script.extend(waiting.into_iter().rev());
+        Ok(())
+    }
+
+    fn tick(&mut self) {
+        // A vehicle may cross on green or yellow, shown either side of the
+        // tick it crosses on.
+        let shows_go = |simulation: &TrafficSimulation, approach, route| {
+            let lights = simulation.lights();
+            lights.is_green_for(approach, route) || lights.is_yellow_for(approach, route)
+        };
+        let before: Vec<(u64, bool)> = self
+            .simulation
+            .vehicles
+            .iter()
+            .map(|vehicle| {
+                let go = shows_go(&self.simulation, vehicle.initial_direction, vehicle.route);
+                (vehicle.id, go)
+            })
+            .collect();
+
+        self.simulation.update();
+        let simulation = &self.simulation;
+        let tick = simulation.tick;
+
+        for vehicle in &simulation.vehicles {
+            self.spawned.insert(vehicle.id);
+            let went_before = before
+                .iter()
+                .find(|(id, _)| *id == vehicle.id)
+                .is_none_or(|&(_, go)| go);
+            if simulation.config.signalled()
+                && vehicle.kind != VehicleKind::Emergency
+                && vehicle.crossed_stop_line()
+                && !went_before
+                && !shows_go(simulation, vehicle.initial_direction, vehicle.route)
+            {
+                self.failures.push(format!(
+                    "tick {}: vehicle {} entered from the {} approach on red",
+                    tick, vehicle.id, vehicle.initial_direction
+                ));
+            }
+        }
+        for (index, vehicle) in simulation.vehicles.iter().enumerate() {
+            for other in &simulation.vehicles[index + 1..] {
+                if vehicle.overlaps(other) {
+                    self.failures.push(format!(
+                        "tick {}: vehicles {} and {} overlap",
+                        tick, vehicle.id, other.id
+                    ));
+                }
+            }
+        }
+        for vehicle in self.simulation.take_exits() {
+            self.spawned.insert(vehicle.id);
+            self.exited.insert(vehicle.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlapping_vehicles_are_reported_with_their_tick() {
+        let mut harness =
+            Harness::new(Config::default()).spawn_at(0, Direction::Up, Route::Straight);
+        harness.step(1).unwrap();
+        let mut twin = harness.simulation.vehicles[0].clone();
+        twin.id = 99;
+        twin.y += 10.0;
+        twin.prev_y += 10.0;
+        harness.simulation.vehicles.push(twin);
+        harness.step(1).unwrap();
+        assert_eq!(harness.failures()[0], "tick 2: vehicles 0 and 99 overlap");
+        assert!(harness.spawned().contains(&99));
+        assert!(harness.position(0).is_some());
+        assert_eq!(harness.position(7), None);
+    }
+}
@@ -0,0 +1,190 @@
+use crate::geometry::World;
+use crate::render::Renderer;
+use crate::snapshot::{Fields, Line};
+use crate::vehicle::Vehicle;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+
+/// Side of a heatmap cell in pixels, a third of a lane.
+pub const HEAT_CELL: u32 = 10;
+
+/// Most opaque a cell is drawn, at the run's hottest stop.
+const MAX_ALPHA: f32 = 170.0;
+
+/// Seconds vehicles have spent over each cell of a grid across the world
+/// since the start of the run, and the part of it they spent stopped or
+/// crawling. Cells are in road coordinates, before any bend; vehicles
+/// beyond the world are not counted.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Heatmap {
+    columns: usize,
+    rows: usize,
+    occupied: Vec<f32>,
+    stopped: Vec<f32>,
+}
+
+impl Default for Heatmap {
+    fn default() -> Self {
+        Self::new(World::default())
+    }
+}
+
+impl Heatmap {
+    pub fn new(world: World) -> Self {
+        let columns = world.width.div_ceil(HEAT_CELL) as usize;
+        let rows = world.height.div_ceil(HEAT_CELL) as usize;
+        Self {
+            columns,
+            rows,
+            occupied: vec![0.0; columns * rows],
+            stopped: vec![0.0; columns * rows],
+        }
+    }
+
+    /// Adds `dt` to every cell the vehicle's footprint touches, and to
+    /// their stop time as well if it is `stopped`.
+    pub fn add(&mut self, vehicle: &Vehicle, stopped: bool, dt: f32) {
+        let (half_width, half_height) = vehicle.half_extents();
+        let span = |low: f32, high: f32, cells: usize| {
+            let cell = |value: f32| (value / HEAT_CELL as f32).floor();
+            let first = cell(low).max(0.0) as usize;
+            let last = cell(high).min(cells as f32 - 1.0);
+            // Entirely before the first cell gives an empty range.
+            first..(last + 1.0).max(0.0) as usize
+        };
+        for row in span(vehicle.y - half_height, vehicle.y + half_height, self.rows) {
+            for column in span(vehicle.x - half_width, vehicle.x + half_width, self.columns) {
+                let index = row * self.columns + column;
+                self.occupied[index] += dt;
+                if stopped {
+                    self.stopped[index] += dt;
+                }
+            }
+        }
+    }
+
+    /// The occupied and stopped seconds of the cell under the point.
+    pub fn at(&self, x: f32, y: f32) -> (f32, f32) {
+        let (column, row) = (x / HEAT_CELL as f32, y / HEAT_CELL as f32);
+        if column < 0.0 || row < 0.0 || column >= self.columns as f32 || row >= self.rows as f32 {
+            return (0.0, 0.0);
+        }
+        let index = row as usize * self.columns + column as usize;
+        (self.occupied[index], self.stopped[index])
+    }
+
+    /// A line for each cell anyone has been over, for a snapshot.
+    pub fn save_state(&self) -> Vec<Line> {
+        self.occupied
+            .iter()
+            .zip(&self.stopped)
+            .enumerate()
+            .filter(|(_, (occupied, _))| **occupied > 0.0)
+            .map(|(index, (&occupied, &stopped))| {
+                Line::new("heat").put(index).put(occupied).put(stopped)
+            })
+            .collect()
+    }
+
+    /// Reads back the lines `save_state` wrote for a heatmap of `world`.
+    pub fn from_state<'a>(
+        world: World,
+        lines: impl Iterator<Item = Fields<'a>>,
+    ) -> Result<Self, String> {
+        let mut heatmap = Self::new(world);
+        for mut fields in lines {
+            let index: usize = fields.take()?;
+            if index >= heatmap.occupied.len() {
+                return Err(format!("No heatmap cell {}", index));
+            }
+            heatmap.occupied[index] = fields.take()?;
+            heatmap.stopped[index] = fields.take()?;
+            fields.finish()?;
+        }
+        Ok(heatmap)
+    }
+
+    /// The most stopped seconds of any cell.
+    pub fn peak(&self) -> f32 {
+        self.stopped.iter().copied().fold(0.0, f32::max)
+    }
+
+    /// Each stopped-in cell in a colour from yellow to red, and more opaque,
+    /// the nearer its stop time is to the peak; cells only driven through
+    /// in a faint blue, so the paths taken show under the queues.
+    pub fn draw(&self, canvas: &mut dyn Renderer) -> Result<(), String> {
+        let peak = self.peak();
+        let busiest = self.occupied.iter().copied().fold(0.0, f32::max);
+        for (index, (&occupied, &stopped)) in self.occupied.iter().zip(&self.stopped).enumerate() {
+            if occupied == 0.0 {
+                continue;
+            }
+            let color = if stopped > 0.0 {
+                // Square-rooted so short queues still show beside the worst.
+                let heat = (stopped / peak).sqrt();
+                Color::RGBA(
+                    255,
+                    (220.0 * (1.0 - heat)) as u8,
+                    0,
+                    (MAX_ALPHA * (0.25 + 0.75 * heat)) as u8,
+                )
+            } else {
+                Color::RGBA(60, 120, 255, (60.0 * occupied / busiest).max(12.0) as u8)
+            };
+            let (column, row) = (index % self.columns, index / self.columns);
+            canvas.set_draw_color(color);
+            canvas.fill_rect(Rect::new(
+                (column as u32 * HEAT_CELL) as i32,
+                (row as u32 * HEAT_CELL) as i32,
+                HEAT_CELL,
+                HEAT_CELL,
+            ))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::direction::Direction;
+    use crate::render::{DrawCall, RecordingRenderer};
+    use crate::simulation::TrafficSimulation;
+
+    #[test]
+    fn a_queue_at_a_red_light_is_the_hottest_spot() {
+        let mut simulation = TrafficSimulation::new(Config::default());
+        // The cross street starts on red.
+        simulation.spawn_vehicle(Direction::Right);
+        simulation.run_ticks(60 * 5);
+        let vehicle = &simulation.vehicles[0];
+        assert!(vehicle.stopped_at_line.is_some());
+        let (x, y) = (vehicle.x, vehicle.y);
+
+        let heatmap = simulation.heatmap();
+        let (occupied, stopped) = heatmap.at(x, y);
+        assert!(stopped > 1.0 && stopped <= occupied);
+        assert_eq!(heatmap.peak(), stopped);
+        // Driven over on the way in, but never stopped on.
+        let (occupied, stopped) = heatmap.at(x - 100.0, y);
+        assert!(occupied > 0.0);
+        assert_eq!(stopped, 0.0);
+        assert_eq!(heatmap.at(-5.0, y), (0.0, 0.0));
+
+        let mut canvas = RecordingRenderer::default();
+        heatmap.draw(&mut canvas).unwrap();
+        let cell = HEAT_CELL as i32;
+        let under = Rect::new(
+            (x as i32).div_euclid(cell) * cell,
+            (y as i32).div_euclid(cell) * cell,
+            HEAT_CELL,
+            HEAT_CELL,
+        );
+        let hottest = canvas.calls.iter().find_map(|call| match call {
+            DrawCall::FillRect(rect, color) if *rect == under => Some(*color),
+            _ => None,
+        });
+        assert_eq!(hottest, Some(Color::RGBA(255, 0, 0, MAX_ALPHA as u8)));
+    }
+}
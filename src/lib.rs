@@ -0,0 +1,28 @@
+#[macro_use]
+pub mod log;
+
+pub mod audio;
+pub mod compare;
+pub mod config;
+pub mod conflicts;
+pub mod digits;
+pub mod direction;
+pub mod environment;
+pub mod geometry;
+pub mod grid;
+pub mod harness;
+pub mod heatmap;
+pub mod lights;
+pub mod metrics;
+pub mod network;
+pub mod panel;
+pub mod pedestrian;
+pub mod profile;
+pub mod remote;
+pub mod render;
+pub mod replay;
+pub mod simulation;
+pub mod snapshot;
+pub mod sprites;
+pub mod stats;
+pub mod vehicle;
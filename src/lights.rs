@@ -0,0 +1,1331 @@
+use crate::direction::{Direction, Route};
+use crate::snapshot::{Field, Fields, Line};
+use std::fmt;
+
+/// `green` and `only` are indexed by `Direction::index`.
+#[derive(Clone, Debug)]
+pub struct Phase {
+    pub green: [bool; 4],
+    /// The one route a green approach may take in this phase, such as the
+    /// left turn of a protected-left phase, which U-turns share; `None`
+    /// lets every route go.
+    pub only: [Option<Route>; 4],
+    pub duration: f32,
+}
+
+impl Phase {
+    /// A phase that gives `green` approaches green for every route.
+    pub fn new(green: [bool; 4], duration: f32) -> Self {
+        Self {
+            green,
+            only: [None; 4],
+            duration,
+        }
+    }
+
+    /// Whether vehicles from `approach` taking `route` have green. A
+    /// U-turn crosses the oncoming lanes as a left turn does, so it goes
+    /// with the left turns.
+    pub fn allows(&self, approach: Direction, route: Route) -> bool {
+        let index = approach.index();
+        let signalled_as = match route {
+            Route::UTurn => Route::TurnLeft,
+            other => other,
+        };
+        self.green[index]
+            && self.only[index].is_none_or(|only| only == route || only == signalled_as)
+    }
+
+    // Whether some movement green in this phase is red in `next`.
+    fn loses_to(&self, next: &Phase) -> bool {
+        Direction::ALL.into_iter().any(|approach| {
+            Route::ALL
+                .into_iter()
+                .any(|route| self.allows(approach, route) && !next.allows(approach, route))
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LightPlan {
+    pub phases: Vec<Phase>,
+}
+
+impl Default for LightPlan {
+    fn default() -> Self {
+        Self {
+            phases: vec![
+                Phase::new([true, true, false, false], 5.0),
+                Phase::new([false; 4], 1.0),
+                Phase::new([false, false, true, true], 5.0),
+                Phase::new([false; 4], 1.0),
+            ],
+        }
+    }
+}
+
+/// Writes the plan in the same form `LightPlan::parse` reads.
+impl fmt::Display for LightPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, phase) in self.phases.iter().enumerate() {
+            if index > 0 {
+                f.write_str(",")?;
+            }
+            let green: Vec<String> = Direction::ALL
+                .into_iter()
+                .filter(|direction| phase.green[direction.index()])
+                .map(|direction| match phase.only[direction.index()] {
+                    Some(route) => format!("{}.{}", direction, route.name()),
+                    None => direction.to_string(),
+                })
+                .collect();
+            if green.is_empty() {
+                f.write_str("none")?;
+            } else {
+                f.write_str(&green.join("+"))?;
+            }
+            write!(f, ":{}", phase.duration)?;
+        }
+        Ok(())
+    }
+}
+
+impl LightPlan {
+    /// The default plan with a left-turn arrow for each pair of opposing
+    /// approaches before their straights go. The arrow phase holds the
+    /// oncoming straights; later in the full phase lefts give way to them.
+    pub fn protected_lefts() -> Self {
+        let arrows = |first: Direction, second: Direction| {
+            let mut green = [false; 4];
+            let mut only = [None; 4];
+            for approach in [first, second] {
+                green[approach.index()] = true;
+                only[approach.index()] = Some(Route::TurnLeft);
+            }
+            Phase {
+                green,
+                only,
+                duration: 3.0,
+            }
+        };
+        Self {
+            phases: vec![
+                arrows(Direction::Up, Direction::Down),
+                Phase::new([true, true, false, false], 5.0),
+                Phase::new([false; 4], 1.0),
+                arrows(Direction::Right, Direction::Left),
+                Phase::new([false, false, true, true], 5.0),
+                Phase::new([false; 4], 1.0),
+            ],
+        }
+    }
+
+    /// Parses a plan such as `up+down:5,none:1,right+left:5,none:1`,
+    /// where each phase lists its green approaches and a duration in seconds.
+    /// An approach written with a route, as in `up.left+down.left:3`, is
+    /// green for that route only. `protected-lefts` names the plan of
+    /// `LightPlan::protected_lefts`.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        if value == "protected-lefts" {
+            return Ok(Self::protected_lefts());
+        }
+        let plan = Self {
+            phases: parse_phases(value)?,
+        };
+        plan.validate()?;
+        Ok(plan)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.phases.is_empty() {
+            return Err("Light plan has no phases".to_string());
+        }
+        for (index, phase) in self.phases.iter().enumerate() {
+            if phase.duration <= 0.0 {
+                return Err(format!("Phase {} must have a positive duration", index));
+            }
+        }
+        Ok(())
+    }
+}
+
+// The phases of a plan as `LightPlan::parse` reads them, without its
+// checks, so the lights' own recovery phases can be read back too.
+fn parse_phases(value: &str) -> Result<Vec<Phase>, String> {
+    let mut phases = Vec::new();
+    for phase in value.split(',') {
+        let (approaches, duration) = phase
+            .split_once(':')
+            .ok_or_else(|| format!("Phase needs a duration: {}", phase))?;
+
+        let mut green = [false; 4];
+        let mut only = [None; 4];
+        if approaches != "none" {
+            for approach in approaches.split('+') {
+                let (approach, route) = match approach.split_once('.') {
+                    Some((approach, route)) => (approach, Some(Route::parse(route)?)),
+                    None => (approach, None),
+                };
+                let index = Direction::parse(approach)?.index();
+                green[index] = true;
+                only[index] = route;
+            }
+        }
+
+        let duration = duration
+            .parse()
+            .map_err(|_| format!("Invalid phase duration: {}", duration))?;
+        phases.push(Phase {
+            green,
+            only,
+            duration,
+        });
+    }
+    Ok(phases)
+}
+
+/// Written as the one phase of a plan.
+impl Field for Phase {
+    fn write(&self) -> String {
+        LightPlan {
+            phases: vec![self.clone()],
+        }
+        .to_string()
+    }
+
+    fn read(word: &str) -> Result<Self, String> {
+        let mut phases = parse_phases(word)?;
+        match (phases.pop(), phases.is_empty()) {
+            (Some(phase), true) => Ok(phase),
+            _ => Err(format!("Expected one phase: {}", word)),
+        }
+    }
+}
+
+/// Intervals the lights add around the plan's own phases, in seconds.
+#[derive(Clone, Copy, Debug)]
+pub struct SignalTiming {
+    /// Shortest time any phase lasts, whatever its configured duration, so
+    /// a degenerate plan can't flip the lights every tick.
+    pub min_phase: f32,
+    /// Shortest time a phase that gives anyone green lasts.
+    pub min_green: f32,
+    /// Yellow shown to approaches losing green; zero skips it.
+    pub yellow: f32,
+    /// All-red time before a phase that gives a new approach green; zero
+    /// skips it.
+    pub all_red: f32,
+    /// Seconds an approach with vehicles waiting may be kept red before an
+    /// adaptive controller must serve it.
+    pub max_wait: f32,
+}
+
+/// What a controller sees when the current phase's time is up, and on
+/// every tick of a plan phase before that.
+pub struct IntersectionState<'a> {
+    /// Vehicles yet to reach the stop line, indexed by `Direction::index`.
+    pub queues: [usize; 4],
+    pub phase: usize,
+    pub phases: &'a [Phase],
+    /// Seconds the current phase has run, holds included.
+    pub phase_age: f32,
+    /// Seconds each approach has been red, indexed by `Direction::index`.
+    pub red_for: [f32; 4],
+    pub max_wait: f32,
+}
+
+/// A controller's decision at the end of a phase.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PhaseCommand {
+    /// Run the current phase for another full duration.
+    Hold,
+    /// Change to the plan phase with this index, through yellow and all-red.
+    Switch(usize),
+}
+
+/// Chooses the plan phase that follows the current one. Implement it to try
+/// a strategy of your own and hand it to
+/// `TrafficSimulation::set_signal_controller`. It must be `Send` and `Sync`,
+/// since the intersections of a grid may tick on threads of their own and
+/// a crowded one reads its lights from several.
+pub trait SignalController: Send + Sync {
+    fn next_phase(&mut self, state: &IntersectionState) -> PhaseCommand;
+
+    /// Called on every tick of a plan phase, `dt` seconds after the last,
+    /// to end it before its time: `Switch` starts the change as
+    /// `TrafficLights::command` would, within the minimum green, and
+    /// `Hold` lets the phase run. Controllers that only choose when a
+    /// phase is up leave this as it is.
+    fn decide(&mut self, _state: &IntersectionState, _dt: f32) -> PhaseCommand {
+        PhaseCommand::Hold
+    }
+}
+
+/// Runs the plan's phases in order, whatever the traffic.
+pub struct FixedCycle;
+
+impl SignalController for FixedCycle {
+    fn next_phase(&mut self, state: &IntersectionState) -> PhaseCommand {
+        PhaseCommand::Switch((state.phase + 1) % state.phases.len())
+    }
+}
+
+/// Gives green to the phase serving the most queued vehicles, holding the
+/// current one while it is still the best. An approach with vehicles
+/// waiting that has been red for `max_wait` is served first, so a busy
+/// street can't keep a quiet one red for ever. With nobody queued it
+/// cycles like `FixedCycle`.
+pub struct MaxPressure;
+
+impl MaxPressure {
+    fn pressure(state: &IntersectionState, phase: usize) -> usize {
+        Direction::ALL
+            .into_iter()
+            .filter(|direction| state.phases[phase].green[direction.index()])
+            .map(|direction| state.queues[direction.index()])
+            .sum()
+    }
+}
+
+impl SignalController for MaxPressure {
+    fn next_phase(&mut self, state: &IntersectionState) -> PhaseCommand {
+        let count = state.phases.len();
+        let starved = Direction::ALL
+            .into_iter()
+            .filter(|direction| {
+                let index = direction.index();
+                state.queues[index] > 0 && state.red_for[index] >= state.max_wait
+            })
+            .max_by(|a, b| state.red_for[a.index()].total_cmp(&state.red_for[b.index()]));
+        if let Some(direction) = starved
+            && let Some(phase) = (1..count)
+                .map(|offset| (state.phase + offset) % count)
+                .find(|&phase| state.phases[phase].green[direction.index()])
+        {
+            return PhaseCommand::Switch(phase);
+        }
+
+        let current = Self::pressure(state, state.phase);
+        // The first of equally pressed phases after the current one wins.
+        let best = (1..count)
+            .map(|offset| (state.phase + offset) % count)
+            .max_by_key(|&phase| (Self::pressure(state, phase), count - phase))
+            .filter(|&phase| Self::pressure(state, phase) > current);
+        match best {
+            Some(phase) => PhaseCommand::Switch(phase),
+            None if current > 0 => PhaseCommand::Hold,
+            None => FixedCycle.next_phase(state),
+        }
+    }
+}
+
+/// Runs the plan in order, but ends a green early once nobody is left
+/// queued for it and someone waits elsewhere, and holds it past its time
+/// while its queue lasts and nobody else waits. Phases that green nobody
+/// run their full time, so a plan's clearances are kept.
+pub struct QueueAdaptive;
+
+impl QueueAdaptive {
+    // Vehicles queued on approaches the current phase keeps red.
+    fn waiting_elsewhere(state: &IntersectionState) -> usize {
+        Direction::ALL
+            .into_iter()
+            .filter(|direction| !state.phases[state.phase].green[direction.index()])
+            .map(|direction| state.queues[direction.index()])
+            .sum()
+    }
+}
+
+impl SignalController for QueueAdaptive {
+    fn next_phase(&mut self, state: &IntersectionState) -> PhaseCommand {
+        if MaxPressure::pressure(state, state.phase) > 0 && Self::waiting_elsewhere(state) == 0 {
+            PhaseCommand::Hold
+        } else {
+            FixedCycle.next_phase(state)
+        }
+    }
+
+    fn decide(&mut self, state: &IntersectionState, _dt: f32) -> PhaseCommand {
+        let greens_anyone = state.phases[state.phase].green.contains(&true);
+        if greens_anyone
+            && MaxPressure::pressure(state, state.phase) == 0
+            && Self::waiting_elsewhere(state) > 0
+        {
+            FixedCycle.next_phase(state)
+        } else {
+            PhaseCommand::Hold
+        }
+    }
+}
+
+/// Never changes phase by itself: the lights hold each phase until
+/// `TrafficLights::command` is told to switch, for a controller that runs
+/// outside the simulation.
+pub struct External;
+
+impl SignalController for External {
+    fn next_phase(&mut self, _state: &IntersectionState) -> PhaseCommand {
+        PhaseCommand::Hold
+    }
+}
+
+/// The controllers that can be picked at startup.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ControllerKind {
+    #[default]
+    Fixed,
+    MaxPressure,
+    QueueAdaptive,
+    External,
+}
+
+impl ControllerKind {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "fixed" => Ok(ControllerKind::Fixed),
+            "max-pressure" => Ok(ControllerKind::MaxPressure),
+            "queue-adaptive" => Ok(ControllerKind::QueueAdaptive),
+            "external" => Ok(ControllerKind::External),
+            _ => Err(format!("Unknown signal controller: {}", value)),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ControllerKind::Fixed => "fixed",
+            ControllerKind::MaxPressure => "max-pressure",
+            ControllerKind::QueueAdaptive => "queue-adaptive",
+            ControllerKind::External => "external",
+        }
+    }
+
+    pub fn build(self) -> Box<dyn SignalController> {
+        match self {
+            ControllerKind::Fixed => Box::new(FixedCycle),
+            ControllerKind::MaxPressure => Box::new(MaxPressure),
+            ControllerKind::QueueAdaptive => Box::new(QueueAdaptive),
+            ControllerKind::External => Box::new(External),
+        }
+    }
+}
+
+/// Where the lights are within the change from one plan phase to the next.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    Phase,
+    Yellow,
+    AllRed,
+}
+
+pub struct TrafficLights {
+    plan: LightPlan,
+    timing: SignalTiming,
+    controller: Box<dyn SignalController>,
+    phase: usize,
+    /// The phase the controller chose to follow the current one.
+    next_phase: usize,
+    stage: Stage,
+    elapsed: f32,
+    /// Seconds since the current phase began, counting any holds, which
+    /// restart `elapsed`.
+    phase_age: f32,
+    red_for: [f32; 4],
+    /// Phases that replace the plan while the intersection recovers from a
+    /// gridlock or is preempted, and the index of the current one.
+    recovery: Vec<Phase>,
+    recovery_step: usize,
+    /// The approach held on green for an emergency vehicle.
+    preempted: Option<Direction>,
+    /// A retimed plan and yellow, waiting for the next stage boundary.
+    pending: Option<(LightPlan, f32)>,
+}
+
+impl TrafficLights {
+    pub fn new(
+        plan: LightPlan,
+        timing: SignalTiming,
+        controller: Box<dyn SignalController>,
+    ) -> Self {
+        Self {
+            next_phase: 1 % plan.phases.len(),
+            plan,
+            timing,
+            controller,
+            phase: 0,
+            stage: Stage::Phase,
+            elapsed: 0.0,
+            phase_age: 0.0,
+            red_for: [0.0; 4],
+            recovery: Vec::new(),
+            recovery_step: 0,
+            preempted: None,
+            pending: None,
+        }
+    }
+
+    /// Lengthens or shortens every green phase by `green` seconds and the
+    /// yellow by `yellow`, within `max_green` and `max_yellow`. Phases
+    /// never drop below the minimum phase and green times. The change waits
+    /// for the next stage boundary so nothing running is cut short.
+    pub fn retime(&mut self, green: f32, yellow: f32, max_green: f32, max_yellow: f32) {
+        let shortest = self.timing.min_green.max(self.timing.min_phase);
+        let (plan, pending_yellow) = self
+            .pending
+            .get_or_insert_with(|| (self.plan.clone(), self.timing.yellow));
+        for phase in &mut plan.phases {
+            if phase.green.contains(&true) {
+                phase.duration = (phase.duration + green).clamp(shortest, max_green);
+            }
+        }
+        *pending_yellow = (*pending_yellow + yellow).clamp(0.0, max_yellow);
+    }
+
+    /// Where the lights are in their plan, and in any recovery or
+    /// preemption, for a snapshot. The plan, timings and controller belong
+    /// to the run's configuration and are left out.
+    pub fn save_state(&self) -> Line {
+        Line::new("lights")
+            .put(self.phase)
+            .put(self.next_phase)
+            .put(self.stage)
+            .put(self.elapsed)
+            .put(self.phase_age)
+            .put_all(self.red_for)
+            .put(self.recovery_step)
+            .put(self.preempted)
+            .put_all(self.recovery.iter().cloned())
+    }
+
+    /// Puts back what `save_state` wrote, failing if the current plan has
+    /// too few phases for it.
+    pub fn restore_state(&mut self, mut fields: Fields) -> Result<(), String> {
+        let phase: usize = fields.take()?;
+        let next_phase: usize = fields.take()?;
+        let stage = fields.take()?;
+        let elapsed = fields.take()?;
+        let phase_age = fields.take()?;
+        let red_for = fields.take_array()?;
+        let recovery_step: usize = fields.take()?;
+        let preempted = fields.take()?;
+        let recovery: Vec<Phase> = fields.take_rest()?;
+        let phases = self.plan.phases.len();
+        if phase.max(next_phase) >= phases {
+            return Err(format!(
+                "The snapshot's lights are in phase {} of a plan with {} phase(s)",
+                phase.max(next_phase),
+                phases
+            ));
+        }
+        self.phase = phase;
+        self.next_phase = next_phase;
+        self.stage = stage;
+        self.elapsed = elapsed;
+        self.phase_age = phase_age;
+        self.red_for = red_for;
+        self.recovery_step = recovery_step;
+        self.preempted = preempted;
+        self.recovery = recovery;
+        Ok(())
+    }
+
+    /// Hands the choice of phases to `controller` from the end of the
+    /// current phase on.
+    pub fn set_controller(&mut self, controller: Box<dyn SignalController>) {
+        self.controller = controller;
+    }
+
+    /// The plan in force from the next stage boundary on.
+    pub fn upcoming_plan(&self) -> &LightPlan {
+        self.pending.as_ref().map_or(&self.plan, |(plan, _)| plan)
+    }
+
+    /// The yellow in force from the next stage boundary on.
+    pub fn upcoming_yellow(&self) -> f32 {
+        self.pending
+            .as_ref()
+            .map_or(self.timing.yellow, |&(_, yellow)| yellow)
+    }
+
+    /// Interrupts the plan to give each approach green on its own, with an
+    /// all-red clearance before each one and at the end. The plan then
+    /// restarts from its first phase.
+    pub fn start_recovery(&mut self, approaches: &[Direction], clearance: f32, green: f32) {
+        self.recovery.clear();
+        for &approach in approaches {
+            let mut phase_green = [false; 4];
+            phase_green[approach.index()] = true;
+            self.recovery.push(Phase::new([false; 4], clearance));
+            self.recovery.push(Phase::new(phase_green, green));
+        }
+        self.recovery.push(Phase::new([false; 4], clearance));
+        self.recovery_step = 0;
+        self.stage = Stage::Phase;
+        self.elapsed = 0.0;
+    }
+
+    /// Interrupts the plan to hold `approach` alone on green, after an
+    /// all-red as long as a yellow and all-red would be, until
+    /// `end_preemption`. Refused during a gridlock recovery, which has the
+    /// box to clear first.
+    pub fn preempt(&mut self, approach: Direction) -> bool {
+        if self.in_recovery() {
+            return false;
+        }
+        if self.preempted == Some(approach) {
+            return true;
+        }
+        let mut green = [false; 4];
+        green[approach.index()] = true;
+        self.recovery = vec![
+            Phase::new([false; 4], self.timing.yellow + self.timing.all_red),
+            Phase::new(green, f32::INFINITY),
+        ];
+        self.recovery_step = 0;
+        self.preempted = Some(approach);
+        self.stage = Stage::Phase;
+        self.elapsed = 0.0;
+        true
+    }
+
+    /// Ends a preemption with an all-red clearance, after which the plan
+    /// restarts from its first phase.
+    pub fn end_preemption(&mut self) {
+        if self.preempted.take().is_none() {
+            return;
+        }
+        self.recovery = vec![Phase::new(
+            [false; 4],
+            self.timing.yellow + self.timing.all_red,
+        )];
+        self.recovery_step = 0;
+        self.elapsed = 0.0;
+    }
+
+    pub fn preempted(&self) -> Option<Direction> {
+        self.preempted
+    }
+
+    /// Seconds one pass through the plan takes in order, counting the
+    /// clamped phase durations and the yellow and all-red between them.
+    pub fn cycle_length(&self) -> f32 {
+        self.phase_start(self.plan.phases.len())
+    }
+
+    /// Seconds into the cycle at which phase `phase` gets its green, with
+    /// the plan run in order from the start of its first phase.
+    pub fn phase_start(&self, phase: usize) -> f32 {
+        let phases = &self.plan.phases;
+        let mut length = 0.0;
+        for (index, phase) in phases.iter().enumerate().take(phase) {
+            let next = &phases[(index + 1) % phases.len()];
+            length += self.phase_duration(phase);
+            if phase.loses_to(next) {
+                length += self.timing.yellow;
+                if next.loses_to(phase) {
+                    length += self.timing.all_red;
+                }
+            }
+        }
+        length
+    }
+
+    /// The offset that lets a platoon leaving this intersection's first
+    /// phase at `speed` pixels per second find the same phase starting at an
+    /// intersection `distance` pixels downstream.
+    pub fn green_wave_offset(&self, distance: f32, speed: f32) -> f32 {
+        (-distance / speed).rem_euclid(self.cycle_length())
+    }
+
+    /// Starts the cycle `offset` seconds in, so neighbouring intersections
+    /// can run the same plan shifted against each other.
+    pub fn skip(&mut self, offset: f32) {
+        let mut remaining = offset;
+        while remaining > 0.0 {
+            let left = self.stage_duration() - self.elapsed;
+            if remaining < left {
+                self.elapsed += remaining;
+                return;
+            }
+            remaining -= left;
+            self.elapsed = self.stage_duration();
+            self.update(0.0, [0; 4]);
+        }
+    }
+
+    pub fn in_recovery(&self) -> bool {
+        self.interrupted() && self.preempted.is_none()
+    }
+
+    // Whether recovery or preemption phases are standing in for the plan.
+    fn interrupted(&self) -> bool {
+        !self.recovery.is_empty()
+    }
+
+    pub fn recovery_step(&self) -> Option<usize> {
+        self.interrupted().then_some(self.recovery_step)
+    }
+
+    fn current(&self) -> &Phase {
+        if self.interrupted() {
+            &self.recovery[self.recovery_step]
+        } else {
+            &self.plan.phases[self.phase]
+        }
+    }
+
+    fn next(&self) -> &Phase {
+        &self.plan.phases[self.next_phase]
+    }
+
+    fn stage_duration(&self) -> f32 {
+        match self.stage {
+            Stage::Phase => self.phase_duration(self.current()),
+            Stage::Yellow => self.timing.yellow,
+            Stage::AllRed => self.timing.all_red,
+        }
+    }
+
+    fn phase_duration(&self, phase: &Phase) -> f32 {
+        let mut duration = phase.duration.max(self.timing.min_phase);
+        if phase.green.contains(&true) {
+            duration = duration.max(self.timing.min_green);
+        }
+        duration
+    }
+
+    // Movements that are green now and not in the next phase.
+    fn losing_green(&self) -> bool {
+        self.current().loses_to(self.next())
+    }
+
+    // Movements that are red now and green in the next phase.
+    fn gaining_green(&self) -> bool {
+        self.next().loses_to(self.current())
+    }
+
+    // Moves towards the phase the controller picks, passing through yellow
+    // and all-red when they are enabled and the change calls for them.
+    fn advance(&mut self, queues: [usize; 4]) {
+        if self.stage == Stage::Phase {
+            let state = IntersectionState {
+                queues,
+                phase: self.phase,
+                phases: &self.plan.phases,
+                phase_age: self.phase_age,
+                red_for: self.red_for,
+                max_wait: self.timing.max_wait,
+            };
+            match self.controller.next_phase(&state) {
+                PhaseCommand::Hold => return,
+                // An out-of-range choice falls back to the plan order.
+                PhaseCommand::Switch(phase) => {
+                    self.next_phase = if phase < self.plan.phases.len() {
+                        phase
+                    } else {
+                        (self.phase + 1) % self.plan.phases.len()
+                    };
+                }
+            }
+        }
+        self.change_stage();
+    }
+
+    /// Starts changing to plan phase `phase` now, rather than when the
+    /// current phase runs out, as an outside controller decides. Returns
+    /// whether the change started: it doesn't during yellow, all-red,
+    /// recovery or preemption, before the current phase has run its
+    /// minimum time, or for the current or a nonexistent phase. `Hold`
+    /// never changes anything.
+    pub fn command(&mut self, command: PhaseCommand) -> bool {
+        let PhaseCommand::Switch(phase) = command else {
+            return false;
+        };
+        let mut minimum = self.timing.min_phase;
+        if self.current().green.contains(&true) {
+            minimum = minimum.max(self.timing.min_green);
+        }
+        if self.interrupted()
+            || self.stage != Stage::Phase
+            || phase == self.phase
+            || phase >= self.plan.phases.len()
+            || self.phase_age < minimum
+        {
+            return false;
+        }
+        self.next_phase = phase;
+        self.elapsed = 0.0;
+        self.change_stage();
+        true
+    }
+
+    // Steps into yellow, all-red or `next_phase`, whichever the change
+    // from the current phase calls for next.
+    fn change_stage(&mut self) {
+        let losing = self.losing_green();
+        let next_stage = match self.stage {
+            Stage::Phase if losing && self.timing.yellow > 0.0 => Some(Stage::Yellow),
+            Stage::Phase | Stage::Yellow
+                if losing && self.gaining_green() && self.timing.all_red > 0.0 =>
+            {
+                Some(Stage::AllRed)
+            }
+            _ => None,
+        };
+        match next_stage {
+            Some(stage) => self.stage = stage,
+            None => {
+                self.stage = Stage::Phase;
+                self.phase = self.next_phase;
+                self.phase_age = 0.0;
+            }
+        }
+    }
+
+    /// `queues` counts the vehicles yet to reach each stop line, for the
+    /// controller.
+    pub fn update(&mut self, dt: f32, queues: [usize; 4]) {
+        // At most one boundary is crossed per call, however large `dt` is.
+        self.elapsed += dt;
+        self.phase_age += dt;
+        if self.elapsed >= self.stage_duration() {
+            self.elapsed = 0.0;
+            if let Some((plan, yellow)) = self.pending.take() {
+                self.plan = plan;
+                self.timing.yellow = yellow;
+            }
+            if self.interrupted() {
+                self.recovery_step += 1;
+                if self.recovery_step == self.recovery.len() {
+                    self.recovery.clear();
+                    self.phase = 0;
+                }
+            } else {
+                self.advance(queues);
+            }
+        }
+
+        for direction in Direction::ALL {
+            if self.is_green(direction) {
+                self.red_for[direction.index()] = 0.0;
+            } else {
+                self.red_for[direction.index()] += dt;
+            }
+        }
+
+        if self.stage == Stage::Phase && !self.interrupted() {
+            let state = IntersectionState {
+                queues,
+                phase: self.phase,
+                phases: &self.plan.phases,
+                phase_age: self.phase_age,
+                red_for: self.red_for,
+                max_wait: self.timing.max_wait,
+            };
+            let command = self.controller.decide(&state, dt);
+            self.command(command);
+        }
+    }
+
+    /// Whether any route from the approach has green. Yellow counts as not
+    /// green: vehicles that haven't reached the stop line stop for it.
+    pub fn is_green(&self, direction: Direction) -> bool {
+        Route::ALL
+            .into_iter()
+            .any(|route| self.is_green_for(direction, route))
+    }
+
+    /// Whether vehicles from `direction` taking `route` have green, which
+    /// in a phase limited to one route is narrower than `is_green`.
+    pub fn is_green_for(&self, direction: Direction, route: Route) -> bool {
+        match self.stage {
+            Stage::Phase => self.current().allows(direction, route),
+            Stage::Yellow => {
+                self.current().allows(direction, route) && self.next().allows(direction, route)
+            }
+            Stage::AllRed => false,
+        }
+    }
+
+    /// Whether some route from the approach is about to lose its green.
+    pub fn is_yellow(&self, direction: Direction) -> bool {
+        Route::ALL
+            .into_iter()
+            .any(|route| self.is_yellow_for(direction, route))
+    }
+
+    /// Whether vehicles from `direction` taking `route` are about to lose
+    /// their green.
+    pub fn is_yellow_for(&self, direction: Direction, route: Route) -> bool {
+        self.stage == Stage::Yellow
+            && self.current().allows(direction, route)
+            && !self.next().allows(direction, route)
+    }
+
+    /// The one route the approach's green or yellow is limited to, which
+    /// its signal head shows as an arrow; `None` when every route goes or
+    /// the approach has red.
+    pub fn arrow(&self, direction: Direction) -> Option<Route> {
+        let phase = self.current();
+        let index = direction.index();
+        if self.stage == Stage::AllRed || !phase.green[index] {
+            return None;
+        }
+        phase.only[index]
+    }
+
+    pub fn phase(&self) -> usize {
+        self.phase
+    }
+
+    pub fn stage(&self) -> Stage {
+        self.stage
+    }
+
+    /// Seconds spent in the current phase or interval.
+    pub fn elapsed(&self) -> f32 {
+        self.elapsed
+    }
+
+    /// Seconds since the approach last had a green light.
+    pub fn red_for(&self, direction: Direction) -> f32 {
+        self.red_for[direction.index()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn degenerate_phases_are_clamped_to_the_minimum() {
+        let plan = LightPlan {
+            phases: vec![
+                Phase::new([true, true, false, false], 0.0),
+                Phase::new([false, false, true, true], -3.0),
+            ],
+        };
+        assert!(plan.validate().is_err());
+
+        let mut lights = TrafficLights::new(
+            plan,
+            SignalTiming {
+                min_phase: 0.5,
+                min_green: 0.0,
+                yellow: 0.0,
+                all_red: 0.0,
+                max_wait: f32::INFINITY,
+            },
+            Box::new(FixedCycle),
+        );
+        let mut changes = 0;
+        for _ in 0..600 {
+            let phase = lights.phase();
+            lights.update(1.0 / 60.0, [0; 4]);
+            if lights.phase() != phase {
+                changes += 1;
+            }
+        }
+        // Ten seconds of 0.5s phases.
+        assert!((19..=20).contains(&changes));
+
+        // A huge step still advances a single phase.
+        let phase = lights.phase();
+        lights.update(1000.0, [0; 4]);
+        assert_eq!(lights.phase(), (phase + 1) % 2);
+    }
+
+    #[test]
+    fn changes_pass_through_yellow_and_all_red() {
+        let plan = LightPlan::parse("up+down:1,right+left:1").unwrap();
+        let timing = SignalTiming {
+            min_phase: 0.1,
+            min_green: 2.0,
+            yellow: 0.5,
+            all_red: 0.5,
+            max_wait: f32::INFINITY,
+        };
+        let mut lights = TrafficLights::new(plan, timing, Box::new(FixedCycle));
+        let mut stages = Vec::new();
+        let mut first_yellow = None;
+        for tick in 0..240 {
+            lights.update(1.0 / 60.0, [0; 4]);
+            let stage = (lights.phase(), lights.stage());
+            if stages.last() != Some(&stage) {
+                stages.push(stage);
+            }
+            if lights.stage() == Stage::Yellow {
+                first_yellow.get_or_insert(tick);
+                assert!(lights.is_yellow(Direction::Up));
+                assert!(!lights.is_green(Direction::Up));
+            }
+            if lights.stage() == Stage::AllRed {
+                assert!(Direction::ALL.iter().all(|&d| !lights.is_green(d)));
+            }
+        }
+        assert_eq!(
+            stages,
+            [
+                (0, Stage::Phase),
+                (0, Stage::Yellow),
+                (0, Stage::AllRed),
+                (1, Stage::Phase),
+            ]
+        );
+        // The one-second phase was stretched to the two-second minimum green.
+        assert!(first_yellow.unwrap() >= 119);
+    }
+
+    #[test]
+    fn max_pressure_serves_the_longest_queue() {
+        let plan = LightPlan::default();
+        let timing = SignalTiming {
+            min_phase: 0.5,
+            min_green: 0.0,
+            yellow: 0.0,
+            all_red: 0.0,
+            max_wait: f32::INFINITY,
+        };
+        let mut lights = TrafficLights::new(plan, timing, ControllerKind::MaxPressure.build());
+        let queues = [4, 0, 0, 1];
+
+        // Phase 0 greens up and down, which carry the longest queue.
+        for _ in 0..60 * 20 {
+            lights.update(1.0 / 60.0, queues);
+            assert_eq!(lights.phase(), 0);
+        }
+
+        // Once the cross street is busier it gets green straight away,
+        // skipping the plan's all-red phase.
+        for _ in 0..60 * 5 {
+            lights.update(1.0 / 60.0, [0, 0, 3, 0]);
+        }
+        assert_eq!(lights.phase(), 2);
+        assert!(lights.is_green(Direction::Right));
+    }
+
+    #[test]
+    fn max_pressure_still_serves_a_quiet_approach_within_the_wait() {
+        let plan = LightPlan::parse("up+down:1,right+left:1").unwrap();
+        let timing = SignalTiming {
+            min_phase: 0.5,
+            min_green: 0.0,
+            yellow: 0.0,
+            all_red: 0.0,
+            max_wait: 10.0,
+        };
+        let mut lights = TrafficLights::new(plan, timing, ControllerKind::MaxPressure.build());
+        let mut waited = 0.0;
+        for tick in 0..60 * 60 {
+            let was_red = !lights.is_green(Direction::Left);
+            lights.update(1.0 / 60.0, [4, 4, 0, 1]);
+            if lights.is_green(Direction::Left) {
+                if was_red {
+                    // The wait ends at the first phase boundary past it.
+                    assert!((10.0..=11.0 + 1.0 / 60.0).contains(&waited), "{}", waited);
+                }
+                waited = 0.0;
+            } else {
+                waited += 1.0 / 60.0;
+            }
+            assert!(waited < 11.5, "left red for {}s at tick {}", waited, tick);
+        }
+        assert!(lights.phase() == 0 || lights.phase() == 1);
+    }
+
+    #[test]
+    fn queue_adaptive_gaps_out_an_empty_green_and_holds_a_busy_one() {
+        let plan = LightPlan::parse("up+down:5,none:1,right+left:5,none:1").unwrap();
+        let timing = SignalTiming {
+            min_phase: 0.5,
+            min_green: 2.0,
+            yellow: 0.0,
+            all_red: 0.0,
+            max_wait: f32::INFINITY,
+        };
+        let mut lights = TrafficLights::new(plan, timing, ControllerKind::QueueAdaptive.build());
+
+        // Nobody for up and down, so their green ends at the minimum and
+        // the clearance phase runs its full second.
+        let mut changes = Vec::new();
+        for tick in 0..60 * 4 {
+            let phase = lights.phase();
+            lights.update(1.0 / 60.0, [0, 0, 2, 0]);
+            if lights.phase() != phase {
+                changes.push((tick, lights.phase()));
+            }
+        }
+        assert_eq!(changes, [(120, 1), (181, 2)]);
+
+        // The cross street keeps its green while it is the only one queued,
+        // well past the phase's five seconds.
+        for _ in 0..60 * 20 {
+            lights.update(1.0 / 60.0, [0, 0, 2, 0]);
+            assert_eq!(lights.phase(), 2);
+        }
+        // Then gives way once it is empty and up has someone.
+        for _ in 0..60 {
+            lights.update(1.0 / 60.0, [1, 0, 0, 0]);
+        }
+        assert_eq!(lights.phase(), 3);
+    }
+
+    #[test]
+    fn green_wave_offsets_carry_a_platoon_through_every_light() {
+        let timing = SignalTiming {
+            min_phase: 0.5,
+            min_green: 0.0,
+            yellow: 1.0,
+            all_red: 0.5,
+            max_wait: f32::INFINITY,
+        };
+        let new_lights = || TrafficLights::new(LightPlan::default(), timing, Box::new(FixedCycle));
+        // Each five-second green is followed by a second of yellow, then the
+        // plan's one-second all-red phase.
+        assert_eq!(new_lights().cycle_length(), 14.0);
+
+        let (speed, spacing) = (120.0, 660.0);
+        let mut corridor: Vec<TrafficLights> = (0..4)
+            .map(|position| {
+                let mut lights = new_lights();
+                let offset = lights.green_wave_offset(position as f32 * spacing, speed);
+                lights.skip(offset);
+                lights
+            })
+            .collect();
+
+        // The platoon leaves the first light one second into its green and
+        // reaches each later one `spacing / speed` seconds after the last.
+        let dt = 1.0 / 60.0;
+        let travel = (spacing / speed / dt).round() as usize;
+        for tick in 0..60 + travel * 3 {
+            for lights in &mut corridor {
+                lights.update(dt, [0; 4]);
+            }
+            if tick >= 60 && (tick - 60) % travel == 0 {
+                let position = (tick - 60) / travel;
+                assert!(
+                    corridor[position].is_green(Direction::Up),
+                    "light {}",
+                    position
+                );
+            }
+        }
+
+        // Unshifted lights would have stopped it at the second light.
+        let mut unshifted = new_lights();
+        for _ in 0..60 + travel {
+            unshifted.update(dt, [0; 4]);
+        }
+        assert!(!unshifted.is_green(Direction::Up));
+    }
+
+    #[test]
+    fn retiming_waits_for_the_next_boundary_and_stays_in_bounds() {
+        let plan = LightPlan::parse("up+down:2,right+left:2").unwrap();
+        let timing = SignalTiming {
+            min_phase: 0.5,
+            min_green: 1.0,
+            yellow: 0.0,
+            all_red: 0.0,
+            max_wait: f32::INFINITY,
+        };
+        let mut lights = TrafficLights::new(plan, timing, Box::new(FixedCycle));
+        let dt = 0.25;
+        for _ in 0..4 {
+            lights.update(dt, [0; 4]);
+        }
+        lights.retime(1.0, 0.0, 10.0, 5.0);
+        assert_eq!(lights.upcoming_plan().to_string(), "up+down:3,right+left:3");
+
+        // The running phase still ends on its old schedule.
+        let mut ticks = 0;
+        while lights.phase() == 0 {
+            lights.update(dt, [0; 4]);
+            ticks += 1;
+        }
+        assert_eq!(ticks, 4);
+        while lights.phase() == 1 {
+            lights.update(dt, [0; 4]);
+            ticks += 1;
+        }
+        assert_eq!(ticks, 4 + 12);
+
+        lights.retime(-100.0, -1.0, 10.0, 5.0);
+        assert_eq!(lights.upcoming_plan().to_string(), "up+down:1,right+left:1");
+        assert_eq!(lights.upcoming_yellow(), 0.0);
+        lights.retime(100.0, 100.0, 10.0, 5.0);
+        assert_eq!(
+            lights.upcoming_plan().to_string(),
+            "up+down:10,right+left:10"
+        );
+        assert_eq!(lights.upcoming_yellow(), 5.0);
+    }
+
+    #[test]
+    fn an_external_controller_switches_only_on_command() {
+        let plan = LightPlan::parse("up+down:1,right+left:1").unwrap();
+        let timing = SignalTiming {
+            min_phase: 0.5,
+            min_green: 1.0,
+            yellow: 0.5,
+            all_red: 0.0,
+            max_wait: f32::INFINITY,
+        };
+        let mut lights = TrafficLights::new(plan, timing, ControllerKind::External.build());
+        for _ in 0..40 {
+            lights.update(0.25, [0; 4]);
+        }
+        assert_eq!((lights.phase(), lights.stage()), (0, Stage::Phase));
+
+        assert!(!lights.command(PhaseCommand::Hold));
+        assert!(!lights.command(PhaseCommand::Switch(0)));
+        assert!(!lights.command(PhaseCommand::Switch(7)));
+        assert!(lights.command(PhaseCommand::Switch(1)));
+        assert_eq!(lights.stage(), Stage::Yellow);
+        assert!(!lights.command(PhaseCommand::Switch(1)));
+        lights.update(0.5, [0; 4]);
+        assert_eq!((lights.phase(), lights.stage()), (1, Stage::Phase));
+
+        // Not before the new phase's minimum green.
+        lights.update(0.5, [0; 4]);
+        assert!(!lights.command(PhaseCommand::Switch(0)));
+        lights.update(0.5, [0; 4]);
+        assert!(lights.command(PhaseCommand::Switch(0)));
+    }
+
+    #[test]
+    fn a_protected_left_phase_greens_only_the_turn() {
+        let plan = LightPlan::parse("up.left:2,up+down:2").unwrap();
+        assert_eq!(plan.to_string(), "up.left:2,up+down:2");
+        assert!(LightPlan::parse("up.u-turn:2").is_err());
+
+        let timing = SignalTiming {
+            min_phase: 0.5,
+            min_green: 0.0,
+            yellow: 0.5,
+            all_red: 0.0,
+            max_wait: f32::INFINITY,
+        };
+        let mut lights = TrafficLights::new(plan, timing, Box::new(FixedCycle));
+        assert!(lights.is_green(Direction::Up));
+        assert!(lights.is_green_for(Direction::Up, Route::TurnLeft));
+        assert!(lights.is_green_for(Direction::Up, Route::UTurn));
+        assert!(!lights.is_green_for(Direction::Up, Route::Straight));
+        assert!(!lights.is_green(Direction::Down));
+
+        // Nothing loses green on the way to the full phase, so there is no
+        // yellow; the way back takes the straights and the other approach.
+        lights.update(2.0, [0; 4]);
+        assert_eq!((lights.phase(), lights.stage()), (1, Stage::Phase));
+        assert!(lights.is_green_for(Direction::Up, Route::Straight));
+        lights.update(2.0, [0; 4]);
+        assert_eq!(lights.stage(), Stage::Yellow);
+        assert!(lights.is_green_for(Direction::Up, Route::TurnLeft));
+        assert!(lights.is_yellow(Direction::Up));
+        assert!(lights.is_yellow(Direction::Down));
+    }
+
+    #[test]
+    fn the_protected_lefts_plan_shows_each_pair_an_arrow_first() {
+        let plan = LightPlan::protected_lefts();
+        assert_eq!(
+            plan.to_string(),
+            "up.left+down.left:3,up+down:5,none:1,right.left+left.left:3,right+left:5,none:1"
+        );
+        assert_eq!(
+            LightPlan::parse(&plan.to_string()).unwrap().to_string(),
+            plan.to_string()
+        );
+
+        let timing = SignalTiming {
+            min_phase: 0.5,
+            min_green: 0.0,
+            yellow: 0.5,
+            all_red: 0.5,
+            max_wait: f32::INFINITY,
+        };
+        let mut lights = TrafficLights::new(plan, timing, Box::new(FixedCycle));
+        assert_eq!(lights.arrow(Direction::Up), Some(Route::TurnLeft));
+        assert_eq!(lights.arrow(Direction::Right), None);
+        lights.update(3.0, [0; 4]);
+        assert_eq!(lights.phase(), 1);
+        assert_eq!(lights.arrow(Direction::Up), None);
+        assert!(lights.is_green_for(Direction::Up, Route::Straight));
+    }
+
+    #[test]
+    fn the_default_plan_clears_between_crossing_flows() {
+        let mut lights = TrafficLights::new(
+            LightPlan::default(),
+            SignalTiming {
+                min_phase: 0.5,
+                min_green: 0.0,
+                yellow: 0.0,
+                all_red: 0.0,
+                max_wait: f32::INFINITY,
+            },
+            Box::new(FixedCycle),
+        );
+        let green = |lights: &TrafficLights, approaches: [Direction; 2]| {
+            approaches.iter().any(|&approach| lights.is_green(approach))
+        };
+        let mut last_axis = None;
+        let mut cleared = false;
+        let mut changes = 0;
+        for _ in 0..60 * 30 {
+            lights.update(1.0 / 60.0, [0; 4]);
+            let vertical = green(&lights, [Direction::Up, Direction::Down]);
+            let horizontal = green(&lights, [Direction::Right, Direction::Left]);
+            assert!(!(vertical && horizontal));
+            if !vertical && !horizontal {
+                cleared = true;
+                continue;
+            }
+            if last_axis.is_some_and(|axis| axis != vertical) {
+                assert!(cleared, "the axes swapped without a clearance");
+                changes += 1;
+            }
+            last_axis = Some(vertical);
+            cleared = false;
+        }
+        // The 12 second cycle swaps axes twice, so about five times in 30s.
+        assert!(changes >= 4, "{}", changes);
+    }
+
+    #[test]
+    fn preemption_holds_one_approach_green_until_it_ends() {
+        let mut lights = TrafficLights::new(
+            LightPlan::default(),
+            SignalTiming {
+                min_phase: 0.5,
+                min_green: 0.0,
+                yellow: 1.0,
+                all_red: 0.5,
+                max_wait: f32::INFINITY,
+            },
+            Box::new(FixedCycle),
+        );
+        assert!(lights.is_green(Direction::Up));
+        assert!(lights.preempt(Direction::Left));
+        assert!(
+            Direction::ALL
+                .iter()
+                .all(|&approach| !lights.is_green(approach))
+        );
+        lights.update(1.5, [0; 4]);
+        for _ in 0..600 {
+            assert!(lights.is_green(Direction::Left));
+            assert!(!lights.is_green(Direction::Up) && !lights.is_green(Direction::Right));
+            lights.update(0.1, [0; 4]);
+        }
+        assert!(!lights.command(PhaseCommand::Switch(1)));
+
+        lights.end_preemption();
+        assert_eq!(lights.preempted(), None);
+        assert!(!lights.is_green(Direction::Left));
+        lights.update(1.5, [0; 4]);
+        assert_eq!((lights.phase(), lights.stage()), (0, Stage::Phase));
+        assert!(lights.is_green(Direction::Up));
+        assert!(!lights.in_recovery());
+    }
+}
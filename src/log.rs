@@ -0,0 +1,140 @@
+use std::env;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// How serious a diagnostic is, from most to least.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.to_ascii_lowercase().as_str() {
+            "error" => Ok(Level::Error),
+            "warn" => Ok(Level::Warn),
+            "info" => Ok(Level::Info),
+            "debug" => Ok(Level::Debug),
+            "trace" => Ok(Level::Trace),
+            _ => Err(format!("Unknown log level: {}", value)),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        }
+    }
+}
+
+static MAX_LEVEL: OnceLock<Option<Level>> = OnceLock::new();
+
+/// The level `RUST_LOG` asks for; warnings and errors when it is unset.
+fn max_level() -> Option<Level> {
+    *MAX_LEVEL.get_or_init(|| match env::var("RUST_LOG") {
+        Ok(filter) => filter_level(&filter),
+        Err(_) => Some(Level::Warn),
+    })
+}
+
+/// The level a `RUST_LOG` filter such as `debug` or
+/// `road_intersection=trace,info` sets for this crate, or `None` for `off`.
+/// A `target=level` directive for this crate beats a bare level; those for
+/// other targets, or for single modules, are ignored, as everything here
+/// logs under one level.
+fn filter_level(filter: &str) -> Option<Level> {
+    let level = |value: &str| match value.trim() {
+        value if value.eq_ignore_ascii_case("off") => Ok(None),
+        value => Level::parse(value).map(Some),
+    };
+    let (mut bare, mut targeted) = (None, None);
+    for directive in filter.split(',') {
+        match directive.split_once('=') {
+            Some((target, value)) if target.trim() == env!("CARGO_CRATE_NAME") => {
+                targeted = level(value).ok().or(targeted);
+            }
+            Some(_) => {}
+            None => bare = level(directive).ok().or(bare),
+        }
+    }
+    targeted.or(bare).unwrap_or(Some(Level::Warn))
+}
+
+pub fn enabled(level: Level) -> bool {
+    max_level().is_some_and(|max| level <= max)
+}
+
+pub fn write(level: Level, target: &str, message: fmt::Arguments) {
+    eprintln!("[{} {}] {}", level.name(), target, message);
+}
+
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($arg:tt)+) => {
+        if $crate::log::enabled($level) {
+            $crate::log::write($level, module_path!(), format_args!($($arg)+));
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)+) => { $crate::log!($crate::log::Level::Error, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)+) => { $crate::log!($crate::log::Level::Warn, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)+) => { $crate::log!($crate::log::Level::Info, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)+) => { $crate::log!($crate::log::Level::Debug, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)+) => { $crate::log!($crate::log::Level::Trace, $($arg)+) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_run_from_most_to_least_serious() {
+        assert!(Level::Error < Level::Warn && Level::Debug < Level::Trace);
+        assert_eq!(Level::parse("DEBUG"), Ok(Level::Debug));
+        assert!(Level::parse("verbose").is_err());
+    }
+
+    #[test]
+    fn only_this_crates_directives_set_the_level() {
+        assert_eq!(filter_level("debug"), Some(Level::Debug));
+        assert_eq!(
+            filter_level("road_intersection=trace,info"),
+            Some(Level::Trace)
+        );
+        assert_eq!(
+            filter_level("info,road_intersection=error"),
+            Some(Level::Error)
+        );
+        assert_eq!(filter_level("sdl2=trace"), Some(Level::Warn));
+        assert_eq!(filter_level("off"), None);
+        assert_eq!(filter_level("trace,road_intersection=off"), None);
+        assert_eq!(filter_level("loud"), Some(Level::Warn));
+    }
+}
@@ -1,297 +1,969 @@
-use rand::Rng;
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
+use road_intersection::audio::{Audio, Soundscape};
+use road_intersection::compare::Comparison;
+use road_intersection::config::{Config, WindowMode};
+use road_intersection::conflicts::ConflictMatrix;
+use road_intersection::direction::{Direction, Route};
+use road_intersection::geometry::{Camera, Viewport, World};
+use road_intersection::lights::ControllerKind;
+use road_intersection::network::Network;
+use road_intersection::panel;
+use road_intersection::remote::{Pacing, Remote};
+use road_intersection::replay::Recording;
+use road_intersection::simulation::{DebugOverlay, SimEvent, TrafficSimulation};
+use road_intersection::snapshot::Snapshot;
+use road_intersection::sprites::{SpriteCanvas, SpriteSheet};
+use road_intersection::stats::{self, Stats};
+use road_intersection::{error, info, warn};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::mouse::{MouseButton, MouseWheelDirection};
 use sdl2::rect::Rect;
-use sdl2::render::Canvas;
-use sdl2::video::Window;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
-
-const WINDOW_WIDTH: u32 = 1000;
-const WINDOW_HEIGHT: u32 = 800;
-const VEHICLE_SIZE: u32 = 40;
-const VEHICLE_SPEED: f32 = 2.0;
-const ROAD_WIDTH: u32 = 120;
-const LANE_WIDTH: u32 = 30;
-const LIGHT_SIZE: u32 = 30;
-const CENTER_X: i32 = (WINDOW_WIDTH / 2) as i32;
-const CENTER_Y: i32 = (WINDOW_HEIGHT / 2) as i32;
-
-#[derive(Clone)]
-struct Vehicle {
-    x: f32,
-    y: f32,
-    direction: String,          
-    initial_direction: String,  
-    route: String,            
-    color: Color,
+use sdl2::render::{BlendMode, Canvas};
+use sdl2::video::{FullscreenType, Window};
+use sdl2::{EventPump, Sdl, VideoSubsystem};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const MAX_TICKS_PER_FRAME: u32 = 5;
+// Ticks asked for by stepping that one frame runs; the rest wait for the
+// frames after, so a remote `step` of millions keeps the window responsive.
+const MAX_STEPS_PER_FRAME: u64 = 100;
+
+// Events handled per frame. Anything beyond stays queued in SDL for the next
+// frame, so a flood of input (held keys, injected events) delays handling by
+// a few frames instead of stalling simulation and rendering. 64 per frame is
+// far more than a person can produce.
+const MAX_EVENTS_PER_FRAME: usize = 64;
+const SAVED_CONFIG_PATH: &str = "config.toml";
+// Where F5 and F9 save and restore without a `load` file.
+const SNAPSHOT_PATH: &str = "snapshot.txt";
+// Seconds each press of the retiming keys adds or takes away.
+const GREEN_STEP: f32 = 1.0;
+const YELLOW_STEP: f32 = 0.5;
+// The range the speed keys move the time scale through, halving or
+// doubling it each press.
+const MIN_TIME_SCALE: f32 = 0.25;
+const MAX_TIME_SCALE: f32 = 8.0;
+// Share of each new frame's rate folded into the HUD's frames per second.
+const FPS_SMOOTHING: f32 = 0.05;
+// How much each notch of the mouse wheel zooms, and how many window
+// pixels each press of Ctrl+W/A/S/D pans.
+const ZOOM_STEP: f32 = 1.25;
+const PAN_STEP: f32 = 40.0;
+
+fn now_in_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System time before UNIX EPOCH!")
+        .as_millis()
 }
 
-impl Vehicle {
-    fn new(direction: &str) -> Self {
-        // let (x, y) = match direction {
-        //     "up" => (CENTER_X as f32 - LANE_WIDTH as f32 / 2.0, WINDOW_HEIGHT as f32),
-        //     "down" => (CENTER_X as f32 + LANE_WIDTH as f32 / 2.0, 0.0),
-        //     "right" => (0.0, CENTER_Y as f32 + LANE_WIDTH as f32 / 2.0),
-        //     "left" => (WINDOW_WIDTH as f32, CENTER_Y as f32 - LANE_WIDTH as f32 / 2.0),
-        //     _ => (0.0, 0.0),
-        // };
-
-        let (x, y) = match direction {
-            "up" => (CENTER_X as f32 - LANE_WIDTH as f32, WINDOW_HEIGHT as f32),
-            "down" => (CENTER_X as f32 + LANE_WIDTH as f32, 0.0),
-            "right" => (0.0, CENTER_Y as f32 + LANE_WIDTH as f32),
-            "left" => (WINDOW_WIDTH as f32, CENTER_Y as f32 - LANE_WIDTH as f32),
-            _ => (0.0, 0.0),
-        };
+/// Multiplies the time scale by `factor` within the speed keys' range and
+/// returns the new wall-clock length of a tick.
+fn change_time_scale(simulation: &mut TrafficSimulation, factor: f32) -> Duration {
+    let scale = &mut simulation.config.time_scale;
+    *scale = (*scale * factor).clamp(MIN_TIME_SCALE, MAX_TIME_SCALE);
+    info!("Time scale: {}x", scale);
+    simulation.config.tick_interval()
+}
 
-        let routes = ["Straight", "TurnLeft", "TurnRight"];
-        let route_str = routes[rand::rng().random_range(0..routes.len())];
+/// The route an arrow key spawns with the modifiers held: Shift for
+/// straight, Ctrl for a left turn, Alt for a right turn, none for random.
+fn held_route(keymod: Mod) -> Option<Route> {
+    if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+        Some(Route::Straight)
+    } else if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) {
+        Some(Route::TurnLeft)
+    } else if keymod.intersects(Mod::LALTMOD | Mod::RALTMOD) {
+        Some(Route::TurnRight)
+    } else {
+        None
+    }
+}
 
-        let color = match route_str {
-            "Straight" => Color::RGB(200, 200, 200),
-            "TurnLeft" => Color::RGB(100, 255, 100),
-            "TurnRight" => Color::RGB(100, 100, 255),
-            _ => Color::RGB(255, 255, 255), 
-        };
+/// Whether `keycode` turns something on or off, so holding it down must not
+/// flip it back and forth with every repeat.
+fn toggles(keycode: Keycode) -> bool {
+    matches!(
+        keycode,
+        Keycode::D
+            | Keycode::G
+            | Keycode::F
+            | Keycode::M
+            | Keycode::H
+            | Keycode::C
+            | Keycode::P
+            | Keycode::Q
+            | Keycode::A
+            | Keycode::T
+            | Keycode::V
+            | Keycode::W
+            | Keycode::L
+            | Keycode::Space
+    )
+}
 
-        Self {
+/// Pans and zooms the camera: the wheel zooms about the mouse, dragging
+/// with the middle button or Ctrl+W/A/S/D pans, Home goes back to the
+/// intersection. Panning stops following a vehicle. `grab` is where a
+/// middle-button drag last was. Returns whether the event was used up;
+/// mouse motion never is, so the hover still sees it.
+fn steer_camera(
+    debug: &mut DebugOverlay,
+    viewport: Viewport,
+    event: &Event,
+    grab: &mut Option<(f32, f32)>,
+) -> bool {
+    let camera = &mut debug.camera;
+    match *event {
+        Event::MouseWheel {
+            y,
+            direction,
+            mouse_x,
+            mouse_y,
+            ..
+        } => {
+            let notches = if direction == MouseWheelDirection::Flipped {
+                -y
+            } else {
+                y
+            };
+            let cursor = viewport.to_logical(mouse_x, mouse_y);
+            camera.zoom_about(ZOOM_STEP.powi(notches), cursor);
+            true
+        }
+        Event::MouseButtonDown {
+            mouse_btn: MouseButton::Middle,
             x,
             y,
-            direction: direction.to_string(),
-            initial_direction: direction.to_string(),
-            route: route_str.to_string(),
-            color,
+            ..
+        } => {
+            *grab = Some(viewport.to_logical(x, y));
+            true
         }
-    }
-
-    fn update(&mut self) {
-        let y_to_up = CENTER_Y as f32 + LANE_WIDTH as f32 / 2.0;
-        let y_to_down = CENTER_Y as f32 - LANE_WIDTH as f32 / 2.0;
-        let x_to_right = CENTER_X as f32 - LANE_WIDTH as f32 / 2.0;
-        let x_to_left = CENTER_X as f32 + LANE_WIDTH as f32 / 2.0;
-
-        match self.initial_direction.as_str() {
-            "up" => {
-                if self.route == "TurnLeft" && self.y <= y_to_up {
-                    self.direction = "right".to_string();
-                } else if self.route == "TurnRight" && self.y <= y_to_down {
-                    self.direction = "left".to_string();
-                }
-            },
-            "down" => {
-                if self.route == "TurnLeft" && self.y >= y_to_down {
-                    self.direction = "left".to_string();
-                } else if self.route == "TurnRight" && self.y >= y_to_up {
-                    self.direction = "right".to_string();
-                }
-            },
-            "right" => {
-                if self.route == "TurnLeft" && self.x >= x_to_right {
-                    self.direction = "up".to_string();
-                } else if self.route == "TurnRight" && self.x >= x_to_left {
-                    self.direction = "down".to_string();
-                }
-            },
-            "left" => {
-                if self.route == "TurnLeft" && self.x <= x_to_left {
-                    self.direction = "down".to_string();
-                } else if self.route == "TurnRight" && self.x <= x_to_right {
-                    self.direction = "up".to_string();
-                }
-            },
-            _ => {}
+        Event::MouseButtonUp {
+            mouse_btn: MouseButton::Middle,
+            ..
+        } => {
+            *grab = None;
+            true
         }
-
-        match self.direction.as_str() {
-            "up" => self.y -= VEHICLE_SPEED,
-            "down" => self.y += VEHICLE_SPEED,
-            "right" => self.x += VEHICLE_SPEED,
-            "left" => self.x -= VEHICLE_SPEED,
-            _ => {}
+        Event::MouseMotion { x, y, .. } => {
+            if let Some((from_x, from_y)) = *grab {
+                let (x, y) = viewport.to_logical(x, y);
+                camera.pan(x - from_x, y - from_y);
+                *grab = Some((x, y));
+                debug.follow = false;
+            }
+            false
+        }
+        Event::KeyDown {
+            keycode: Some(Keycode::Home),
+            ..
+        } => {
+            *camera = Camera::new(camera.world);
+            debug.follow = false;
+            true
         }
+        Event::KeyDown {
+            keycode: Some(keycode),
+            keymod,
+            ..
+        } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+            // The view moves the way the key points, so the world the
+            // other way.
+            let (dx, dy) = match keycode {
+                Keycode::W => (0.0, PAN_STEP),
+                Keycode::A => (PAN_STEP, 0.0),
+                Keycode::S => (0.0, -PAN_STEP),
+                Keycode::D => (-PAN_STEP, 0.0),
+                _ => return false,
+            };
+            camera.pan(dx, dy);
+            debug.follow = false;
+            true
+        }
+        _ => false,
     }
+}
 
-    fn is_off_screen(&self) -> bool {
-        self.x < -50.0
-            || self.x > WINDOW_WIDTH as f32 + 50.0
-            || self.y < -50.0
-            || self.y > WINDOW_HEIGHT as f32 + 50.0
+/// A fresh run of `config`, taking its spawns from `replay` if a recording
+/// was loaded and starting from `snapshot` if one was.
+fn start(
+    config: &Config,
+    replay: Option<&Recording>,
+    snapshot: Option<&Snapshot>,
+) -> Result<TrafficSimulation, String> {
+    let mut simulation = match replay {
+        Some(recording) => TrafficSimulation::replaying(config.clone(), recording.clone()),
+        None => TrafficSimulation::new(config.clone()),
+    };
+    if let Some(snapshot) = snapshot {
+        simulation.restore(snapshot)?;
     }
+    Ok(simulation)
+}
 
-    fn get_rect(&self) -> Rect {
-        Rect::new(
-            self.x as i32 - (VEHICLE_SIZE / 2) as i32,
-            self.y as i32 - (VEHICLE_SIZE / 2) as i32,
-            VEHICLE_SIZE,
-            VEHICLE_SIZE,
-        )
-    }
+/// The file F5 and F9 save to and restore from.
+fn snapshot_path(config: &Config) -> &Path {
+    Path::new(config.load.as_deref().unwrap_or(SNAPSHOT_PATH))
 }
 
-struct TrafficSimulation {
-    vehicles: Vec<Vehicle>,
+/// Writes the run's spawns to the file `record` names, if it names one.
+fn save_recording(simulation: &TrafficSimulation) -> Result<(), String> {
+    if let (Some(path), Some(recording)) = (&simulation.config.record, simulation.recording()) {
+        recording.save(Path::new(path))?;
+        println!("Recorded {} spawn(s) to {}", recording.spawns.len(), path);
+    }
+    Ok(())
 }
 
-fn now_in_millis() -> u128 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .expect("System time before UNIX EPOCH!")
-        .as_millis()
+// Fields drop in declaration order, so handles that depend on a subsystem
+// come before the subsystem itself.
+struct App {
+    canvas: Canvas<Window>,
+    viewport: Viewport,
+    /// What the window shows before `viewport` scales it.
+    world: World,
+    event_pump: EventPump,
+    _video_subsystem: VideoSubsystem,
+    sdl_context: Sdl,
 }
 
-impl TrafficSimulation {
-    fn new() -> Self {
-        Self {
-            vehicles: Vec::new(),
+impl App {
+    /// Initializes SDL step by step. If a step fails, everything created so
+    /// far is dropped in reverse order and the failing step is reported.
+    fn init(config: &Config) -> Result<Self, String> {
+        let sdl_context = sdl2::init().map_err(|e| format!("Could not initialize SDL: {}", e))?;
+        let video_subsystem = sdl_context
+            .video()
+            .map_err(|e| format!("Could not initialize video subsystem: {}", e))?;
+
+        let scale = config.scale;
+        let world = config.world();
+        let mut builder = video_subsystem.window(
+            "Road Intersection",
+            (world.width as f32 * scale) as u32,
+            (world.height as f32 * scale) as u32,
+        );
+        match config.window_position {
+            Some((x, y)) => builder.position(x, y),
+            None => builder.position_centered(),
+        };
+        match config.window_mode {
+            WindowMode::Windowed => {
+                builder.resizable();
+            }
+            WindowMode::Borderless => {
+                builder.borderless();
+            }
+            WindowMode::Fullscreen => {
+                builder.fullscreen_desktop();
+            }
         }
+        let window = builder
+            .build()
+            .map_err(|e| format!("Could not create window: {}", e))?;
+
+        let mut canvas = window
+            .into_canvas()
+            .present_vsync()
+            .build()
+            .map_err(|e| format!("Could not create canvas: {}", e))?;
+        let viewport = if config.window_mode == WindowMode::Fullscreen {
+            let (width, height) = canvas.output_size()?;
+            Viewport::fit(world, width, height)
+        } else {
+            Viewport::scaled(scale)
+        };
+        apply_viewport(&mut canvas, world, viewport)?;
+        canvas.set_blend_mode(BlendMode::Blend);
+        let event_pump = sdl_context
+            .event_pump()
+            .map_err(|e| format!("Could not create event pump: {}", e))?;
+
+        Ok(Self {
+            canvas,
+            viewport,
+            world,
+            event_pump,
+            _video_subsystem: video_subsystem,
+            sdl_context,
+        })
+    }
+
+    /// Refits the scene to the window as it is now, after a resize or a
+    /// switch in or out of fullscreen. Only the scale and the letterbox
+    /// change, so vehicles keep their places in the scene.
+    fn fit_to_window(&mut self) -> Result<(), String> {
+        let (width, height) = self.canvas.output_size()?;
+        self.viewport = Viewport::fit(self.world, width, height);
+        apply_viewport(&mut self.canvas, self.world, self.viewport)
     }
 
-    fn spawn_vehicle(&mut self, direction: &str) {
-        self.vehicles.push(Vehicle::new(direction));
+    /// Covers the desktop, or goes back to the window it came from.
+    fn toggle_fullscreen(&mut self) -> Result<(), String> {
+        let window = self.canvas.window_mut();
+        let next = match window.fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            _ => FullscreenType::Off,
+        };
+        window.set_fullscreen(next)?;
+        self.fit_to_window()
     }
 
-    fn update(&mut self) {
-        for vehicle in &mut self.vehicles {
-            vehicle.update();
+    /// Handles the window events every loop shares: resizes and F11.
+    fn handle_window_event(&mut self, event: &Event) -> Result<(), String> {
+        match event {
+            Event::Window {
+                win_event: WindowEvent::SizeChanged(..),
+                ..
+            } => self.fit_to_window(),
+            Event::KeyDown {
+                keycode: Some(Keycode::F11),
+                repeat: false,
+                ..
+            } => self.toggle_fullscreen(),
+            _ => Ok(()),
         }
-        self.vehicles.retain(|vehicle| !vehicle.is_off_screen());
     }
+}
+
+/// Drawing stays in simulation coordinates; SDL scales it to the window,
+/// inside the letterbox `viewport` leaves round `world`.
+fn apply_viewport(
+    canvas: &mut Canvas<Window>,
+    world: World,
+    viewport: Viewport,
+) -> Result<(), String> {
+    canvas
+        .set_scale(viewport.scale, viewport.scale)
+        .map_err(|e| format!("Could not set render scale: {}", e))?;
+    // The viewport rectangle is given before scaling.
+    canvas.set_viewport(Rect::new(
+        (viewport.offset_x as f32 / viewport.scale) as i32,
+        (viewport.offset_y as f32 / viewport.scale) as i32,
+        world.width,
+        world.height,
+    ));
+    Ok(())
+}
 
-    fn render(&self, canvas: &mut Canvas<Window>) -> Result<(), String> {
-        canvas.set_draw_color(Color::RGB(0, 0, 0));
-        canvas.clear();
-        self.draw_roads(canvas)?;
-        self.draw_traffic_lights(canvas)?;
+/// The split-screen loop for `--compare`: the same arrivals feed both runs,
+/// so only spawning, draining, the debug overlay and quitting are offered.
+fn compare(
+    app: &mut App,
+    mut sprites: Option<SpriteSheet>,
+    config: Config,
+    other: ControllerKind,
+) -> Result<(), String> {
+    let tick = config.tick_interval();
+    let mut comparison = Comparison::new(config, other);
+
+    let started = Instant::now();
+    let mut previous_frame = started;
+    let mut accumulator = Duration::ZERO;
+    let mut debug = DebugOverlay {
+        camera: Camera::new(app.world),
+        ..DebugOverlay::default()
+    };
 
-        for vehicle in &self.vehicles {
-            canvas.set_draw_color(vehicle.color);
-            canvas.fill_rect(vehicle.get_rect())?;
+    'running: loop {
+        for _ in 0..MAX_EVENTS_PER_FRAME {
+            let Some(event) = app.event_pump.poll_event() else {
+                break;
+            };
+            app.handle_window_event(&event)?;
+            match event {
+                Event::Quit { .. } => break 'running,
+                Event::MouseButtonDown {
+                    mouse_btn: MouseButton::Left,
+                    x,
+                    y,
+                    ..
+                } => {
+                    let (x, y) = app.viewport.to_logical(x, y);
+                    let (x, y) = comparison.to_world(x, y);
+                    if let Some(direction) = app.world.approach_at(x, y) {
+                        comparison.request_spawn(direction, None, now_in_millis());
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    keymod,
+                    repeat,
+                    ..
+                } if !(repeat && toggles(keycode)) => {
+                    let direction = match keycode {
+                        Keycode::Up => Direction::Up,
+                        Keycode::Down => Direction::Down,
+                        Keycode::Right => Direction::Right,
+                        Keycode::Left => Direction::Left,
+                        Keycode::Escape => break 'running,
+                        Keycode::R => {
+                            comparison.request_random_spawn(now_in_millis());
+                            continue;
+                        }
+                        Keycode::B => {
+                            comparison.queue_burst();
+                            continue;
+                        }
+                        Keycode::D => {
+                            debug.enabled = !debug.enabled;
+                            continue;
+                        }
+                        Keycode::N => {
+                            comparison.start_drain();
+                            continue;
+                        }
+                        Keycode::T => {
+                            comparison.toggle_auto_spawn();
+                            continue;
+                        }
+                        _ => continue,
+                    };
+                    comparison.request_spawn(direction, held_route(keymod), now_in_millis());
+                }
+                _ => {}
+            }
         }
 
-        canvas.present();
-        Ok(())
+        comparison.release_spawns(now_in_millis());
+        let now = Instant::now();
+        accumulator += now - previous_frame;
+        previous_frame = now;
+        accumulator = accumulator.min(tick * MAX_TICKS_PER_FRAME);
+        while accumulator >= tick {
+            comparison.update();
+            accumulator -= tick;
+        }
+
+        debug.wall_clock = started.elapsed();
+        let alpha = accumulator.as_secs_f32() / tick.as_secs_f32();
+        let mut canvas = SpriteCanvas::new(&mut app.canvas, sprites.as_mut());
+        comparison.render(&mut canvas, alpha, &debug)?;
     }
 
-    fn draw_roads(&self, canvas: &mut Canvas<Window>) -> Result<(), String> {
-        let line_color = Color::RGB(255, 255, 255);
+    comparison.print_summary();
+    Ok(())
+}
+
+/// The loop for `--grid`: arrivals come onto the map at its edges, from the
+/// keys or from `auto_spawn`; the debug overlay and quitting are offered.
+fn network(
+    app: &mut App,
+    mut sprites: Option<SpriteSheet>,
+    config: Config,
+    (columns, rows): (usize, usize),
+) -> Result<(), String> {
+    let tick = config.tick_interval();
+    let mut network = Network::new(config, columns, rows);
+
+    let started = Instant::now();
+    let mut previous_frame = started;
+    let mut accumulator = Duration::ZERO;
+    let mut debug = DebugOverlay {
+        camera: Camera::new(app.world),
+        ..DebugOverlay::default()
+    };
+    let mut grab = None;
 
-        canvas.set_draw_color(line_color);
-        for x in (0..WINDOW_WIDTH).step_by(30) {
-            canvas.fill_rect(Rect::new(x as i32, CENTER_Y - 2, 15, 4))?;
+    'running: loop {
+        for _ in 0..MAX_EVENTS_PER_FRAME {
+            let Some(event) = app.event_pump.poll_event() else {
+                break;
+            };
+            app.handle_window_event(&event)?;
+            if steer_camera(&mut debug, app.viewport, &event, &mut grab) {
+                continue;
+            }
+            match event {
+                Event::Quit { .. } => break 'running,
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    repeat,
+                    ..
+                } if !(repeat && toggles(keycode)) => match keycode {
+                    Keycode::Up => network.request_spawn(Direction::Up),
+                    Keycode::Down => network.request_spawn(Direction::Down),
+                    Keycode::Right => network.request_spawn(Direction::Right),
+                    Keycode::Left => network.request_spawn(Direction::Left),
+                    Keycode::Escape => break 'running,
+                    Keycode::R => network.request_random_spawn(),
+                    Keycode::B => network.queue_burst(),
+                    Keycode::D => debug.enabled = !debug.enabled,
+                    Keycode::T => network.toggle_auto_spawn(),
+                    _ => {}
+                },
+                _ => {}
+            }
         }
-        for y in (0..WINDOW_HEIGHT).step_by(30) {
-            canvas.fill_rect(Rect::new(CENTER_X - 2, y as i32, 4, 15))?;
+
+        let now = Instant::now();
+        accumulator += now - previous_frame;
+        previous_frame = now;
+        accumulator = accumulator.min(tick * MAX_TICKS_PER_FRAME);
+        while accumulator >= tick {
+            network.update();
+            accumulator -= tick;
         }
 
-        canvas.set_draw_color(line_color);
-        canvas.draw_line(
-            (0, CENTER_Y - ROAD_WIDTH as i32 / 2),
-            (WINDOW_WIDTH as i32, CENTER_Y - ROAD_WIDTH as i32 / 2),
-        )?;
-        canvas.draw_line(
-            (0, CENTER_Y + ROAD_WIDTH as i32 / 2),
-            (WINDOW_WIDTH as i32, CENTER_Y + ROAD_WIDTH as i32 / 2),
-        )?;
-        canvas.draw_line(
-            (CENTER_X - ROAD_WIDTH as i32 / 2, 0),
-            (CENTER_X - ROAD_WIDTH as i32 / 2, WINDOW_HEIGHT as i32),
-        )?;
-        canvas.draw_line(
-            (CENTER_X + ROAD_WIDTH as i32 / 2, 0),
-            (CENTER_X + ROAD_WIDTH as i32 / 2, WINDOW_HEIGHT as i32),
-        )?;
-
-        Ok(())
+        debug.wall_clock = started.elapsed();
+        let alpha = accumulator.as_secs_f32() / tick.as_secs_f32();
+        let mut canvas = SpriteCanvas::new(&mut app.canvas, sprites.as_mut());
+        network.render(&mut canvas, alpha, &debug)?;
     }
 
-    fn draw_traffic_lights(&self, canvas: &mut Canvas<Window>) -> Result<(), String> {
-        canvas.set_draw_color(Color::RGB(255, 0, 0));
-        let half_road = ROAD_WIDTH as i32 / 2;
-
-        let lights = [
-            Rect::new(
-                CENTER_X - half_road - LIGHT_SIZE as i32,
-                CENTER_Y - half_road - LIGHT_SIZE as i32,
-                LIGHT_SIZE,
-                LIGHT_SIZE,
-            ),
-            Rect::new(
-                CENTER_X + half_road,
-                CENTER_Y - half_road - LIGHT_SIZE as i32,
-                LIGHT_SIZE,
-                LIGHT_SIZE,
-            ),
-            Rect::new(
-                CENTER_X - half_road - LIGHT_SIZE as i32,
-                CENTER_Y + half_road,
-                LIGHT_SIZE,
-                LIGHT_SIZE,
-            ),
-            Rect::new(
-                CENTER_X + half_road,
-                CENTER_Y + half_road,
-                LIGHT_SIZE,
-                LIGHT_SIZE,
-            ),
-        ];
-
-        for light in &lights {
-            canvas.fill_rect(*light)?;
-        }
-        Ok(())
-    }
+    network.print_summary();
+    Ok(())
 }
 
 fn main() -> Result<(), String> {
-    let sdl_context = sdl2::init()?;
-    let video_subsystem = sdl_context.video()?;
-
-    let window = video_subsystem
-        .window("hhhh", WINDOW_WIDTH, WINDOW_HEIGHT)
-        .position_centered()
-        .build()
-        .expect("Could not create window");
-
-    let mut canvas = window.into_canvas().build().expect("Could not create canvas");
-    let mut event_pump = sdl_context.event_pump()?;
-    let mut simulation = TrafficSimulation::new();
+    let config =
+        Config::from_args_or_file(std::env::args().skip(1), Some(Path::new(SAVED_CONFIG_PATH)))?;
+    if config.print_conflicts {
+        let table = ConflictMatrix::compute().table(|movement| config.permits(movement));
+        print!("{}", table);
+        return Ok(());
+    }
+    let replay = match &config.replay {
+        Some(path) => Some(Recording::load(Path::new(path))?),
+        None => None,
+    };
+    let loaded = match &config.load {
+        Some(path) => Some(Snapshot::load(Path::new(path))?),
+        None => None,
+    };
+
+    if let Some(seconds) = config.soak_seconds() {
+        let config = Config {
+            seed: config.seed.or(Some(0)),
+            ..config
+        };
+        if let Some((columns, rows)) = config.grid {
+            let mut network = Network::new(config, columns, rows);
+            let failures = network.soak(seconds);
+            network.print_summary();
+            for failure in &failures {
+                error!("{}", failure);
+            }
+            if !failures.is_empty() {
+                return Err(format!(
+                    "Soak test failed with {} problem(s)",
+                    failures.len()
+                ));
+            }
+            return Ok(());
+        }
+        let mut simulation = start(&config, replay.as_ref(), loaded.as_ref())?;
+        let failures = simulation.soak(seconds);
+        simulation.print_summary();
+        save_recording(&simulation)?;
+        simulation.write_metrics()?;
+        for failure in &failures {
+            error!("{}", failure);
+        }
+        if !failures.is_empty() {
+            return Err(format!(
+                "Soak test failed with {} problem(s)",
+                failures.len()
+            ));
+        }
+        return Ok(());
+    }
 
-    let delay: u128 = 1000;
-    let mut last_spawn_time: [u128; 4] = [0, 0, 0, 0];
+    let mut app = App::init(&config)?;
+    let textures = app.canvas.texture_creator();
+    let mut sprites = if config.simple_render {
+        None
+    } else {
+        SpriteSheet::load(&textures, Path::new(&config.assets))
+            .inspect_err(|e| warn!("{}; drawing vehicles as rectangles", e))
+            .ok()
+    };
+    if let Some(other) = config.compare {
+        return compare(&mut app, sprites, config, other);
+    }
+    if let Some(grid) = config.grid {
+        return network(&mut app, sprites, config, grid);
+    }
+    let mut tick = config.tick_interval();
+    let print_hashes = config.print_hashes;
+    let shared_stats = Arc::new(Mutex::new(Stats::default()));
+    if let Some(address) = &config.serve {
+        stats::serve(address, Arc::clone(&shared_stats))?;
+    }
+    let mut remote = match &config.remote {
+        Some(address) => Some(Remote::listen(address)?),
+        None => None,
+    };
+    let mut simulation = start(&config, replay.as_ref(), loaded.as_ref())?;
+    let mut audio = if config.audio {
+        Audio::open(&app.sdl_context, Path::new(&config.assets))
+            .inspect_err(|e| warn!("{}; running without sound", e))
+            .ok()
+    } else {
+        None
+    };
+    let mut soundscape = Soundscape::default();
+
+    let started = Instant::now();
+    let mut previous_frame = started;
+    let mut accumulator = Duration::ZERO;
+    let mut debug = DebugOverlay {
+        camera: Camera::new(app.world),
+        ..DebugOverlay::default()
+    };
+    // The vehicle held under the mouse, by id.
+    let mut dragging = None;
+    let mut grab = None;
+    // The tuning panel's slider being dragged.
+    let mut sliding = None;
+    // While paused only the ticks asked for with Period run.
+    let mut paused = false;
+    let mut steps: u64 = 0;
 
     'running: loop {
-        for event in event_pump.poll_iter() {
+        for _ in 0..MAX_EVENTS_PER_FRAME {
+            let Some(event) = app.event_pump.poll_event() else {
+                break;
+            };
+            app.handle_window_event(&event)?;
+            if steer_camera(&mut debug, app.viewport, &event, &mut grab) {
+                continue;
+            }
             match event {
                 Event::Quit { .. } => break 'running,
+                Event::MouseMotion { x, y, .. } => {
+                    let (x, y) = app.viewport.to_logical(x, y);
+                    debug.mouse = Some((x as i32, y as i32));
+                    if let Some(knob) = sliding {
+                        panel::slide(&mut simulation, knob, x);
+                    }
+                    if let Some(id) = dragging {
+                        let (x, y) = debug.camera.to_world(x, y);
+                        let (x, y) = simulation.config.bend().straighten(x, y);
+                        if !simulation.drag_vehicle(id, x, y) {
+                            dragging = None;
+                        }
+                    }
+                }
+                Event::MouseButtonDown {
+                    mouse_btn: MouseButton::Left,
+                    x,
+                    y,
+                    ..
+                } => {
+                    let (x, y) = app.viewport.to_logical(x, y);
+                    // The panel sits over the world and takes its clicks.
+                    if debug.panel && panel::contains(&simulation, x, y) {
+                        sliding = panel::knob_at(&simulation, x, y);
+                        if let Some(knob) = sliding {
+                            panel::slide(&mut simulation, knob, x);
+                        }
+                        continue;
+                    }
+                    let world = debug.camera.to_world(x, y);
+                    // Lights stand beside the box, off the bent roads.
+                    let (x, y) = simulation.config.bend().straighten(world.0, world.1);
+                    let clicked = simulation
+                        .vehicles
+                        .iter()
+                        .find(|vehicle| vehicle.contains_point(x, y))
+                        .map(|vehicle| vehicle.id);
+                    // Pressing on the vehicle already selected picks it up.
+                    if clicked.is_some() && clicked == debug.selected {
+                        dragging = clicked;
+                    } else if clicked.is_some() {
+                        debug.selected = clicked;
+                    } else if let Some(approach) = simulation.light_at(world.0, world.1) {
+                        simulation.toggle_light_hold(approach);
+                    } else if let Some(direction) = app.world.approach_at(x, y) {
+                        simulation.request_spawn(direction, None, now_in_millis());
+                    }
+                }
+                Event::MouseButtonUp {
+                    mouse_btn: MouseButton::Left,
+                    ..
+                } => {
+                    if let Some(id) = dragging.take() {
+                        simulation.release_vehicle(id);
+                    }
+                    if let Some(knob) = sliding.take() {
+                        info!("Tuned {} to {:.2}", knob.name(), knob.value(&simulation));
+                    }
+                }
                 Event::KeyDown {
                     keycode: Some(keycode),
+                    keymod,
+                    repeat,
                     ..
-                } => {
-                    let current_time = now_in_millis();
-
-                    let (direction, timer_index) = match keycode {
-                        Keycode::Up => ("up", 0),
-                        Keycode::Down => ("down", 1),
-                        Keycode::Right => ("right", 2),
-                        Keycode::Left => ("left", 3),
+                } if !(repeat && toggles(keycode)) => {
+                    let direction = match keycode {
+                        Keycode::Up => Direction::Up,
+                        Keycode::Down => Direction::Down,
+                        Keycode::Right => Direction::Right,
+                        Keycode::Left => Direction::Left,
                         Keycode::Escape => break 'running,
+                        Keycode::R => {
+                            simulation.request_random_spawn(now_in_millis());
+                            continue;
+                        }
+                        Keycode::B => {
+                            simulation.queue_burst();
+                            continue;
+                        }
+                        Keycode::D => {
+                            debug.enabled = !debug.enabled;
+                            continue;
+                        }
+                        Keycode::G => {
+                            debug.ghost = !debug.ghost;
+                            continue;
+                        }
+                        Keycode::N => {
+                            simulation.start_drain();
+                            continue;
+                        }
+                        Keycode::F => {
+                            debug.follow = !debug.follow && debug.selected.is_some();
+                            continue;
+                        }
+                        Keycode::M => {
+                            debug.minimap = !debug.minimap;
+                            continue;
+                        }
+                        Keycode::H => {
+                            debug.hud = !debug.hud;
+                            continue;
+                        }
+                        // M is the minimap, so the heatmap is on C, for
+                        // congestion.
+                        Keycode::C => {
+                            debug.heatmap = !debug.heatmap;
+                            continue;
+                        }
+                        Keycode::P => {
+                            debug.panel = !debug.panel;
+                            continue;
+                        }
+                        // M is the minimap, so mute is on Q, for quiet.
+                        Keycode::Q => {
+                            if let Some(audio) = &mut audio {
+                                audio.muted = !audio.muted;
+                            }
+                            continue;
+                        }
+                        Keycode::A => {
+                            simulation.toggle_controller();
+                            continue;
+                        }
+                        Keycode::T => {
+                            simulation.toggle_auto_spawn();
+                            continue;
+                        }
+                        Keycode::E => {
+                            simulation.spawn_emergency();
+                            continue;
+                        }
+                        Keycode::LeftBracket | Keycode::RightBracket => {
+                            let sign = if keycode == Keycode::LeftBracket {
+                                -1.0
+                            } else {
+                                1.0
+                            };
+                            simulation.retime_lights(sign * GREEN_STEP, 0.0);
+                            continue;
+                        }
+                        Keycode::Semicolon | Keycode::Quote => {
+                            let sign = if keycode == Keycode::Semicolon {
+                                -1.0
+                            } else {
+                                1.0
+                            };
+                            simulation.retime_lights(0.0, sign * YELLOW_STEP);
+                            continue;
+                        }
+                        Keycode::V => {
+                            let style = &mut simulation.config.road_style;
+                            *style = style.toggled();
+                            continue;
+                        }
+                        // Ctrl+W pans; on its own it changes the weather.
+                        Keycode::W => {
+                            let weather = simulation.config.weather.toggled();
+                            simulation.set_weather(weather);
+                            continue;
+                        }
+                        // Day to night and back.
+                        Keycode::L => {
+                            simulation.advance_clock(12.0);
+                            continue;
+                        }
+                        Keycode::S => {
+                            match simulation.config.save(Path::new(SAVED_CONFIG_PATH)) {
+                                Ok(()) => println!("Saved configuration to {}", SAVED_CONFIG_PATH),
+                                Err(e) => error!("{}", e),
+                            }
+                            continue;
+                        }
+                        Keycode::F5 => {
+                            let path = snapshot_path(&config);
+                            match simulation.snapshot().save(path) {
+                                Ok(()) => println!("Saved a snapshot to {}", path.display()),
+                                Err(e) => error!("{}", e),
+                            }
+                            continue;
+                        }
+                        // Back to the last F5, keeping the settings changed
+                        // since.
+                        Keycode::F9 => {
+                            let path = snapshot_path(&config);
+                            match Snapshot::load(path).and_then(|s| simulation.restore(&s)) {
+                                Ok(()) => {
+                                    soundscape = Soundscape::default();
+                                    dragging = None;
+                                    debug.selected = None;
+                                    debug.follow = false;
+                                    info!("Restored the snapshot from {}", path.display());
+                                }
+                                Err(e) => error!("{}", e),
+                            }
+                            continue;
+                        }
+                        Keycode::Space => {
+                            paused = !paused;
+                            continue;
+                        }
+                        Keycode::Period => {
+                            if paused {
+                                steps += 1;
+                            }
+                            continue;
+                        }
+                        // A fresh run from the configuration as it was at
+                        // launch, the seed and any loaded snapshot included.
+                        Keycode::Backspace => {
+                            simulation = start(&config, replay.as_ref(), loaded.as_ref())?;
+                            soundscape = Soundscape::default();
+                            tick = config.tick_interval();
+                            dragging = None;
+                            debug.selected = None;
+                            debug.follow = false;
+                            info!("Restarted the simulation");
+                            continue;
+                        }
+                        Keycode::Equals | Keycode::Plus | Keycode::KpPlus => {
+                            tick = change_time_scale(&mut simulation, 2.0);
+                            continue;
+                        }
+                        Keycode::Minus | Keycode::KpMinus => {
+                            tick = change_time_scale(&mut simulation, 0.5);
+                            continue;
+                        }
                         _ => continue,
                     };
+                    simulation.request_spawn(direction, held_route(keymod), now_in_millis());
+                }
+                _ => {}
+            }
+        }
 
-                    if current_time - last_spawn_time[timer_index] > delay {
-                        simulation.spawn_vehicle(direction);
-                        last_spawn_time[timer_index] = current_time;
+        if let Some(remote) = &mut remote {
+            for pacing in remote.poll(&mut simulation, now_in_millis()) {
+                match pacing {
+                    Pacing::Pause => paused = true,
+                    Pacing::Resume => paused = false,
+                    Pacing::Step(ticks) => {
+                        paused = true;
+                        steps = steps.saturating_add(ticks);
                     }
                 }
-                _ => {}
             }
         }
 
-        simulation.update();
-        simulation.render(&mut canvas)?;
-        ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
+        let now = Instant::now();
+        let elapsed = now - previous_frame;
+        previous_frame = now;
+        if !elapsed.is_zero() {
+            let fps = 1.0 / elapsed.as_secs_f32();
+            debug.fps = if debug.fps == 0.0 {
+                fps
+            } else {
+                debug.fps + (fps - debug.fps) * FPS_SMOOTHING
+            };
+        }
+
+        simulation.release_spawns(now_in_millis());
+        let mut ticks = steps.min(MAX_STEPS_PER_FRAME);
+        steps -= ticks;
+        if !paused {
+            // After a stall, drop the backlog instead of trying to catch up all at once.
+            // Sped up, a frame gets proportionally more ticks so it can keep pace.
+            let backlog =
+                (tick * MAX_TICKS_PER_FRAME).mul_f32(simulation.config.time_scale.max(1.0));
+            accumulator = (accumulator + elapsed).min(backlog);
+            while accumulator >= tick {
+                ticks += 1;
+                accumulator -= tick;
+            }
+        }
+        for _ in 0..ticks {
+            simulation.update();
+            if let Some(remote) = &mut remote {
+                remote.broadcast(&simulation);
+            }
+
+            if print_hashes {
+                println!(
+                    "tick {} hash {:016x}",
+                    simulation.tick,
+                    simulation.state_hash()
+                );
+            }
+            if simulation.events().contains(&SimEvent::Drained)
+                && let Some(report) = simulation.drain_report()
+            {
+                println!("{}", report);
+            }
+            if simulation.config.pause_on_collision
+                && simulation
+                    .events()
+                    .iter()
+                    .any(|event| matches!(event, SimEvent::Collision { .. }))
+            {
+                info!("Paused on a collision at tick {}", simulation.tick);
+                paused = true;
+                accumulator = Duration::ZERO;
+                steps = 0;
+                break;
+            }
+        }
+
+        if let Some(audio) = &mut audio {
+            audio.play(soundscape.listen(&simulation));
+        }
+
+        if simulation.config.serve.is_some()
+            && let Ok(mut stats) = shared_stats.lock()
+        {
+            *stats = simulation.stats();
+        }
+
+        debug.wall_clock = started.elapsed();
+        // Paused, show the last tick run rather than part way to the next.
+        let alpha = if paused {
+            1.0
+        } else {
+            accumulator.as_secs_f32() / tick.as_secs_f32()
+        };
+        simulation.track_camera(&mut debug, alpha);
+        let mut canvas = SpriteCanvas::new(&mut app.canvas, sprites.as_mut());
+        simulation.render(&mut canvas, alpha, &debug)?;
     }
 
-    Ok(())
-}
\ No newline at end of file
+    simulation.print_summary();
+    save_recording(&simulation)?;
+    simulation.write_metrics()
+}
@@ -0,0 +1,143 @@
+use crate::direction::Direction;
+use std::fs;
+use std::path::Path;
+
+/// One approach's figures at one moment of a run. Exits and waits count
+/// every vehicle that has left since the start.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MetricsSample {
+    pub seconds: f64,
+    pub approach: Direction,
+    pub exited: usize,
+    /// Exits per simulated minute since the start.
+    pub throughput: f64,
+    /// Seconds exited vehicles spent stopped or crawling, on average.
+    pub mean_wait: f32,
+    /// The wait 95% of exited vehicles did no worse than.
+    pub p95_wait: f32,
+    /// Vehicles yet to reach the stop line.
+    pub queue: usize,
+}
+
+const COLUMNS: [&str; 7] = [
+    "seconds",
+    "approach",
+    "exited",
+    "throughput",
+    "mean_wait",
+    "p95_wait",
+    "queue",
+];
+
+/// The nearest-rank `percent`th percentile of `values`, 0 for none.
+pub fn percentile(values: &[f32], percent: f32) -> f32 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f32::total_cmp);
+    let rank = (percent / 100.0 * sorted.len() as f32).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+impl MetricsSample {
+    fn values(&self) -> [String; 7] {
+        [
+            format!("{:.3}", self.seconds),
+            self.approach.to_string(),
+            self.exited.to_string(),
+            format!("{:.3}", self.throughput),
+            format!("{:.3}", self.mean_wait),
+            format!("{:.3}", self.p95_wait),
+            self.queue.to_string(),
+        ]
+    }
+}
+
+/// A header row, then a row per sample.
+pub fn to_csv(samples: &[MetricsSample]) -> String {
+    let mut text = COLUMNS.join(",") + "\n";
+    for sample in samples {
+        text.push_str(&sample.values().join(","));
+        text.push('\n');
+    }
+    text
+}
+
+/// An array with an object per sample, keyed by the CSV column names.
+pub fn to_json(samples: &[MetricsSample]) -> String {
+    let records: Vec<String> = samples
+        .iter()
+        .map(|sample| {
+            let fields: Vec<String> = COLUMNS
+                .iter()
+                .zip(sample.values())
+                .map(|(column, value)| match *column {
+                    "approach" => format!("\"{}\": \"{}\"", column, value),
+                    _ => format!("\"{}\": {}", column, value),
+                })
+                .collect();
+            format!("  {{{}}}", fields.join(", "))
+        })
+        .collect();
+    format!("[\n{}\n]\n", records.join(",\n"))
+}
+
+/// Writes `samples` as CSV or JSON, as the file's extension says.
+pub fn save(path: &Path, samples: &[MetricsSample]) -> Result<(), String> {
+    let text = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("json") => to_json(samples),
+        _ => to_csv(samples),
+    };
+    fs::write(path, text).map_err(|e| format!("Could not write {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_are_written_as_csv_rows_or_json_records() {
+        let samples = [
+            MetricsSample {
+                seconds: 60.0,
+                approach: Direction::Up,
+                exited: 12,
+                throughput: 12.0,
+                mean_wait: 3.25,
+                p95_wait: 9.5,
+                queue: 4,
+            },
+            MetricsSample {
+                seconds: 60.0,
+                approach: Direction::Left,
+                exited: 0,
+                throughput: 0.0,
+                mean_wait: 0.0,
+                p95_wait: 0.0,
+                queue: 0,
+            },
+        ];
+        assert_eq!(
+            to_csv(&samples),
+            "seconds,approach,exited,throughput,mean_wait,p95_wait,queue\n\
+             60.000,up,12,12.000,3.250,9.500,4\n\
+             60.000,left,0,0.000,0.000,0.000,0\n"
+        );
+        let json = to_json(&samples);
+        assert!(
+            json.starts_with("[\n  {\"seconds\": 60.000, \"approach\": \"up\", \"exited\": 12,")
+        );
+        assert!(json.contains("\"p95_wait\": 9.500, \"queue\": 4},\n"));
+        assert!(json.ends_with("\"queue\": 0}\n]\n"));
+        assert_eq!(to_json(&[]), "[\n\n]\n");
+    }
+
+    #[test]
+    fn the_95th_percentile_is_the_nearest_rank() {
+        let waits: Vec<f32> = (1..=20).map(|wait| wait as f32).collect();
+        assert_eq!(percentile(&waits, 95.0), 19.0);
+        assert_eq!(percentile(&waits[..1], 95.0), 1.0);
+        assert_eq!(percentile(&[], 95.0), 0.0);
+    }
+}
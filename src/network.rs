@@ -0,0 +1,517 @@
+use crate::config::{ColorMode, Config};
+use crate::direction::{Direction, Route};
+use crate::geometry::Camera;
+use crate::render::{Inset, Renderer, Viewed};
+use crate::simulation::{DebugOverlay, TrafficSimulation, drawn_kind, permitted_routes};
+use crate::vehicle::Vehicle;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use sdl2::pixels::Color;
+use std::collections::{HashMap, VecDeque};
+use std::thread;
+
+/// A vehicle waiting to enter an intersection, either from outside the map
+/// or off the road from the one before.
+struct Arrival {
+    vehicle: Vehicle,
+    /// Network tick from which it may enter, once it has driven the road
+    /// segment.
+    ready_at: u64,
+    trip: Trip,
+}
+
+/// Where a vehicle goes once it is on the map.
+#[derive(Clone)]
+struct Trip {
+    /// The route to take at each intersection after the current one.
+    routes: VecDeque<Route>,
+    /// Network tick it came onto the map.
+    entered: u64,
+    /// Intersections crossed so far.
+    crossed: usize,
+}
+
+/// Intersections laid out in `columns` by `rows`, each a simulation of its
+/// own with its own lights, joined arm to arm by road segments. Vehicles
+/// come on at the edges and are routed across the map, straight on and at
+/// most one turn, to leave by another edge. A vehicle driving off one
+/// intersection towards a neighbour is taken off it and, after
+/// `segment_seconds`, spawned on the neighbour's matching approach.
+pub struct Network {
+    pub columns: usize,
+    pub rows: usize,
+    /// Row by row from the top, each from the left.
+    pub intersections: Vec<TrafficSimulation>,
+    rng: StdRng,
+    /// Per intersection and approach, in `Direction::index` order, oldest
+    /// first.
+    waiting: Vec<[VecDeque<Arrival>; 4]>,
+    /// Trips under way, by intersection and the id the vehicle has there.
+    trips: HashMap<(usize, u64), Trip>,
+    tick: u64,
+    next_id: u64,
+    auto_spawn: bool,
+    /// Trips that reached an edge and left the map.
+    pub completed: usize,
+    // Intersections crossed and ticks taken, summed over completed trips.
+    crossings: usize,
+    trip_ticks: u64,
+    // Whether each intersection was gridlocked on the last soak tick.
+    gridlocked: Vec<bool>,
+}
+
+impl Network {
+    /// Every intersection runs `config`, seeded in turn from its seed, with
+    /// its own random arrivals off: the network makes them all.
+    pub fn new(config: Config, columns: usize, rows: usize) -> Self {
+        let seed = config.seed.unwrap_or_else(rand::random);
+        let auto_spawn = config.auto_spawn;
+        let intersections = (0..columns * rows)
+            .map(|index| {
+                let mut intersection = TrafficSimulation::new(Config {
+                    seed: Some(seed.wrapping_add(index as u64 + 1)),
+                    auto_spawn: false,
+                    grid: None,
+                    metrics_out: None,
+                    serve: None,
+                    ..config.clone()
+                });
+                intersection.keep_exits();
+                intersection
+            })
+            .collect();
+        Self {
+            columns,
+            rows,
+            intersections,
+            rng: StdRng::seed_from_u64(seed),
+            waiting: (0..columns * rows).map(|_| Default::default()).collect(),
+            trips: HashMap::new(),
+            tick: 0,
+            next_id: 0,
+            auto_spawn,
+            completed: 0,
+            crossings: 0,
+            trip_ticks: 0,
+            gridlocked: vec![false; columns * rows],
+        }
+    }
+
+    fn config(&self) -> &Config {
+        &self.intersections[0].config
+    }
+
+    fn index(&self, (column, row): (usize, usize)) -> usize {
+        row * self.columns + column
+    }
+
+    /// The intersection next to `index` in `direction`, if the map goes on.
+    fn neighbour(&self, index: usize, direction: Direction) -> Option<usize> {
+        let (dx, dy) = direction.delta();
+        let column = (index % self.columns) as i64 + dx as i64;
+        let row = (index / self.columns) as i64 + dy as i64;
+        let on_map =
+            (0..self.columns as i64).contains(&column) && (0..self.rows as i64).contains(&row);
+        on_map.then(|| self.index((column as usize, row as usize)))
+    }
+
+    /// The intersections vehicles travelling `direction` come onto the map
+    /// at: the bottom row for those heading up, and so on.
+    fn entries(&self, direction: Direction) -> Vec<usize> {
+        (0..self.columns * self.rows)
+            .filter(|&index| self.neighbour(index, direction.opposite()).is_none())
+            .collect()
+    }
+
+    /// The routes a vehicle coming onto `entry` heading `direction` takes
+    /// at each intersection in turn: straight on to a random exit, or
+    /// straight, one turn where the routes allow it, then straight to the
+    /// edge.
+    fn plan(&mut self, entry: usize, direction: Direction) -> VecDeque<Route> {
+        let routes = permitted_routes(self.config(), direction);
+        let turn = routes[self.rng.random_range(0..routes.len())];
+        let ahead = {
+            let mut count = 1;
+            let mut index = entry;
+            while let Some(next) = self.neighbour(index, direction) {
+                index = next;
+                count += 1;
+            }
+            count
+        };
+        let turn_at = self.rng.random_range(0..ahead);
+        let mut plan = VecDeque::new();
+        let (mut index, mut heading) = (entry, direction);
+        loop {
+            let route = if plan.len() == turn_at {
+                turn
+            } else {
+                Route::Straight
+            };
+            plan.push_back(route);
+            heading = route.exit_direction(heading);
+            match self.neighbour(index, heading) {
+                Some(next) => index = next,
+                None => return plan,
+            }
+        }
+    }
+
+    /// Puts a new vehicle in the queue to come onto `entry` heading
+    /// `direction`.
+    fn queue(&mut self, entry: usize, direction: Direction) {
+        let mut routes = self.plan(entry, direction);
+        let route = routes.pop_front().unwrap_or(Route::Straight);
+        let kind = drawn_kind(&self.intersections[0].config, &mut self.rng);
+        let color_mode = self.config().color_mode;
+        let mut vehicle = Vehicle::new(self.next_id, direction, route, color_mode, &mut self.rng);
+        vehicle.kind = kind;
+        vehicle.tie_break = self.rng.random();
+        self.next_id += 1;
+        self.waiting[entry][direction.index()].push_back(Arrival {
+            vehicle,
+            ready_at: self.tick,
+            trip: Trip {
+                routes,
+                entered: self.tick,
+                crossed: 0,
+            },
+        });
+    }
+
+    /// A vehicle onto the map heading `direction`, at one of the
+    /// intersections on that edge picked at random.
+    pub fn request_spawn(&mut self, direction: Direction) {
+        let entries = self.entries(direction);
+        let entry = entries[self.rng.random_range(0..entries.len())];
+        self.queue(entry, direction);
+    }
+
+    /// A vehicle onto the map from a random edge.
+    pub fn request_random_spawn(&mut self) {
+        let direction = Direction::ALL[self.rng.random_range(0..4)];
+        self.request_spawn(direction);
+    }
+
+    /// `burst_size` vehicles at every way onto the map.
+    pub fn queue_burst(&mut self) {
+        for direction in Direction::ALL {
+            for entry in self.entries(direction) {
+                for _ in 0..self.config().burst_size {
+                    self.queue(entry, direction);
+                }
+            }
+        }
+    }
+
+    /// Starts or stops the random arrivals of `auto_spawn`.
+    pub fn toggle_auto_spawn(&mut self) {
+        self.auto_spawn = !self.auto_spawn;
+    }
+
+    /// Vehicles on the map or waiting to come onto it.
+    pub fn vehicles(&self) -> usize {
+        let driving: usize = self
+            .intersections
+            .iter()
+            .map(|run| run.vehicles.len())
+            .sum();
+        let waiting: usize = self.waiting.iter().flatten().map(VecDeque::len).sum();
+        driving + waiting
+    }
+
+    /// Every edge intersection draws random arrivals on its outer arms as a
+    /// single one would; then each intersection admits what it can of the
+    /// vehicles waiting for it, one per approach, and advances a tick, and
+    /// the vehicles that drove off are sent down the road to the next one
+    /// or counted off the map.
+    pub fn update(&mut self) {
+        if self.auto_spawn {
+            let chance = self.config().arrival_rate * self.config().tick_seconds();
+            for index in 0..self.intersections.len() {
+                if self.rng.random::<f32>() < chance {
+                    let outer: Vec<Direction> = Direction::ALL
+                        .into_iter()
+                        .filter(|&direction| self.neighbour(index, direction.opposite()).is_none())
+                        .collect();
+                    if !outer.is_empty() {
+                        let direction = outer[self.rng.random_range(0..outer.len())];
+                        self.queue(index, direction);
+                    }
+                }
+            }
+        }
+        self.step(None);
+    }
+
+    // Admits, advances every intersection by a tick, then moves the exits
+    // on. With `failures`, each intersection's tick is checked as a soak
+    // checks it, the problems named by intersection.
+    fn step(&mut self, failures: Option<&mut Vec<String>>) {
+        for index in 0..self.intersections.len() {
+            for queue in &mut self.waiting[index] {
+                if let Some(arrival) = queue.front()
+                    && arrival.ready_at <= self.tick
+                    && let Some(id) = self.intersections[index].admit(&arrival.vehicle)
+                {
+                    let arrival = queue.pop_front().unwrap();
+                    self.trips.insert((index, id), arrival.trip);
+                }
+            }
+        }
+        let found = self.advance(failures.is_some());
+        if let Some(failures) = failures {
+            for (index, found) in found.into_iter().enumerate() {
+                failures.extend(
+                    found
+                        .into_iter()
+                        .map(|failure| format!("intersection {}: {}", index, failure)),
+                );
+            }
+        }
+        self.tick += 1;
+
+        let segment_ticks = self
+            .config()
+            .seconds_to_ticks(self.config().segment_seconds) as u64;
+        for index in 0..self.intersections.len() {
+            for exit in self.intersections[index].take_exits() {
+                let Some(mut trip) = self.trips.remove(&(index, exit.id)) else {
+                    continue;
+                };
+                trip.crossed += 1;
+                let heading = exit.direction;
+                let next = self.neighbour(index, heading);
+                let (Some(next), Some(route)) = (next, trip.routes.pop_front()) else {
+                    self.completed += 1;
+                    self.crossings += trip.crossed;
+                    self.trip_ticks += self.tick - trip.entered;
+                    continue;
+                };
+                let color_mode = self.config().color_mode;
+                let mut vehicle = Vehicle::new(exit.id, heading, route, color_mode, &mut self.rng);
+                vehicle.kind = exit.kind;
+                vehicle.tie_break = exit.tie_break;
+                if matches!(color_mode, ColorMode::ById | ColorMode::Random) {
+                    vehicle.color = exit.color;
+                }
+                self.waiting[next][heading.index()].push_back(Arrival {
+                    vehicle,
+                    ready_at: self.tick + segment_ticks,
+                    trip,
+                });
+            }
+        }
+    }
+
+    // Ticks every intersection, checked as a soak checks it if `soak`, and
+    // returns the problems found at each. The intersections are split into
+    // `threads` runs of neighbours, each ticked on a thread that has them to
+    // itself: between admitting and moving the exits on, nothing passes from
+    // one intersection to another, so the split cannot change the run.
+    fn advance(&mut self, soak: bool) -> Vec<Vec<String>> {
+        let mut found = vec![Vec::new(); self.intersections.len()];
+        let threads = self.config().worker_threads().min(self.intersections.len());
+        if threads <= 1 {
+            tick_all(
+                &mut self.intersections,
+                &mut self.gridlocked,
+                &mut found,
+                soak,
+            );
+            return found;
+        }
+        let share = self.intersections.len().div_ceil(threads);
+        thread::scope(|scope| {
+            for ((intersections, gridlocked), found) in self
+                .intersections
+                .chunks_mut(share)
+                .zip(self.gridlocked.chunks_mut(share))
+                .zip(found.chunks_mut(share))
+            {
+                scope.spawn(move || tick_all(intersections, gridlocked, found, soak));
+            }
+        });
+        found
+    }
+
+    /// Keeps every way onto the map supplied for `seconds` of simulated
+    /// time, checking each intersection's ticks as a single soak does.
+    pub fn soak(&mut self, seconds: f64) -> Vec<String> {
+        let mut failures = Vec::new();
+        while self.intersections[0].simulated_seconds() < seconds {
+            for direction in Direction::ALL {
+                for entry in self.entries(direction) {
+                    if self.waiting[entry][direction.index()].is_empty() {
+                        self.queue(entry, direction);
+                    }
+                }
+            }
+            self.step(Some(&mut failures));
+        }
+        failures
+    }
+
+    /// The scale and top-left corner each intersection is drawn at so the
+    /// whole map fits the window, centred.
+    fn layout(&self) -> (f32, i32, i32) {
+        let world = self.config().world();
+        let scale = (1.0 / self.columns as f32).min(1.0 / self.rows as f32);
+        let width = (world.width as f32 * scale) as i32;
+        let height = (world.height as f32 * scale) as i32;
+        let left = (world.width as i32 - width * self.columns as i32) / 2;
+        let top = (world.height as i32 - height * self.rows as i32) / 2;
+        (scale, left, top)
+    }
+
+    /// Draws every intersection in its place on the map, touching its
+    /// neighbours so the roads run on from one to the next. The camera
+    /// pans and zooms the whole map; each intersection is drawn unmoved.
+    pub fn render(
+        &self,
+        canvas: &mut dyn Renderer,
+        alpha: f32,
+        debug: &DebugOverlay,
+    ) -> Result<(), String> {
+        canvas.set_draw_color(Color::RGB(20, 20, 20));
+        canvas.clear();
+        let canvas = &mut Viewed::new(canvas, debug.camera);
+        let world = self.config().world();
+        let debug = &DebugOverlay {
+            camera: Camera::new(world),
+            ..debug.clone()
+        };
+        let (scale, left, top) = self.layout();
+        let width = (world.width as f32 * scale) as i32;
+        let height = (world.height as f32 * scale) as i32;
+        for (index, intersection) in self.intersections.iter().enumerate() {
+            let column = (index % self.columns) as i32;
+            let row = (index / self.columns) as i32;
+            let corner = (left + column * width, top + row * height);
+            intersection.render(&mut Inset::new(canvas, world, scale, corner), alpha, debug)?;
+        }
+        canvas.present();
+        Ok(())
+    }
+
+    pub fn print_summary(&self) {
+        println!(
+            "Network: {}x{} intersections, {:.1}s simulated",
+            self.columns,
+            self.rows,
+            self.intersections[0].simulated_seconds()
+        );
+        println!(
+            "Trips completed: {}, still on the map: {}",
+            self.completed,
+            self.vehicles()
+        );
+        if self.completed > 0 {
+            println!(
+                "Mean intersections crossed: {:.2}, mean trip time: {:.1}s",
+                self.crossings as f64 / self.completed as f64,
+                self.config().ticks_to_seconds(self.trip_ticks) / self.completed as f64
+            );
+        }
+        for (index, intersection) in self.intersections.iter().enumerate() {
+            let stats = intersection.stats();
+            println!(
+                "  ({}, {}): {} through, {} collision(s), mean delay {:.1}s",
+                index % self.columns,
+                index / self.columns,
+                stats.exited,
+                stats.collisions,
+                intersection.mean_delay()
+            );
+        }
+    }
+}
+
+// Ticks each of `intersections` in turn, with the gridlock flag and the
+// problems found that go with it.
+fn tick_all(
+    intersections: &mut [TrafficSimulation],
+    gridlocked: &mut [bool],
+    found: &mut [Vec<String>],
+    soak: bool,
+) {
+    for ((intersection, gridlocked), found) in intersections.iter_mut().zip(gridlocked).zip(found) {
+        if soak {
+            intersection.soak_tick(found, gridlocked);
+        } else {
+            intersection.update();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vehicles_cross_several_intersections_to_an_edge() {
+        let config = Config {
+            seed: Some(3),
+            grid: Some((3, 2)),
+            ..Config::default()
+        };
+        let mut network = Network::new(config, 3, 2);
+        assert_eq!(network.entries(Direction::Up), [3, 4, 5]);
+        assert_eq!(network.entries(Direction::Left), [2, 5]);
+        for _ in 0..20 {
+            let plan = network.plan(3, Direction::Right);
+            let turns = plan
+                .iter()
+                .filter(|&&route| route != Route::Straight)
+                .count();
+            assert!(turns <= 1, "{:?}", plan);
+            assert!((1..=4).contains(&plan.len()), "{:?}", plan);
+        }
+
+        network.queue_burst();
+        let mut failures = Vec::new();
+        for _ in 0..60 * 90 {
+            network.step(Some(&mut failures));
+        }
+        assert_eq!(failures, Vec::<String>::new());
+        assert_eq!(network.vehicles(), 0);
+        // Ten ways onto a 3x2 map, `burst_size` vehicles each.
+        assert_eq!(network.completed, 10 * network.config().burst_size);
+        // Straight across the long way alone crosses three.
+        assert!(network.crossings > network.completed);
+        let handed_on: usize = network
+            .intersections
+            .iter()
+            .map(|intersection| intersection.stats().exited)
+            .sum();
+        assert_eq!(handed_on, network.crossings);
+    }
+
+    #[test]
+    fn ticking_on_several_threads_changes_nothing() {
+        let run = |threads| {
+            let config = Config {
+                seed: Some(5),
+                grid: Some((3, 3)),
+                auto_spawn: true,
+                arrival_rate: 2.0,
+                threads,
+                ..Config::default()
+            };
+            let mut network = Network::new(config, 3, 3);
+            for _ in 0..60 * 30 {
+                network.update();
+            }
+            let hashes: Vec<u64> = network
+                .intersections
+                .iter()
+                .map(TrafficSimulation::state_hash)
+                .collect();
+            (hashes, network.completed, network.vehicles())
+        };
+        let single = run(1);
+        assert!(single.1 > 0 && single.2 > 0);
+        assert_eq!(run(2), single);
+        assert_eq!(run(4), single);
+    }
+}
@@ -0,0 +1,238 @@
+use crate::config::{MAX_SPEED_LIMIT, MIN_BASE_GAP};
+use crate::digits;
+use crate::render::Renderer;
+use crate::simulation::TrafficSimulation;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+
+const ROW_HEIGHT: i32 = 24;
+const PADDING: i32 = 8;
+const SWATCH: u32 = 12;
+const TRACK_LEFT: i32 = PADDING + SWATCH as i32 + PADDING;
+const TRACK_WIDTH: i32 = 150;
+const PANEL_WIDTH: u32 = 260;
+
+/// A setting the tuning panel has a slider for. Each row is told apart by
+/// the colour of its swatch, as the HUD's readouts are.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Knob {
+    /// The longest green phase; the others move with it.
+    Green,
+    Yellow,
+    ArrivalRate,
+    /// Taken up by vehicles spawned from then on.
+    CruiseSpeed,
+    BaseGap,
+}
+
+impl Knob {
+    pub const ALL: [Knob; 5] = [
+        Knob::Green,
+        Knob::Yellow,
+        Knob::ArrivalRate,
+        Knob::CruiseSpeed,
+        Knob::BaseGap,
+    ];
+
+    /// The config key the knob sets, for the log.
+    pub fn name(self) -> &'static str {
+        match self {
+            Knob::Green => "green",
+            Knob::Yellow => "yellow",
+            Knob::ArrivalRate => "arrival_rate",
+            Knob::CruiseSpeed => "cruise_speed",
+            Knob::BaseGap => "base_gap",
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            Knob::Green => Color::RGB(0, 255, 0),
+            Knob::Yellow => Color::RGB(255, 200, 0),
+            Knob::ArrivalRate => Color::RGB(0, 255, 255),
+            Knob::CruiseSpeed => Color::RGB(255, 0, 255),
+            Knob::BaseGap => Color::RGB(255, 255, 255),
+        }
+    }
+
+    /// The values at the two ends of the slider, all ones the config
+    /// accepts.
+    fn range(self, simulation: &TrafficSimulation) -> (f32, f32) {
+        let config = &simulation.config;
+        match self {
+            Knob::Green => (
+                config.min_green.max(config.min_phase_duration),
+                config.max_green,
+            ),
+            Knob::Yellow => (0.0, config.max_yellow),
+            Knob::ArrivalRate => (0.05, config.tick_rate.min(2.0)),
+            Knob::CruiseSpeed => (30.0, MAX_SPEED_LIMIT),
+            Knob::BaseGap => (MIN_BASE_GAP, 40.0),
+        }
+    }
+
+    pub fn value(self, simulation: &TrafficSimulation) -> f32 {
+        let config = &simulation.config;
+        match self {
+            Knob::Green => simulation.upcoming_green(),
+            Knob::Yellow => config.yellow,
+            Knob::ArrivalRate => config.arrival_rate,
+            Knob::CruiseSpeed => config.cruise_speed,
+            Knob::BaseGap => config.base_gap,
+        }
+    }
+
+    /// Sets the knob, within its range. The config follows, so a save
+    /// keeps the new value.
+    pub fn set(self, simulation: &mut TrafficSimulation, value: f32) {
+        let (low, high) = self.range(simulation);
+        let value = value.clamp(low, high);
+        let change = value - self.value(simulation);
+        match self {
+            Knob::Green => simulation.retime_lights(change, 0.0),
+            Knob::Yellow => simulation.retime_lights(0.0, change),
+            Knob::ArrivalRate => simulation.config.arrival_rate = value,
+            Knob::CruiseSpeed => simulation.config.cruise_speed = value,
+            Knob::BaseGap => simulation.config.base_gap = value,
+        }
+    }
+}
+
+/// The knobs the panel shows: the light timings only when there are
+/// lights.
+fn knobs(simulation: &TrafficSimulation) -> Vec<Knob> {
+    let signalled = simulation.config.signalled();
+    Knob::ALL
+        .into_iter()
+        .filter(|knob| signalled || !matches!(knob, Knob::Green | Knob::Yellow))
+        .collect()
+}
+
+/// Where the panel sits: the bottom-right corner, a row per knob.
+fn panel_rect(simulation: &TrafficSimulation) -> Rect {
+    let world = simulation.config.world();
+    let height = knobs(simulation).len() as i32 * ROW_HEIGHT + PADDING;
+    Rect::new(
+        world.width as i32 - PANEL_WIDTH as i32 - PADDING,
+        world.height as i32 - height - PADDING,
+        PANEL_WIDTH,
+        height as u32,
+    )
+}
+
+/// Whether the window point falls on the panel, so a click there is kept
+/// from the world beneath.
+pub fn contains(simulation: &TrafficSimulation, x: f32, y: f32) -> bool {
+    panel_rect(simulation).contains_point((x as i32, y as i32))
+}
+
+/// The knob whose row the window point is on.
+pub fn knob_at(simulation: &TrafficSimulation, x: f32, y: f32) -> Option<Knob> {
+    let panel = panel_rect(simulation);
+    if !panel.contains_point((x as i32, y as i32)) {
+        return None;
+    }
+    let row = (y as i32 - panel.top() - PADDING / 2) / ROW_HEIGHT;
+    knobs(simulation).get(row.max(0) as usize).copied()
+}
+
+/// Sets `knob` to the value at window column `x` of its track; points
+/// past either end give that end's value.
+pub fn slide(simulation: &mut TrafficSimulation, knob: Knob, x: f32) {
+    let left = (panel_rect(simulation).left() + TRACK_LEFT) as f32;
+    let along = ((x - left) / TRACK_WIDTH as f32).clamp(0.0, 1.0);
+    let (low, high) = knob.range(simulation);
+    knob.set(simulation, low + (high - low) * along);
+}
+
+/// A row per knob: its swatch, a track with a handle where the value sits
+/// between the ends, then the value itself.
+pub fn draw(canvas: &mut dyn Renderer, simulation: &TrafficSimulation) -> Result<(), String> {
+    let panel = panel_rect(simulation);
+    canvas.set_draw_color(Color::RGBA(0, 0, 0, 200));
+    canvas.fill_rect(panel)?;
+    for (row, knob) in knobs(simulation).into_iter().enumerate() {
+        let top = panel.top() + PADDING + row as i32 * ROW_HEIGHT;
+        let middle = top + digits::DIGIT_HEIGHT as i32 / 2;
+        canvas.set_draw_color(knob.color());
+        canvas.fill_rect(Rect::new(
+            panel.left() + PADDING,
+            middle - SWATCH as i32 / 2,
+            SWATCH,
+            SWATCH,
+        ))?;
+
+        let left = panel.left() + TRACK_LEFT;
+        canvas.set_draw_color(Color::RGB(120, 120, 120));
+        canvas.fill_rect(Rect::new(left, middle - 1, TRACK_WIDTH as u32, 2))?;
+        let (low, high) = knob.range(simulation);
+        let value = knob.value(simulation);
+        let along = ((value - low) / (high - low)).clamp(0.0, 1.0);
+        let handle = left + (along * TRACK_WIDTH as f32).round() as i32;
+        canvas.set_draw_color(knob.color());
+        canvas.fill_rect(Rect::from_center((handle, middle), 6, 16))?;
+
+        let text = match knob {
+            Knob::ArrivalRate => format!("{:.2}", value),
+            _ => format!("{:.1}", value),
+        };
+        let x = left + TRACK_WIDTH + PADDING + 2;
+        digits::draw_text(canvas, x, top, &text, knob.color())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, ControlMode};
+
+    fn track_end(simulation: &TrafficSimulation, end: f32) -> f32 {
+        let left = (panel_rect(simulation).left() + TRACK_LEFT) as f32;
+        left + end * TRACK_WIDTH as f32
+    }
+
+    #[test]
+    fn sliding_a_knob_sets_the_config_a_save_writes() {
+        let mut simulation = TrafficSimulation::new(Config::default());
+        let panel = panel_rect(&simulation);
+        let row = |index: i32| (panel.top() + PADDING + index * ROW_HEIGHT + 4) as f32;
+        let (start, x) = (track_end(&simulation, 0.0), track_end(&simulation, 0.5));
+        assert_eq!(knob_at(&simulation, x, row(0)), Some(Knob::Green));
+        assert_eq!(knob_at(&simulation, x, row(4)), Some(Knob::BaseGap));
+        assert_eq!(knob_at(&simulation, x, panel.top() as f32 - 1.0), None);
+
+        // Past the right end is the top of the range.
+        slide(
+            &mut simulation,
+            Knob::Green,
+            crate::geometry::WINDOW_WIDTH as f32,
+        );
+        assert_eq!(Knob::Green.value(&simulation), simulation.config.max_green);
+        slide(&mut simulation, Knob::Yellow, 0.0);
+        assert_eq!(simulation.config.yellow, 0.0);
+        slide(&mut simulation, Knob::BaseGap, x);
+        assert_eq!(simulation.config.base_gap, 20.5);
+        slide(&mut simulation, Knob::CruiseSpeed, start);
+        assert_eq!(simulation.config.cruise_speed, 30.0);
+
+        let path = std::env::temp_dir().join("road_intersection_tuned.toml");
+        simulation.config.save(&path).unwrap();
+        let saved = Config::from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(saved.base_gap, 20.5);
+        assert_eq!(saved.cruise_speed, 30.0);
+        assert_eq!(saved.yellow, 0.0);
+        let max_green = saved.max_green;
+        assert_eq!(TrafficSimulation::new(saved).upcoming_green(), max_green);
+
+        // Without lights only the traffic knobs are shown.
+        let simulation = TrafficSimulation::new(Config {
+            control_mode: ControlMode::Uncontrolled,
+            ..Config::default()
+        });
+        let panel = panel_rect(&simulation);
+        let y = (panel.top() + PADDING + 4) as f32;
+        assert_eq!(knob_at(&simulation, x, y), Some(Knob::ArrivalRate));
+    }
+}
@@ -0,0 +1,107 @@
+use crate::direction::Direction;
+use crate::geometry::{CROSSWALK_WIDTH, World};
+use sdl2::rect::Rect;
+
+/// Pixels per second a pedestrian walks at, about an eighth of a car's
+/// cruise, so a crossing takes around eight seconds.
+pub const WALK_SPEED: f32 = 15.0;
+
+/// Side of the square a pedestrian takes up and is drawn as.
+pub const PEDESTRIAN_SIZE: f32 = 6.0;
+
+/// Someone crossing the arm that `arm`'s traffic comes in on, over its
+/// crosswalk from one kerb to the other.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pedestrian {
+    pub id: u64,
+    /// The approach whose arm is being crossed.
+    pub arm: Direction,
+    /// Which kerb they set off from: 1.0 for the one with the larger x or
+    /// y, -1.0 for the other.
+    pub side: f32,
+    /// Pixels covered from where they waited on the pavement.
+    pub walked: f32,
+    /// Whether they have stepped off the kerb; once they have, they keep
+    /// going.
+    pub walking: bool,
+    /// Seconds spent waiting at the kerb.
+    pub waited: f32,
+}
+
+impl Pedestrian {
+    pub fn new(id: u64, arm: Direction, side: f32) -> Self {
+        Self {
+            id,
+            arm,
+            side,
+            walked: 0.0,
+            walking: false,
+            waited: 0.0,
+        }
+    }
+
+    /// Where the pedestrian stands: on the middle of the crosswalk's width,
+    /// starting just off the road and walking across it.
+    pub fn position(&self, world: World) -> (f32, f32) {
+        let across = self.side * (world.half_road() + PEDESTRIAN_SIZE - self.walked);
+        let (x, y) = crosswalk_centre(world, self.arm);
+        match self.arm {
+            Direction::Up | Direction::Down => (x + across, y),
+            Direction::Right | Direction::Left => (x, y + across),
+        }
+    }
+
+    /// Whether they have reached the pavement on the far side.
+    pub fn crossed(&self, world: World) -> bool {
+        self.walked >= world.road_width as f32 + 2.0 * PEDESTRIAN_SIZE
+    }
+
+    /// Whether they are out on the road, rather than on either pavement.
+    pub fn on_road(&self, world: World) -> bool {
+        self.walking && !self.crossed(world)
+    }
+
+    pub fn get_rect(&self, world: World) -> Rect {
+        let (x, y) = self.position(world);
+        let half = PEDESTRIAN_SIZE / 2.0;
+        Rect::new(
+            (x - half) as i32,
+            (y - half) as i32,
+            PEDESTRIAN_SIZE as u32,
+            PEDESTRIAN_SIZE as u32,
+        )
+    }
+}
+
+/// The middle of the crosswalk on `arm`'s approach, just outside the
+/// intersection box.
+pub fn crosswalk_centre(world: World, arm: Direction) -> (f32, f32) {
+    let distance = world.half_road() + CROSSWALK_WIDTH as f32 / 2.0;
+    let (center_x, center_y) = world.centre();
+    let (dx, dy) = arm.delta();
+    (
+        center_x - dx as f32 * distance,
+        center_y - dy as f32 * distance,
+    )
+}
+
+/// Half the width and height of the crosswalk on `arm`'s approach, which
+/// spans the road.
+pub fn crosswalk_half_extents(world: World, arm: Direction) -> (f32, f32) {
+    let (along, across) = (world.half_road(), CROSSWALK_WIDTH as f32 / 2.0);
+    match arm {
+        Direction::Up | Direction::Down => (along, across),
+        Direction::Right | Direction::Left => (across, along),
+    }
+}
+
+pub fn crosswalk_rect(world: World, arm: Direction) -> Rect {
+    let (x, y) = crosswalk_centre(world, arm);
+    let (half_width, half_height) = crosswalk_half_extents(world, arm);
+    Rect::new(
+        (x - half_width) as i32,
+        (y - half_height) as i32,
+        (half_width * 2.0) as u32,
+        (half_height * 2.0) as u32,
+    )
+}
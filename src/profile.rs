@@ -0,0 +1,135 @@
+use std::time::{Duration, Instant};
+
+/// The parts of a tick that are timed separately.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    /// Light and controller updates.
+    Signals,
+    /// Finding each vehicle's leader and deciding whether it must yield.
+    Following,
+    /// Moving every vehicle one step.
+    Movement,
+    /// Pairwise overlap checks and the collision policy.
+    Collisions,
+    /// Starvation and gridlock checks, despawns and spawns.
+    Bookkeeping,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 5] = [
+        Stage::Signals,
+        Stage::Following,
+        Stage::Movement,
+        Stage::Collisions,
+        Stage::Bookkeeping,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Signals => "signals",
+            Stage::Following => "following",
+            Stage::Movement => "movement",
+            Stage::Collisions => "collisions",
+            Stage::Bookkeeping => "bookkeeping",
+        }
+    }
+}
+
+/// Time spent in each stage over a window of ticks, and how many vehicle
+/// pairs were compared. Nothing is timed unless a simulation holds one.
+#[derive(Default)]
+pub struct Profile {
+    totals: [Duration; 5],
+    ticks: u64,
+    pair_checks: u64,
+    peak_vehicles: usize,
+    lap_start: Option<Instant>,
+}
+
+impl Profile {
+    /// Starts timing a tick with `vehicles` on the road.
+    pub fn start_tick(&mut self, vehicles: usize) {
+        self.ticks += 1;
+        self.peak_vehicles = self.peak_vehicles.max(vehicles);
+        self.lap_start = Some(Instant::now());
+    }
+
+    /// Adds the pairs collision detection compared this tick: those the
+    /// spatial grid put in a cell together. Leader search sorts each
+    /// direction instead, so it is left out.
+    pub fn count_pair_checks(&mut self, pairs: usize) {
+        self.pair_checks += pairs as u64;
+    }
+
+    /// Charges the time since the last lap to `stage`.
+    pub fn lap(&mut self, stage: Stage) {
+        let now = Instant::now();
+        if let Some(start) = self.lap_start {
+            self.totals[stage as usize] += now - start;
+        }
+        self.lap_start = Some(now);
+    }
+
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    /// A table of the average time per tick in each stage since the last
+    /// report, then starts a new window.
+    pub fn report(&mut self) -> String {
+        let ticks = self.ticks.max(1) as f64;
+        let total: Duration = self.totals.iter().sum();
+        let mut table = format!(
+            "profile: {} ticks, up to {} vehicles, {} pair checks per tick\n",
+            self.ticks,
+            self.peak_vehicles,
+            self.pair_checks / self.ticks.max(1)
+        );
+        for stage in Stage::ALL {
+            let spent = self.totals[stage as usize];
+            let share = if total.is_zero() {
+                0.0
+            } else {
+                spent.as_secs_f64() / total.as_secs_f64() * 100.0
+            };
+            table.push_str(&format!(
+                "  {:<12}{:>9.1}us {:>5.1}%\n",
+                stage.name(),
+                spent.as_secs_f64() * 1e6 / ticks,
+                share
+            ));
+        }
+        table.push_str(&format!(
+            "  {:<12}{:>9.1}us\n",
+            "tick",
+            total.as_secs_f64() * 1e6 / ticks
+        ));
+        *self = Profile::default();
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_lists_every_stage_and_starts_a_new_window() {
+        let mut profile = Profile::default();
+        for _ in 0..4 {
+            profile.start_tick(10);
+            profile.count_pair_checks(45);
+            for stage in Stage::ALL {
+                profile.lap(stage);
+            }
+        }
+        assert_eq!(profile.ticks(), 4);
+        let report = profile.report();
+        assert!(report.starts_with("profile: 4 ticks, up to 10 vehicles, 45 pair checks"));
+        for stage in Stage::ALL {
+            assert!(report.contains(stage.name()), "{}", report);
+        }
+        assert_eq!(report.lines().count(), 1 + Stage::ALL.len() + 1);
+        assert_eq!(profile.ticks(), 0);
+    }
+}
@@ -0,0 +1,584 @@
+use crate::direction::{Direction, Route};
+use crate::lights::{PhaseCommand, Stage};
+use crate::simulation::TrafficSimulation;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+/// How long a write to a client may block before it is dropped as too slow
+/// to keep up.
+const WRITE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// A scalar from a command line; commands have no nested values.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Value {
+    Str(String),
+    Number(f64),
+    Bool(bool),
+    Null,
+}
+
+/// What a client asked for.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Spawn {
+        approach: Direction,
+        route: Option<Route>,
+    },
+    Switch(usize),
+    Subscribe,
+    Unsubscribe,
+    Telemetry,
+    Pace(Pacing),
+}
+
+/// Commands the main loop carries out, since it owns the clock.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pacing {
+    Pause,
+    Resume,
+    /// Pause, then run this many ticks.
+    Step(u64),
+}
+
+impl Command {
+    /// Reads one line of the protocol.
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let fields = parse_object(line)?;
+        let field = |name: &str| {
+            fields
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value)
+        };
+        let text = |name: &str| match field(name) {
+            Some(Value::Str(value)) => Ok(Some(value.as_str())),
+            None | Some(Value::Null) => Ok(None),
+            Some(_) => Err(format!("\"{}\" must be a string", name)),
+        };
+        let count = |name: &str| match field(name) {
+            Some(Value::Number(value)) if *value >= 0.0 && value.fract() == 0.0 => {
+                Ok(*value as u64)
+            }
+            Some(_) => Err(format!("\"{}\" must be a whole number", name)),
+            None => Err(format!("\"{}\" is missing", name)),
+        };
+        let name = text("command")?.ok_or("\"command\" is missing")?;
+        match name {
+            "spawn" => Ok(Command::Spawn {
+                approach: Direction::parse(text("approach")?.ok_or("\"approach\" is missing")?)?,
+                route: text("route")?.map(Route::parse).transpose()?,
+            }),
+            "switch" => Ok(Command::Switch(count("phase")? as usize)),
+            "subscribe" => Ok(Command::Subscribe),
+            "unsubscribe" => Ok(Command::Unsubscribe),
+            "telemetry" => Ok(Command::Telemetry),
+            "pause" => Ok(Command::Pace(Pacing::Pause)),
+            "resume" => Ok(Command::Pace(Pacing::Resume)),
+            "step" => Ok(Command::Pace(Pacing::Step(count("ticks")?))),
+            _ => Err(format!("Unknown command: {}", name)),
+        }
+    }
+}
+
+// A flat JSON object, its fields in order.
+pub(crate) fn parse_object(line: &str) -> Result<Vec<(String, Value)>, String> {
+    let mut chars = line.trim().chars().peekable();
+    let mut fields = Vec::new();
+    let skip_space = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    };
+    if chars.next() != Some('{') {
+        return Err("Expected a JSON object".to_string());
+    }
+    skip_space(&mut chars);
+    if chars.next_if_eq(&'}').is_some() {
+        return Ok(fields);
+    }
+    loop {
+        skip_space(&mut chars);
+        let Value::Str(key) = parse_value(&mut chars)? else {
+            return Err("Expected a string key".to_string());
+        };
+        skip_space(&mut chars);
+        if chars.next() != Some(':') {
+            return Err(format!("Expected ':' after \"{}\"", key));
+        }
+        skip_space(&mut chars);
+        fields.push((key, parse_value(&mut chars)?));
+        skip_space(&mut chars);
+        match chars.next() {
+            Some(',') => continue,
+            Some('}') => break,
+            _ => return Err("Expected ',' or '}'".to_string()),
+        }
+    }
+    if chars.next().is_some() {
+        return Err("Unexpected text after the object".to_string());
+    }
+    Ok(fields)
+}
+
+fn parse_value(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<Value, String> {
+    match chars.peek() {
+        Some('"') => {
+            chars.next();
+            let mut text = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => return Ok(Value::Str(text)),
+                    Some('\\') => match chars.next() {
+                        Some('n') => text.push('\n'),
+                        Some('t') => text.push('\t'),
+                        Some('r') => text.push('\r'),
+                        Some('u') => {
+                            let hex: String = chars.by_ref().take(4).collect();
+                            let code = u32::from_str_radix(&hex, 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| format!("Bad escape: \\u{}", hex))?;
+                            text.push(code);
+                        }
+                        Some(c @ ('"' | '\\' | '/')) => text.push(c),
+                        _ => return Err("Bad escape in a string".to_string()),
+                    },
+                    Some(c) => text.push(c),
+                    None => return Err("Unterminated string".to_string()),
+                }
+            }
+        }
+        Some('{' | '[') => Err("Nested values are not supported".to_string()),
+        Some(_) => {
+            let mut word = String::new();
+            while let Some(c) = chars.next_if(|c| !matches!(c, ',' | '}') && !c.is_whitespace()) {
+                word.push(c);
+            }
+            match word.as_str() {
+                "true" => Ok(Value::Bool(true)),
+                "false" => Ok(Value::Bool(false)),
+                "null" => Ok(Value::Null),
+                _ => word
+                    .parse()
+                    .map(Value::Number)
+                    .map_err(|_| format!("Bad value: {}", word)),
+            }
+        }
+        None => Err("Expected a value".to_string()),
+    }
+}
+
+/// `text` as a JSON string, quotes included.
+fn quoted(text: &str) -> String {
+    let mut out = String::from('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn reply(fields: &[(&str, String)]) -> String {
+    let mut line = String::from("{\"type\": \"reply\", \"ok\": true");
+    for (name, value) in fields {
+        line.push_str(&format!(", \"{}\": {}", name, value));
+    }
+    line.push('}');
+    line
+}
+
+fn failure(error: &str) -> String {
+    format!(
+        "{{\"type\": \"reply\", \"ok\": false, \"error\": {}}}",
+        quoted(error)
+    )
+}
+
+/// One line describing the tick just run: the lights, as `null` when the
+/// run has none, each vehicle with its speed in pixels per second, and the
+/// stats the `/metrics` endpoint serves.
+pub fn telemetry(simulation: &TrafficSimulation) -> String {
+    let config = &simulation.config;
+    let lights = if config.signalled() {
+        let lights = simulation.lights();
+        let stage = match lights.stage() {
+            Stage::Phase => "phase",
+            Stage::Yellow => "yellow",
+            Stage::AllRed => "all-red",
+        };
+        let heads: Vec<String> = Direction::ALL
+            .into_iter()
+            .map(|approach| {
+                let aspect = if lights.is_green(approach) {
+                    "green"
+                } else if lights.is_yellow(approach) {
+                    "yellow"
+                } else {
+                    "red"
+                };
+                format!("\"{}\": \"{}\"", approach, aspect)
+            })
+            .collect();
+        format!(
+            "{{\"phase\": {}, \"stage\": \"{}\", {}}}",
+            lights.phase(),
+            stage,
+            heads.join(", ")
+        )
+    } else {
+        "null".to_string()
+    };
+    let vehicles: Vec<String> = simulation
+        .vehicles
+        .iter()
+        .map(|vehicle| {
+            format!(
+                "{{\"id\": {}, \"kind\": \"{}\", \"approach\": \"{}\", \"route\": \"{}\", \"x\": {:.2}, \"y\": {:.2}, \"speed\": {:.2}}}",
+                vehicle.id,
+                vehicle.kind.name(),
+                vehicle.initial_direction,
+                vehicle.route.name(),
+                vehicle.x,
+                vehicle.y,
+                vehicle.velocity * config.tick_rate
+            )
+        })
+        .collect();
+    format!(
+        "{{\"type\": \"telemetry\", \"tick\": {}, \"lights\": {}, \"vehicles\": [{}], \"stats\": {}}}",
+        simulation.tick,
+        lights,
+        vehicles.join(", "),
+        simulation.stats().json()
+    )
+}
+
+enum Incoming {
+    Connected(u64, TcpStream),
+    Line(u64, String),
+    Closed(u64),
+}
+
+struct Client {
+    id: u64,
+    stream: TcpStream,
+    subscribed: bool,
+}
+
+/// Remote control over a local TCP socket, for `--remote`. Clients are
+/// accepted on a background thread and read on one thread each; `poll`
+/// carries out their commands on the simulation's thread.
+///
+/// Each line a client sends is one JSON object naming a `command`, and each
+/// gets one reply line back:
+///
+/// - `{"command": "spawn", "approach": "up", "route": "left"}` spawns as a
+///   key press would, cooldown included; `route` may be left out for a
+///   random one. The reply's `spawned` says whether it went on the road,
+///   and `held` how many presses the approach is holding until it can.
+/// - `{"command": "switch", "phase": 2}` starts the change to a plan phase
+///   as `TrafficLights::command` would; the reply's `accepted` says
+///   whether it did.
+/// - `{"command": "subscribe"}` and `{"command": "unsubscribe"}` start and
+///   stop a telemetry line after every tick; `{"command": "telemetry"}`
+///   sends one now.
+/// - `{"command": "pause"}`, `{"command": "resume"}` and
+///   `{"command": "step", "ticks": 10}` pace the run, as Space and Period
+///   do; a step pauses first, so an agent can run in lockstep.
+///
+/// Replies are `{"type": "reply", "ok": true, ...}`, or `"ok": false` with
+/// an `error`. Telemetry lines are `{"type": "telemetry", ...}` with the
+/// tick, the lights, every vehicle and the run's `Stats`.
+pub struct Remote {
+    address: SocketAddr,
+    incoming: Receiver<Incoming>,
+    clients: Vec<Client>,
+}
+
+impl Remote {
+    /// Listens on `address`, or on 127.0.0.1 if it is only a port.
+    pub fn listen(address: &str) -> Result<Self, String> {
+        let address = if address.parse::<u16>().is_ok() {
+            format!("127.0.0.1:{}", address)
+        } else {
+            address.to_string()
+        };
+        let listener = TcpListener::bind(&address)
+            .map_err(|e| format!("Could not listen on {}: {}", address, e))?;
+        let bound = listener
+            .local_addr()
+            .map_err(|e| format!("Could not listen on {}: {}", address, e))?;
+        let (sender, incoming) = mpsc::channel();
+        thread::spawn(move || accept(listener, sender));
+        info!("Accepting remote control on {}", bound);
+        Ok(Self {
+            address: bound,
+            incoming,
+            clients: Vec::new(),
+        })
+    }
+
+    /// Where clients connect, with the port picked if 0 was asked for.
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Carries out every command that has come in since the last call and
+    /// answers it, returning the pacing ones for the loop. `wall_millis`
+    /// times spawn cooldowns, as for key presses.
+    pub fn poll(&mut self, simulation: &mut TrafficSimulation, wall_millis: u128) -> Vec<Pacing> {
+        let mut pacing = Vec::new();
+        while let Ok(incoming) = self.incoming.try_recv() {
+            let (id, line) = match incoming {
+                Incoming::Connected(id, stream) => {
+                    self.clients.push(Client {
+                        id,
+                        stream,
+                        subscribed: false,
+                    });
+                    continue;
+                }
+                Incoming::Closed(id) => {
+                    self.clients.retain(|client| client.id != id);
+                    continue;
+                }
+                Incoming::Line(id, line) => (id, line),
+            };
+            let Some(client) = self.clients.iter_mut().find(|client| client.id == id) else {
+                continue;
+            };
+            let answer = match Command::parse(&line) {
+                Ok(Command::Spawn { approach, route }) => {
+                    let spawned = simulation.request_spawn(approach, route, wall_millis);
+                    let held = simulation.held_spawns()[approach.index()];
+                    reply(&[("spawned", spawned.to_string()), ("held", held.to_string())])
+                }
+                Ok(Command::Switch(phase)) => {
+                    let accepted = simulation.command_lights(PhaseCommand::Switch(phase));
+                    reply(&[("accepted", accepted.to_string())])
+                }
+                Ok(Command::Subscribe) => {
+                    client.subscribed = true;
+                    reply(&[])
+                }
+                Ok(Command::Unsubscribe) => {
+                    client.subscribed = false;
+                    reply(&[])
+                }
+                Ok(Command::Telemetry) => telemetry(simulation),
+                Ok(Command::Pace(pace)) => {
+                    pacing.push(pace);
+                    reply(&[])
+                }
+                Err(e) => failure(&e),
+            };
+            if !send(&mut client.stream, &answer) {
+                self.clients.retain(|client| client.id != id);
+            }
+        }
+        pacing
+    }
+
+    /// Sends the tick just run to every subscriber, dropping any that
+    /// cannot keep up.
+    pub fn broadcast(&mut self, simulation: &TrafficSimulation) {
+        if !self.clients.iter().any(|client| client.subscribed) {
+            return;
+        }
+        let line = telemetry(simulation);
+        self.clients
+            .retain_mut(|client| !client.subscribed || send(&mut client.stream, &line));
+    }
+}
+
+// Writes one line, warning and returning false if the client has gone or
+// is too slow.
+fn send(stream: &mut TcpStream, line: &str) -> bool {
+    match stream.write_all(format!("{}\n", line).as_bytes()) {
+        Ok(()) => true,
+        Err(e) => {
+            warn!("Dropped a remote client: {}", e);
+            false
+        }
+    }
+}
+
+fn accept(listener: TcpListener, sender: Sender<Incoming>) {
+    for (id, stream) in listener.incoming().enumerate() {
+        let id = id as u64;
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let Ok(writer) = stream.try_clone() else {
+            continue;
+        };
+        if let Err(e) = writer.set_write_timeout(Some(WRITE_TIMEOUT)) {
+            warn!("Could not set up a remote client: {}", e);
+            continue;
+        }
+        if sender.send(Incoming::Connected(id, writer)).is_err() {
+            return;
+        }
+        let sender = sender.clone();
+        thread::spawn(move || {
+            for line in BufReader::new(stream).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if !line.trim().is_empty() && sender.send(Incoming::Line(id, line)).is_err() {
+                    return;
+                }
+            }
+            let _ = sender.send(Incoming::Closed(id));
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use std::time::Instant;
+
+    #[test]
+    fn commands_are_read_from_flat_json_objects() {
+        assert_eq!(
+            Command::parse(r#" {"command": "spawn", "approach": "up", "route": "left"} "#),
+            Ok(Command::Spawn {
+                approach: Direction::Up,
+                route: Some(Route::TurnLeft),
+            })
+        );
+        assert_eq!(
+            Command::parse(r#"{"approach":"right","command":"spawn","route":null}"#),
+            Ok(Command::Spawn {
+                approach: Direction::Right,
+                route: None,
+            })
+        );
+        assert_eq!(
+            Command::parse(r#"{"command": "switch", "phase": 2}"#),
+            Ok(Command::Switch(2))
+        );
+        assert_eq!(
+            Command::parse(r#"{"command": "step", "ticks": 10}"#),
+            Ok(Command::Pace(Pacing::Step(10)))
+        );
+        assert_eq!(
+            Command::parse(r#"{"command": "subscribe"}"#),
+            Ok(Command::Subscribe)
+        );
+        assert!(Command::parse(r#"{"command": "switch", "phase": 1.5}"#).is_err());
+        assert!(Command::parse(r#"{"command": "spawn"}"#).is_err());
+        assert!(Command::parse(r#"{"command": "fly"}"#).is_err());
+        assert!(Command::parse(r#"{"command": "step", "ticks": [1]}"#).is_err());
+        assert!(Command::parse(r#"{"command": "pause"} trailing"#).is_err());
+        assert!(Command::parse("pause").is_err());
+        assert_eq!(
+            failure("Unknown command: \"x\""),
+            r#"{"type": "reply", "ok": false, "error": "Unknown command: \"x\""}"#
+        );
+    }
+
+    struct TestClient {
+        writer: TcpStream,
+        reader: BufReader<TcpStream>,
+        pacing: Vec<Pacing>,
+    }
+
+    impl TestClient {
+        fn connect(remote: &Remote) -> Self {
+            let stream = TcpStream::connect(remote.address()).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_millis(10)))
+                .unwrap();
+            Self {
+                writer: stream.try_clone().unwrap(),
+                reader: BufReader::new(stream),
+                pacing: Vec::new(),
+            }
+        }
+
+        // Sends `command` and waits for the next line back, polling the
+        // remote meanwhile as the main loop would.
+        fn ask(
+            &mut self,
+            command: &str,
+            remote: &mut Remote,
+            simulation: &mut TrafficSimulation,
+        ) -> String {
+            writeln!(self.writer, "{}", command).unwrap();
+            let deadline = Instant::now() + Duration::from_secs(5);
+            let mut line = String::new();
+            while Instant::now() < deadline {
+                self.pacing.extend(remote.poll(simulation, 0));
+                if self.reader.read_line(&mut line).is_ok() && line.ends_with('\n') {
+                    return line.trim_end().to_string();
+                }
+            }
+            panic!("no answer to {} in time", command);
+        }
+    }
+
+    #[test]
+    fn a_client_spawns_switches_and_gets_telemetry_every_tick() {
+        let mut simulation = TrafficSimulation::new(Config::default());
+        let mut remote = Remote::listen("127.0.0.1:0").unwrap();
+        let mut client = TestClient::connect(&remote);
+        let mut ask = |command: &str, remote: &mut Remote, simulation: &mut TrafficSimulation| {
+            client.ask(command, remote, simulation)
+        };
+
+        let answer = ask(
+            r#"{"command": "spawn", "approach": "right", "route": "straight"}"#,
+            &mut remote,
+            &mut simulation,
+        );
+        assert_eq!(
+            answer,
+            r#"{"type": "reply", "ok": true, "spawned": true, "held": 0}"#
+        );
+        assert_eq!(simulation.vehicles.len(), 1);
+        // The first green has not yet run its minimum.
+        let switch = r#"{"command": "switch", "phase": 2}"#;
+        let answer = ask(switch, &mut remote, &mut simulation);
+        assert_eq!(
+            answer,
+            r#"{"type": "reply", "ok": true, "accepted": false}"#
+        );
+        simulation.run_ticks(60 * 3);
+        let answer = ask(switch, &mut remote, &mut simulation);
+        assert_eq!(answer, r#"{"type": "reply", "ok": true, "accepted": true}"#);
+        let answer = ask(r#"{"command": "warp"}"#, &mut remote, &mut simulation);
+        assert!(answer.starts_with(r#"{"type": "reply", "ok": false, "error": "Unknown"#));
+        let step = r#"{"command": "step", "ticks": 3}"#;
+        let answer = ask(step, &mut remote, &mut simulation);
+        assert_eq!(answer, r#"{"type": "reply", "ok": true}"#);
+        ask(r#"{"command": "subscribe"}"#, &mut remote, &mut simulation);
+
+        simulation.update();
+        remote.broadcast(&simulation);
+        let line = ask(
+            r#"{"command": "unsubscribe"}"#,
+            &mut remote,
+            &mut simulation,
+        );
+        assert!(line.starts_with(&format!(
+            "{{\"type\": \"telemetry\", \"tick\": {}, \"lights\": {{\"phase\": 2, \"stage\": \"phase\", \"up\": \"red\", \"down\": \"red\", \"right\": \"green\"",
+            simulation.tick
+        )));
+        assert!(line.contains(
+            "\"vehicles\": [{\"id\": 0, \"kind\": \"car\", \"approach\": \"right\", \"route\": \"straight\", \"x\": "
+        ));
+        assert!(line.contains("\"stats\": {\"tick\": "));
+        assert!(line.ends_with('}'));
+        assert_eq!(client.pacing, [Pacing::Step(3)]);
+    }
+}
@@ -0,0 +1,452 @@
+use crate::geometry::{Bend, Camera, World};
+use crate::vehicle::VehicleKind;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+/// The drawing calls the simulation makes, so a frame can go to a window or
+/// be recorded in a test.
+pub trait Renderer {
+    fn set_draw_color(&mut self, color: Color);
+    fn clear(&mut self);
+    fn fill_rect(&mut self, rect: Rect) -> Result<(), String>;
+    fn draw_rect(&mut self, rect: Rect) -> Result<(), String>;
+    fn draw_line(&mut self, start: (i32, i32), end: (i32, i32)) -> Result<(), String>;
+    fn present(&mut self);
+
+    /// Draws `kind`'s sprite `size` pixels long and wide, centred on
+    /// `centre`, turned `angle` radians clockwise from pointing right and
+    /// tinted `tint`. Returns false, having drawn nothing, when there is no
+    /// sprite to draw, so the caller can draw rectangles instead.
+    fn draw_sprite(
+        &mut self,
+        _kind: VehicleKind,
+        _centre: (i32, i32),
+        _size: (u32, u32),
+        _angle: f32,
+        _tint: Color,
+    ) -> Result<bool, String> {
+        Ok(false)
+    }
+}
+
+impl Renderer for Canvas<Window> {
+    fn set_draw_color(&mut self, color: Color) {
+        Canvas::set_draw_color(self, color);
+    }
+
+    fn clear(&mut self) {
+        Canvas::clear(self);
+    }
+
+    fn fill_rect(&mut self, rect: Rect) -> Result<(), String> {
+        Canvas::fill_rect(self, rect)
+    }
+
+    fn draw_rect(&mut self, rect: Rect) -> Result<(), String> {
+        Canvas::draw_rect(self, rect)
+    }
+
+    fn draw_line(&mut self, start: (i32, i32), end: (i32, i32)) -> Result<(), String> {
+        Canvas::draw_line(self, start, end)
+    }
+
+    fn present(&mut self) {
+        Canvas::present(self);
+    }
+}
+
+/// Passes every call on with its coordinates taken through `camera`, from
+/// the world to the window, so the world can be panned and zoomed while
+/// overlays stay put.
+pub struct Viewed<'a> {
+    inner: &'a mut dyn Renderer,
+    camera: Camera,
+}
+
+impl<'a> Viewed<'a> {
+    pub fn new(inner: &'a mut dyn Renderer, camera: Camera) -> Self {
+        Self { inner, camera }
+    }
+
+    fn point(&self, (x, y): (i32, i32)) -> (i32, i32) {
+        self.camera.to_screen(x as f32, y as f32)
+    }
+
+    // Both corners are taken through the camera, so shapes that meet in
+    // the world still meet once zoomed. None shrinks below a pixel.
+    fn rect(&self, rect: Rect) -> Rect {
+        let (left, top) = self.point((rect.left(), rect.top()));
+        let (right, bottom) = self.point((rect.right(), rect.bottom()));
+        let size = |length: i32| length.max(1) as u32;
+        Rect::new(left, top, size(right - left), size(bottom - top))
+    }
+}
+
+impl Renderer for Viewed<'_> {
+    fn set_draw_color(&mut self, color: Color) {
+        self.inner.set_draw_color(color);
+    }
+
+    fn clear(&mut self) {
+        self.inner.clear();
+    }
+
+    fn fill_rect(&mut self, rect: Rect) -> Result<(), String> {
+        let rect = self.rect(rect);
+        self.inner.fill_rect(rect)
+    }
+
+    fn draw_rect(&mut self, rect: Rect) -> Result<(), String> {
+        let rect = self.rect(rect);
+        self.inner.draw_rect(rect)
+    }
+
+    fn draw_line(&mut self, start: (i32, i32), end: (i32, i32)) -> Result<(), String> {
+        let (start, end) = (self.point(start), self.point(end));
+        self.inner.draw_line(start, end)
+    }
+
+    fn present(&mut self) {
+        self.inner.present();
+    }
+
+    fn draw_sprite(
+        &mut self,
+        kind: VehicleKind,
+        centre: (i32, i32),
+        (length, width): (u32, u32),
+        angle: f32,
+        tint: Color,
+    ) -> Result<bool, String> {
+        let zoom = self.camera.zoom;
+        let size = |length: u32| ((length as f32 * zoom).round() as u32).max(1);
+        self.inner.draw_sprite(
+            kind,
+            self.point(centre),
+            (size(length), size(width)),
+            angle,
+            tint,
+        )
+    }
+}
+
+/// Draws a whole frame of `world` into a smaller rectangle of the window:
+/// coordinates are scaled by `scale`, then moved by `dx`, `dy`. Clearing
+/// fills only the rectangle and presenting is left to the caller, so
+/// several frames can share one window.
+pub struct Inset<'a> {
+    inner: &'a mut dyn Renderer,
+    world: World,
+    scale: f32,
+    dx: i32,
+    dy: i32,
+}
+
+impl<'a> Inset<'a> {
+    pub fn new(
+        inner: &'a mut dyn Renderer,
+        world: World,
+        scale: f32,
+        (dx, dy): (i32, i32),
+    ) -> Self {
+        Self {
+            inner,
+            world,
+            scale,
+            dx,
+            dy,
+        }
+    }
+
+    fn point(&self, (x, y): (i32, i32)) -> (i32, i32) {
+        (
+            (x as f32 * self.scale).floor() as i32 + self.dx,
+            (y as f32 * self.scale).floor() as i32 + self.dy,
+        )
+    }
+
+    // Sizes never shrink below a pixel, so thin lines and small lamps stay
+    // visible.
+    fn rect(&self, rect: Rect) -> Rect {
+        let (x, y) = self.point((rect.x(), rect.y()));
+        let size = |length: u32| ((length as f32 * self.scale).round() as u32).max(1);
+        Rect::new(x, y, size(rect.width()), size(rect.height()))
+    }
+}
+
+impl Renderer for Inset<'_> {
+    fn set_draw_color(&mut self, color: Color) {
+        self.inner.set_draw_color(color);
+    }
+
+    fn clear(&mut self) {
+        let area = self.rect(self.world.bounds());
+        if let Err(e) = self.inner.fill_rect(area) {
+            warn!("Could not clear an inset: {}", e);
+        }
+    }
+
+    fn fill_rect(&mut self, rect: Rect) -> Result<(), String> {
+        let rect = self.rect(rect);
+        self.inner.fill_rect(rect)
+    }
+
+    fn draw_rect(&mut self, rect: Rect) -> Result<(), String> {
+        let rect = self.rect(rect);
+        self.inner.draw_rect(rect)
+    }
+
+    fn draw_line(&mut self, start: (i32, i32), end: (i32, i32)) -> Result<(), String> {
+        let (start, end) = (self.point(start), self.point(end));
+        self.inner.draw_line(start, end)
+    }
+
+    fn present(&mut self) {}
+
+    fn draw_sprite(
+        &mut self,
+        kind: VehicleKind,
+        centre: (i32, i32),
+        (length, width): (u32, u32),
+        angle: f32,
+        tint: Color,
+    ) -> Result<bool, String> {
+        let size = |length: u32| ((length as f32 * self.scale).round() as u32).max(1);
+        self.inner.draw_sprite(
+            kind,
+            self.point(centre),
+            (size(length), size(width)),
+            angle,
+            tint,
+        )
+    }
+}
+
+/// Pixels in each slice of a shape, or piece of a line, that `Bent` moves
+/// as one.
+const BEND_SLICE: i32 = 4;
+
+/// Draws the straight layout along curved approaches: shapes are cut into
+/// slices across their length and lines into short pieces, each moved by
+/// the bend where it lies. A straight bend passes every call on unchanged.
+pub struct Bent<'a> {
+    inner: &'a mut dyn Renderer,
+    bend: Bend,
+}
+
+impl<'a> Bent<'a> {
+    pub fn new(inner: &'a mut dyn Renderer, bend: Bend) -> Self {
+        Self { inner, bend }
+    }
+
+    fn point(&self, (x, y): (i32, i32)) -> (i32, i32) {
+        let (dx, dy) = self.bend.offset(x as f32, y as f32);
+        (x + dx.round() as i32, y + dy.round() as i32)
+    }
+}
+
+impl Renderer for Bent<'_> {
+    fn set_draw_color(&mut self, color: Color) {
+        self.inner.set_draw_color(color);
+    }
+
+    fn clear(&mut self) {
+        self.inner.clear();
+    }
+
+    fn fill_rect(&mut self, rect: Rect) -> Result<(), String> {
+        if self.bend.is_straight() {
+            return self.inner.fill_rect(rect);
+        }
+        let across = rect.width() >= rect.height();
+        let length = if across { rect.width() } else { rect.height() } as i32;
+        let mut from = 0;
+        while from < length {
+            let size = BEND_SLICE.min(length - from);
+            let slice = if across {
+                Rect::new(rect.x() + from, rect.y(), size as u32, rect.height())
+            } else {
+                Rect::new(rect.x(), rect.y() + from, rect.width(), size as u32)
+            };
+            let center = self.point(slice.center().into());
+            self.inner
+                .fill_rect(Rect::from_center(center, slice.width(), slice.height()))?;
+            from += size;
+        }
+        Ok(())
+    }
+
+    fn draw_rect(&mut self, rect: Rect) -> Result<(), String> {
+        if self.bend.is_straight() {
+            return self.inner.draw_rect(rect);
+        }
+        let (left, top) = (rect.left(), rect.top());
+        let (right, bottom) = (rect.right() - 1, rect.bottom() - 1);
+        self.draw_line((left, top), (right, top))?;
+        self.draw_line((right, top), (right, bottom))?;
+        self.draw_line((right, bottom), (left, bottom))?;
+        self.draw_line((left, bottom), (left, top))
+    }
+
+    fn draw_line(&mut self, start: (i32, i32), end: (i32, i32)) -> Result<(), String> {
+        if self.bend.is_straight() {
+            return self.inner.draw_line(start, end);
+        }
+        let length = (end.0 - start.0).abs().max((end.1 - start.1).abs());
+        let pieces = (length / BEND_SLICE).max(1);
+        let along = |piece: i32| {
+            (
+                start.0 + (end.0 - start.0) * piece / pieces,
+                start.1 + (end.1 - start.1) * piece / pieces,
+            )
+        };
+        for piece in 0..pieces {
+            let (from, to) = (self.point(along(piece)), self.point(along(piece + 1)));
+            self.inner.draw_line(from, to)?;
+        }
+        Ok(())
+    }
+
+    fn present(&mut self) {
+        self.inner.present();
+    }
+
+    // A sprite is moved whole, by the bend at its centre.
+    fn draw_sprite(
+        &mut self,
+        kind: VehicleKind,
+        centre: (i32, i32),
+        size: (u32, u32),
+        angle: f32,
+        tint: Color,
+    ) -> Result<bool, String> {
+        self.inner
+            .draw_sprite(kind, self.point(centre), size, angle, tint)
+    }
+}
+
+/// One recorded call, with the colour that was current when it was made.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DrawCall {
+    Clear(Color),
+    FillRect(Rect, Color),
+    DrawRect(Rect, Color),
+    Line((i32, i32), (i32, i32), Color),
+    Present,
+    /// Kind, centre, size and angle in whole degrees, with the tint in
+    /// place of the draw colour.
+    Sprite(VehicleKind, (i32, i32), (u32, u32), i32, Color),
+}
+
+/// Keeps every call instead of drawing it.
+pub struct RecordingRenderer {
+    pub calls: Vec<DrawCall>,
+    color: Color,
+    /// Whether to take sprites, as a window that loaded them would, or
+    /// turn them down as one without them does.
+    pub sprites: bool,
+}
+
+impl Default for RecordingRenderer {
+    fn default() -> Self {
+        Self {
+            calls: Vec::new(),
+            color: Color::RGB(0, 0, 0),
+            sprites: false,
+        }
+    }
+}
+
+impl RecordingRenderer {
+    /// One call per line, for reading a frame or updating a test's
+    /// expectations.
+    pub fn dump(&self) -> String {
+        let rgb = |color: Color| format!("rgb({}, {}, {})", color.r, color.g, color.b);
+        let rect = |rect: Rect| {
+            format!(
+                "{},{} {}x{}",
+                rect.x(),
+                rect.y(),
+                rect.width(),
+                rect.height()
+            )
+        };
+        let mut dump = String::new();
+        for call in &self.calls {
+            let line = match *call {
+                DrawCall::Clear(color) => format!("clear {}", rgb(color)),
+                DrawCall::FillRect(r, color) => format!("fill {} {}", rect(r), rgb(color)),
+                DrawCall::DrawRect(r, color) => format!("rect {} {}", rect(r), rgb(color)),
+                DrawCall::Line(start, end, color) => format!(
+                    "line {},{} {},{} {}",
+                    start.0,
+                    start.1,
+                    end.0,
+                    end.1,
+                    rgb(color)
+                ),
+                DrawCall::Present => "present".to_string(),
+                DrawCall::Sprite(kind, centre, size, angle, tint) => format!(
+                    "sprite {} {},{} {}x{} {}deg {}",
+                    kind.name(),
+                    centre.0,
+                    centre.1,
+                    size.0,
+                    size.1,
+                    angle,
+                    rgb(tint)
+                ),
+            };
+            dump.push_str(&line);
+            dump.push('\n');
+        }
+        dump
+    }
+}
+
+impl Renderer for RecordingRenderer {
+    fn set_draw_color(&mut self, color: Color) {
+        self.color = color;
+    }
+
+    fn clear(&mut self) {
+        self.calls.push(DrawCall::Clear(self.color));
+    }
+
+    fn fill_rect(&mut self, rect: Rect) -> Result<(), String> {
+        self.calls.push(DrawCall::FillRect(rect, self.color));
+        Ok(())
+    }
+
+    fn draw_rect(&mut self, rect: Rect) -> Result<(), String> {
+        self.calls.push(DrawCall::DrawRect(rect, self.color));
+        Ok(())
+    }
+
+    fn draw_line(&mut self, start: (i32, i32), end: (i32, i32)) -> Result<(), String> {
+        self.calls.push(DrawCall::Line(start, end, self.color));
+        Ok(())
+    }
+
+    fn present(&mut self) {
+        self.calls.push(DrawCall::Present);
+    }
+
+    fn draw_sprite(
+        &mut self,
+        kind: VehicleKind,
+        centre: (i32, i32),
+        size: (u32, u32),
+        angle: f32,
+        tint: Color,
+    ) -> Result<bool, String> {
+        if self.sprites {
+            let degrees = angle.to_degrees().round() as i32;
+            self.calls
+                .push(DrawCall::Sprite(kind, centre, size, degrees, tint));
+        }
+        Ok(self.sprites)
+    }
+}
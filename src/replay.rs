@@ -0,0 +1,263 @@
+use crate::direction::{Direction, Route};
+use crate::vehicle::VehicleKind;
+use sdl2::pixels::Color;
+use std::fs;
+use std::path::Path;
+
+/// A vehicle as it was put on the road, with everything that was drawn at
+/// random for it, so a replay needs no random numbers of its own.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VehicleSpawn {
+    pub approach: Direction,
+    pub route: Route,
+    pub kind: VehicleKind,
+    /// Cruise speed in pixels per tick, before `VehicleKind::speed_factor`.
+    pub speed: f32,
+    pub color: Color,
+    pub tie_break: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Spawn {
+    Vehicle(VehicleSpawn),
+    /// Vehicles added to, or with a negative change taken from, an
+    /// approach's queue of arrivals waiting for their spawn point.
+    Queued {
+        approach: Direction,
+        change: i64,
+    },
+    Pedestrian {
+        arm: Direction,
+        side: f32,
+    },
+    /// A spawn turned away because the spawn point was taken.
+    Refused {
+        approach: Direction,
+    },
+}
+
+/// One spawn and when it happened: on `tick`, either during that tick's
+/// update or after it, between ticks, as key presses are.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpawnRecord {
+    pub tick: u64,
+    pub between_ticks: bool,
+    pub spawn: Spawn,
+}
+
+/// Every spawn of a run, in the order they happened, and the seed it ran
+/// with. Replayed into a run with the same settings it reproduces the
+/// original tick for tick.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Recording {
+    pub seed: u64,
+    pub spawns: Vec<SpawnRecord>,
+}
+
+impl Recording {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            spawns: Vec::new(),
+        }
+    }
+
+    /// One line per spawn after a `seed` line. Speeds are written in full
+    /// so they read back to the same bits.
+    pub fn to_text(&self) -> String {
+        let mut lines = vec![
+            "# Spawns recorded by road_intersection; play back with --replay".to_string(),
+            format!("seed {}", self.seed),
+        ];
+        for record in &self.spawns {
+            let when = if record.between_ticks {
+                "between"
+            } else {
+                "during"
+            };
+            let what = match &record.spawn {
+                Spawn::Vehicle(vehicle) => format!(
+                    "vehicle {} {} {} {:?} {} {} {} {}",
+                    vehicle.approach,
+                    vehicle.route.name(),
+                    vehicle.kind.name(),
+                    vehicle.speed,
+                    vehicle.color.r,
+                    vehicle.color.g,
+                    vehicle.color.b,
+                    vehicle.tie_break
+                ),
+                Spawn::Queued { approach, change } => format!("queued {} {}", approach, change),
+                Spawn::Pedestrian { arm, side } => format!("pedestrian {} {:?}", arm, side),
+                Spawn::Refused { approach } => format!("refused {}", approach),
+            };
+            lines.push(format!("{} {} {}", record.tick, when, what));
+        }
+        lines.join("\n") + "\n"
+    }
+
+    /// Reads back what `to_text` wrote.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut seed = None;
+        let mut spawns: Vec<SpawnRecord> = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fail = |e: String| format!("line {}: {}", number + 1, e);
+            if let Some(value) = line.strip_prefix("seed ") {
+                seed = Some(parse_field("seed", value).map_err(fail)?);
+                continue;
+            }
+            let record = parse_record(line).map_err(fail)?;
+            if let Some(last) = spawns.last()
+                && last.tick > record.tick
+            {
+                return Err(fail("spawns must be in tick order".to_string()));
+            }
+            spawns.push(record);
+        }
+        let seed = seed.ok_or("The recording has no seed line")?;
+        Ok(Self { seed, spawns })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        Self::parse(&contents).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.to_text())
+            .map_err(|e| format!("Could not write {}: {}", path.display(), e))
+    }
+}
+
+fn parse_field<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid {}: {}", name, value))
+}
+
+fn parse_record(line: &str) -> Result<SpawnRecord, String> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let field = |index: usize| {
+        fields
+            .get(index)
+            .copied()
+            .ok_or_else(|| format!("Too few fields: {}", line))
+    };
+    let tick = parse_field("tick", field(0)?)?;
+    let between_ticks = match field(1)? {
+        "between" => true,
+        "during" => false,
+        other => return Err(format!("Expected between or during: {}", other)),
+    };
+    let (spawn, count) = match field(2)? {
+        "vehicle" => (
+            Spawn::Vehicle(VehicleSpawn {
+                approach: Direction::parse(field(3)?)?,
+                route: Route::parse(field(4)?)?,
+                kind: VehicleKind::parse(field(5)?)?,
+                speed: parse_field("speed", field(6)?)?,
+                color: Color::RGB(
+                    parse_field("colour", field(7)?)?,
+                    parse_field("colour", field(8)?)?,
+                    parse_field("colour", field(9)?)?,
+                ),
+                tie_break: parse_field("tie-break", field(10)?)?,
+            }),
+            11,
+        ),
+        "queued" => (
+            Spawn::Queued {
+                approach: Direction::parse(field(3)?)?,
+                change: parse_field("queue change", field(4)?)?,
+            },
+            5,
+        ),
+        "pedestrian" => (
+            Spawn::Pedestrian {
+                arm: Direction::parse(field(3)?)?,
+                side: parse_field("side", field(4)?)?,
+            },
+            5,
+        ),
+        "refused" => (
+            Spawn::Refused {
+                approach: Direction::parse(field(3)?)?,
+            },
+            4,
+        ),
+        other => return Err(format!("Unknown spawn: {}", other)),
+    };
+    if fields.len() != count {
+        return Err(format!("Too many fields: {}", line));
+    }
+    Ok(SpawnRecord {
+        tick,
+        between_ticks,
+        spawn,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_recording_reads_back_to_the_same_spawns() {
+        let recording = Recording {
+            seed: 17,
+            spawns: vec![
+                SpawnRecord {
+                    tick: 0,
+                    between_ticks: true,
+                    spawn: Spawn::Vehicle(VehicleSpawn {
+                        approach: Direction::Left,
+                        route: Route::TurnRight,
+                        kind: VehicleKind::Emergency,
+                        speed: 1.0 / 3.0,
+                        color: Color::RGB(64, 200, 255),
+                        tie_break: u64::MAX,
+                    }),
+                },
+                SpawnRecord {
+                    tick: 12,
+                    between_ticks: false,
+                    spawn: Spawn::Queued {
+                        approach: Direction::Up,
+                        change: -1,
+                    },
+                },
+                SpawnRecord {
+                    tick: 12,
+                    between_ticks: true,
+                    spawn: Spawn::Refused {
+                        approach: Direction::Right,
+                    },
+                },
+                SpawnRecord {
+                    tick: 12,
+                    between_ticks: false,
+                    spawn: Spawn::Pedestrian {
+                        arm: Direction::Down,
+                        side: -1.0,
+                    },
+                },
+            ],
+        };
+        let text = recording.to_text();
+        assert_eq!(Recording::parse(&text).unwrap(), recording, "{}", text);
+
+        for bad in [
+            "0 between vehicle up straight car 1.0 1 2 3 4",
+            "seed 1\n0 whenever queued up 1",
+            "seed 1\n0 during queued up 1 extra",
+            "seed 1\n5 during queued up 1\n4 during queued up 1",
+        ] {
+            assert!(Recording::parse(bad).is_err(), "{}", bad);
+        }
+    }
+}
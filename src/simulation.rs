@@ -0,0 +1,7240 @@
+use crate::config::{
+    CollisionPolicy, ColorMode, Config, ControlMode, MAX_SPEED_LIMIT, RoadStyle, StopCondition,
+};
+use crate::conflicts::{ConflictMatrix, Movement};
+use crate::digits;
+use crate::direction::{Direction, Route};
+use crate::environment::{self, Weather};
+use crate::geometry::{
+    Camera, ISLAND_RADIUS, LIGHT_SIZE, Layout, ROAD_WIDTH, TICK_RATE, VEHICLE_SIZE, VEHICLE_SPEED,
+    World,
+};
+use crate::grid::{SpatialGrid, overlapping_pairs};
+use crate::heatmap::Heatmap;
+use crate::lights::{self, ControllerKind, PhaseCommand, SignalController, TrafficLights};
+use crate::metrics::{self, MetricsSample};
+use crate::panel;
+use crate::pedestrian::{
+    PEDESTRIAN_SIZE, Pedestrian, WALK_SPEED, crosswalk_centre, crosswalk_half_extents,
+    crosswalk_rect,
+};
+use crate::profile::{Profile, Stage};
+use crate::render::{Bent, Renderer, Viewed};
+use crate::replay::{Recording, Spawn, SpawnRecord, VehicleSpawn};
+use crate::snapshot::{self, Line, Snapshot};
+use crate::stats::Stats;
+use crate::vehicle::{
+    DespawnReason, Vehicle, VehicleKind, half_extents, spawn_position, turn_speed,
+};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use std::collections::{HashSet, VecDeque};
+use std::path::Path;
+use std::time::Duration;
+
+// How close to its stop line, in pixels, an opposing left-turner must be to
+// claim the apex ahead of a vehicle with a higher id.
+const APEX_HORIZON: f32 = ROAD_WIDTH as f32;
+
+// Vehicles moving slower than this (pixels per second) accumulate delay.
+const DELAY_SPEED_THRESHOLD: f32 = 12.0;
+
+// The minimap shows the whole world at this fraction of its size, in the
+// top-right corner.
+const MINIMAP_SCALE: f32 = 0.15;
+const MINIMAP_MARGIN: i32 = 8;
+
+// Relative speed, in pixels per second, from which a collision counts as
+// major: a car running at full speed into a stopped one.
+const MAJOR_IMPACT_SPEED: f32 = VEHICLE_SPEED * TICK_RATE;
+
+// How long, in ticks, a collision flashes for.
+const FLASH_TICKS: u32 = 30;
+// How long, in ticks, a removed vehicle takes to shrink and fade away.
+const FADE_TICKS: u32 = 20;
+
+// Pixels between the footprints of crossing vehicles that count as a
+// close call. Cross traffic passes a car at its stop line 10 apart.
+const CLOSE_CALL_MARGIN: f32 = 8.0;
+
+// How much further back than its stopping distance the most aggressive
+// driver goes on through a yellow, and how much of the gap that grows with
+// speed it gives up, as shares of a careful driver's.
+const AGGRESSIVE_YELLOW_REACH: f32 = 1.0;
+const AGGRESSIVE_GAP_CUT: f32 = 0.5;
+// Drivers at least this aggressive are outlined.
+const AGGRESSIVE_MARKER: f32 = 0.5;
+
+// Simulated seconds a headless drain may take before it counts as stuck.
+const DRAIN_LIMIT_SECONDS: f64 = 600.0;
+
+// Vehicles each thread must have before a tick's following and entry
+// checks are shared among `threads`; below that, starting the threads
+// costs more than the checks.
+const VEHICLES_PER_THREAD: usize = 64;
+
+/// How bad a collision was, judged by how fast the two vehicles closed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CollisionSeverity {
+    Minor,
+    Major,
+}
+
+impl CollisionSeverity {
+    pub const ALL: [CollisionSeverity; 2] = [CollisionSeverity::Minor, CollisionSeverity::Major];
+
+    pub fn classify(relative_speed: f32) -> Self {
+        if relative_speed >= MAJOR_IMPACT_SPEED {
+            CollisionSeverity::Major
+        } else {
+            CollisionSeverity::Minor
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CollisionSeverity::Minor => "minor",
+            CollisionSeverity::Major => "major",
+        }
+    }
+}
+
+// Spawning stopped at `started` with `load` vehicles on the road; `finished`
+// is the tick the last of them left.
+struct Drain {
+    started: u64,
+    load: usize,
+    finished: Option<u64>,
+}
+
+/// Something that happened to a vehicle or the road, as listed by
+/// `TrafficSimulation::events`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SimEvent {
+    Spawned {
+        id: u64,
+        direction: Direction,
+        route: Route,
+    },
+    /// Swung onto its exit direction.
+    Turned {
+        id: u64,
+    },
+    /// Came to a halt from moving.
+    Stopped {
+        id: u64,
+    },
+    /// Started touching another vehicle; `a` was spawned first.
+    Collision {
+        a: u64,
+        b: u64,
+    },
+    Despawned {
+        id: u64,
+        reason: DespawnReason,
+    },
+    /// The last vehicle left the road during a drain.
+    Drained,
+}
+
+/// What an outside controller sees after each `TrafficSimulation::step`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Observation {
+    /// Vehicles yet to reach each stop line, indexed by `Direction::index`.
+    pub queues: [usize; 4],
+    /// Vehicles that drove off the screen during the tick.
+    pub exited: usize,
+    /// The plan phase in force, or being left during yellow and all-red.
+    pub phase: usize,
+    pub stage: lights::Stage,
+    /// Whether the action changed the lights.
+    pub accepted: bool,
+    /// Minus the seconds of delay all vehicles picked up during the tick,
+    /// so it is never positive and 0 means nobody was held up.
+    pub reward: f64,
+    /// What happened during the tick, spawns made just before it included.
+    pub events: Vec<SimEvent>,
+}
+
+// A recent collision drawn as a fading square, bigger for harder impacts.
+struct Flash {
+    x: f32,
+    y: f32,
+    relative_speed: f32,
+    ticks_left: u32,
+}
+
+// A vehicle just taken off the road, drawn carrying on as it shrinks and
+// fades out, so it doesn't simply vanish.
+struct Fade {
+    vehicle: Vehicle,
+    ticks_left: u32,
+}
+
+pub struct TrafficSimulation {
+    pub config: Config,
+    pub vehicles: Vec<Vehicle>,
+    lights: TrafficLights,
+    rng: StdRng,
+    // Only for settling ties, so spawn draws never shift them.
+    tie_rng: StdRng,
+    starvation_warned: [bool; 4],
+    delay_totals: [f32; 4],
+    exited: [usize; 4],
+    // The delay of every exited vehicle, by approach, for percentiles.
+    exit_delays: [Vec<f32>; 4],
+    // Samples taken for `metrics_out` so far.
+    metrics: Vec<MetricsSample>,
+    // Exits by origin approach, then by the direction they drove off in,
+    // which names the arm they left by; both indexed by `Direction::index`.
+    trips: [[usize; 4]; 4],
+    refused_spawns: [usize; 4],
+    // Vehicles removed for each `DespawnReason`, in `DespawnReason::ALL` order.
+    despawned: [usize; 3],
+    // The most vehicles seen waiting at once on each approach.
+    max_queue: [usize; 4],
+    spawn_blocked: [bool; 4],
+    pending_burst: [usize; 4],
+    // Seconds that every vehicle inside the intersection has been standing still.
+    box_stalled_for: f32,
+    // When each approach last spawned from the keyboard, in milliseconds on
+    // the clock chosen by `tick_cooldown`.
+    last_spawn_time: [Option<u128>; 4],
+    // Key presses each approach could not spawn yet, with the route asked
+    // for, oldest first.
+    held_spawns: [VecDeque<Option<Route>>; 4],
+    pub tick: u64,
+    conflicts: ConflictMatrix,
+    next_id: u64,
+    pub pedestrians: Vec<Pedestrian>,
+    next_pedestrian_id: u64,
+    pedestrians_crossed: usize,
+    // Seconds the pedestrians who have crossed spent waiting at the kerb.
+    pedestrian_wait_total: f32,
+    collisions: usize,
+    // Collisions by `CollisionSeverity`, in `CollisionSeverity::ALL` order.
+    collision_severity: [usize; 2],
+    flashes: Vec<Flash>,
+    fades: Vec<Fade>,
+    // Per-stage tick timings, kept only when `profile_every` asks for them.
+    profile: Option<Profile>,
+    drain: Option<Drain>,
+    // The condition from `stop_when` that ended the soak, if one did.
+    stopped_by: Option<StopCondition>,
+    // Seconds of delay every vehicle ever on the road has picked up, kept
+    // even for vehicles that are gone.
+    delay_accrued: f64,
+    // Vehicle moves undone by `rollback_collisions`.
+    rollbacks: usize,
+    // Vehicles `check_lanes` has already warned about.
+    off_lane: HashSet<u64>,
+    colliding_pairs: HashSet<(u64, u64)>,
+    // Pairs that have come within `CLOSE_CALL_MARGIN` without touching,
+    // each counted once in `close_calls`.
+    close_pairs: HashSet<(u64, u64)>,
+    close_calls: usize,
+    // Vehicles that crossed their stop line on yellow.
+    yellow_runs: usize,
+    // The fewest and most ticks an exited vehicle spent on the road.
+    crossing_ticks: Option<(u64, u64)>,
+    // The lowest and highest average speed, in pixels per second, and
+    // delay, in seconds, of an exited vehicle, and the sum of the speeds.
+    trip_speeds: Option<(f32, f32)>,
+    speed_total: f32,
+    delays: Option<(f32, f32)>,
+    // Events since the end of the tick before last; the first
+    // `previous_events` of them were raised before or during that tick.
+    events: Vec<SimEvent>,
+    previous_events: usize,
+    // Every spawn so far, kept only when `record` asks for it.
+    recording: Option<Recording>,
+    // The recorded spawns still to come, when replaying; nothing else
+    // spawns while this is set.
+    replay: Option<VecDeque<SpawnRecord>>,
+    // Replayed arrivals per approach waiting for their spawn point, oldest
+    // first. They only wait when the run has gone differently from the
+    // recorded one.
+    replay_waiting: [VecDeque<VehicleSpawn>; 4],
+    // Whether `update` is running, so spawns can be recorded as made during
+    // a tick or between two.
+    updating: bool,
+    // Vehicles that have driven off, kept for `take_exits` once
+    // `keep_exits` asks for them.
+    kept_exits: Option<Vec<Vehicle>>,
+    // The approach held on green by clicking its light, until clicked again.
+    held_light: Option<Direction>,
+    // Where vehicles have been, and stopped, since the start.
+    heatmap: Heatmap,
+}
+
+// FNV-1a, used instead of `DefaultHasher` because its output must not change
+// between Rust versions or platforms.
+struct StableHasher(u64);
+
+impl StableHasher {
+    fn new() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+
+    fn write(&mut self, value: u64) {
+        for byte in value.to_le_bytes() {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+/// The stop line across `approach`'s incoming half of the road, `setback`
+/// pixels from the centre. Its edge nearest the centre is where vehicle
+/// fronts stop.
+fn stop_line_rect(world: World, approach: Direction, setback: f32) -> Rect {
+    const WIDTH: i32 = 4;
+    let half_road = world.road_width as i32 / 2;
+    let (center_x, center_y) = (world.center_x(), world.center_y());
+    let setback = setback as i32;
+    match approach {
+        Direction::Up => Rect::new(
+            center_x - half_road,
+            center_y + setback,
+            half_road as u32,
+            WIDTH as u32,
+        ),
+        Direction::Down => Rect::new(
+            center_x,
+            center_y - setback - WIDTH,
+            half_road as u32,
+            WIDTH as u32,
+        ),
+        Direction::Right => Rect::new(
+            center_x - setback - WIDTH,
+            center_y,
+            WIDTH as u32,
+            half_road as u32,
+        ),
+        Direction::Left => Rect::new(
+            center_x + setback,
+            center_y - half_road,
+            WIDTH as u32,
+            half_road as u32,
+        ),
+    }
+}
+
+// The housing of the approach's signal head, in the corner beside its stop
+// line: `LIGHT_SIZE` wide and three lamps tall, growing away from the road
+// so it never covers it.
+fn light_head_rect(world: World, approach: Direction) -> Rect {
+    let half_road = world.road_width as i32 / 2;
+    let (center_x, center_y) = (world.center_x(), world.center_y());
+    let size = LIGHT_SIZE as i32;
+    let height = size * 2 / 3 * 3;
+    let left = center_x - half_road - size;
+    let right = center_x + half_road;
+    let top = center_y - half_road - height;
+    let bottom = center_y + half_road;
+    // Each corner light belongs to the approach whose stop line it faces.
+    let (x, y) = match approach {
+        Direction::Right => (left, top),
+        Direction::Down => (right, top),
+        Direction::Up => (left, bottom),
+        Direction::Left => (right, bottom),
+    };
+    Rect::new(x, y, LIGHT_SIZE, height as u32)
+}
+
+/// The two strips of road, each `road_width` across and running to the
+/// window edge or, for a missing arm, the edge of the intersection box.
+fn asphalt_rects(world: World, layout: Layout) -> [Rect; 2] {
+    let half_road = world.road_width as i32 / 2;
+    let (center_x, center_y) = (world.center_x(), world.center_y());
+    let reach = |arm: Direction, edge: i32| if layout.has_arm(arm) { edge } else { 0 };
+    let left = center_x - half_road - reach(Direction::Left, center_x - half_road);
+    let right =
+        center_x + half_road + reach(Direction::Right, world.width as i32 - center_x - half_road);
+    let top = center_y - half_road - reach(Direction::Up, center_y - half_road);
+    let bottom =
+        center_y + half_road + reach(Direction::Down, world.height as i32 - center_y - half_road);
+    [
+        Rect::new(
+            left,
+            center_y - half_road,
+            (right - left) as u32,
+            world.road_width,
+        ),
+        Rect::new(
+            center_x - half_road,
+            top,
+            world.road_width,
+            (bottom - top) as u32,
+        ),
+    ]
+}
+
+/// The approach whose traffic arrives from the right of a driver heading
+/// `direction`.
+fn arriving_from_the_right(direction: Direction) -> Direction {
+    match direction {
+        Direction::Up => Direction::Left,
+        Direction::Left => Direction::Down,
+        Direction::Down => Direction::Right,
+        Direction::Right => Direction::Up,
+    }
+}
+
+// `check` of every index below `count`, in order. With enough of them,
+// runs of neighbouring indices go to up to `threads` scoped threads; each
+// check only reads, so the results are the same either way.
+fn check_each<T: Send>(count: usize, threads: usize, check: impl Fn(usize) -> T + Sync) -> Vec<T> {
+    let threads = threads.min(count / VEHICLES_PER_THREAD);
+    if threads <= 1 {
+        return (0..count).map(check).collect();
+    }
+    let share = count.div_ceil(threads);
+    let check = &check;
+    std::thread::scope(|scope| {
+        let runs: Vec<_> = (0..count)
+            .step_by(share)
+            .map(|start| {
+                scope.spawn(move || {
+                    (start..(start + share).min(count))
+                        .map(check)
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        runs.into_iter()
+            .flat_map(|run| run.join().expect("a vehicle check panicked"))
+            .collect()
+    })
+}
+
+/// Whether `vehicle`'s trip takes it over `arm`'s crosswalk: the one it
+/// comes in on, or the one on the arm it leaves by.
+fn drives_over(vehicle: &Vehicle, arm: Direction) -> bool {
+    vehicle.initial_direction == arm || vehicle.exit_direction().opposite() == arm
+}
+
+// The routes a vehicle may take from `approach`: never towards an arm the
+// layout doesn't have, or on a banned movement.
+pub(crate) fn permitted_routes(config: &Config, approach: Direction) -> Vec<Route> {
+    Route::ALL
+        .into_iter()
+        .filter(|&route| config.permits(Movement { approach, route }))
+        .collect()
+}
+
+// An approach picked at random, as often as its share of
+// `approach_weights`.
+pub(crate) fn weighted_approach(config: &Config, rng: &mut StdRng) -> Direction {
+    let weights: Vec<(Direction, f32)> = Direction::ALL
+        .into_iter()
+        .filter(|&approach| config.layout.accepts_approach(approach))
+        .map(|approach| (approach, config.approach_weights[approach.index()]))
+        .collect();
+    let total: f32 = weights.iter().map(|(_, weight)| weight).sum();
+    let mut pick = rng.random_range(0.0..total);
+    let mut direction = weights[0].0;
+    for (approach, weight) in weights {
+        if weight > 0.0 {
+            direction = approach;
+        }
+        if pick < weight {
+            break;
+        }
+        pick -= weight;
+    }
+    direction
+}
+
+/// A kind for a new vehicle, as often as each one's share. Only draws when
+/// some share is set, so runs of cars alone keep their random sequence.
+pub(crate) fn drawn_kind(config: &Config, rng: &mut StdRng) -> VehicleKind {
+    if config.truck_share + config.motorcycle_share <= 0.0 {
+        return VehicleKind::Car;
+    }
+    let pick = rng.random::<f32>();
+    if pick < config.truck_share {
+        VehicleKind::Truck
+    } else if pick < config.truck_share + config.motorcycle_share {
+        VehicleKind::Motorcycle
+    } else {
+        VehicleKind::Car
+    }
+}
+
+/// The square a collision flashes in: wider the faster the vehicles closed.
+fn flash_rect(flash: &Flash) -> Rect {
+    let size = (20.0 + flash.relative_speed / 4.0) as u32;
+    Rect::from_center((flash.x as i32, flash.y as i32), size, size)
+}
+
+/// Where a world rectangle lands on the minimap, never smaller than a pixel.
+fn to_minimap(world: World, rect: Rect) -> Rect {
+    let origin_x =
+        world.width as i32 - MINIMAP_MARGIN - (world.width as f32 * MINIMAP_SCALE) as i32;
+    let scale = |value: i32| (value as f32 * MINIMAP_SCALE) as i32;
+    Rect::new(
+        origin_x + scale(rect.x()),
+        MINIMAP_MARGIN + scale(rect.y()),
+        scale(rect.width() as i32).max(1) as u32,
+        scale(rect.height() as i32).max(1) as u32,
+    )
+}
+
+/// Both edges of every arm the layout has, from the window edge to the box,
+/// and a line closing the box off where an arm is missing.
+fn arm_edges(world: World, layout: Layout) -> Vec<((i32, i32), (i32, i32))> {
+    let half_road = world.road_width as i32 / 2;
+    let (center_x, center_y) = (world.center_x(), world.center_y());
+    let (left, right) = (center_x - half_road, center_x + half_road);
+    let (top, bottom) = (center_y - half_road, center_y + half_road);
+    let (width, height) = (world.width as i32, world.height as i32);
+
+    let mut edges = Vec::new();
+    for arm in Direction::ALL {
+        if !layout.has_arm(arm) {
+            edges.push(match arm {
+                Direction::Left => ((left, top), (left, bottom)),
+                Direction::Right => ((right, top), (right, bottom)),
+                Direction::Up => ((left, top), (right, top)),
+                Direction::Down => ((left, bottom), (right, bottom)),
+            });
+            continue;
+        }
+        edges.extend(match arm {
+            Direction::Left => [((0, top), (left, top)), ((0, bottom), (left, bottom))],
+            Direction::Right => [
+                ((right, top), (width, top)),
+                ((right, bottom), (width, bottom)),
+            ],
+            Direction::Up => [((left, 0), (left, top)), ((right, 0), (right, top))],
+            Direction::Down => [
+                ((left, bottom), (left, height)),
+                ((right, bottom), (right, height)),
+            ],
+        });
+    }
+    edges
+}
+
+/// Whether any part of the world rect falls inside the window, as seen
+/// through `camera`.
+fn on_screen(rect: Rect, camera: Camera) -> bool {
+    let visible = camera.visible();
+    rect.right() > visible.left()
+        && rect.bottom() > visible.top()
+        && rect.left() < visible.right()
+        && rect.top() < visible.bottom()
+}
+
+/// The dashed centre line of every arm the layout has. Dashes run from the
+/// window edge towards the intersection and stop short of the box.
+fn center_line_dashes(world: World, layout: Layout) -> Vec<Rect> {
+    const DASH: i32 = 15;
+    const PERIOD: usize = 30;
+    let half_road = world.road_width as i32 / 2;
+    let (center_x, center_y) = (world.center_x(), world.center_y());
+    let mut dashes = Vec::new();
+
+    // Each arm as the travel-axis span between its window edge and the box.
+    let arms = [
+        (Direction::Left, 0, center_x - half_road),
+        (Direction::Right, center_x + half_road, world.width as i32),
+        (Direction::Up, 0, center_y - half_road),
+        (Direction::Down, center_y + half_road, world.height as i32),
+    ];
+    for (arm, start, end) in arms {
+        if !layout.has_arm(arm) {
+            continue;
+        }
+        for along in (start..end).step_by(PERIOD) {
+            let length = DASH.min(end - along) as u32;
+            dashes.push(match arm {
+                Direction::Left | Direction::Right => Rect::new(along, center_y - 2, length, 4),
+                Direction::Up | Direction::Down => Rect::new(center_x - 2, along, 4, length),
+            });
+        }
+    }
+    dashes
+}
+
+impl TrafficSimulation {
+    pub fn new(mut config: Config) -> Self {
+        if config.record.is_some() && config.seed.is_none() {
+            config.seed = Some(rand::random());
+        }
+        let mut lights = TrafficLights::new(
+            config.light_plan.clone(),
+            config.signal_timing(),
+            config.signal_controller.build(),
+        );
+        let mut rng = match config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_os_rng(),
+        };
+        let tie_rng = match config.tie_break_seed.or(config.seed) {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_os_rng(),
+        };
+        let cruising = config.cruise_speed;
+        let mut offset = lights.phase_start(config.initial_phase)
+            + config.phase_offset
+            + lights.green_wave_offset(config.wave_distance, cruising);
+        // Only draw when asked to, so other runs keep their random sequence.
+        if config.random_phase_offset {
+            offset += rng.random_range(0.0..lights.cycle_length());
+        }
+        lights.skip(offset);
+        let profile = (config.profile_every > 0).then(Profile::default);
+        let recording = config
+            .record
+            .as_ref()
+            .zip(config.seed)
+            .map(|(_, seed)| Recording::new(seed));
+        let heatmap = Heatmap::new(config.world());
+
+        Self {
+            lights,
+            rng,
+            tie_rng,
+            config,
+            vehicles: Vec::new(),
+            starvation_warned: [false; 4],
+            delay_totals: [0.0; 4],
+            exited: [0; 4],
+            exit_delays: Default::default(),
+            metrics: Vec::new(),
+            trips: [[0; 4]; 4],
+            refused_spawns: [0; 4],
+            despawned: [0; 3],
+            max_queue: [0; 4],
+            spawn_blocked: [false; 4],
+            pending_burst: [0; 4],
+            box_stalled_for: 0.0,
+            last_spawn_time: [None; 4],
+            held_spawns: Default::default(),
+            tick: 0,
+            conflicts: ConflictMatrix::compute(),
+            next_id: 0,
+            pedestrians: Vec::new(),
+            next_pedestrian_id: 0,
+            pedestrians_crossed: 0,
+            pedestrian_wait_total: 0.0,
+            collisions: 0,
+            collision_severity: [0; 2],
+            flashes: Vec::new(),
+            fades: Vec::new(),
+            profile,
+            drain: None,
+            stopped_by: None,
+            off_lane: HashSet::new(),
+            rollbacks: 0,
+            delay_accrued: 0.0,
+            colliding_pairs: HashSet::new(),
+            close_pairs: HashSet::new(),
+            close_calls: 0,
+            yellow_runs: 0,
+            crossing_ticks: None,
+            trip_speeds: None,
+            speed_total: 0.0,
+            delays: None,
+            events: Vec::new(),
+            previous_events: 0,
+            recording,
+            replay: None,
+            replay_waiting: Default::default(),
+            updating: false,
+            kept_exits: None,
+            held_light: None,
+            heatmap,
+        }
+    }
+
+    /// A run that takes its spawns from `recording`, under its seed. With
+    /// the settings it was recorded under it repeats that run exactly.
+    pub fn replaying(config: Config, recording: Recording) -> Self {
+        let mut simulation = Self::new(Config {
+            seed: Some(recording.seed),
+            ..config
+        });
+        simulation.replay = Some(recording.spawns.into());
+        simulation
+    }
+
+    /// The spawns made so far, when the run is being recorded.
+    pub fn recording(&self) -> Option<&Recording> {
+        self.recording.as_ref()
+    }
+
+    /// Keeps every vehicle that exits from now on, for `take_exits`.
+    pub fn keep_exits(&mut self) {
+        self.kept_exits.get_or_insert_with(Vec::new);
+    }
+
+    /// The vehicles that have exited since the last call, as they were on
+    /// their last tick on the road.
+    pub fn take_exits(&mut self) -> Vec<Vehicle> {
+        self.kept_exits
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    fn record(&mut self, spawn: Spawn) {
+        if let Some(recording) = &mut self.recording {
+            recording.spawns.push(SpawnRecord {
+                tick: self.tick,
+                between_ticks: !self.updating,
+                spawn,
+            });
+        }
+    }
+
+    // Makes the replayed spawns due now: those recorded between ticks when
+    // called before an update, otherwise those recorded during one, bar
+    // pedestrians, who arrive part way through.
+    fn replay_spawns(&mut self, between_ticks: bool) {
+        let Some(replay) = &mut self.replay else {
+            return;
+        };
+        let mut due = Vec::new();
+        while let Some(record) = replay.front()
+            && record.tick == self.tick
+            && record.between_ticks == between_ticks
+            && !matches!(record.spawn, Spawn::Pedestrian { .. })
+        {
+            due.extend(replay.pop_front().map(|record| record.spawn));
+        }
+        for direction in Direction::ALL {
+            self.admit_replayed(direction);
+        }
+        for spawn in due {
+            match spawn {
+                Spawn::Vehicle(vehicle) => {
+                    let direction = vehicle.approach;
+                    self.replay_waiting[direction.index()].push_back(vehicle);
+                    self.admit_replayed(direction);
+                }
+                Spawn::Queued { approach, change } => {
+                    self.record(Spawn::Queued { approach, change });
+                    let pending = &mut self.pending_burst[approach.index()];
+                    *pending = pending.saturating_add_signed(change as isize);
+                }
+                Spawn::Refused { approach } => self.refuse_spawn(approach),
+                Spawn::Pedestrian { .. } => unreachable!(),
+            }
+        }
+    }
+
+    // Counts a spawn turned away from a taken spawn point.
+    fn refuse_spawn(&mut self, direction: Direction) {
+        self.refused_spawns[direction.index()] += 1;
+        self.spawn_blocked[direction.index()] = true;
+        self.record(Spawn::Refused {
+            approach: direction,
+        });
+    }
+
+    // Puts the oldest replayed arrival waiting on `direction` on the road,
+    // unless its spawn point is taken or its queue is full.
+    fn admit_replayed(&mut self, direction: Direction) {
+        let Some(arrival) = self.replay_waiting[direction.index()].front() else {
+            return;
+        };
+        if !self.spawning_enabled() {
+            self.replay_waiting[direction.index()].clear();
+            return;
+        }
+        if self.queue_full(direction) || self.spawn_point_occupied_for(direction, arrival.kind) {
+            return;
+        }
+        let arrival = self.replay_waiting[direction.index()]
+            .pop_front()
+            .expect("checked above");
+        // The colour comes from the recording, so no mode that draws one.
+        let mut vehicle = Vehicle::new(
+            self.next_id,
+            direction,
+            arrival.route,
+            ColorMode::ByRoute,
+            &mut self.rng,
+        );
+        vehicle.color = arrival.color;
+        vehicle.kind = arrival.kind;
+        vehicle.tie_break = arrival.tie_break;
+        self.place(vehicle, arrival.speed);
+    }
+
+    /// Returns whether a vehicle was actually added.
+    pub fn spawn_vehicle(&mut self, direction: Direction) -> bool {
+        self.spawn_vehicle_with(direction, self.spawn_speed(), None)
+            .unwrap_or(false)
+    }
+
+    // `cruise_speed` in pixels per tick.
+    fn spawn_speed(&self) -> f32 {
+        self.config.cruise_speed / self.config.tick_rate
+    }
+
+    /// Spawns with a chosen speed (pixels per tick) and, optionally, a fixed
+    /// route, for building scenarios. Arguments the
+    /// layout can't honour are errors; an occupied spawn point is a refusal
+    /// just as for `spawn_vehicle`.
+    pub fn spawn_vehicle_with(
+        &mut self,
+        direction: Direction,
+        speed: f32,
+        route: Option<Route>,
+    ) -> Result<bool, String> {
+        self.spawn_kind(direction, speed, route, None)
+    }
+
+    /// Spawns an emergency vehicle on an approach picked by
+    /// `approach_weights`, refused just as any other spawn.
+    pub fn spawn_emergency(&mut self) -> bool {
+        let direction = weighted_approach(&self.config, &mut self.rng);
+        let speed = self.spawn_speed();
+        match self.spawn_kind(direction, speed, None, Some(VehicleKind::Emergency)) {
+            Ok(spawned) => spawned,
+            Err(e) => {
+                warn!("{}", e);
+                false
+            }
+        }
+    }
+
+    // `spawn_vehicle_with`, as `kind` if one is given and otherwise as
+    // drawn from the shares of each kind.
+    fn spawn_kind(
+        &mut self,
+        direction: Direction,
+        speed: f32,
+        route: Option<Route>,
+        kind: Option<VehicleKind>,
+    ) -> Result<bool, String> {
+        if !self.spawning_enabled() {
+            debug!("Refused a spawn on the {} approach: draining", direction);
+            return Ok(false);
+        }
+        if self.replay.is_some() {
+            debug!("Refused a spawn on the {} approach: replaying", direction);
+            return Ok(false);
+        }
+        self.check_spawn_route(direction, route)?;
+        if speed <= 0.0 {
+            return Err(format!("Spawn speed must be positive: {}", speed));
+        }
+        if self.queue_full(direction) {
+            debug!("Refused a spawn on the {} approach: queue full", direction);
+            return Ok(false);
+        }
+
+        let kind = kind.unwrap_or_else(|| drawn_kind(&self.config, &mut self.rng));
+
+        if self.spawn_point_occupied_for(direction, kind) {
+            debug!(
+                "Refused a spawn on the {} approach: spawn point occupied",
+                direction
+            );
+            self.refuse_spawn(direction);
+            return Ok(false);
+        }
+
+        let routes = permitted_routes(&self.config, direction);
+        let route = route.unwrap_or_else(|| routes[self.rng.random_range(0..routes.len())]);
+
+        let mut vehicle = Vehicle::new(
+            self.next_id,
+            direction,
+            route,
+            self.config.color_mode,
+            &mut self.rng,
+        );
+        vehicle.kind = kind;
+        vehicle.tie_break = self.tie_rng.random();
+        self.place(vehicle, speed);
+        Ok(true)
+    }
+
+    // Why the layout or the banned movements rule out spawning on
+    // `direction` along `route`, if they do.
+    fn check_spawn_route(&self, direction: Direction, route: Option<Route>) -> Result<(), String> {
+        let layout = self.config.layout;
+        if !layout.accepts_approach(direction) {
+            return Err(format!("The layout has no {} approach", direction));
+        }
+        let Some(route) = route else {
+            return Ok(());
+        };
+        if !layout.has_arm(route.exit_direction(direction)) {
+            return Err(format!(
+                "The layout has no {} arm for that route",
+                route.exit_direction(direction)
+            ));
+        }
+        let movement = Movement {
+            approach: direction,
+            route,
+        };
+        if !self.config.permits(movement) {
+            return Err(format!("The {} movement is banned", movement.name()));
+        }
+        Ok(())
+    }
+
+    /// Spawns a copy of a vehicle made elsewhere, keeping its route, kind,
+    /// colour and tie-break, so two runs can be fed the very same arrivals.
+    /// Refused, without counting, while the spawn point is occupied, the
+    /// queue is full or the road is draining. Returns the id it was given.
+    pub fn admit(&mut self, arrival: &Vehicle) -> Option<u64> {
+        let direction = arrival.initial_direction;
+        if !self.spawning_enabled()
+            || self.queue_full(direction)
+            || self.spawn_point_occupied_for(direction, arrival.kind)
+        {
+            return None;
+        }
+        let mut vehicle = arrival.clone();
+        let id = self.next_id;
+        vehicle.id = id;
+        self.place(vehicle, self.spawn_speed());
+        Some(id)
+    }
+
+    // Fits a new vehicle out for this run's config and puts it on the road.
+    fn place(&mut self, mut vehicle: Vehicle, speed: f32) {
+        let (direction, route, kind) = (vehicle.initial_direction, vehicle.route, vehicle.kind);
+        vehicle.spawned_at = self.tick;
+        vehicle.driven = 0.0;
+        vehicle.speed_limits = self
+            .config
+            .speed_limits
+            .map(|limit| limit / self.config.tick_rate);
+        vehicle.cruise_speed = speed * kind.speed_factor();
+        vehicle.traction = self.config.traction();
+        vehicle.world = self.config.world();
+        vehicle.tick_rate = self.config.tick_rate;
+        // Made for whatever world, it enters this one.
+        (vehicle.x, vehicle.y) = spawn_position(vehicle.world, direction);
+        (vehicle.prev_x, vehicle.prev_y) = (vehicle.x, vehicle.y);
+        vehicle.aggressiveness = self.driver_aggressiveness(vehicle.tie_break);
+        vehicle.velocity = vehicle.top_speed();
+        vehicle.turn_speed = turn_speed(self.config.turn_radius(route)) / self.config.tick_rate;
+        // Turns taken at full speed are still drawn round a lane width.
+        if self.config.curved_turns {
+            vehicle.drawn_turn_radius = self
+                .config
+                .turn_radius(route)
+                .max(self.config.lane_width as f32);
+        }
+        vehicle.circulates = self.config.layout == Layout::Roundabout;
+        vehicle.length = kind.length(self.config.vehicle_length);
+        vehicle.width = kind.width(self.config.vehicle_width);
+        vehicle.stop_setback = self.config.stop_setback(direction);
+        vehicle.reaction_ticks = self.config.seconds_to_ticks(self.config.reaction_time);
+        if self.config.max_jerk > 0.0 {
+            vehicle.max_jerk = self.config.max_jerk / self.config.tick_rate.powi(3);
+        }
+        info!(
+            "Spawned {:?} {} as {}",
+            kind,
+            vehicle.id,
+            Movement {
+                approach: direction,
+                route
+            }
+            .name()
+        );
+        self.events.push(SimEvent::Spawned {
+            id: vehicle.id,
+            direction,
+            route,
+        });
+        self.record(Spawn::Vehicle(VehicleSpawn {
+            approach: direction,
+            route,
+            kind,
+            speed,
+            color: vehicle.color,
+            tie_break: vehicle.tie_break,
+        }));
+        self.vehicles.push(vehicle);
+        self.next_id += 1;
+    }
+
+    /// Seconds of simulated time, derived from the tick count.
+    pub fn simulated_seconds(&self) -> f64 {
+        self.config.ticks_to_seconds(self.tick)
+    }
+
+    pub fn stats(&self) -> Stats {
+        Stats {
+            tick: self.tick,
+            simulated_seconds: self.simulated_seconds(),
+            vehicles: self.vehicles.len(),
+            queues: self.queue_lengths(),
+            spawned: self.next_id,
+            exited: self.despawned[0],
+            collisions: self.collisions,
+            collision_severity: self.collision_severity,
+            cost: self.cost(),
+            throughput: self.throughput(),
+            control: self.config.control_name(),
+            weather: self.config.weather.name(),
+            trips: self.trips,
+        }
+    }
+
+    /// One number for an optimiser to minimise: the weighted average delay,
+    /// longest queue and collisions, less the weighted exits per minute.
+    pub fn cost(&self) -> f64 {
+        let config = &self.config;
+        let delay = self.mean_delay();
+        let longest_queue = self.max_queue.iter().copied().max().unwrap_or(0);
+        config.cost_delay_weight * delay - config.cost_throughput_weight * self.throughput()
+            + config.cost_queue_weight * longest_queue as f64
+            + config.cost_collision_weight * self.collisions as f64
+    }
+
+    /// Exits per simulated minute since the start, over every approach.
+    pub fn throughput(&self) -> f64 {
+        let exits: usize = self.exited.iter().sum();
+        let minutes = self.simulated_seconds() / 60.0;
+        if minutes > 0.0 {
+            exits as f64 / minutes
+        } else {
+            0.0
+        }
+    }
+
+    /// Seconds of delay per exited vehicle, over every approach.
+    pub fn mean_delay(&self) -> f64 {
+        let exits: usize = self.exited.iter().sum();
+        if exits == 0 {
+            0.0
+        } else {
+            self.delay_totals.iter().sum::<f32>() as f64 / exits as f64
+        }
+    }
+
+    fn simulated_millis(&self) -> u128 {
+        (self.simulated_seconds() * 1000.0) as u128
+    }
+
+    /// Spawns for a key press, on `route` if one is given. A press made
+    /// while the approach is still cooling down, its spawn point is taken or
+    /// its queue is full is held, behind any held before it, until
+    /// `release_spawns` can spawn it; past `spawn_buffer` held presses it is
+    /// dropped. True only if a vehicle spawned now.
+    pub fn request_spawn(
+        &mut self,
+        direction: Direction,
+        route: Option<Route>,
+        wall_millis: u128,
+    ) -> bool {
+        if !self.spawning_enabled() || self.replay.is_some() {
+            // Refused, and logged as such, like any other spawn.
+            return self.spawn_requested(direction, route, wall_millis);
+        }
+        if let Err(e) = self.check_spawn_route(direction, route) {
+            warn!("{}", e);
+            return false;
+        }
+        self.release_spawns(wall_millis);
+        if self.held_spawns[direction.index()].is_empty()
+            && self.spawn_ready(direction, wall_millis)
+        {
+            return self.spawn_requested(direction, route, wall_millis);
+        }
+        let held = &mut self.held_spawns[direction.index()];
+        if held.len() >= self.config.spawn_buffer {
+            debug!(
+                "Dropped a key press on the {} approach: {} already held",
+                direction,
+                held.len()
+            );
+            return false;
+        }
+        held.push_back(route);
+        false
+    }
+
+    /// Spawns the oldest held key press on each approach that has finished
+    /// cooling down and has room for it. Called once a frame, so presses
+    /// are not left waiting on the next one.
+    pub fn release_spawns(&mut self, wall_millis: u128) {
+        for direction in Direction::ALL {
+            if !self.held_spawns[direction.index()].is_empty()
+                && self.spawn_ready(direction, wall_millis)
+            {
+                let route = self.held_spawns[direction.index()]
+                    .pop_front()
+                    .expect("checked above");
+                self.spawn_requested(direction, route, wall_millis);
+            }
+        }
+    }
+
+    /// Key presses held on each approach, indexed by `Direction::index`.
+    pub fn held_spawns(&self) -> [usize; 4] {
+        Direction::ALL.map(|direction| self.held_spawns[direction.index()].len())
+    }
+
+    // Whether a key press on `direction` could spawn now: the approach has
+    // cooled down, its spawn point is clear and its queue has room.
+    fn spawn_ready(&self, direction: Direction, wall_millis: u128) -> bool {
+        let now = self.cooldown_millis(wall_millis);
+        let cooldown = self.config.spawn_cooldown_millis();
+        self.last_spawn_time[direction.index()].is_none_or(|last| now - last > cooldown)
+            && !self.spawn_point_occupied(direction)
+            && !self.queue_full(direction)
+    }
+
+    // Spawns for a key press and restarts the approach's cooldown. A
+    // refused spawn leaves the cooldown alone so the key can be retried.
+    fn spawn_requested(
+        &mut self,
+        direction: Direction,
+        route: Option<Route>,
+        wall_millis: u128,
+    ) -> bool {
+        match self.spawn_vehicle_with(direction, self.spawn_speed(), route) {
+            Ok(true) => {
+                self.last_spawn_time[direction.index()] = Some(self.cooldown_millis(wall_millis));
+                true
+            }
+            Ok(false) => false,
+            Err(e) => {
+                warn!("{}", e);
+                false
+            }
+        }
+    }
+
+    /// A key-press spawn from an approach picked at random, as often as its
+    /// share of `approach_weights`.
+    pub fn request_random_spawn(&mut self, wall_millis: u128) -> bool {
+        let direction = weighted_approach(&self.config, &mut self.rng);
+        self.request_spawn(direction, None, wall_millis)
+    }
+
+    /// The clock key-press cooldowns are measured on, in milliseconds: the
+    /// simulated clock under `tick_cooldown`, otherwise the wall clock sped
+    /// up by `time_scale`, which it runs slower than simulated time.
+    pub fn cooldown_millis(&self, wall_millis: u128) -> u128 {
+        if self.config.tick_cooldown {
+            self.simulated_millis()
+        } else {
+            (wall_millis as f64 * self.config.time_scale as f64) as u128
+        }
+    }
+
+    /// Queues `burst_size` vehicles on every approach. They skip the key
+    /// cooldown but still wait for their spawn point to clear, one per tick.
+    pub fn queue_burst(&mut self) {
+        if !self.spawning_enabled() || self.replay.is_some() {
+            return;
+        }
+        for direction in Direction::ALL {
+            if self.config.layout.accepts_approach(direction) {
+                self.queue_arrivals(direction, self.config.burst_size as i64);
+            }
+        }
+    }
+
+    // Adds `change` arrivals to, or takes them from, those waiting on
+    // `direction`.
+    fn queue_arrivals(&mut self, direction: Direction, change: i64) {
+        let pending = &mut self.pending_burst[direction.index()];
+        *pending = pending.saturating_add_signed(change as isize);
+        self.record(Spawn::Queued {
+            approach: direction,
+            change,
+        });
+    }
+
+    /// False once a drain has started; every spawn source is refused from
+    /// then on.
+    pub fn spawning_enabled(&self) -> bool {
+        self.drain.is_none()
+    }
+
+    /// Stops all spawning, drops any queued bursts and times how long the
+    /// vehicles already on the road take to leave. Does nothing if a drain
+    /// is already running.
+    pub fn start_drain(&mut self) {
+        if self.drain.is_some() {
+            return;
+        }
+        info!("Draining {} vehicle(s)", self.vehicles.len());
+        self.pending_burst = [0; 4];
+        self.held_spawns = Default::default();
+        self.drain = Some(Drain {
+            started: self.tick,
+            load: self.vehicles.len(),
+            finished: None,
+        });
+    }
+
+    /// How long the drain took, once the road is empty.
+    pub fn drain_report(&self) -> Option<String> {
+        let drain = self.drain.as_ref()?;
+        let ticks = drain.finished? - drain.started;
+        Some(format!(
+            "Drained {} vehicle(s) in {:.1}s ({} ticks)",
+            drain.load,
+            self.config.ticks_to_seconds(ticks),
+            ticks
+        ))
+    }
+
+    /// Starts or stops the random arrivals of `auto_spawn`.
+    pub fn toggle_auto_spawn(&mut self) {
+        self.config.auto_spawn = !self.config.auto_spawn;
+        info!(
+            "Automatic arrivals {}",
+            if self.config.auto_spawn { "on" } else { "off" }
+        );
+    }
+
+    // Arrivals come one at a time as a Poisson process, from an approach
+    // picked by `approach_weights`, and queue like a burst until their
+    // spawn point is clear.
+    fn generate_arrivals(&mut self) {
+        if !self.config.auto_spawn || !self.spawning_enabled() || self.replay.is_some() {
+            return;
+        }
+        if self.rng.random::<f32>() < self.config.arrival_rate * self.config.tick_seconds() {
+            let direction = weighted_approach(&self.config, &mut self.rng);
+            self.queue_arrivals(direction, 1);
+        }
+    }
+
+    fn spawn_pending_burst(&mut self) {
+        for direction in Direction::ALL {
+            let index = direction.index();
+            if self.pending_burst[index] > 0
+                && !self.spawn_point_occupied(direction)
+                && self.spawn_vehicle(direction)
+            {
+                self.queue_arrivals(direction, -1);
+            }
+        }
+    }
+
+    /// Every removal from `vehicles` goes through here so it is counted
+    /// under its reason; exits also close the vehicle's trip. All but the
+    /// respawned, which never really arrived, fade out rather than vanish.
+    fn despawn_where(&mut self, reason: DespawnReason, remove: impl Fn(&Vehicle) -> bool) {
+        let reason_index = DespawnReason::ALL
+            .iter()
+            .position(|&r| r == reason)
+            .unwrap();
+        let (removed, kept): (Vec<Vehicle>, Vec<Vehicle>) = std::mem::take(&mut self.vehicles)
+            .into_iter()
+            .partition(|vehicle| remove(vehicle));
+        self.vehicles = kept;
+        for vehicle in removed {
+            debug!("Vehicle {} despawned: {}", vehicle.id, reason.name());
+            self.events.push(SimEvent::Despawned {
+                id: vehicle.id,
+                reason,
+            });
+            self.despawned[reason_index] += 1;
+            if reason == DespawnReason::Exited {
+                self.finish_trip(&vehicle);
+            }
+            if reason != DespawnReason::Respawned {
+                self.fades.push(Fade {
+                    vehicle,
+                    ticks_left: FADE_TICKS,
+                });
+            }
+        }
+    }
+
+    /// Closes the record of a vehicle's trip on the tick it exits, adding
+    /// its delay, time on the road and speed to the run's statistics. Every
+    /// exit comes through here exactly once.
+    fn finish_trip(&mut self, vehicle: &Vehicle) {
+        if let Some(kept) = &mut self.kept_exits {
+            kept.push(vehicle.clone());
+        }
+        let index = vehicle.initial_direction.index();
+        self.delay_totals[index] += vehicle.delay;
+        self.exited[index] += 1;
+        self.exit_delays[index].push(vehicle.delay);
+        self.trips[index][vehicle.direction.index()] += 1;
+        let ticks = self.tick - vehicle.spawned_at;
+        self.crossing_ticks = Some(match self.crossing_ticks {
+            Some((shortest, longest)) => (shortest.min(ticks), longest.max(ticks)),
+            None => (ticks, ticks),
+        });
+        let speed = vehicle.driven / self.config.ticks_to_seconds(ticks.max(1)) as f32;
+        self.speed_total += speed;
+        let widen = |range: Option<(f32, f32)>, value: f32| match range {
+            Some((low, high)) => Some((low.min(value), high.max(value))),
+            None => Some((value, value)),
+        };
+        self.trip_speeds = widen(self.trip_speeds, speed);
+        self.delays = widen(self.delays, vehicle.delay);
+    }
+
+    /// Spawn sources each check the spawn point, but should two of them still
+    /// place vehicles on the very same point, the later one is taken back and
+    /// queued to spawn again next tick, with a fresh route.
+    fn delay_coincident_spawns(&mut self) {
+        let mut index = 0;
+        while index < self.vehicles.len() {
+            let vehicle = &self.vehicles[index];
+            let at_spawn =
+                (vehicle.x, vehicle.y) == spawn_position(vehicle.world, vehicle.initial_direction);
+            let twin = self.vehicles[..index]
+                .iter()
+                .find(|other| at_spawn && other.x == vehicle.x && other.y == vehicle.y);
+            match twin {
+                Some(twin) => {
+                    warn!(
+                        "Vehicles {} and {} spawned at ({}, {}); delaying {} to the next tick",
+                        twin.id, vehicle.id, vehicle.x, vehicle.y, vehicle.id
+                    );
+                    let (id, approach) = (vehicle.id, vehicle.initial_direction);
+                    self.despawn_where(DespawnReason::Respawned, |vehicle| vehicle.id == id);
+                    self.pending_burst[approach.index()] += 1;
+                }
+                None => index += 1,
+            }
+        }
+    }
+
+    /// The following gap a driver of the given aggressiveness should keep at
+    /// the given speed (pixels per second): stopped vehicles pack tightly,
+    /// fast ones leave more room.
+    fn required_gap(&self, speed: f32, aggressiveness: f32) -> f32 {
+        self.config.base_gap + self.gap_per_speed(aggressiveness) * speed
+    }
+
+    fn gap_per_speed(&self, aggressiveness: f32) -> f32 {
+        self.config.gap_per_speed * (1.0 - aggressiveness * AGGRESSIVE_GAP_CUT)
+    }
+
+    // A driver's aggressiveness, spread evenly up to `aggressiveness`. It is
+    // worked out from the vehicle's tie-break rather than drawn from a
+    // random stream, so every other draw is left as it was and a replayed or
+    // shared arrival drives just as it did the first time.
+    fn driver_aggressiveness(&self, tie_break: u64) -> f32 {
+        // Scrambled first, so the bolder drivers don't also lose every tie.
+        let scattered = tie_break.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 40;
+        self.config.aggressiveness * scattered as f32 / (1u64 << 24) as f32
+    }
+
+    /// Each vehicle's leader, by index, with the bumper-to-bumper gap to
+    /// it: the closest vehicle ahead in its lane heading the same way.
+    /// Vehicles are sorted along each direction of travel, so each search
+    /// looks back from the vehicle's own place in its direction and stops
+    /// once nothing further ahead could be closer.
+    fn leaders(&self) -> Vec<Option<(f32, usize)>> {
+        let progress = |vehicle: &Vehicle| match vehicle.direction {
+            Direction::Up => -vehicle.y,
+            Direction::Down => vehicle.y,
+            Direction::Right => vehicle.x,
+            Direction::Left => -vehicle.x,
+        };
+        let mut leaders = vec![None; self.vehicles.len()];
+        let turned: Vec<usize> = (0..self.vehicles.len())
+            .filter(|&index| {
+                let vehicle = &self.vehicles[index];
+                vehicle.direction != vehicle.initial_direction
+            })
+            .collect();
+        for direction in Direction::ALL {
+            let mut order: Vec<usize> = (0..self.vehicles.len())
+                .filter(|&index| self.vehicles[index].direction == direction)
+                .collect();
+            // Front of the road first.
+            order.sort_by(|&a, &b| {
+                progress(&self.vehicles[b]).total_cmp(&progress(&self.vehicles[a]))
+            });
+            let longest = order
+                .iter()
+                .map(|&index| self.vehicles[index].length)
+                .fold(0.0, f32::max);
+            for (place, &index) in order.iter().enumerate() {
+                let vehicle = &self.vehicles[index];
+                let mut best: Option<(f32, usize)> = None;
+                for &other in order[..place].iter().rev() {
+                    let ahead = progress(&self.vehicles[other]) - progress(vehicle);
+                    if best.is_some_and(|(gap, _)| ahead - (vehicle.length + longest) / 2.0 >= gap)
+                    {
+                        break;
+                    }
+                    if let Some(gap) = vehicle.gap_to(&self.vehicles[other])
+                        && best.is_none_or(|(best_gap, _)| gap < best_gap)
+                    {
+                        best = Some((gap, other));
+                    }
+                }
+                // Vehicles that turned off this lane, until they clear it.
+                for &other in &turned {
+                    let turned = &self.vehicles[other];
+                    if turned.direction != direction
+                        && turned.initial_direction == vehicle.initial_direction
+                        && let Some(gap) = vehicle.gap_to(turned)
+                        && best.is_none_or(|(best_gap, _)| gap < best_gap)
+                    {
+                        best = Some((gap, other));
+                    }
+                }
+                leaders[index] = best;
+            }
+        }
+        leaders
+    }
+
+    /// The furthest a vehicle may move this tick, and the distance it has
+    /// to brake in: up to the following gap behind its leader, plus however
+    /// far the leader itself would take to stop.
+    fn step_limits(&self, index: usize, leader: Option<(f32, usize)>, dt: f32) -> (f32, f32) {
+        let vehicle = &self.vehicles[index];
+        let leader = leader.map(|(gap, other)| (gap, &self.vehicles[other]));
+        let (step, brake_room) = match leader {
+            Some((gap, leader)) => {
+                let spacing = vehicle.kind.gap_factor().max(leader.kind.gap_factor());
+                let free = (gap
+                    - spacing * self.required_gap(vehicle.speed(dt), vehicle.aggressiveness))
+                .max(0.0);
+                let mut brake_room = free + leader.stopping_distance();
+                if vehicle.max_jerk.is_finite() {
+                    // A jerk-limited vehicle can't stop short at the
+                    // following gap, so it aims for the leader's speed plus
+                    // enough to close the rest of the gap over the time its
+                    // acceleration takes to wind up and down again. The gap
+                    // it needs keeps growing while it winds down.
+                    let jerk = vehicle.max_jerk;
+                    let ramp = vehicle.max_acceleration() / jerk;
+                    let easing = vehicle.acceleration.max(0.0).powi(2) / (2.0 * jerk);
+                    let free_later = free
+                        - self.gap_per_speed(vehicle.aggressiveness)
+                            * self.config.tick_rate
+                            * easing;
+                    let keep = (leader.velocity + free_later / (2.0 * ramp)).max(0.0);
+                    let room = keep.powi(2) / (2.0 * vehicle.deceleration());
+                    brake_room = brake_room.min(room + vehicle.braking_lag());
+                }
+                (free, brake_room)
+            }
+            None => (f32::INFINITY, f32::INFINITY),
+        };
+        if self.yields_at_apex(vehicle)
+            || self.exit_blocked(vehicle)
+            || self.crosswalk_busy(vehicle)
+            || self.gives_way_to_emergency(vehicle)
+            || self.box_clearing(vehicle)
+        {
+            let to_stop_line = vehicle.stop_line_distance();
+            (step.min(to_stop_line), brake_room.min(to_stop_line))
+        } else {
+            (step, brake_room)
+        }
+    }
+
+    /// Don't block the box: a vehicle that hasn't entered holds at its stop
+    /// line unless its exit lane has room past the box for it and for every
+    /// vehicle already in the box bound the same way. Only stopped vehicles
+    /// on the exit lane take up room; moving ones will have driven on by
+    /// the time it gets there.
+    fn exit_blocked(&self, vehicle: &Vehicle) -> bool {
+        if vehicle.stop_line_distance() < 0.0 {
+            return false;
+        }
+        let exit = vehicle.exit_direction();
+        let (dx, dy) = exit.delta();
+        let along = |x: f32, y: f32| x * dx as f32 + y * dy as f32;
+        let (center_x, center_y) = vehicle.world.centre();
+        let edge = along(center_x, center_y) + vehicle.world.half_road();
+        let room = self
+            .vehicles
+            .iter()
+            .filter(|other| {
+                other.direction == exit
+                    && other.velocity == 0.0
+                    && other.lane_offset() < other.world.lane_width as f32 / 2.0
+                    && !other.overlaps_intersection()
+                    && along(other.x, other.y) > edge
+            })
+            .map(|other| along(other.x, other.y) - other.length / 2.0 - edge)
+            .min_by(f32::total_cmp);
+        let Some(room) = room else {
+            return false;
+        };
+        let needed: f32 = self
+            .vehicles
+            .iter()
+            .filter(|other| other.entered() && other.exit_direction() == exit)
+            .chain(std::iter::once(vehicle))
+            .map(|other| other.length + self.config.base_gap)
+            .sum();
+        room < needed
+    }
+
+    // Holds the lights green for the first emergency vehicle still to get
+    // through the box, then for the approach held from the mouse, and hands
+    // them back to the plan once neither is.
+    fn preempt_for_emergency(&mut self) {
+        let first = self
+            .vehicles
+            .iter()
+            .filter(|vehicle| vehicle.kind == VehicleKind::Emergency)
+            .filter(|vehicle| !vehicle.cleared_intersection())
+            .min_by_key(|vehicle| vehicle.id);
+        match first {
+            Some(vehicle) => {
+                let approach = vehicle.initial_direction;
+                if self.lights.preempted() != Some(approach) && self.lights.preempt(approach) {
+                    info!(
+                        "Preempted the lights for emergency vehicle {} on the {} approach",
+                        vehicle.id, approach
+                    );
+                }
+            }
+            None => match self.held_light {
+                Some(approach) => {
+                    if self.lights.preempted() != Some(approach) {
+                        self.lights.preempt(approach);
+                    }
+                }
+                None => {
+                    if self.lights.preempted().is_some() {
+                        info!("Preemption over; the plan resumes");
+                        self.lights.end_preemption();
+                    }
+                }
+            },
+        }
+    }
+
+    /// Whether a vehicle that hasn't entered must hold at its line while an
+    /// emergency vehicle from another approach gets through: every vehicle
+    /// must for the first one, and later ones must for earlier ones. A
+    /// vehicle too close to its line to stop carries on.
+    fn gives_way_to_emergency(&self, vehicle: &Vehicle) -> bool {
+        let to_stop_line = vehicle.stop_line_distance();
+        if to_stop_line < 0.0 || to_stop_line < vehicle.stopping_distance() {
+            return false;
+        }
+        self.vehicles.iter().any(|other| {
+            other.kind == VehicleKind::Emergency
+                && other.initial_direction != vehicle.initial_direction
+                && !other.cleared_intersection()
+                && (vehicle.kind != VehicleKind::Emergency || other.id < vehicle.id)
+        })
+    }
+
+    /// Whether a vehicle that hasn't entered must hold at its line for
+    /// someone out on a crosswalk it would drive over: the one on its own
+    /// arm, or the one on the arm it leaves by.
+    fn crosswalk_busy(&self, vehicle: &Vehicle) -> bool {
+        if self.pedestrians.is_empty() || vehicle.stop_line_distance() < 0.0 {
+            return false;
+        }
+        self.pedestrians.iter().any(|pedestrian| {
+            pedestrian.on_road(vehicle.world) && drives_over(vehicle, pedestrian.arm)
+        })
+    }
+
+    /// Whether the walk signal is lit on `arm`'s crosswalk: under the
+    /// lights, while its approach has neither green nor yellow.
+    fn walk_signal(&self, arm: Direction) -> bool {
+        self.config.signalled() && !self.lights.is_green(arm) && !self.lights.is_yellow(arm)
+    }
+
+    /// Whether pedestrians may step off the kerb onto `arm`'s crosswalk:
+    /// the walk signal is lit, where there are lights, and no vehicle on its
+    /// way over the crosswalk is on it, past its stop line, or too close to
+    /// the line to stop.
+    fn may_walk(&self, arm: Direction) -> bool {
+        if self.config.signalled() && !self.walk_signal(arm) {
+            return false;
+        }
+        let world = self.config.world();
+        let (x, y) = crosswalk_centre(world, arm);
+        let (half_width, half_height) = crosswalk_half_extents(world, arm);
+        !self.vehicles.iter().any(|vehicle| {
+            vehicle.overlaps_footprint(x, y, half_width, half_height)
+                || (drives_over(vehicle, arm)
+                    && !vehicle.cleared_intersection()
+                    && vehicle.stop_line_distance()
+                        < vehicle.stopping_distance() + vehicle.velocity)
+        })
+    }
+
+    // New pedestrians arrive as a Poisson process at a random kerb of a
+    // random crosswalk. Those waiting step off together when they may, and
+    // the ones on the far pavement leave.
+    fn update_pedestrians(&mut self, dt: f32) {
+        if !self.config.has_pedestrians() {
+            return;
+        }
+        let mut arrivals = Vec::new();
+        if let Some(replay) = &mut self.replay {
+            while let Some(record) = replay.front()
+                && record.tick == self.tick
+                && let Spawn::Pedestrian { arm, side } = record.spawn
+            {
+                arrivals.push((arm, side));
+                replay.pop_front();
+            }
+        } else if self.spawning_enabled()
+            && self.rng.random::<f32>() < self.config.pedestrian_rate * dt
+        {
+            let arms: Vec<Direction> = Direction::ALL
+                .into_iter()
+                .filter(|&arm| self.config.layout.accepts_approach(arm))
+                .collect();
+            let arm = arms[self.rng.random_range(0..arms.len())];
+            let side = if self.rng.random::<bool>() { 1.0 } else { -1.0 };
+            arrivals.push((arm, side));
+        }
+        for (arm, side) in arrivals {
+            self.pedestrians
+                .push(Pedestrian::new(self.next_pedestrian_id, arm, side));
+            self.next_pedestrian_id += 1;
+            self.record(Spawn::Pedestrian { arm, side });
+        }
+
+        let walkable = Direction::ALL.map(|arm| self.may_walk(arm));
+        for pedestrian in &mut self.pedestrians {
+            if !pedestrian.walking && walkable[pedestrian.arm.index()] {
+                pedestrian.walking = true;
+            }
+            if pedestrian.walking {
+                pedestrian.walked += WALK_SPEED * dt;
+            } else {
+                pedestrian.waited += dt;
+            }
+        }
+        let world = self.config.world();
+        for pedestrian in self
+            .pedestrians
+            .iter()
+            .filter(|pedestrian| pedestrian.crossed(world))
+        {
+            self.pedestrians_crossed += 1;
+            self.pedestrian_wait_total += pedestrian.waited;
+        }
+        self.pedestrians
+            .retain(|pedestrian| !pedestrian.crossed(world));
+    }
+
+    /// Under the lights, a green is no licence to drive into a vehicle from
+    /// a crossing approach still in the box from its own green, as slow
+    /// ones in the rain can be after the all-red. A vehicle that hasn't
+    /// entered holds at its line until any conflicting one has got clear,
+    /// unless it is too close to the line to stop.
+    fn box_clearing(&self, vehicle: &Vehicle) -> bool {
+        let to_stop_line = vehicle.stop_line_distance();
+        if !self.config.signalled()
+            || to_stop_line < 0.0
+            || to_stop_line < vehicle.stopping_distance()
+        {
+            return false;
+        }
+        let movement = Movement {
+            approach: vehicle.initial_direction,
+            route: vehicle.route,
+        };
+        self.vehicles.iter().any(|other| {
+            let crossing = other.initial_direction != vehicle.initial_direction
+                && other.initial_direction != vehicle.initial_direction.opposite();
+            let other_movement = Movement {
+                approach: other.initial_direction,
+                route: other.route,
+            };
+            crossing && other.entered() && self.conflicts.conflicts(movement, other_movement)
+        })
+    }
+
+    /// Opposing approaches get green together, so movements from them that
+    /// conflict, such as a left turn and the oncoming straight, must
+    /// take turns. A vehicle that hasn't entered yet holds at its stop line
+    /// while a conflicting one is inside, or is about to enter and has the
+    /// lower id.
+    fn yields_at_apex(&self, vehicle: &Vehicle) -> bool {
+        if vehicle.stop_line_distance() < 0.0 {
+            return false;
+        }
+        let movement = Movement {
+            approach: vehicle.initial_direction,
+            route: vehicle.route,
+        };
+        self.vehicles.iter().any(|other| {
+            let other_movement = Movement {
+                approach: other.initial_direction,
+                route: other.route,
+            };
+            if other.initial_direction != vehicle.initial_direction.opposite()
+                || !self.conflicts.conflicts(movement, other_movement)
+            {
+                return false;
+            }
+            let to_stop_line = other.stop_line_distance();
+            if to_stop_line < 0.0 {
+                other.entered()
+            } else {
+                other.id < vehicle.id && to_stop_line < APEX_HORIZON && self.may_enter(other)
+            }
+        })
+    }
+
+    /// Whether `vehicle` may cross its stop line now: on green for its own
+    /// route under the lights, or on yellow if it is too close to stop, or
+    /// by the rules of the unsignalled modes. A roundabout is entered as an
+    /// uncontrolled junction, but giving way to the left, where the ring
+    /// comes from.
+    fn may_enter(&self, vehicle: &Vehicle) -> bool {
+        let roundabout = self.config.layout == Layout::Roundabout;
+        let mode = if roundabout {
+            ControlMode::Uncontrolled
+        } else {
+            self.config.control_mode
+        };
+        if mode == ControlMode::Lights {
+            let (approach, route) = (vehicle.initial_direction, vehicle.route);
+            return self.lights.is_green_for(approach, route)
+                || (self.lights.is_yellow_for(approach, route)
+                    && vehicle.runs_yellow == Some(true));
+        }
+        if vehicle.stop_line_distance() < 0.0 {
+            return true;
+        }
+        // Only the first vehicle of each queue has a claim; the ones behind
+        // it can't take their turn anyway.
+        if !self.leads_its_queue(vehicle) {
+            return false;
+        }
+        let movement = Movement {
+            approach: vehicle.initial_direction,
+            route: vehicle.route,
+        };
+        let conflicting = |other: &&Vehicle| {
+            other.initial_direction != vehicle.initial_direction
+                && self.conflicts.conflicts(
+                    movement,
+                    Movement {
+                        approach: other.initial_direction,
+                        route: other.route,
+                    },
+                )
+        };
+        let box_busy = self
+            .vehicles
+            .iter()
+            .filter(conflicting)
+            .any(|other| other.entered());
+        // Car following only sees vehicles heading the same way, so don't
+        // follow one from this approach round its turn.
+        let turning_ahead = self.vehicles.iter().any(|other| {
+            other.initial_direction == vehicle.initial_direction
+                && other.direction != vehicle.direction
+                && other.overlaps_intersection()
+        });
+        if box_busy || turning_ahead {
+            return false;
+        }
+
+        let waiting = self
+            .vehicles
+            .iter()
+            .filter(conflicting)
+            .filter(|other| other.stop_line_distance() >= 0.0);
+        match mode {
+            ControlMode::Lights => unreachable!(),
+            ControlMode::AllWayStop => {
+                let Some(stopped) = vehicle.stopped_at_line else {
+                    return false;
+                };
+                let turn = (stopped, vehicle.tie_break, vehicle.id);
+                !waiting
+                    .filter_map(|other| {
+                        let tick = other.stopped_at_line?;
+                        Some((tick, other.tie_break, other.id))
+                    })
+                    .any(|other| other < turn)
+            }
+            ControlMode::Uncontrolled => {
+                let arriving: Vec<&Vehicle> = waiting
+                    .filter(|other| other.stop_line_distance() < APEX_HORIZON)
+                    .filter(|other| self.leads_its_queue(other))
+                    .collect();
+                // With traffic arriving on all four approaches everyone may
+                // have someone on their right, so the tie-break decides.
+                let approaches: HashSet<Direction> = self
+                    .vehicles
+                    .iter()
+                    .filter(|other| (0.0..APEX_HORIZON).contains(&other.stop_line_distance()))
+                    .map(|other| other.initial_direction)
+                    .collect();
+                if approaches.len() == 4 {
+                    let claim = |other: &Vehicle| (other.tie_break, other.id);
+                    return arriving.iter().all(|other| claim(other) > claim(vehicle));
+                }
+                let right = arriving_from_the_right(vehicle.initial_direction);
+                let priority = if roundabout { right.opposite() } else { right };
+                !arriving
+                    .iter()
+                    .any(|other| other.initial_direction == priority)
+            }
+        }
+    }
+
+    /// Whether no vehicle on the same approach is closer to the stop line.
+    fn leads_its_queue(&self, vehicle: &Vehicle) -> bool {
+        let distance = vehicle.stop_line_distance();
+        !self.vehicles.iter().any(|other| {
+            other.initial_direction == vehicle.initial_direction
+                && (0.0..distance).contains(&other.stop_line_distance())
+        })
+    }
+
+    fn spawn_point_occupied(&self, direction: Direction) -> bool {
+        self.spawn_point_occupied_for(direction, VehicleKind::Car)
+    }
+
+    /// Whether a vehicle of `kind` spawned now would overlap another.
+    fn spawn_point_occupied_for(&self, direction: Direction, kind: VehicleKind) -> bool {
+        let (x, y) = spawn_position(self.config.world(), direction);
+        let (half_width, half_height) = half_extents(
+            kind.length(self.config.vehicle_length),
+            kind.width(self.config.vehicle_width),
+            direction,
+        );
+        self.vehicles
+            .iter()
+            .any(|vehicle| vehicle.overlaps_footprint(x, y, half_width, half_height))
+    }
+
+    // Whether `direction` already has `queue_cap` vehicles a lane short of
+    // its stop line.
+    fn queue_full(&self, direction: Direction) -> bool {
+        self.config
+            .queue_cap
+            .is_some_and(|cap| self.queue_lengths()[direction.index()] >= cap)
+    }
+
+    /// Vehicles on each approach that have yet to reach the stop line.
+    fn queue_lengths(&self) -> [usize; 4] {
+        let mut queues = [0; 4];
+        for vehicle in &self.vehicles {
+            if vehicle.stop_line_distance() >= 0.0 {
+                queues[vehicle.initial_direction.index()] += 1;
+            }
+        }
+        queues
+    }
+
+    fn lap(&mut self, stage: Stage) {
+        if let Some(profile) = &mut self.profile {
+            profile.lap(stage);
+        }
+    }
+
+    pub fn update(&mut self) {
+        if let Some(profile) = &mut self.profile {
+            profile.start_tick(self.vehicles.len());
+        }
+        self.events.drain(..self.previous_events);
+        self.replay_spawns(true);
+        self.updating = true;
+        self.tick += 1;
+        let dt = self.config.tick_seconds();
+        self.lights.update(dt, self.queue_lengths());
+        if self.config.signalled() {
+            self.preempt_for_emergency();
+        }
+
+        if self.lights.in_recovery() {
+            for vehicle in &mut self.vehicles {
+                if self.lights.is_green(vehicle.initial_direction) {
+                    vehicle.frozen = false;
+                }
+            }
+        }
+
+        self.lap(Stage::Signals);
+
+        // Limits come from the positions at the start of the tick, so the update
+        // order of the vehicles doesn't matter.
+        let threads = self.config.worker_threads();
+        let leaders = self.leaders();
+        let limits = check_each(self.vehicles.len(), threads, |index| {
+            self.step_limits(index, leaders[index], dt)
+        });
+
+        // Each vehicle decides once, as its yellow comes on, whether it is too
+        // close to stop; deciding again as it brakes would change its mind.
+        // Aggressive drivers count as too close from further back.
+        for vehicle in &mut self.vehicles {
+            if !self
+                .lights
+                .is_yellow_for(vehicle.initial_direction, vehicle.route)
+            {
+                vehicle.runs_yellow = None;
+            } else if vehicle.runs_yellow.is_none() {
+                let to_stop_line = vehicle.stop_line_distance();
+                let reach = 1.0 + vehicle.aggressiveness * AGGRESSIVE_YELLOW_REACH;
+                vehicle.runs_yellow =
+                    Some(to_stop_line >= 0.0 && to_stop_line < vehicle.stopping_distance() * reach);
+            }
+        }
+        let greens = check_each(self.vehicles.len(), threads, |index| {
+            self.may_enter(&self.vehicles[index])
+        });
+        self.lap(Stage::Following);
+
+        // Where everyone was, in case a move has to be undone.
+        let before: Vec<(f32, f32, Direction)> = if self.config.rollback_collisions {
+            self.vehicles
+                .iter()
+                .map(|vehicle| (vehicle.x, vehicle.y, vehicle.direction))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let mut waiting = [0; 4];
+        let mut delayed = 0.0;
+        for ((vehicle, (max_step, brake_room)), green) in
+            self.vehicles.iter_mut().zip(limits).zip(greens)
+        {
+            let (direction, moving) = (vehicle.direction, vehicle.velocity > 0.0);
+            vehicle.update(green, max_step, brake_room);
+            if vehicle.crossed_stop_line() && vehicle.runs_yellow == Some(true) {
+                self.yellow_runs += 1;
+            }
+            if vehicle.direction != direction {
+                self.events.push(SimEvent::Turned { id: vehicle.id });
+            }
+            if moving && vehicle.velocity == 0.0 {
+                self.events.push(SimEvent::Stopped { id: vehicle.id });
+            }
+            if vehicle.velocity == 0.0
+                && vehicle.stopped_at_line.is_none()
+                && (0.0..1.0).contains(&vehicle.stop_line_distance())
+            {
+                vehicle.stopped_at_line = Some(self.tick);
+            }
+            vehicle.ease_heading(self.config.smooth_headings);
+            let crawling = vehicle.speed(dt) < DELAY_SPEED_THRESHOLD;
+            if crawling {
+                vehicle.delay += dt;
+                delayed += dt;
+            }
+            self.heatmap.add(vehicle, crawling, dt);
+            if vehicle.is_waiting() {
+                waiting[vehicle.initial_direction.index()] += 1;
+            }
+        }
+        for (max, waiting) in self.max_queue.iter_mut().zip(waiting) {
+            *max = (*max).max(waiting);
+        }
+        self.delay_accrued += delayed as f64;
+        self.update_pedestrians(dt);
+        self.lap(Stage::Movement);
+
+        for flash in &mut self.flashes {
+            flash.ticks_left -= 1;
+        }
+        self.flashes.retain(|flash| flash.ticks_left > 0);
+        // Those leaving drive on as they fade; a crashed one stays put.
+        for fade in &mut self.fades {
+            fade.vehicle.update(true, f32::INFINITY, f32::INFINITY);
+            fade.ticks_left -= 1;
+        }
+        self.fades.retain(|fade| fade.ticks_left > 0);
+        if self.config.rollback_collisions {
+            self.roll_back_overlaps(&before);
+        }
+        let candidates = SpatialGrid::new(&self.vehicles, 0.0).candidate_pairs();
+        if let Some(profile) = &mut self.profile {
+            profile.count_pair_checks(candidates.len());
+        }
+        let collisions: Vec<(usize, usize)> = candidates
+            .into_iter()
+            .filter(|&(i, j)| self.vehicles[i].overlaps(&self.vehicles[j]))
+            .collect();
+        self.handle_collisions(&collisions);
+        self.count_close_calls();
+        self.lap(Stage::Collisions);
+        self.check_starvation();
+        self.check_gridlock(dt);
+        self.check_lanes();
+
+        let at_box_exit = self.config.despawn_at_box_exit;
+        self.despawn_where(DespawnReason::Exited, |vehicle| {
+            vehicle.has_left(vehicle.world.bounds())
+                || (at_box_exit && vehicle.cleared_intersection())
+        });
+
+        for direction in Direction::ALL {
+            if self.spawn_blocked[direction.index()] && !self.spawn_point_occupied(direction) {
+                self.spawn_blocked[direction.index()] = false;
+            }
+        }
+        self.generate_arrivals();
+        self.spawn_pending_burst();
+        self.replay_spawns(false);
+        self.delay_coincident_spawns();
+        if let Some(drain) = &mut self.drain
+            && drain.finished.is_none()
+            && self.vehicles.is_empty()
+        {
+            drain.finished = Some(self.tick);
+            self.events.push(SimEvent::Drained);
+        }
+
+        if self.config.check_invariants {
+            for violation in self.invariant_violations() {
+                if self.config.invariant_panic {
+                    panic!("Invariant violated at tick {}: {}", self.tick, violation);
+                }
+                error!("Invariant violated at tick {}: {}", self.tick, violation);
+            }
+        }
+
+        let every = self.config.seconds_to_ticks(self.config.metrics_every) as u64;
+        if every > 0 && self.tick.is_multiple_of(every) {
+            self.sample_metrics();
+            if let Err(e) = self.save_metrics() {
+                warn!("{}", e);
+            }
+        }
+
+        self.previous_events = self.events.len();
+        self.updating = false;
+        self.lap(Stage::Bookkeeping);
+        let every = self.config.profile_every;
+        if let Some(profile) = &mut self.profile
+            && profile.ticks() >= every
+        {
+            print!("{}", profile.report());
+        }
+    }
+
+    /// Runs for `seconds` of simulated time with every approach spawning as
+    /// fast as its spawn point clears, checking the invariants after every
+    /// tick. Returns what went wrong: broken invariants, collisions and
+    /// gridlocks, each once with the tick it first happened on. Any of
+    /// `stop_when` ends it sooner. With `drain` set it then stops spawning
+    /// and keeps going until the road is empty, which fails if it takes
+    /// longer than `DRAIN_LIMIT_SECONDS`.
+    pub fn soak(&mut self, seconds: f64) -> Vec<String> {
+        let mut failures = Vec::new();
+        let mut gridlocked = false;
+        while self.simulated_seconds() < seconds {
+            for direction in Direction::ALL {
+                if self.config.layout.accepts_approach(direction) {
+                    self.spawn_vehicle(direction);
+                }
+            }
+            self.soak_tick(&mut failures, &mut gridlocked);
+            if let Some(condition) = self.met_stop_condition() {
+                info!("Stopping the soak: {}", condition.name());
+                self.stopped_by = Some(condition);
+                break;
+            }
+        }
+        if self.config.drain {
+            self.start_drain();
+            let deadline = self.simulated_seconds() + DRAIN_LIMIT_SECONDS;
+            while !self.vehicles.is_empty() {
+                if self.simulated_seconds() >= deadline {
+                    failures.push(format!(
+                        "tick {}: {} vehicle(s) still on the road after draining for {}s",
+                        self.tick,
+                        self.vehicles.len(),
+                        DRAIN_LIMIT_SECONDS
+                    ));
+                    break;
+                }
+                self.soak_tick(&mut failures, &mut gridlocked);
+            }
+        }
+        failures
+    }
+
+    /// Runs one tick under an outside signal controller, for an agent that
+    /// learns to drive the lights. Build the simulation with the
+    /// `external` signal controller so the lights never change on their
+    /// own, spawn traffic as the experiment needs, then call this in a
+    /// loop.
+    ///
+    /// The action is applied before the tick: `Switch(p)` starts the change
+    /// to plan phase `p`, through yellow and all-red, if
+    /// `TrafficLights::command` accepts it; `Hold` and refused switches
+    /// keep the lights as they are.
+    pub fn step(&mut self, action: PhaseCommand) -> Observation {
+        let accepted = self.lights.command(action);
+        let (exited, delay) = (self.despawned[0], self.delay_accrued);
+        self.update();
+        Observation {
+            queues: self.queue_lengths(),
+            exited: self.despawned[0] - exited,
+            phase: self.lights.phase(),
+            stage: self.lights.stage(),
+            accepted,
+            reward: delay - self.delay_accrued,
+            events: self.events().to_vec(),
+        }
+    }
+
+    /// Asks the lights to start changing now, as `step` does, without
+    /// running a tick. Returns whether they accepted.
+    pub fn command_lights(&mut self, command: PhaseCommand) -> bool {
+        self.lights.command(command)
+    }
+
+    /// Runs `ticks` ticks, no matter what happens during them.
+    pub fn run_ticks(&mut self, ticks: u64) {
+        for _ in 0..ticks {
+            self.update();
+        }
+    }
+
+    /// The vehicle with `id`, while it is on the road.
+    pub fn vehicle(&self, id: u64) -> Option<&Vehicle> {
+        self.vehicles.iter().find(|vehicle| vehicle.id == id)
+    }
+
+    /// Where vehicles have spent their time, and where they have queued,
+    /// since the start.
+    pub fn heatmap(&self) -> &Heatmap {
+        &self.heatmap
+    }
+
+    /// The signals, for asking what each approach is shown. They are only
+    /// drawn, and only stop anyone, when `Config::signalled`.
+    pub fn lights(&self) -> &TrafficLights {
+        &self.lights
+    }
+
+    /// What happened during the last tick, in order, with any spawns made
+    /// between it and the tick before.
+    pub fn events(&self) -> &[SimEvent] {
+        &self.events[..self.previous_events]
+    }
+
+    fn met_stop_condition(&self) -> Option<StopCondition> {
+        self.config
+            .stop_when
+            .iter()
+            .copied()
+            .find(|&condition| match condition {
+                StopCondition::Exited(count) => self.exited.iter().sum::<usize>() >= count,
+                StopCondition::Seconds(seconds) => self.simulated_seconds() >= seconds,
+                StopCondition::Collision => self.collisions > 0,
+                StopCondition::Gridlock => self.is_gridlocked(),
+            })
+    }
+
+    pub(crate) fn soak_tick(&mut self, failures: &mut Vec<String>, gridlocked: &mut bool) {
+        let collisions = self.collisions;
+        self.update();
+        for violation in self.invariant_violations() {
+            failures.push(format!("tick {}: {}", self.tick, violation));
+        }
+        if self.collisions > collisions {
+            failures.push(format!(
+                "tick {}: {} new collision(s)",
+                self.tick,
+                self.collisions - collisions
+            ));
+        }
+        if self.is_gridlocked() && !*gridlocked {
+            failures.push(format!(
+                "tick {}: the intersection is gridlocked",
+                self.tick
+            ));
+        }
+        *gridlocked = self.is_gridlocked();
+    }
+
+    /// The rules every tick should leave intact, as messages naming the
+    /// vehicles that break them.
+    fn invariant_violations(&self) -> Vec<String> {
+        let mut violations = Vec::new();
+        for (i, j) in self.detect_collisions() {
+            let (vehicle, other) = (&self.vehicles[i], &self.vehicles[j]);
+            if vehicle.direction == other.direction {
+                violations.push(format!(
+                    "vehicles {} and {} overlap in the same lane",
+                    vehicle.id, other.id
+                ));
+            }
+        }
+        for vehicle in &self.vehicles {
+            // A vehicle may overshoot its turn line by up to one step.
+            let offset = vehicle.lane_offset();
+            if offset > vehicle.cruise_speed + 0.01 {
+                violations.push(format!(
+                    "vehicle {} is {:.1}px off its lane",
+                    vehicle.id, offset
+                ));
+            }
+
+            if self.config.signalled()
+                && self.lights.stage() == lights::Stage::AllRed
+                && vehicle.crossed_stop_line()
+            {
+                violations.push(format!(
+                    "vehicle {} entered the intersection during all-red",
+                    vehicle.id
+                ));
+            }
+
+            for pedestrian in &self.pedestrians {
+                let (x, y) = pedestrian.position(vehicle.world);
+                let half = PEDESTRIAN_SIZE / 2.0;
+                if vehicle.overlaps_footprint(x, y, half, half) {
+                    violations.push(format!(
+                        "vehicle {} ran into pedestrian {}",
+                        vehicle.id, pedestrian.id
+                    ));
+                }
+            }
+        }
+        violations
+    }
+
+    /// Everything the run has been through, for `restore`. The random
+    /// streams are reseeded from themselves and the seeds saved, so the
+    /// run goes on from a restore exactly as it does from here, though not
+    /// as it would have without the snapshot. The configuration, metrics
+    /// samples, recording and events are left out.
+    pub fn snapshot(&mut self) -> Snapshot {
+        let seeds: (u64, u64) = (self.rng.random(), self.tie_rng.random());
+        self.rng = StdRng::seed_from_u64(seeds.0);
+        self.tie_rng = StdRng::seed_from_u64(seeds.1);
+
+        let mut snapshot = Snapshot::default();
+        snapshot.push(Line::new("layout").put(self.config.layout.to_string()));
+        snapshot.push(
+            Line::new("clock")
+                .put(self.tick)
+                .put(self.next_id)
+                .put(self.next_pedestrian_id)
+                .put(seeds),
+        );
+        snapshot.push(self.lights.save_state());
+        snapshot.push(Line::new("held").put(self.held_light));
+        snapshot.push(
+            Line::new("counters")
+                .put(self.collisions)
+                .put_all(self.collision_severity)
+                .put_all(self.despawned)
+                .put(self.pedestrians_crossed)
+                .put(self.pedestrian_wait_total)
+                .put(self.box_stalled_for)
+                .put(self.delay_accrued)
+                .put(self.rollbacks)
+                .put(self.close_calls)
+                .put(self.yellow_runs)
+                .put(self.crossing_ticks)
+                .put(self.trip_speeds)
+                .put(self.speed_total)
+                .put(self.delays),
+        );
+        for approach in Direction::ALL {
+            let index = approach.index();
+            snapshot.push(
+                Line::new("approach")
+                    .put(approach)
+                    .put(self.delay_totals[index])
+                    .put(self.exited[index])
+                    .put_all(self.trips[index])
+                    .put(self.refused_spawns[index])
+                    .put(self.max_queue[index])
+                    .put(self.spawn_blocked[index])
+                    .put(self.pending_burst[index])
+                    .put(self.last_spawn_time[index])
+                    .put(self.starvation_warned[index])
+                    .put_all(self.exit_delays[index].iter().copied()),
+            );
+            if !self.held_spawns[index].is_empty() {
+                snapshot.push(
+                    Line::new("held_spawns")
+                        .put(approach)
+                        .put_all(self.held_spawns[index].iter().copied()),
+                );
+            }
+        }
+        if let Some(drain) = &self.drain {
+            snapshot.push(
+                Line::new("drain")
+                    .put(drain.started)
+                    .put(drain.load)
+                    .put(drain.finished),
+            );
+        }
+        for vehicle in &self.vehicles {
+            snapshot.push(snapshot::vehicle_line(vehicle));
+        }
+        for pedestrian in &self.pedestrians {
+            snapshot.push(snapshot::pedestrian_line(pedestrian));
+        }
+        for flash in &self.flashes {
+            snapshot.push(
+                Line::new("flash")
+                    .put(flash.x)
+                    .put(flash.y)
+                    .put(flash.relative_speed)
+                    .put(flash.ticks_left),
+            );
+        }
+        for fade in &self.fades {
+            snapshot.push(snapshot::fade_line(fade.ticks_left, &fade.vehicle));
+        }
+        // Sorted, so the same state always writes the same file.
+        let mut sets = [
+            (
+                "colliding",
+                self.colliding_pairs.iter().copied().collect::<Vec<_>>(),
+            ),
+            ("close", self.close_pairs.iter().copied().collect()),
+        ];
+        for (name, pairs) in &mut sets {
+            pairs.sort_unstable();
+            snapshot.push(Line::new(name).put_all(pairs.iter().copied()));
+        }
+        let mut off_lane: Vec<u64> = self.off_lane.iter().copied().collect();
+        off_lane.sort_unstable();
+        snapshot.push(Line::new("off_lane").put_all(off_lane));
+        for line in self.heatmap.save_state() {
+            snapshot.push(line);
+        }
+        snapshot
+    }
+
+    /// Puts the run back as `snapshot` found it, under the configuration it
+    /// has now, so light timings and the controller can be tuned between
+    /// restores. Fails, changing nothing, if the snapshot was taken on
+    /// another layout, its lights are in a phase the plan no longer has, or
+    /// the run is being recorded or replayed.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), String> {
+        if self.recording.is_some() || self.replay.is_some() {
+            return Err("A recorded or replayed run cannot be restored".to_string());
+        }
+        let mut fields = snapshot.line("layout")?;
+        let layout: String = fields.take()?;
+        fields.finish()?;
+        if layout != self.config.layout.to_string() {
+            return Err(format!(
+                "The snapshot was taken on the {} layout, not {}",
+                layout, self.config.layout
+            ));
+        }
+        let mut clock = snapshot.line("clock")?;
+        let tick = clock.take()?;
+        let next_id = clock.take()?;
+        let next_pedestrian_id = clock.take()?;
+        let (seed, tie_seed): (u64, u64) = clock.take()?;
+        clock.finish()?;
+
+        let mut held = snapshot.line("held")?;
+        let held_light = held.take()?;
+        held.finish()?;
+
+        let mut counters = snapshot.line("counters")?;
+        let collisions = counters.take()?;
+        let collision_severity = counters.take_array()?;
+        let despawned = counters.take_array()?;
+        let pedestrians_crossed = counters.take()?;
+        let pedestrian_wait_total = counters.take()?;
+        let box_stalled_for = counters.take()?;
+        let delay_accrued = counters.take()?;
+        let rollbacks = counters.take()?;
+        let close_calls = counters.take()?;
+        let yellow_runs = counters.take()?;
+        let crossing_ticks = counters.take()?;
+        let trip_speeds = counters.take()?;
+        let speed_total = counters.take()?;
+        let delays = counters.take()?;
+        counters.finish()?;
+
+        let mut delay_totals = [0.0; 4];
+        let mut exited = [0; 4];
+        let mut trips = [[0; 4]; 4];
+        let mut refused_spawns = [0; 4];
+        let mut max_queue = [0; 4];
+        let mut spawn_blocked = [false; 4];
+        let mut pending_burst = [0; 4];
+        let mut last_spawn_time = [None; 4];
+        let mut starvation_warned = [false; 4];
+        let mut exit_delays: [Vec<f32>; 4] = Default::default();
+        let mut seen = [false; 4];
+        for mut fields in snapshot.all("approach") {
+            let index = fields.take::<Direction>()?.index();
+            seen[index] = true;
+            delay_totals[index] = fields.take()?;
+            exited[index] = fields.take()?;
+            trips[index] = fields.take_array()?;
+            refused_spawns[index] = fields.take()?;
+            max_queue[index] = fields.take()?;
+            spawn_blocked[index] = fields.take()?;
+            pending_burst[index] = fields.take()?;
+            last_spawn_time[index] = fields.take()?;
+            starvation_warned[index] = fields.take()?;
+            exit_delays[index] = fields.take_rest()?;
+        }
+        if seen.contains(&false) {
+            return Err("The snapshot needs an approach line for every approach".to_string());
+        }
+        let mut held_spawns: [VecDeque<Option<Route>>; 4] = Default::default();
+        for mut fields in snapshot.all("held_spawns") {
+            let index = fields.take::<Direction>()?.index();
+            held_spawns[index] = fields.take_rest()?.into();
+        }
+
+        let drain = match snapshot.all("drain").next() {
+            Some(mut fields) => {
+                let drain = Drain {
+                    started: fields.take()?,
+                    load: fields.take()?,
+                    finished: fields.take()?,
+                };
+                fields.finish()?;
+                Some(drain)
+            }
+            None => None,
+        };
+        let mut vehicles = snapshot
+            .all("vehicle")
+            .map(snapshot::read_vehicle)
+            .collect::<Result<Vec<_>, _>>()?;
+        for vehicle in &mut vehicles {
+            vehicle.traction = self.config.traction();
+            vehicle.world = self.config.world();
+            vehicle.tick_rate = self.config.tick_rate;
+        }
+        let pedestrians = snapshot
+            .all("pedestrian")
+            .map(snapshot::read_pedestrian)
+            .collect::<Result<Vec<_>, _>>()?;
+        let flashes = snapshot
+            .all("flash")
+            .map(|mut fields| {
+                let flash = Flash {
+                    x: fields.take()?,
+                    y: fields.take()?,
+                    relative_speed: fields.take()?,
+                    ticks_left: fields.take()?,
+                };
+                fields.finish()?;
+                Ok(flash)
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        let fades = snapshot
+            .all("fade")
+            .map(|mut fields| {
+                let ticks_left = fields.take()?;
+                let mut vehicle = snapshot::read_vehicle(fields)?;
+                vehicle.traction = self.config.traction();
+                vehicle.world = self.config.world();
+                vehicle.tick_rate = self.config.tick_rate;
+                Ok(Fade {
+                    vehicle,
+                    ticks_left,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        let pairs = |name: &str| -> Result<HashSet<(u64, u64)>, String> {
+            let pairs: Vec<(u64, u64)> = snapshot.line(name)?.take_rest()?;
+            Ok(pairs.into_iter().collect())
+        };
+        let colliding_pairs = pairs("colliding")?;
+        let close_pairs = pairs("close")?;
+        let off_lane: Vec<u64> = snapshot.line("off_lane")?.take_rest()?;
+        let heatmap = Heatmap::from_state(self.config.world(), snapshot.all("heat"))?;
+
+        // Everything else is read, and the lights check what they read
+        // before they change, so a failure here still changes nothing.
+        self.lights.restore_state(snapshot.line("lights")?)?;
+        self.rng = StdRng::seed_from_u64(seed);
+        self.tie_rng = StdRng::seed_from_u64(tie_seed);
+        self.tick = tick;
+        self.next_id = next_id;
+        self.next_pedestrian_id = next_pedestrian_id;
+        self.held_light = held_light;
+        self.collisions = collisions;
+        self.collision_severity = collision_severity;
+        self.despawned = despawned;
+        self.pedestrians_crossed = pedestrians_crossed;
+        self.pedestrian_wait_total = pedestrian_wait_total;
+        self.box_stalled_for = box_stalled_for;
+        self.delay_accrued = delay_accrued;
+        self.rollbacks = rollbacks;
+        self.close_calls = close_calls;
+        self.yellow_runs = yellow_runs;
+        self.crossing_ticks = crossing_ticks;
+        self.trip_speeds = trip_speeds;
+        self.speed_total = speed_total;
+        self.delays = delays;
+        self.delay_totals = delay_totals;
+        self.exited = exited;
+        self.trips = trips;
+        self.refused_spawns = refused_spawns;
+        self.max_queue = max_queue;
+        self.spawn_blocked = spawn_blocked;
+        self.pending_burst = pending_burst;
+        self.last_spawn_time = last_spawn_time;
+        self.held_spawns = held_spawns;
+        self.starvation_warned = starvation_warned;
+        self.exit_delays = exit_delays;
+        self.drain = drain;
+        self.vehicles = vehicles;
+        self.pedestrians = pedestrians;
+        self.flashes = flashes;
+        self.fades = fades;
+        self.colliding_pairs = colliding_pairs;
+        self.close_pairs = close_pairs;
+        self.off_lane = off_lane.into_iter().collect();
+        self.heatmap = heatmap;
+        self.events.clear();
+        self.previous_events = 0;
+        Ok(())
+    }
+
+    /// A hash of everything that determines future behavior. Runs with the same
+    /// seed and inputs produce the same sequence, so the first mismatching
+    /// tick shows where two runs diverged.
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = StableHasher::new();
+        hasher.write(self.lights.phase() as u64);
+        hasher.write(self.lights.stage() as u64);
+        hasher.write(self.lights.elapsed().to_bits() as u64);
+        hasher.write(
+            self.lights
+                .recovery_step()
+                .map_or(0, |step| step as u64 + 1),
+        );
+        hasher.write(
+            self.lights
+                .preempted()
+                .map_or(0, |approach| approach.index() as u64 + 1),
+        );
+        for pending in self.pending_burst {
+            hasher.write(pending as u64);
+        }
+
+        let mut vehicles: Vec<&Vehicle> = self.vehicles.iter().collect();
+        vehicles.sort_by_key(|vehicle| vehicle.id);
+        for vehicle in vehicles {
+            hasher.write(vehicle.id);
+            hasher.write(vehicle.x.to_bits() as u64);
+            hasher.write(vehicle.y.to_bits() as u64);
+            hasher.write(vehicle.direction.index() as u64);
+            hasher.write(vehicle.initial_direction.index() as u64);
+            hasher.write(vehicle.route as u64);
+            hasher.write(vehicle.frozen as u64);
+            hasher.write(vehicle.held as u64);
+            hasher.write(vehicle.cruise_speed.to_bits() as u64);
+            hasher.write(vehicle.velocity.to_bits() as u64);
+            hasher.write(vehicle.kind as u64);
+            hasher.write(vehicle.reaction_timer as u64);
+            hasher.write(vehicle.runs_yellow.map_or(0, |runs| runs as u64 + 1));
+        }
+        for pedestrian in &self.pedestrians {
+            hasher.write(pedestrian.id);
+            hasher.write(pedestrian.arm.index() as u64);
+            hasher.write(pedestrian.walked.to_bits() as u64);
+            hasher.write(pedestrian.walking as u64);
+        }
+        hasher.0
+    }
+
+    /// Average seconds spent stopped or crawling by vehicles from the given
+    /// approach that have left the screen.
+    fn average_delay(&self, approach: Direction) -> f32 {
+        let index = approach.index();
+        if self.exited[index] == 0 {
+            0.0
+        } else {
+            self.delay_totals[index] / self.exited[index] as f32
+        }
+    }
+
+    // Notes each approach's figures as they stand, for `metrics_out`.
+    fn sample_metrics(&mut self) {
+        if self.config.metrics_out.is_none() {
+            return;
+        }
+        let seconds = self.simulated_seconds();
+        let queues = self.queue_lengths();
+        for approach in Direction::ALL {
+            if !self.config.layout.accepts_approach(approach) {
+                continue;
+            }
+            let index = approach.index();
+            let exited = self.exited[index];
+            self.metrics.push(MetricsSample {
+                seconds,
+                approach,
+                exited,
+                throughput: if seconds > 0.0 {
+                    exited as f64 * 60.0 / seconds
+                } else {
+                    0.0
+                },
+                mean_wait: self.average_delay(approach),
+                p95_wait: metrics::percentile(&self.exit_delays[index], 95.0),
+                queue: queues[index],
+            });
+        }
+    }
+
+    fn save_metrics(&self) -> Result<(), String> {
+        match &self.config.metrics_out {
+            Some(path) => metrics::save(Path::new(path), &self.metrics),
+            None => Ok(()),
+        }
+    }
+
+    /// Takes the run's last metrics sample, unless one was just taken, and
+    /// writes them all to `metrics_out`, if it is set.
+    pub fn write_metrics(&mut self) -> Result<(), String> {
+        if self
+            .metrics
+            .last()
+            .is_none_or(|sample| sample.seconds < self.simulated_seconds())
+        {
+            self.sample_metrics();
+        }
+        self.save_metrics()
+    }
+
+    fn detect_collisions(&self) -> Vec<(usize, usize)> {
+        overlapping_pairs(&self.vehicles)
+    }
+
+    // Counts each pair of vehicles from different approaches the first time
+    // their footprints come within `CLOSE_CALL_MARGIN` of each other without
+    // touching while heading across each other. Parallel lanes pass closer
+    // than that every time.
+    fn count_close_calls(&mut self) {
+        let nearby = SpatialGrid::new(&self.vehicles, CLOSE_CALL_MARGIN).candidate_pairs();
+        for (i, j) in nearby {
+            let (vehicle, other) = (&self.vehicles[i], &self.vehicles[j]);
+            let crossing = (vehicle.direction.delta().0 == 0) != (other.direction.delta().0 == 0);
+            if other.initial_direction == vehicle.initial_direction
+                || !crossing
+                || vehicle.overlaps(other)
+            {
+                continue;
+            }
+            let (half_width, half_height) = other.half_extents();
+            if vehicle.overlaps_footprint(
+                other.x,
+                other.y,
+                half_width + CLOSE_CALL_MARGIN,
+                half_height + CLOSE_CALL_MARGIN,
+            ) && self.close_pairs.insert((vehicle.id, other.id))
+            {
+                debug!("Vehicles {} and {} had a close call", vehicle.id, other.id);
+                self.close_calls += 1;
+            }
+        }
+    }
+
+    // Puts every vehicle whose move this tick made it newly overlap another
+    // back where it was, stopped. Undoing one move can uncover another
+    // overlap behind it, so this repeats until only vehicles that haven't
+    // moved are left overlapping.
+    fn roll_back_overlaps(&mut self, before: &[(f32, f32, Direction)]) {
+        loop {
+            let moved: HashSet<usize> = self
+                .detect_collisions()
+                .into_iter()
+                .filter(|&(i, j)| {
+                    !self
+                        .colliding_pairs
+                        .contains(&(self.vehicles[i].id, self.vehicles[j].id))
+                })
+                .flat_map(|(i, j)| [i, j])
+                .filter(|&i| {
+                    let (x, y, _) = before[i];
+                    (self.vehicles[i].x, self.vehicles[i].y) != (x, y)
+                })
+                .collect();
+            if moved.is_empty() {
+                return;
+            }
+            for index in moved {
+                let vehicle = &mut self.vehicles[index];
+                (vehicle.x, vehicle.y, vehicle.direction) = before[index];
+                vehicle.velocity = 0.0;
+                debug!("Rolled back vehicle {} at tick {}", vehicle.id, self.tick);
+                self.rollbacks += 1;
+            }
+        }
+    }
+
+    fn handle_collisions(&mut self, collisions: &[(usize, usize)]) {
+        // A pair stays overlapped for many ticks, so only count it once.
+        let pairs: HashSet<(u64, u64)> = collisions
+            .iter()
+            .map(|&(i, j)| (self.vehicles[i].id, self.vehicles[j].id))
+            .collect();
+        self.collisions += pairs.difference(&self.colliding_pairs).count();
+
+        // Only newly touching pairs are frozen, so vehicles released by
+        // gridlock recovery can drive out of an overlap.
+        let new_collisions: Vec<(usize, usize)> = collisions
+            .iter()
+            .copied()
+            .filter(|&(i, j)| {
+                !self
+                    .colliding_pairs
+                    .contains(&(self.vehicles[i].id, self.vehicles[j].id))
+            })
+            .collect();
+        for &(i, j) in &new_collisions {
+            let (a, b) = (&self.vehicles[i], &self.vehicles[j]);
+            let relative_speed = a.relative_speed(b);
+            let severity = CollisionSeverity::classify(relative_speed);
+            warn!(
+                "Vehicles {} and {} collided at tick {} ({}, closing at {:.0}px/s)",
+                a.id,
+                b.id,
+                self.tick,
+                severity.name(),
+                relative_speed
+            );
+            self.collision_severity[severity as usize] += 1;
+            self.events.push(SimEvent::Collision { a: a.id, b: b.id });
+            self.flashes.push(Flash {
+                x: (a.x + b.x) / 2.0,
+                y: (a.y + b.y) / 2.0,
+                relative_speed,
+                ticks_left: FLASH_TICKS,
+            });
+        }
+        self.colliding_pairs = pairs;
+
+        match self.config.collision_policy {
+            CollisionPolicy::LogOnly => {}
+            CollisionPolicy::Stop => {
+                for (i, j) in new_collisions {
+                    self.vehicles[i].frozen = true;
+                    self.vehicles[j].frozen = true;
+                }
+            }
+            CollisionPolicy::Remove => {
+                let involved: HashSet<u64> = self
+                    .colliding_pairs
+                    .iter()
+                    .flat_map(|&(a, b)| [a, b])
+                    .collect();
+                self.despawn_where(DespawnReason::Collided, |vehicle| {
+                    involved.contains(&vehicle.id)
+                });
+            }
+        }
+    }
+
+    // Warns once per vehicle that is further than `lane_tolerance` from the
+    // lane for its direction, such as after a bad turn exit.
+    fn check_lanes(&mut self) {
+        if !self.config.check_lanes {
+            return;
+        }
+        for vehicle in &self.vehicles {
+            let distance = vehicle.lane_offset();
+            if distance > self.config.lane_tolerance && self.off_lane.insert(vehicle.id) {
+                warn!(
+                    "Vehicle {} heading {} is {:.1}px off its lane at ({:.1}, {:.1})",
+                    vehicle.id, vehicle.direction, distance, vehicle.x, vehicle.y
+                );
+            }
+        }
+    }
+
+    // Warns once per red spell when an approach has vehicles waiting but the
+    // light plan has not given it green for too long.
+    fn check_starvation(&mut self) {
+        if !self.config.signalled() {
+            return;
+        }
+        for approach in Direction::ALL {
+            let index = approach.index();
+            let red_for = self.lights.red_for(approach);
+            if red_for == 0.0 {
+                self.starvation_warned[index] = false;
+                continue;
+            }
+            if self.starvation_warned[index] || red_for < self.config.starvation_warning {
+                continue;
+            }
+
+            let waiting = self
+                .vehicles
+                .iter()
+                .filter(|vehicle| vehicle.initial_direction == approach && vehicle.is_waiting())
+                .count();
+            if waiting > 0 {
+                warn!(
+                    "The {} approach has had no green light for {:.0}s with {} vehicle(s) waiting; check the light plan",
+                    approach, red_for, waiting
+                );
+                self.starvation_warned[index] = true;
+            }
+        }
+    }
+
+    fn is_gridlocked(&self) -> bool {
+        self.box_stalled_for >= self.config.gridlock_timeout
+    }
+
+    fn check_gridlock(&mut self, dt: f32) {
+        let boxed: Vec<&Vehicle> = self
+            .vehicles
+            .iter()
+            .filter(|vehicle| vehicle.in_intersection())
+            .collect();
+        let moving = boxed
+            .iter()
+            .any(|vehicle| vehicle.x != vehicle.prev_x || vehicle.y != vehicle.prev_y);
+        if boxed.is_empty() || moving || self.lights.in_recovery() {
+            self.box_stalled_for = 0.0;
+            return;
+        }
+
+        let was_gridlocked = self.is_gridlocked();
+        self.box_stalled_for += dt;
+        if was_gridlocked || !self.is_gridlocked() {
+            return;
+        }
+
+        warn!(
+            "Gridlock: {} vehicle(s) have not moved in the intersection for {:.0}s",
+            boxed.len(),
+            self.box_stalled_for
+        );
+        if self.config.gridlock_recovery {
+            let approaches: Vec<Direction> = Direction::ALL
+                .into_iter()
+                .filter(|&direction| self.config.layout.accepts_approach(direction))
+                .collect();
+            self.lights.start_recovery(
+                &approaches,
+                self.config.recovery_clearance,
+                self.config.recovery_green,
+            );
+            self.box_stalled_for = 0.0;
+        }
+    }
+
+    pub fn render(
+        &self,
+        canvas: &mut dyn Renderer,
+        alpha: f32,
+        debug: &DebugOverlay,
+    ) -> Result<(), String> {
+        // alpha is how far we are between the last two ticks; 1.0 draws the exact state
+        let alpha = if self.config.interpolate { alpha } else { 1.0 };
+
+        canvas.set_draw_color(match self.config.road_style {
+            RoadStyle::Schematic => Color::RGB(0, 0, 0),
+            RoadStyle::Satellite => Color::RGB(48, 82, 44),
+        });
+        canvas.clear();
+
+        // The world is drawn through the camera; the overlays after it are
+        // fixed to the window.
+        let culled = self.draw_world(&mut Viewed::new(canvas, debug.camera), alpha, debug)?;
+        let daylight = environment::daylight(self.hour_of_day());
+        if daylight < 1.0 {
+            environment::draw_night(canvas, self.config.world(), daylight)?;
+            // Lamps and headlights show through the dark undimmed.
+            let canvas = &mut Viewed::new(canvas, debug.camera);
+            if self.config.signalled() {
+                self.draw_traffic_lights(canvas)?;
+            }
+            self.draw_headlights(&mut Bent::new(canvas, self.config.bend()), alpha, debug)?;
+        }
+        if self.config.weather == Weather::Rain {
+            environment::draw_rain(canvas, self.config.world(), self.tick)?;
+        }
+
+        if debug.enabled {
+            // Tick, wall-clock seconds, simulated seconds, culled vehicles.
+            let clock = Color::RGB(255, 255, 0);
+            let lines = [
+                self.tick.to_string(),
+                format!("{:.1}", debug.wall_clock.as_secs_f32()),
+                format!("{:.1}", self.simulated_seconds()),
+                culled.to_string(),
+            ];
+            let row_y = |row: usize| 8 + row as i32 * (digits::DIGIT_HEIGHT as i32 + 6);
+            for (row, text) in lines.iter().enumerate() {
+                digits::draw_text(canvas, 8, row_y(row), text, clock)?;
+            }
+            // Then the longest green and the yellow, in their own colours,
+            // as they will run from the next boundary.
+            if self.config.signalled() {
+                let timings = [
+                    (self.upcoming_green(), Color::RGB(0, 255, 0)),
+                    (self.lights.upcoming_yellow(), Color::RGB(255, 200, 0)),
+                ];
+                for (row, (seconds, color)) in timings.into_iter().enumerate() {
+                    let text = format!("{:.1}", seconds);
+                    digits::draw_text(canvas, 8, row_y(lines.len() + row), &text, color)?;
+                }
+            }
+        }
+
+        if debug.minimap {
+            self.draw_minimap(canvas, debug.camera.visible())?;
+        }
+
+        if debug.hud {
+            self.draw_hud(canvas, debug.fps)?;
+        }
+
+        if debug.panel {
+            panel::draw(canvas, self)?;
+        }
+
+        if let (true, Some((x, y))) = (debug.enabled, debug.mouse) {
+            let text = format!("{} {}", x, y);
+            digits::draw_text(canvas, x + 12, y + 12, &text, Color::RGB(255, 255, 0))?;
+        }
+
+        canvas.present();
+        Ok(())
+    }
+
+    // Vehicles on the road in white, then in grey by approach in
+    // `Direction::ALL` order, key presses held by approach in blue while any
+    // are, collisions so far in red, the light plan's phase in the colour of
+    // its stage, simulated seconds in magenta and frames per second in cyan,
+    // bottom row last.
+    fn draw_hud(&self, canvas: &mut dyn Renderer, fps: f32) -> Result<(), String> {
+        let mut per_approach = [0; 4];
+        for vehicle in &self.vehicles {
+            per_approach[vehicle.initial_direction.index()] += 1;
+        }
+        let per_approach: Vec<String> = per_approach.iter().map(usize::to_string).collect();
+        let mut lines = vec![
+            (self.vehicles.len().to_string(), Color::RGB(255, 255, 255)),
+            (per_approach.join(" "), Color::RGB(160, 160, 160)),
+            (self.collisions.to_string(), Color::RGB(255, 60, 60)),
+        ];
+        let held = self.held_spawns();
+        if held.iter().any(|&held| held > 0) {
+            let held: Vec<String> = held.iter().map(usize::to_string).collect();
+            lines.insert(2, (held.join(" "), Color::RGB(120, 200, 255)));
+        }
+        if self.config.signalled() {
+            let color = match self.lights.stage() {
+                lights::Stage::Phase => Color::RGB(0, 255, 0),
+                lights::Stage::Yellow => Color::RGB(255, 200, 0),
+                lights::Stage::AllRed => Color::RGB(255, 0, 0),
+            };
+            lines.push((self.lights.phase().to_string(), color));
+        }
+        lines.push((
+            format!("{:.0}", self.simulated_seconds()),
+            Color::RGB(255, 0, 255),
+        ));
+        lines.push((format!("{:.0}", fps), Color::RGB(0, 255, 255)));
+        let row_height = digits::DIGIT_HEIGHT as i32 + 6;
+        let top = self.config.world().height as i32 - 8 - lines.len() as i32 * row_height + 6;
+        for (row, (text, color)) in lines.iter().enumerate() {
+            digits::draw_text(canvas, 8, top + row as i32 * row_height, text, *color)?;
+        }
+        Ok(())
+    }
+
+    /// Holds vehicle `id` where the mouse is, snapped onto its own path,
+    /// until `release_vehicle`. Returns whether the vehicle is still there.
+    pub fn drag_vehicle(&mut self, id: u64, x: f32, y: f32) -> bool {
+        let Some(vehicle) = self.vehicles.iter_mut().find(|vehicle| vehicle.id == id) else {
+            return false;
+        };
+        vehicle.held = true;
+        vehicle.drag_to(x, y);
+        true
+    }
+
+    /// Lets a dragged vehicle drive on from where it was dropped.
+    pub fn release_vehicle(&mut self, id: u64) {
+        if let Some(vehicle) = self.vehicles.iter_mut().find(|vehicle| vehicle.id == id) {
+            vehicle.held = false;
+        }
+    }
+
+    /// Lengthens or shortens the green phases and the yellow by the given
+    /// seconds from the next stage boundary, within `max_green` and
+    /// `max_yellow`. The config follows, so a save keeps the new timing.
+    pub fn retime_lights(&mut self, green: f32, yellow: f32) {
+        let (max_green, max_yellow) = (self.config.max_green, self.config.max_yellow);
+        self.lights.retime(green, yellow, max_green, max_yellow);
+        self.config.light_plan = self.lights.upcoming_plan().clone();
+        self.config.yellow = self.lights.upcoming_yellow();
+    }
+
+    /// The longest green phase of the plan in force from the next stage
+    /// boundary on.
+    pub fn upcoming_green(&self) -> f32 {
+        self.lights
+            .upcoming_plan()
+            .phases
+            .iter()
+            .filter(|phase| phase.green.contains(&true))
+            .map(|phase| phase.duration)
+            .fold(0.0, f32::max)
+    }
+
+    /// Swaps the fixed cycle for the max-pressure controller or back, from
+    /// the end of the current phase; the queue-adaptive one goes back to
+    /// the fixed cycle. An external controller is left alone, since
+    /// whatever drives it would lose the lights. The config follows, so a
+    /// save keeps the choice.
+    pub fn toggle_controller(&mut self) {
+        let kind = match self.config.signal_controller {
+            ControllerKind::Fixed => ControllerKind::MaxPressure,
+            ControllerKind::MaxPressure | ControllerKind::QueueAdaptive => ControllerKind::Fixed,
+            ControllerKind::External => return,
+        };
+        self.lights.set_controller(kind.build());
+        self.config.signal_controller = kind;
+        info!("Signal controller: {}", kind.name());
+    }
+
+    /// Hands the lights to a controller of your own from the end of the
+    /// current phase, in place of the configured one. The config still
+    /// names the configured one, so a save or a toggle goes back to it.
+    pub fn set_signal_controller(&mut self, controller: Box<dyn SignalController>) {
+        self.lights.set_controller(controller);
+    }
+
+    /// Changes the weather for everyone on the road at once as well as for
+    /// vehicles still to come. The config follows, so a save keeps it.
+    pub fn set_weather(&mut self, weather: Weather) {
+        self.config.weather = weather;
+        let traction = self.config.traction();
+        for vehicle in &mut self.vehicles {
+            vehicle.traction = traction;
+        }
+        info!("Weather: {}", weather.name());
+    }
+
+    /// Moves the clock on by `hours` by moving the hour the run started
+    /// at, which the config keeps.
+    pub fn advance_clock(&mut self, hours: f32) {
+        let start = &mut self.config.time_of_day;
+        *start = (*start + hours).rem_euclid(24.0);
+        info!("Time of day: {:.1}h", self.hour_of_day());
+    }
+
+    /// The hour of the day now, from 0 to 24.
+    pub fn hour_of_day(&self) -> f32 {
+        environment::hour_of_day(
+            self.config.time_of_day,
+            self.config.day_length,
+            self.simulated_seconds(),
+        )
+    }
+
+    /// The approach whose signal head covers the point, if the lights are
+    /// drawn.
+    pub fn light_at(&self, x: f32, y: f32) -> Option<Direction> {
+        let world = self.config.world();
+        if !self.config.signalled() {
+            return None;
+        }
+        Direction::ALL.into_iter().find(|&approach| {
+            self.config.layout.accepts_approach(approach)
+                && light_head_rect(world, approach).contains_point((x as i32, y as i32))
+        })
+    }
+
+    /// Holds `approach` alone on green, as for an emergency vehicle, or
+    /// hands the lights back to the plan if it was already held. Emergency
+    /// vehicles still take the lights first. Holds aren't recorded, so a
+    /// replay of a run with them goes differently.
+    pub fn toggle_light_hold(&mut self, approach: Direction) {
+        if self.held_light == Some(approach) {
+            self.held_light = None;
+            info!("Released the lights on the {} approach", approach);
+        } else {
+            self.held_light = Some(approach);
+            info!("Holding the lights green for the {} approach", approach);
+        }
+    }
+
+    /// Eases the camera towards the selected vehicle while following, or
+    /// back to its home otherwise. Following stops, and the
+    /// selection clears, once the vehicle has despawned.
+    pub fn track_camera(&self, debug: &mut DebugOverlay, alpha: f32) {
+        let alpha = if self.config.interpolate { alpha } else { 1.0 };
+        let mut target = None;
+        if let (true, Some(id)) = (debug.follow, debug.selected) {
+            match self.vehicles.iter().find(|vehicle| vehicle.id == id) {
+                Some(vehicle) => target = Some(vehicle.interpolated_position(alpha)),
+                None => {
+                    debug.follow = false;
+                    debug.selected = None;
+                }
+            }
+        }
+        let (x, y) = target.unwrap_or(debug.camera.home);
+        debug.camera.ease_towards(x, y);
+    }
+
+    // Everything that lives in world coordinates, which `canvas` takes
+    // through the camera. Returns how many vehicles were culled.
+    fn draw_world(
+        &self,
+        canvas: &mut dyn Renderer,
+        alpha: f32,
+        debug: &DebugOverlay,
+    ) -> Result<usize, String> {
+        let world = self.config.world();
+        if debug.enabled {
+            self.draw_debug_grid(canvas)?;
+        }
+        // Lights and readouts stand beside the box, off the bent roads.
+        let bend = self.config.bend();
+        self.draw_roads(&mut Bent::new(canvas, bend))?;
+        if self.config.signalled() {
+            self.draw_traffic_lights(canvas)?;
+        }
+        self.draw_delay_readout(canvas)?;
+        let canvas = &mut Bent::new(canvas, bend);
+        self.draw_blocked_spawns(canvas)?;
+        if debug.heatmap {
+            self.heatmap.draw(canvas)?;
+        }
+
+        // Removed vehicles shrink about their centre as they fade.
+        for fade in &self.fades {
+            let vehicle = &fade.vehicle;
+            let left = fade.ticks_left as f32 / FADE_TICKS as f32;
+            let (half_width, half_height) = vehicle.half_extents();
+            let (x, y) = vehicle.drawn_position(alpha);
+            let centre = (x.round() as i32, y.round() as i32);
+            let shrunk = |extent: f32| (2.0 * extent * left).round() as u32;
+            let rect = Rect::from_center(centre, shrunk(half_width), shrunk(half_height));
+            if !on_screen(rect, debug.camera) {
+                continue;
+            }
+            let Color { r, g, b, .. } = vehicle.color;
+            let color = Color::RGBA(r, g, b, (255.0 * left) as u8);
+            let sprite = !self.config.simple_render
+                && canvas.draw_sprite(
+                    vehicle.kind,
+                    centre,
+                    (shrunk(vehicle.length / 2.0), shrunk(vehicle.width / 2.0)),
+                    vehicle.drawn_heading(alpha),
+                    color,
+                )?;
+            if !sprite {
+                canvas.set_draw_color(color);
+                canvas.fill_rect(rect)?;
+            }
+        }
+
+        // Vehicles entirely outside the window are skipped; they are still
+        // simulated until `has_left` removes them.
+        let mut culled = 0;
+        for vehicle in &self.vehicles {
+            if !on_screen(vehicle.get_rect(alpha), debug.camera) {
+                culled += 1;
+                continue;
+            }
+            // Emergency vehicles flash red and blue, four times a second.
+            let color = if vehicle.kind != VehicleKind::Emergency {
+                vehicle.color
+            } else if ((self.simulated_seconds() * 4.0) as u64).is_multiple_of(2) {
+                Color::RGB(255, 0, 0)
+            } else {
+                Color::RGB(0, 80, 255)
+            };
+            let (x, y) = vehicle.drawn_position(alpha);
+            let size = (vehicle.length.round() as u32, vehicle.width.round() as u32);
+            let sprite = !self.config.simple_render
+                && canvas.draw_sprite(
+                    vehicle.kind,
+                    (x.round() as i32, y.round() as i32),
+                    size,
+                    vehicle.drawn_heading(alpha),
+                    color,
+                )?;
+            if !sprite {
+                canvas.set_draw_color(color);
+                canvas.fill_rect(vehicle.get_rect(alpha))?;
+
+                if self.config.color_mode != ColorMode::ByRoute {
+                    canvas.set_draw_color(Color::RGB(0, 0, 0));
+                    canvas.fill_rect(vehicle.get_glyph_rect(alpha))?;
+                }
+
+                // Trucks get a dark band across the middle.
+                if vehicle.kind == VehicleKind::Truck {
+                    let rect = vehicle.get_rect(alpha);
+                    canvas.set_draw_color(Color::RGB(60, 60, 60));
+                    canvas.fill_rect(Rect::new(
+                        rect.x() + 4,
+                        rect.y() + 4,
+                        rect.width() - 8,
+                        rect.height() - 8,
+                    ))?;
+                    canvas.set_draw_color(vehicle.color);
+                    canvas.fill_rect(Rect::new(
+                        rect.x() + 8,
+                        rect.y() + 8,
+                        rect.width() - 16,
+                        rect.height() - 16,
+                    ))?;
+                }
+
+                canvas.set_draw_color(Color::RGB(0, 0, 0));
+                let (start, end) = vehicle.nose(alpha);
+                canvas.draw_line(start, end)?;
+            }
+
+            if vehicle.aggressiveness >= AGGRESSIVE_MARKER {
+                canvas.set_draw_color(Color::RGB(255, 90, 0));
+                canvas.draw_rect(vehicle.get_rect(alpha))?;
+            }
+
+            if debug.enabled && vehicle.in_intersection() {
+                canvas.set_draw_color(Color::RGB(160, 160, 160));
+                canvas.draw_rect(vehicle.get_rect(alpha))?;
+            }
+        }
+
+        canvas.set_draw_color(Color::RGB(255, 210, 160));
+        for pedestrian in &self.pedestrians {
+            canvas.fill_rect(pedestrian.get_rect(world))?;
+        }
+
+        for flash in &self.flashes {
+            canvas.set_draw_color(Color::RGBA(
+                255,
+                match CollisionSeverity::classify(flash.relative_speed) {
+                    CollisionSeverity::Minor => 200,
+                    CollisionSeverity::Major => 40,
+                },
+                0,
+                (255 * flash.ticks_left / FLASH_TICKS) as u8,
+            ));
+            canvas.fill_rect(flash_rect(flash))?;
+        }
+
+        if let Some(selected) = debug
+            .selected
+            .and_then(|id| self.vehicles.iter().find(|vehicle| vehicle.id == id))
+        {
+            canvas.set_draw_color(Color::RGB(255, 255, 0));
+            canvas.draw_rect(selected.get_rect(alpha))?;
+            if debug.ghost && selected.route != Route::Straight {
+                self.draw_ghost_path(canvas, selected)?;
+            }
+        }
+
+        // What a click would act on: a signal head, or the stop line of the
+        // approach it would spawn on.
+        if let Some((x, y)) = debug.mouse {
+            let (x, y) = debug.camera.to_world(x as f32, y as f32);
+            canvas.set_draw_color(Color::RGB(0, 255, 255));
+            if let Some(approach) = self.light_at(x, y) {
+                canvas.draw_rect(light_head_rect(world, approach))?;
+            } else if let Some(approach) = {
+                let (x, y) = bend.straighten(x, y);
+                world.approach_at(x, y)
+            } && self.config.layout.accepts_approach(approach)
+            {
+                let setback = self.config.stop_setback(approach);
+                canvas.fill_rect(stop_line_rect(world, approach, setback))?;
+            }
+        }
+        Ok(culled)
+    }
+
+    // A beam ahead of each vehicle on screen, widening and fading.
+    fn draw_headlights(
+        &self,
+        canvas: &mut dyn Renderer,
+        alpha: f32,
+        debug: &DebugOverlay,
+    ) -> Result<(), String> {
+        for vehicle in &self.vehicles {
+            if !on_screen(vehicle.get_rect(alpha), debug.camera) {
+                continue;
+            }
+            let (x, y) = vehicle.drawn_position(alpha);
+            let heading = vehicle.drawn_heading(alpha);
+            let (dx, dy) = (heading.cos(), heading.sin());
+            for step in 1..=4 {
+                let reach = vehicle.length / 2.0 + step as f32 * 7.0;
+                let size = (vehicle.width * (0.4 + 0.15 * step as f32)) as u32;
+                canvas.set_draw_color(Color::RGBA(255, 240, 190, 90 - step as u8 * 18));
+                canvas.fill_rect(Rect::from_center(
+                    ((x + dx * reach) as i32, (y + dy * reach) as i32),
+                    size,
+                    size,
+                ))?;
+            }
+        }
+        Ok(())
+    }
+
+    // The world's roads and intersection with a dot per vehicle, and an
+    // outline of `visible`, the part of the world the window shows.
+    fn draw_minimap(&self, canvas: &mut dyn Renderer, visible: Rect) -> Result<(), String> {
+        let world = self.config.world();
+        canvas.set_draw_color(Color::RGBA(0, 0, 0, 200));
+        canvas.fill_rect(to_minimap(world, world.bounds()))?;
+        canvas.set_draw_color(Color::RGB(90, 90, 90));
+        for strip in asphalt_rects(world, self.config.layout) {
+            canvas.fill_rect(to_minimap(world, strip))?;
+        }
+        let half_road = world.road_width as i32 / 2;
+        canvas.set_draw_color(Color::RGB(140, 140, 140));
+        canvas.fill_rect(to_minimap(
+            world,
+            Rect::new(
+                world.center_x() - half_road,
+                world.center_y() - half_road,
+                world.road_width,
+                world.road_width,
+            ),
+        ))?;
+        for vehicle in &self.vehicles {
+            canvas.set_draw_color(vehicle.color);
+            let dot = to_minimap(world, Rect::new(vehicle.x as i32, vehicle.y as i32, 1, 1));
+            canvas.fill_rect(Rect::new(dot.x() - 1, dot.y() - 1, 3, 3))?;
+        }
+        canvas.set_draw_color(Color::RGB(255, 255, 255));
+        canvas.draw_rect(to_minimap(world, world.bounds()))?;
+        canvas.set_draw_color(Color::RGB(255, 255, 0));
+        canvas.draw_rect(to_minimap(world, visible))
+    }
+
+    // Translucent outlines along the path a turning vehicle will take, spaced
+    // so consecutive ghosts don't merge into a solid band.
+    fn draw_ghost_path(&self, canvas: &mut dyn Renderer, vehicle: &Vehicle) -> Result<(), String> {
+        canvas.set_draw_color(Color::RGBA(255, 255, 255, 70));
+        for ghost in vehicle.projected_path().iter().step_by(12) {
+            canvas.draw_rect(ghost.get_rect(1.0))?;
+        }
+        Ok(())
+    }
+
+    // Faint 50px grid with the axes through the center labelled every 100px.
+    fn draw_debug_grid(&self, canvas: &mut dyn Renderer) -> Result<(), String> {
+        let world = self.config.world();
+        let (center_x, center_y) = (world.center_x(), world.center_y());
+        let width = world.width as i32;
+        let height = world.height as i32;
+
+        canvas.set_draw_color(Color::RGB(40, 40, 40));
+        for x in (0..width).step_by(50) {
+            canvas.draw_line((x, 0), (x, height))?;
+        }
+        for y in (0..height).step_by(50) {
+            canvas.draw_line((0, y), (width, y))?;
+        }
+
+        canvas.set_draw_color(Color::RGB(90, 90, 90));
+        canvas.draw_line((0, center_y), (width, center_y))?;
+        canvas.draw_line((center_x, 0), (center_x, height))?;
+
+        let label = Color::RGB(120, 120, 120);
+        for x in (0..width).step_by(100) {
+            digits::draw_text(canvas, x + 3, center_y + 4, &x.to_string(), label)?;
+        }
+        for y in (0..height).step_by(100) {
+            digits::draw_text(canvas, center_x + 4, y + 3, &y.to_string(), label)?;
+        }
+        Ok(())
+    }
+
+    fn draw_roads(&self, canvas: &mut dyn Renderer) -> Result<(), String> {
+        let world = self.config.world();
+        let (center_x, center_y) = (world.center_x(), world.center_y());
+        let line_color = Color::RGB(255, 255, 255);
+        let half_road = world.road_width as i32 / 2;
+        let layout = self.config.layout;
+
+        // A missing arm shrinks its road to the edge of the intersection.
+        let left = if layout.has_arm(Direction::Left) {
+            0
+        } else {
+            center_x - half_road
+        };
+        let right = if layout.has_arm(Direction::Right) {
+            world.width as i32
+        } else {
+            center_x + half_road
+        };
+        let top = if layout.has_arm(Direction::Up) {
+            0
+        } else {
+            center_y - half_road
+        };
+        let bottom = if layout.has_arm(Direction::Down) {
+            world.height as i32
+        } else {
+            center_y + half_road
+        };
+
+        // Asphalt goes down first so the markings sit on top of it. Its two
+        // strips overlap in the intersection box.
+        if self.config.road_style == RoadStyle::Satellite {
+            canvas.set_draw_color(Color::RGB(72, 72, 76));
+            for rect in asphalt_rects(world, layout) {
+                canvas.fill_rect(rect)?;
+            }
+        }
+
+        canvas.set_draw_color(line_color);
+        for dash in center_line_dashes(world, layout) {
+            canvas.fill_rect(dash)?;
+        }
+
+        match self.config.road_style {
+            RoadStyle::Schematic => {
+                canvas.draw_line((left, center_y - half_road), (right, center_y - half_road))?;
+                canvas.draw_line((left, center_y + half_road), (right, center_y + half_road))?;
+                canvas.draw_line((center_x - half_road, top), (center_x - half_road, bottom))?;
+                canvas.draw_line((center_x + half_road, top), (center_x + half_road, bottom))?;
+            }
+            // Edges only run along the arms, leaving the box open.
+            RoadStyle::Satellite => {
+                for (start, end) in arm_edges(world, layout) {
+                    canvas.draw_line(start, end)?;
+                }
+            }
+        }
+
+        // A roundabout's island, grassed and kerbed.
+        if layout == Layout::Roundabout {
+            canvas.set_draw_color(Color::RGB(70, 120, 60));
+            fill_circle(canvas, center_x, center_y, ISLAND_RADIUS)?;
+            canvas.set_draw_color(line_color);
+            draw_circle(canvas, center_x, center_y, ISLAND_RADIUS)?;
+        }
+
+        self.draw_stop_lines(canvas)?;
+        if self.config.has_pedestrians() {
+            self.draw_crosswalks(canvas)?;
+        }
+        self.draw_lane_arrows(canvas)?;
+        self.draw_speed_limits(canvas)
+    }
+
+    // Each limited approach's limit, in pixels per second, in red beside
+    // the road across from its delay readout.
+    fn draw_speed_limits(&self, canvas: &mut dyn Renderer) -> Result<(), String> {
+        let world = self.config.world();
+        let (center_x, center_y) = (world.center_x(), world.center_y());
+        let half_road = world.road_width as i32 / 2;
+        let margin = 10;
+        let text_width = 40;
+        let below_road = center_y - half_road - margin - digits::DIGIT_HEIGHT as i32;
+        let positions = [
+            (
+                center_x - half_road - margin - text_width,
+                world.height as i32 - margin - digits::DIGIT_HEIGHT as i32,
+            ),
+            (center_x - half_road - margin - text_width, margin),
+            (margin, below_road),
+            (world.width as i32 - 60, below_road),
+        ];
+        for (approach, (x, y)) in Direction::ALL.into_iter().zip(positions) {
+            let limit = self.config.speed_limits[approach.index()];
+            if !self.config.layout.accepts_approach(approach) || limit >= MAX_SPEED_LIMIT {
+                continue;
+            }
+            let text = format!("{:.0}", limit);
+            digits::draw_text(canvas, x, y, &text, Color::RGB(255, 60, 60))?;
+        }
+        Ok(())
+    }
+
+    // Zebra stripes across each arm by the box, with a walk signal at both
+    // kerbs: white while it may be crossed, red otherwise. Without lights
+    // the signals stay dark and pedestrians cross at gaps.
+    fn draw_crosswalks(&self, canvas: &mut dyn Renderer) -> Result<(), String> {
+        let world = self.config.world();
+        const STRIPE: i32 = 6;
+        const SIGNAL: u32 = 6;
+        let half_road = world.road_width as f32 / 2.0;
+        for arm in Direction::ALL {
+            if !self.config.layout.accepts_approach(arm) {
+                continue;
+            }
+            let rect = crosswalk_rect(world, arm);
+            canvas.set_draw_color(Color::RGB(220, 220, 220));
+            let vertical = matches!(arm, Direction::Up | Direction::Down);
+            let span = if vertical {
+                rect.width()
+            } else {
+                rect.height()
+            } as i32;
+            for offset in (STRIPE / 2..span).step_by(2 * STRIPE as usize) {
+                canvas.fill_rect(if vertical {
+                    Rect::new(rect.x() + offset, rect.y(), STRIPE as u32, rect.height())
+                } else {
+                    Rect::new(rect.x(), rect.y() + offset, rect.width(), STRIPE as u32)
+                })?;
+            }
+
+            if !self.config.signalled() {
+                continue;
+            }
+            canvas.set_draw_color(if self.walk_signal(arm) {
+                Color::RGB(255, 255, 255)
+            } else {
+                Color::RGB(255, 60, 60)
+            });
+            let (x, y) = crosswalk_centre(world, arm);
+            let kerb = half_road + PEDESTRIAN_SIZE * 2.0;
+            for side in [-1.0, 1.0] {
+                let (sx, sy) = if vertical {
+                    (x + side * kerb, y)
+                } else {
+                    (x, y + side * kerb)
+                };
+                let half = SIGNAL as i32 / 2;
+                canvas.fill_rect(Rect::new(
+                    sx as i32 - half,
+                    sy as i32 - half,
+                    SIGNAL,
+                    SIGNAL,
+                ))?;
+            }
+        }
+        Ok(())
+    }
+
+    // A bar across each incoming lane, on the approach side of where the
+    // vehicles' fronts stop.
+    fn draw_stop_lines(&self, canvas: &mut dyn Renderer) -> Result<(), String> {
+        let world = self.config.world();
+        canvas.set_draw_color(Color::RGB(255, 255, 255));
+        for approach in Direction::ALL {
+            if self.config.layout.accepts_approach(approach) {
+                let setback = self.config.stop_setback(approach);
+                canvas.fill_rect(stop_line_rect(world, approach, setback))?;
+            }
+        }
+        Ok(())
+    }
+
+    // Paints one arrow per movement in each approach lane, short of the stop
+    // line. Every approach has a single lane shared by all routes whose exit
+    // arm exists; banned ones are painted red, like a "no turn" sign.
+    fn draw_lane_arrows(&self, canvas: &mut dyn Renderer) -> Result<(), String> {
+        let world = self.config.world();
+        let layout = self.config.layout;
+
+        for approach in Direction::ALL {
+            if !layout.accepts_approach(approach) {
+                continue;
+            }
+            let setback = self.config.stop_setback(approach) as i32 + 60;
+            let (fx, fy) = approach.delta();
+            let (lane_x, lane_y) = spawn_position(world, approach);
+            // Lane centre on the cross axis, `setback` before the centre on the travel axis.
+            let (ax, ay) = if fx == 0 {
+                (lane_x as i32, world.center_y() - fy * setback)
+            } else {
+                (world.center_x() - fx * setback, lane_y as i32)
+            };
+            let tail = (ax - fx * 16, ay - fy * 16);
+            let bend = (ax + fx * 4, ay + fy * 4);
+            canvas.set_draw_color(Color::RGB(255, 255, 255));
+            canvas.draw_line(tail, bend)?;
+
+            for route in Route::ALL {
+                let exit = route.exit_direction(approach);
+                // U-turns get an arrow only where they are allowed at all.
+                if !layout.has_arm(exit) || (route == Route::UTurn && !self.config.u_turns) {
+                    continue;
+                }
+                let banned = !self.config.permits(Movement { approach, route });
+                canvas.set_draw_color(if banned {
+                    Color::RGB(220, 40, 40)
+                } else {
+                    Color::RGB(255, 255, 255)
+                });
+                // A U-turn hooks over to the left-turn side before heading back.
+                let start = if route == Route::UTurn {
+                    let (sx, sy) = Route::TurnLeft.exit_direction(approach).delta();
+                    let hook = (bend.0 + sx * 8, bend.1 + sy * 8);
+                    canvas.draw_line(bend, hook)?;
+                    hook
+                } else {
+                    bend
+                };
+                let (ex, ey) = exit.delta();
+                let length = if exit == approach { 12 } else { 10 };
+                let tip = (start.0 + ex * length, start.1 + ey * length);
+                canvas.draw_line(start, tip)?;
+                // Arrow head: two strokes back from the tip, either side of the shaft.
+                for side in [-1, 1] {
+                    canvas.draw_line(
+                        tip,
+                        (
+                            tip.0 - ex * 5 + ey * side * 5,
+                            tip.1 - ey * 5 + ex * side * 5,
+                        ),
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Shows each approach's average delay next to the edge its traffic enters
+    // from, with its longest queue so far in orange on the line towards the
+    // centre.
+    fn draw_delay_readout(&self, canvas: &mut dyn Renderer) -> Result<(), String> {
+        let world = self.config.world();
+        let (center_x, center_y) = (world.center_x(), world.center_y());
+        let half_road = world.road_width as i32 / 2;
+        let margin = 10;
+        let positions = [
+            (
+                center_x + half_road + margin,
+                world.height as i32 - margin - digits::DIGIT_HEIGHT as i32,
+            ),
+            (center_x + half_road + margin, margin),
+            (margin, center_y + half_road + margin),
+            (world.width as i32 - 60, center_y + half_road + margin),
+        ];
+
+        for (approach, (x, y)) in Direction::ALL.into_iter().zip(positions) {
+            if !self.config.layout.accepts_approach(approach) {
+                continue;
+            }
+            let text = format!("{:.1}", self.average_delay(approach));
+            digits::draw_text(canvas, x, y, &text, Color::RGB(255, 255, 255))?;
+            let line = digits::DIGIT_HEIGHT as i32 + 4;
+            let queue_y = if approach == Direction::Up {
+                y - line
+            } else {
+                y + line
+            };
+            let text = self.max_queue[approach.index()].to_string();
+            digits::draw_text(canvas, x, queue_y, &text, Color::RGB(255, 160, 0))?;
+        }
+        Ok(())
+    }
+
+    // Outlines spawn points that refused a vehicle and are still occupied.
+    fn draw_blocked_spawns(&self, canvas: &mut dyn Renderer) -> Result<(), String> {
+        let world = self.config.world();
+        canvas.set_draw_color(Color::RGB(255, 0, 0));
+        for direction in Direction::ALL {
+            if self.spawn_blocked[direction.index()] {
+                let (x, y) = spawn_position(world, direction);
+                let size = VEHICLE_SIZE + 8;
+                canvas.draw_rect(Rect::new(
+                    x as i32 - (size / 2) as i32,
+                    y as i32 - (size / 2) as i32,
+                    size,
+                    size,
+                ))?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn print_summary(&self) {
+        println!(
+            "Simulated time: {:.1}s ({} ticks)",
+            self.simulated_seconds(),
+            self.tick
+        );
+        let severity: Vec<String> = CollisionSeverity::ALL
+            .iter()
+            .zip(self.collision_severity)
+            .map(|(severity, count)| format!("{} {}", count, severity.name()))
+            .collect();
+        println!(
+            "Control: {}; weather {}; throughput {:.1} vehicles/min",
+            self.config.control_name(),
+            self.config.weather.name(),
+            self.throughput()
+        );
+        println!("Collisions: {} ({})", self.collisions, severity.join(", "));
+        let despawned: Vec<String> = DespawnReason::ALL
+            .iter()
+            .zip(self.despawned)
+            .map(|(reason, count)| format!("{} {}", count, reason.name()))
+            .collect();
+        println!("Despawned: {}", despawned.join(", "));
+        if let Some(condition) = self.stopped_by {
+            println!("Stopped early: {}", condition.name());
+        }
+        if let Some(report) = self.drain_report() {
+            println!("{}", report);
+        }
+        println!("Close calls: {}", self.close_calls);
+        println!("Yellow runs: {}", self.yellow_runs);
+        if self.config.has_pedestrians() {
+            let waited = if self.pedestrians_crossed == 0 {
+                0.0
+            } else {
+                self.pedestrian_wait_total / self.pedestrians_crossed as f32
+            };
+            println!(
+                "Pedestrians: {} crossed, average wait {:.1}s",
+                self.pedestrians_crossed, waited
+            );
+        }
+        if let Some((shortest, longest)) = self.crossing_ticks {
+            println!(
+                "Time on the road: {:.1}s to {:.1}s",
+                self.config.ticks_to_seconds(shortest),
+                self.config.ticks_to_seconds(longest)
+            );
+        }
+        if let (Some((slowest, fastest)), Some((least, most))) = (self.trip_speeds, self.delays) {
+            let exited: usize = self.exited.iter().sum();
+            let delay: f32 = self.delay_totals.iter().sum();
+            println!(
+                "Average speed: {:.1} px/s ({:.1} to {:.1})",
+                self.speed_total / exited as f32,
+                slowest,
+                fastest
+            );
+            println!(
+                "Delay: {:.2}s average ({:.2}s to {:.2}s)",
+                delay / exited as f32,
+                least,
+                most
+            );
+        }
+        println!("Cost: {:.3}", self.cost());
+        if self.config.check_lanes {
+            println!("Off lane: {} vehicle(s)", self.off_lane.len());
+        }
+        if self.config.rollback_collisions {
+            println!("Rollbacks: {}", self.rollbacks);
+        }
+        println!("Origin-destination (rows from, columns heading):");
+        let heading: Vec<String> = Direction::ALL
+            .iter()
+            .map(|exit| format!("{:>7}", exit.to_string()))
+            .collect();
+        println!("  {:<7}{}", "", heading.join(""));
+        for origin in Direction::ALL {
+            let counts: Vec<String> = self.trips[origin.index()]
+                .iter()
+                .map(|count| format!("{:>7}", count))
+                .collect();
+            println!("  {:<7}{}", origin.to_string(), counts.join(""));
+        }
+        println!("Per approach:");
+        for approach in Direction::ALL {
+            let index = approach.index();
+            println!(
+                "  {}: {} exited, average delay {:.2}s, longest queue {}, {} spawn(s) refused",
+                approach,
+                self.exited[index],
+                self.average_delay(approach),
+                self.max_queue[index],
+                self.refused_spawns[index]
+            );
+        }
+    }
+
+    // Each approach gets a signal head, a dark housing with red, yellow and
+    // green lamps from top to bottom. A green for one route alone keeps the
+    // red lit and shows the yellow and green as arrows pointing where that
+    // route leaves. A head held from the mouse is framed in white.
+    fn draw_traffic_lights(&self, canvas: &mut dyn Renderer) -> Result<(), String> {
+        let world = self.config.world();
+        let size = LIGHT_SIZE as i32;
+        let slot = size * 2 / 3;
+        for approach in Direction::ALL {
+            if !self.config.layout.accepts_approach(approach) {
+                continue;
+            }
+            let head = light_head_rect(world, approach);
+            let (x, y) = (head.x(), head.y());
+            canvas.set_draw_color(Color::RGB(30, 30, 30));
+            canvas.fill_rect(head)?;
+            if self.held_light == Some(approach) {
+                canvas.set_draw_color(Color::RGB(255, 255, 255));
+                canvas.draw_rect(head)?;
+            }
+
+            let arrow = self.lights.arrow(approach);
+            let (green, yellow) = match arrow {
+                Some(route) => (
+                    self.lights.is_green_for(approach, route),
+                    self.lights.is_yellow_for(approach, route),
+                ),
+                None => (
+                    self.lights.is_green(approach),
+                    self.lights.is_yellow(approach),
+                ),
+            };
+            let lamps = [
+                (
+                    Color::RGB(255, 0, 0),
+                    Color::RGB(70, 0, 0),
+                    arrow.is_some() || (!green && !yellow),
+                ),
+                (Color::RGB(255, 200, 0), Color::RGB(70, 55, 0), yellow),
+                (Color::RGB(0, 255, 0), Color::RGB(0, 70, 0), green),
+            ];
+            for (index, (lit, dark, on)) in lamps.into_iter().enumerate() {
+                canvas.set_draw_color(if on { lit } else { dark });
+                let center = (x + size / 2, y + slot * index as i32 + slot / 2);
+                match arrow {
+                    Some(route) if index > 0 => {
+                        let exit = route.exit_direction(approach);
+                        draw_arrow(canvas, center, exit, slot * 2 / 5)?;
+                    }
+                    _ => fill_circle(canvas, center.0, center.1, slot * 2 / 5)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn fill_circle(
+    canvas: &mut dyn Renderer,
+    center_x: i32,
+    center_y: i32,
+    radius: i32,
+) -> Result<(), String> {
+    for dy in -radius..=radius {
+        let half = ((radius * radius - dy * dy) as f32).sqrt() as i32;
+        canvas.draw_line(
+            (center_x - half, center_y + dy),
+            (center_x + half, center_y + dy),
+        )?;
+    }
+    Ok(())
+}
+
+// A thick arrow through `center` pointing `direction`, `reach` pixels to
+// its tip and back to its tail.
+fn draw_arrow(
+    canvas: &mut dyn Renderer,
+    (center_x, center_y): (i32, i32),
+    direction: Direction,
+    reach: i32,
+) -> Result<(), String> {
+    let (dx, dy) = direction.delta();
+    let tip = (center_x + dx * reach, center_y + dy * reach);
+    let head = reach * 2 / 3;
+    for width in -1..=1 {
+        // Sideways from the shaft, so each stroke is doubled up beside itself.
+        let (sx, sy) = (dy * width, dx * width);
+        canvas.draw_line(
+            (center_x - dx * reach + sx, center_y - dy * reach + sy),
+            (tip.0 + sx, tip.1 + sy),
+        )?;
+        for side in [-1, 1] {
+            canvas.draw_line(
+                (tip.0 + sx, tip.1 + sy),
+                (
+                    tip.0 - dx * head + dy * side * head + sx,
+                    tip.1 - dy * head + dx * side * head + sy,
+                ),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// The outline of a circle, as 32 straight sides.
+fn draw_circle(
+    canvas: &mut dyn Renderer,
+    center_x: i32,
+    center_y: i32,
+    radius: i32,
+) -> Result<(), String> {
+    const SIDES: usize = 32;
+    let point = |side: usize| {
+        let angle = side as f32 / SIDES as f32 * std::f32::consts::TAU;
+        (
+            center_x + (radius as f32 * angle.cos()).round() as i32,
+            center_y + (radius as f32 * angle.sin()).round() as i32,
+        )
+    };
+    for side in 0..SIDES {
+        canvas.draw_line(point(side), point(side + 1))?;
+    }
+    Ok(())
+}
+
+/// View-only debugging aids; none of this affects the simulation.
+#[derive(Clone, Default)]
+pub struct DebugOverlay {
+    pub enabled: bool,
+    pub mouse: Option<(i32, i32)>,
+    /// Real time since start; shown next to the tick count.
+    pub wall_clock: Duration,
+    /// Vehicle picked with the mouse, by id.
+    pub selected: Option<u64>,
+    /// Whether the selected vehicle's projected path is drawn.
+    pub ghost: bool,
+    /// Whether the minimap is drawn in the top-right corner.
+    pub minimap: bool,
+    /// Whether the camera keeps the selected vehicle centred.
+    pub follow: bool,
+    pub camera: Camera,
+    /// Whether the HUD is drawn in the bottom-left corner.
+    pub hud: bool,
+    /// Whether the tuning panel is drawn in the bottom-right corner.
+    pub panel: bool,
+    /// Whether the heatmap of where vehicles have queued is drawn over the
+    /// roads.
+    pub heatmap: bool,
+    /// Frames per second, smoothed; shown on the HUD.
+    pub fps: f32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MIN_BASE_GAP;
+    use crate::environment::Traction;
+    use crate::geometry::{WINDOW_HEIGHT, WINDOW_WIDTH};
+    use crate::render::{DrawCall, RecordingRenderer};
+    use crate::vehicle::{lane_centre, next_direction};
+
+    // Where the roads cross in the default world.
+    const CENTER_X: i32 = (WINDOW_WIDTH / 2) as i32;
+    const CENTER_Y: i32 = (WINDOW_HEIGHT / 2) as i32;
+
+    fn exit_edge(vehicle: &Vehicle) -> Direction {
+        if vehicle.x < 0.0 {
+            Direction::Left
+        } else if vehicle.x > WINDOW_WIDTH as f32 {
+            Direction::Right
+        } else if vehicle.y < 0.0 {
+            Direction::Up
+        } else {
+            Direction::Down
+        }
+    }
+
+    fn run_until_exit(direction: Direction, route: Route) -> Direction {
+        let mut simulation = TrafficSimulation::new(Config::default());
+        simulation.spawn_vehicle(direction);
+        simulation.vehicles[0].route = route;
+
+        let mut last = simulation.vehicles[0].clone();
+        for _ in 0..10_000 {
+            simulation.update();
+            match simulation.vehicles.first() {
+                Some(vehicle) => last = vehicle.clone(),
+                None => return exit_edge(&last),
+            }
+        }
+        panic!("{} {:?} never left the screen", direction, route);
+    }
+
+    #[test]
+    fn interpolation_blends_previous_and_current_position() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut vehicle = Vehicle::new(
+            0,
+            Direction::Right,
+            Route::Straight,
+            ColorMode::ByRoute,
+            &mut rng,
+        );
+        vehicle.update(true, f32::INFINITY, f32::INFINITY);
+
+        assert_eq!(
+            vehicle.interpolated_position(0.0),
+            (vehicle.prev_x, vehicle.prev_y)
+        );
+        assert_eq!(vehicle.interpolated_position(1.0), (vehicle.x, vehicle.y));
+        assert_eq!(
+            vehicle.interpolated_position(0.5).0,
+            vehicle.prev_x + VEHICLE_SPEED / 2.0
+        );
+    }
+
+    #[test]
+    fn red_light_holds_vehicles_at_the_stop_line() {
+        let config = Config {
+            light_plan: lights::LightPlan::parse("up+down:100").unwrap(),
+            ..Config::default()
+        };
+        let mut simulation = TrafficSimulation::new(config);
+        simulation.spawn_vehicle(Direction::Right);
+
+        for _ in 0..1000 {
+            simulation.update();
+        }
+
+        let vehicle = &simulation.vehicles[0];
+        assert_eq!(vehicle.stop_line_distance(), 0.0);
+        assert!(vehicle.is_waiting());
+    }
+
+    #[test]
+    fn a_vehicle_past_its_stop_line_carries_on_through_red() {
+        let config = Config {
+            light_plan: lights::LightPlan::parse("up:3,none:100").unwrap(),
+            ..Config::default()
+        };
+        let mut simulation = TrafficSimulation::new(config);
+        simulation
+            .spawn_vehicle_with(Direction::Up, VEHICLE_SPEED, Some(Route::Straight))
+            .unwrap();
+        let mut red_in_the_box = false;
+        while let Some(vehicle) = simulation.vehicles.first() {
+            if vehicle.stop_line_distance() < 0.0 {
+                assert!(vehicle.velocity > 0.0);
+                red_in_the_box |= !simulation.lights.is_green(Direction::Up);
+            }
+            simulation.update();
+            assert!(simulation.tick < 1000);
+        }
+        assert!(red_in_the_box);
+        assert_eq!(simulation.exited[Direction::Up.index()], 1);
+    }
+
+    #[test]
+    fn starved_approach_with_waiting_vehicles_is_reported() {
+        let config = Config {
+            light_plan: lights::LightPlan::parse("up+down:100").unwrap(),
+            starvation_warning: 10.0,
+            ..Config::default()
+        };
+        let mut simulation = TrafficSimulation::new(config);
+        simulation.spawn_vehicle(Direction::Right);
+        simulation.spawn_vehicle(Direction::Up);
+
+        for _ in 0..(11 * 60) {
+            simulation.update();
+        }
+
+        assert!(simulation.starvation_warned[Direction::Right.index()]);
+        assert!(!simulation.starvation_warned[Direction::Left.index()]);
+        assert!(!simulation.starvation_warned[Direction::Up.index()]);
+    }
+
+    #[test]
+    fn random_colors_are_reproducible_with_a_seed() {
+        let colors = || {
+            let config = Config {
+                color_mode: ColorMode::Random,
+                seed: Some(7),
+                ..Config::default()
+            };
+            let mut simulation = TrafficSimulation::new(config);
+            for direction in Direction::ALL {
+                simulation.spawn_vehicle(direction);
+            }
+            simulation
+                .vehicles
+                .iter()
+                .map(|vehicle| vehicle.color)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(colors(), colors());
+    }
+
+    #[test]
+    fn id_colors_are_stable_and_distinct() {
+        let colors = |seed: u64| {
+            let mut simulation = TrafficSimulation::new(Config {
+                color_mode: ColorMode::ById,
+                seed: Some(seed),
+                ..Config::default()
+            });
+            for direction in Direction::ALL {
+                simulation.spawn_vehicle(direction);
+            }
+            simulation
+                .vehicles
+                .iter()
+                .map(|vehicle| vehicle.color)
+                .collect::<Vec<_>>()
+        };
+
+        let first = colors(1);
+        assert_eq!(first, colors(2));
+        let distinct: HashSet<(u8, u8, u8)> = first
+            .iter()
+            .map(|color| (color.r, color.g, color.b))
+            .collect();
+        assert_eq!(distinct.len(), first.len());
+    }
+
+    #[test]
+    fn delay_is_accumulated_for_vehicles_held_at_red() {
+        let config = Config {
+            light_plan: lights::LightPlan::parse("up+down:6,right+left:6").unwrap(),
+            ..Config::default()
+        };
+        let mut simulation = TrafficSimulation::new(config);
+        simulation.spawn_vehicle(Direction::Right);
+        simulation.vehicles[0].route = Route::Straight;
+        simulation.spawn_vehicle(Direction::Up);
+        simulation.vehicles[1].route = Route::Straight;
+
+        while !simulation.vehicles.is_empty() {
+            simulation.update();
+        }
+
+        // The right-bound car reaches its stop line after 3.5s and waits for green at 6s.
+        let right = simulation.average_delay(Direction::Right);
+        assert!((right - 2.5).abs() < 0.1, "unexpected delay {}", right);
+        assert_eq!(simulation.average_delay(Direction::Up), 0.0);
+    }
+
+    #[test]
+    fn t_junction_never_uses_the_missing_arm() {
+        let config = Config {
+            layout: Layout::parse("t-junction").unwrap(),
+            seed: Some(3),
+            ..Config::default()
+        };
+        let mut simulation = TrafficSimulation::new(config);
+
+        simulation.spawn_vehicle(Direction::Down);
+        assert!(simulation.vehicles.is_empty());
+
+        for _ in 0..50 {
+            for direction in [Direction::Up, Direction::Right, Direction::Left] {
+                simulation.spawn_vehicle(direction);
+            }
+        }
+        assert!(
+            simulation
+                .vehicles
+                .iter()
+                .all(|vehicle| vehicle.exit_direction() != Direction::Up)
+        );
+    }
+
+    #[test]
+    fn same_seed_and_inputs_give_the_same_hash_sequence() {
+        let run = |seed| {
+            let config = Config {
+                seed: Some(seed),
+                ..Config::default()
+            };
+            let mut simulation = TrafficSimulation::new(config);
+            let mut hashes = Vec::new();
+            for tick in 0..600 {
+                if tick % 40 == 0 {
+                    simulation.spawn_vehicle(Direction::ALL[tick / 40 % 4]);
+                }
+                simulation.update();
+                hashes.push(simulation.state_hash());
+            }
+            hashes
+        };
+
+        assert_eq!(run(11), run(11));
+        assert_ne!(run(11), run(12));
+    }
+
+    #[test]
+    fn occupied_spawn_point_refuses_and_counts_spawns() {
+        let mut simulation = TrafficSimulation::new(Config::default());
+
+        assert!(simulation.spawn_vehicle(Direction::Left));
+        assert!(!simulation.spawn_vehicle(Direction::Left));
+        assert_eq!(simulation.vehicles.len(), 1);
+        assert_eq!(simulation.refused_spawns[Direction::Left.index()], 1);
+        assert!(simulation.spawn_blocked[Direction::Left.index()]);
+
+        while simulation.spawn_point_occupied(Direction::Left) {
+            simulation.update();
+        }
+        assert!(!simulation.spawn_blocked[Direction::Left.index()]);
+        assert!(simulation.spawn_vehicle(Direction::Left));
+    }
+
+    #[test]
+    fn cruise_speed_and_spawn_cooldown_come_from_the_config() {
+        let config = Config::from_args(
+            ["--cruise-speed", "180", "--spawn-cooldown", "0.25"]
+                .into_iter()
+                .map(String::from),
+        )
+        .unwrap();
+        let mut simulation = TrafficSimulation::new(config);
+        assert!(simulation.request_spawn(Direction::Up, None, 0));
+        assert_eq!(simulation.vehicles[0].cruise_speed, 3.0);
+        while simulation.spawn_point_occupied(Direction::Up) {
+            simulation.update();
+        }
+        assert!(!simulation.request_spawn(Direction::Up, None, 250));
+        simulation.release_spawns(250);
+        assert_eq!(simulation.vehicles.len(), 1);
+        simulation.release_spawns(251);
+        assert_eq!(simulation.vehicles.len(), 2);
+        assert!(Config::from_args(["--cruise-speed", "0"].into_iter().map(String::from)).is_err());
+    }
+
+    #[test]
+    fn a_full_queue_quietly_refuses_spawns() {
+        let mut simulation = TrafficSimulation::new(Config {
+            light_plan: lights::LightPlan::parse("right:100").unwrap(),
+            queue_cap: Some(2),
+            ..Config::default()
+        });
+        for tick in 0..2000 {
+            if tick % 100 == 0 {
+                simulation.spawn_vehicle(Direction::Up);
+            }
+            simulation.update();
+        }
+        assert_eq!(simulation.queue_lengths()[Direction::Up.index()], 2);
+        assert_eq!(simulation.refused_spawns, [0; 4]);
+        assert!(!simulation.spawn_blocked[Direction::Up.index()]);
+        // The refusal leaves the cooldown alone.
+        assert!(!simulation.request_spawn(Direction::Up, None, 0));
+        assert_eq!(simulation.last_spawn_time[Direction::Up.index()], None);
+    }
+
+    #[test]
+    fn following_gap_grows_with_speed() {
+        let config = Config {
+            light_plan: lights::LightPlan::parse("right+left:100").unwrap(),
+            ..Config::default()
+        };
+        let mut simulation = TrafficSimulation::new(config);
+        // Vehicles can't close a gap faster than they cruise, so start the
+        // follower as close as it may be while both move at full speed.
+        let cruising = VEHICLE_SPEED * simulation.config.tick_rate;
+        let spacing = VEHICLE_SIZE as f32 + simulation.required_gap(cruising, 0.0) + VEHICLE_SPEED;
+        simulation.spawn_vehicle(Direction::Right);
+        simulation.vehicles[0].x += spacing;
+        simulation.vehicles[0].prev_x += spacing;
+        simulation.spawn_vehicle(Direction::Right);
+        for vehicle in &mut simulation.vehicles {
+            vehicle.route = Route::Straight;
+        }
+
+        for _ in 0..150 {
+            simulation.update();
+        }
+
+        let gap = simulation.vehicles[1]
+            .gap_to(&simulation.vehicles[0])
+            .unwrap();
+        let expected = simulation.required_gap(cruising, 0.0);
+        assert!(expected > simulation.required_gap(0.0, 0.0));
+        assert!(
+            gap >= expected && gap <= expected + VEHICLE_SPEED,
+            "moving gap {} should be about {}",
+            gap,
+            expected
+        );
+    }
+
+    #[test]
+    fn stopped_queue_packs_to_the_base_gap() {
+        let config = Config {
+            light_plan: lights::LightPlan::parse("up+down:100").unwrap(),
+            ..Config::default()
+        };
+        let mut simulation = TrafficSimulation::new(config);
+        for tick in 0..600 {
+            if tick % 60 == 0 && tick < 180 {
+                simulation.spawn_vehicle(Direction::Right);
+            }
+            simulation.update();
+        }
+
+        for pair in simulation.vehicles.windows(2) {
+            let gap = pair[1].gap_to(&pair[0]).unwrap();
+            assert!(
+                (gap - simulation.config.base_gap).abs() <= VEHICLE_SPEED,
+                "queued gap {}",
+                gap
+            );
+        }
+        assert_eq!(simulation.collisions, 0);
+    }
+
+    #[test]
+    fn queued_vehicles_pull_away_one_reaction_time_apart() {
+        let config = Config {
+            light_plan: lights::LightPlan::parse("up+down:20,right+left:100").unwrap(),
+            reaction_time: 0.5,
+            ..Config::default()
+        };
+        let reaction = config.seconds_to_ticks(config.reaction_time);
+        let mut simulation = TrafficSimulation::new(config);
+        let mut green_at = None;
+        let mut started: Vec<Option<u64>> = vec![None; 4];
+        for tick in 0..1500 {
+            if tick % 60 == 0 && tick < 240 {
+                simulation.spawn_vehicle(Direction::Right);
+            }
+            simulation.update();
+            if simulation.lights.is_green(Direction::Right) {
+                green_at.get_or_insert(simulation.tick);
+            }
+            for vehicle in &simulation.vehicles {
+                if green_at.is_some() && vehicle.velocity > 0.0 {
+                    started[vehicle.id as usize].get_or_insert(simulation.tick);
+                }
+            }
+        }
+
+        let started: Vec<u64> = started.into_iter().map(Option::unwrap).collect();
+        // The first car reacts to the green, each later one to its leader.
+        assert_eq!(started[0], green_at.unwrap() + reaction as u64);
+        for pair in started.windows(2) {
+            let lag = pair[1] - pair[0];
+            assert!(
+                (reaction as u64..=reaction as u64 + 2).contains(&lag),
+                "lag {}",
+                lag
+            );
+        }
+    }
+
+    #[test]
+    fn longest_queue_is_remembered_after_it_clears() {
+        let config = Config {
+            light_plan: lights::LightPlan::parse("up+down:20,right+left:20").unwrap(),
+            ..Config::default()
+        };
+        let mut simulation = TrafficSimulation::new(config);
+        for tick in 0..2400 {
+            if tick % 60 == 0 && tick < 240 {
+                simulation.spawn_vehicle(Direction::Right);
+            }
+            simulation.update();
+        }
+        assert!(simulation.vehicles.is_empty());
+        assert_eq!(simulation.max_queue, [0, 0, 4, 0]);
+    }
+
+    #[test]
+    fn every_route_exits_through_the_expected_edge() {
+        use Direction::{Down, Left, Right, Up};
+        use Route::{Straight, TurnLeft, TurnRight, UTurn};
+
+        let cases = [
+            (Up, Straight, Up),
+            (Up, TurnLeft, Right),
+            (Up, TurnRight, Left),
+            (Down, Straight, Down),
+            (Down, TurnLeft, Left),
+            (Down, TurnRight, Right),
+            (Right, Straight, Right),
+            (Right, TurnLeft, Up),
+            (Right, TurnRight, Down),
+            (Left, Straight, Left),
+            (Left, TurnLeft, Down),
+            (Left, TurnRight, Up),
+            (Up, UTurn, Down),
+            (Down, UTurn, Up),
+            (Right, UTurn, Left),
+            (Left, UTurn, Right),
+        ];
+
+        for (direction, route, expected) in cases {
+            assert_eq!(
+                run_until_exit(direction, route),
+                expected,
+                "{} {:?} left through the wrong edge",
+                direction,
+                route
+            );
+        }
+    }
+
+    #[test]
+    fn a_u_turn_rounds_the_median_and_heads_back_out_past_its_stop_line() {
+        let mut simulation = TrafficSimulation::new(Config {
+            u_turns: true,
+            light_plan: lights::LightPlan::parse("up:100").unwrap(),
+            ..Config::default()
+        });
+        simulation
+            .spawn_vehicle_with(Direction::Up, VEHICLE_SPEED, Some(Route::UTurn))
+            .unwrap();
+        let path = simulation.vehicles[0].projected_path();
+
+        // Up, across on the lane heading right, then down the far side of
+        // its own road, turning exactly on each lane's centre line.
+        let (across, back) = (Direction::Right, Direction::Down);
+        let turn = lane_centre(World::default(), across);
+        assert_eq!(
+            next_direction(
+                World::default(),
+                Direction::Up,
+                Route::UTurn,
+                0.0,
+                turn + 1.0
+            ),
+            Direction::Up
+        );
+        assert_eq!(
+            next_direction(World::default(), Direction::Up, Route::UTurn, 0.0, turn),
+            across
+        );
+        let second = lane_centre(World::default(), back);
+        assert_eq!(
+            next_direction(
+                World::default(),
+                Direction::Up,
+                Route::UTurn,
+                second - 1.0,
+                turn
+            ),
+            across
+        );
+        assert_eq!(
+            next_direction(World::default(), Direction::Up, Route::UTurn, second, turn),
+            back
+        );
+        assert_eq!(
+            next_direction(
+                World::default(),
+                Direction::Up,
+                Route::UTurn,
+                second,
+                WINDOW_HEIGHT as f32
+            ),
+            back
+        );
+        let mut headings: Vec<Direction> = path.iter().map(|ghost| ghost.direction).collect();
+        headings.dedup();
+        assert_eq!(headings, [Direction::Up, across, back]);
+
+        // Heading back, it never comes up to its stop line again, and leaves
+        // through the edge it came in by.
+        let entered = path
+            .iter()
+            .position(|ghost| ghost.stop_line_distance() < 0.0)
+            .unwrap();
+        for pair in path[entered..].windows(2) {
+            assert!(pair[1].stop_line_distance() <= pair[0].stop_line_distance());
+        }
+        let exit = path.last().unwrap();
+        assert!(exit.cleared_intersection());
+        assert!(exit.lane_offset() <= VEHICLE_SPEED);
+        assert_eq!(exit_edge(exit), Direction::Down);
+
+        // Dragged to the far side of the road, it lands on its way out.
+        let vehicle = &mut simulation.vehicles[0];
+        vehicle.drag_to(second, CENTER_Y as f32 + 200.0);
+        assert_eq!(vehicle.direction, back);
+        assert_eq!((vehicle.x, vehicle.y), (second, CENTER_Y as f32 + 200.0));
+
+        assert!(!Config::default().permits(Movement::parse("up-uturn").unwrap()));
+    }
+
+    #[test]
+    fn vehicles_change_direction_exactly_on_their_turn_line() {
+        for approach in Direction::ALL {
+            for route in Route::ALL {
+                let exit = route.exit_direction(approach);
+                let (x, y) = spawn_position(World::default(), approach);
+                // Where along the approach the vehicle is said to be.
+                let at = |along: f32| match approach {
+                    Direction::Up | Direction::Down => {
+                        next_direction(World::default(), approach, route, x, along)
+                    }
+                    Direction::Right | Direction::Left => {
+                        next_direction(World::default(), approach, route, along, y)
+                    }
+                };
+                let (dx, dy) = approach.delta();
+                let forward = (dx + dy) as f32;
+                if route == Route::Straight {
+                    for along in [0.0, 400.0, 500.0, 800.0, 1000.0] {
+                        assert_eq!(at(along), approach);
+                    }
+                    continue;
+                }
+                // The first turn; a U-turn's second one is checked on its own.
+                let turned = route.headings(approach)[1];
+                let turn = lane_centre(World::default(), turned);
+                assert_eq!(at(turn - forward), approach, "{} {:?}", approach, route);
+                assert_eq!(at(turn), turned, "{} {:?}", approach, route);
+                assert_eq!(
+                    at(turn + forward * 50.0),
+                    turned,
+                    "{} {:?}",
+                    approach,
+                    route
+                );
+                if route != Route::UTurn {
+                    assert_eq!(turned, exit);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn turns_join_the_lane_straight_traffic_drives_in() {
+        for direction in Direction::ALL {
+            for route in Route::ALL {
+                for kind in [VehicleKind::Car, VehicleKind::Truck] {
+                    let mut simulation = TrafficSimulation::new(Config {
+                        u_turns: true,
+                        ..Config::default()
+                    });
+                    simulation
+                        .spawn_vehicle_with(direction, VEHICLE_SPEED, Some(route))
+                        .unwrap();
+                    let vehicle = &mut simulation.vehicles[0];
+                    vehicle.kind = kind;
+                    vehicle.length = kind.length(VEHICLE_SIZE as f32);
+                    let path = vehicle.projected_path();
+                    let exit = path.last().unwrap();
+                    assert_eq!(exit.direction, route.exit_direction(direction));
+                    assert!(exit.lane_offset() <= VEHICLE_SPEED);
+
+                    // Once turned, the tail stays on the far side of where
+                    // the vehicle's side was in the approach lane, until a
+                    // U-turn heads back the way it came.
+                    let lane = lane_centre(World::default(), direction);
+                    let half_side = VEHICLE_SIZE as f32 / 2.0;
+                    let turned = |ghost: &&Vehicle| {
+                        ghost.direction != direction && ghost.direction != direction.opposite()
+                    };
+                    for ghost in path.iter().filter(turned) {
+                        let (half_width, half_height) = ghost.half_extents();
+                        let (dx, dy) = ghost.direction.delta();
+                        let (centre, half, ahead) = if dx == 0 {
+                            (ghost.y, half_height, dy as f32)
+                        } else {
+                            (ghost.x, half_width, dx as f32)
+                        };
+                        let overhang = (lane - half_side * ahead) - (centre - half * ahead);
+                        assert!(
+                            overhang * ahead < 0.01,
+                            "{} {:?} {:?} overhangs by {}",
+                            direction,
+                            route,
+                            kind,
+                            overhang.abs()
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    // Two straight-through vehicles from crossing approaches meet in the box
+    // and freeze each other.
+    fn gridlocked_simulation(gridlock_recovery: bool) -> TrafficSimulation {
+        let config = Config {
+            collision_policy: CollisionPolicy::Stop,
+            gridlock_recovery,
+            ..Config::default()
+        };
+        let mut simulation = TrafficSimulation::new(config);
+        let crossing = (
+            CENTER_X as f32 - crate::geometry::LANE_WIDTH as f32,
+            CENTER_Y as f32 + crate::geometry::LANE_WIDTH as f32,
+        );
+        for direction in [Direction::Up, Direction::Right] {
+            simulation.spawn_vehicle(direction);
+            let vehicle = simulation.vehicles.last_mut().unwrap();
+            vehicle.route = Route::Straight;
+            (vehicle.x, vehicle.y) = crossing;
+            (vehicle.prev_x, vehicle.prev_y) = crossing;
+        }
+        simulation
+    }
+
+    #[test]
+    fn gridlock_recovery_drains_the_intersection() {
+        let mut simulation = gridlocked_simulation(true);
+        simulation.update();
+        assert!(simulation.vehicles.iter().all(|vehicle| vehicle.frozen));
+
+        for _ in 0..30 * 60 {
+            simulation.update();
+        }
+        assert!(simulation.vehicles.is_empty());
+        assert!(!simulation.lights.in_recovery());
+        assert_eq!(simulation.collisions, 1);
+    }
+
+    #[test]
+    fn gridlock_persists_without_recovery() {
+        let mut simulation = gridlocked_simulation(false);
+        for _ in 0..30 * 60 {
+            simulation.update();
+        }
+        assert!(simulation.is_gridlocked());
+        assert_eq!(simulation.vehicles.len(), 2);
+    }
+
+    #[test]
+    fn tick_cooldown_ignores_the_wall_clock() {
+        let config = Config {
+            tick_cooldown: true,
+            ..Config::default()
+        };
+        let mut simulation = TrafficSimulation::new(config);
+        assert!(simulation.request_spawn(Direction::Up, None, 0));
+        // Wall-clock time is ignored, however much of it has passed.
+        assert!(!simulation.request_spawn(Direction::Up, None, u128::MAX));
+
+        for _ in 0..60 {
+            simulation.update();
+        }
+        assert_eq!(simulation.tick, 60);
+        simulation.release_spawns(0);
+        assert_eq!(simulation.held_spawns()[Direction::Up.index()], 1);
+        simulation.update();
+        simulation.release_spawns(0);
+        assert_eq!(simulation.held_spawns()[Direction::Up.index()], 0);
+        assert_eq!(simulation.vehicles.len(), 2);
+    }
+
+    #[test]
+    fn key_presses_wait_out_the_cooldown_and_a_taken_spawn_point() {
+        let config = Config {
+            spawn_buffer: 2,
+            ..Config::default()
+        };
+        let mut simulation = TrafficSimulation::new(config.clone());
+        assert!(simulation.request_spawn(Direction::Up, Some(Route::TurnLeft), 0));
+        assert!(!simulation.request_spawn(Direction::Up, Some(Route::TurnRight), 100));
+        assert!(!simulation.request_spawn(Direction::Up, None, 200));
+        // Past the buffer, presses are dropped.
+        assert!(!simulation.request_spawn(Direction::Up, None, 300));
+        assert_eq!(simulation.held_spawns(), [2, 0, 0, 0]);
+
+        let debug = DebugOverlay {
+            hud: true,
+            ..DebugOverlay::default()
+        };
+        let mut recorder = RecordingRenderer::default();
+        simulation.render(&mut recorder, 1.0, &debug).unwrap();
+        let held_row = recorder
+            .calls
+            .iter()
+            .filter(|call| {
+                matches!(call, DrawCall::FillRect(rect, color)
+                    if *color == Color::RGB(120, 200, 255) && rect.x() < 100)
+            })
+            .count();
+        // Five segments for the 2 held, six each for the three zeros.
+        assert_eq!(held_row, 5 + 3 * 6);
+
+        let mut restored = TrafficSimulation::new(config);
+        restored.restore(&simulation.snapshot()).unwrap();
+        assert_eq!(restored.held_spawns(), [2, 0, 0, 0]);
+
+        // Cooled down, but the first vehicle has yet to drive off.
+        simulation.release_spawns(1001);
+        assert_eq!(simulation.vehicles.len(), 1);
+        while simulation.spawn_point_occupied(Direction::Up) {
+            simulation.update();
+        }
+        simulation.release_spawns(1001);
+        assert_eq!(simulation.vehicles.len(), 2);
+        assert_eq!(simulation.vehicles[1].route, Route::TurnRight);
+        assert_eq!(simulation.held_spawns(), [1, 0, 0, 0]);
+        // Waiting is not being refused.
+        assert_eq!(simulation.refused_spawns, [0; 4]);
+
+        simulation.start_drain();
+        assert_eq!(simulation.held_spawns(), [0; 4]);
+    }
+
+    #[test]
+    fn time_scale_speeds_up_the_wall_clock_only() {
+        let config = Config {
+            tick_rate: 50.0,
+            time_scale: 4.0,
+            ..Config::default()
+        };
+        assert_eq!(config.tick_interval(), Duration::from_millis(5));
+        assert_eq!(config.ticks_to_seconds(150), 3.0);
+
+        let mut simulation = TrafficSimulation::new(config);
+        for _ in 0..150 {
+            simulation.update();
+        }
+        assert_eq!(simulation.simulated_seconds(), 3.0);
+
+        // A quarter of a wall-clock second is a whole simulated one.
+        assert!(simulation.request_spawn(Direction::Up, None, 0));
+        for _ in 0..60 {
+            simulation.update();
+        }
+        assert!(!simulation.request_spawn(Direction::Up, None, 250));
+        simulation.release_spawns(250);
+        assert_eq!(simulation.vehicles.len(), 1);
+        simulation.release_spawns(251);
+        assert_eq!(simulation.vehicles.len(), 2);
+    }
+
+    #[test]
+    fn runs_do_not_depend_on_the_playback_speed() {
+        let run = |time_scale| {
+            let mut simulation = TrafficSimulation::new(Config {
+                seed: Some(8),
+                time_scale,
+                tick_cooldown: true,
+                ..Config::default()
+            });
+            let mut hashes = Vec::new();
+            for tick in 0..1200u64 {
+                // Key presses tagged by tick, as a recorded run would replay them.
+                if tick % 45 == 0 {
+                    let direction = Direction::ALL[(tick / 45) as usize % 4];
+                    simulation.request_spawn(direction, None, tick as u128 * 1000);
+                }
+                simulation.update();
+                hashes.push(simulation.state_hash());
+            }
+            hashes
+        };
+        let normal = run(1.0);
+        assert_eq!(run(0.1), normal);
+        assert_eq!(run(8.0), normal);
+    }
+
+    #[test]
+    fn scenario_spawns_honour_speed_and_route() {
+        let mut simulation = TrafficSimulation::new(Config::default());
+        assert_eq!(
+            simulation.spawn_vehicle_with(Direction::Left, 3.5, Some(Route::TurnRight)),
+            Ok(true)
+        );
+        let start = simulation.vehicles[0].x;
+        simulation.update();
+        assert_eq!(simulation.vehicles[0].route, Route::TurnRight);
+        assert_eq!(start - simulation.vehicles[0].x, 3.5);
+
+        assert!(
+            simulation
+                .spawn_vehicle_with(Direction::Up, 0.0, None)
+                .is_err()
+        );
+
+        let mut t_junction = TrafficSimulation::new(Config {
+            layout: Layout::ThreeWay {
+                missing: Direction::Up,
+            },
+            ..Config::default()
+        });
+        assert!(
+            t_junction
+                .spawn_vehicle_with(Direction::Up, 2.0, Some(Route::Straight))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn opposing_left_turners_take_turns_at_the_apex() {
+        for approaches in [
+            [Direction::Up, Direction::Down],
+            [Direction::Right, Direction::Left],
+        ] {
+            let config = Config {
+                light_plan: lights::LightPlan::parse("up+down+right+left:100").unwrap(),
+                ..Config::default()
+            };
+            let mut simulation = TrafficSimulation::new(config);
+            for direction in approaches {
+                let spawned =
+                    simulation.spawn_vehicle_with(direction, VEHICLE_SPEED, Some(Route::TurnLeft));
+                assert_eq!(spawned, Ok(true));
+            }
+
+            for _ in 0..600 {
+                simulation.update();
+                assert!(simulation.detect_collisions().is_empty());
+            }
+            assert!(simulation.vehicles.is_empty());
+        }
+    }
+
+    #[test]
+    fn projected_path_ends_past_the_exit_edge() {
+        for direction in Direction::ALL {
+            for route in [Route::TurnLeft, Route::TurnRight] {
+                let mut simulation = TrafficSimulation::new(Config::default());
+                simulation
+                    .spawn_vehicle_with(direction, VEHICLE_SPEED, Some(route))
+                    .unwrap();
+                let vehicle = &simulation.vehicles[0];
+                let ghost = vehicle.projected_path().pop().unwrap();
+                assert_eq!(exit_edge(&ghost), route.exit_direction(direction));
+            }
+        }
+    }
+
+    #[test]
+    fn projected_path_drives_a_dragged_vehicle_out() {
+        let mut simulation = TrafficSimulation::new(Config::default());
+        simulation
+            .spawn_vehicle_with(Direction::Up, VEHICLE_SPEED, Some(Route::TurnLeft))
+            .unwrap();
+        let (id, x, y) = {
+            let vehicle = &simulation.vehicles[0];
+            (vehicle.id, vehicle.x, vehicle.y - 40.0)
+        };
+        assert!(simulation.drag_vehicle(id, x, y));
+        let vehicle = &mut simulation.vehicles[0];
+        assert!(vehicle.held);
+        let ghost = vehicle.projected_path().pop().unwrap();
+        assert_eq!(
+            exit_edge(&ghost),
+            Route::TurnLeft.exit_direction(Direction::Up)
+        );
+
+        vehicle.cruise_speed = 0.0;
+        assert!(vehicle.projected_path().is_empty());
+    }
+
+    #[test]
+    fn no_vehicle_enters_the_box_during_all_red() {
+        let config = Config {
+            light_plan: lights::LightPlan::parse("up+down:3,right+left:3").unwrap(),
+            yellow: 1.0,
+            all_red: 2.0,
+            seed: Some(3),
+            ..Config::default()
+        };
+        let mut simulation = TrafficSimulation::new(config);
+        let mut all_red_ticks = 0;
+        for tick in 0..1200 {
+            if tick % 30 == 0 {
+                for direction in Direction::ALL {
+                    simulation.spawn_vehicle(direction);
+                }
+            }
+            let entered: HashSet<u64> = simulation
+                .vehicles
+                .iter()
+                .filter(|vehicle| vehicle.stop_line_distance() < 0.0)
+                .map(|vehicle| vehicle.id)
+                .collect();
+            simulation.update();
+            if simulation.lights.stage() == lights::Stage::AllRed {
+                all_red_ticks += 1;
+                assert!(simulation.vehicles.iter().all(|vehicle| {
+                    vehicle.stop_line_distance() >= 0.0 || entered.contains(&vehicle.id)
+                }));
+            }
+        }
+        assert!(all_red_ticks > 0);
+    }
+
+    #[test]
+    fn trucks_start_braking_for_red_earlier_than_cars() {
+        let first_braking_distance = |kind: VehicleKind| {
+            let config = Config {
+                light_plan: lights::LightPlan::parse("up+down:100").unwrap(),
+                ..Config::default()
+            };
+            let mut simulation = TrafficSimulation::new(config);
+            simulation.spawn_vehicle(Direction::Right);
+            simulation.vehicles[0].kind = kind;
+            simulation.vehicles[0].length = kind.length(VEHICLE_SIZE as f32);
+            loop {
+                let to_stop_line = simulation.vehicles[0].stop_line_distance();
+                simulation.update();
+                if simulation.vehicles[0].velocity < VEHICLE_SPEED {
+                    return to_stop_line;
+                }
+            }
+        };
+
+        let car = first_braking_distance(VehicleKind::Car);
+        let truck = first_braking_distance(VehicleKind::Truck);
+        assert!(truck > car, "truck braked at {}, car at {}", truck, car);
+    }
+
+    #[test]
+    fn a_stop_from_cruise_speed_takes_as_far_at_any_tick_rate() {
+        let stopping = |tick_rate: f32| {
+            let mut rng = StdRng::seed_from_u64(0);
+            let mut vehicle = Vehicle::new(
+                0,
+                Direction::Right,
+                Route::Straight,
+                ColorMode::ByRoute,
+                &mut rng,
+            );
+            vehicle.tick_rate = tick_rate;
+            vehicle.cruise_speed = Config::default().cruise_speed / tick_rate;
+            vehicle.velocity = vehicle.cruise_speed;
+            let start = vehicle.x;
+            while vehicle.velocity > 0.0 {
+                vehicle.update(true, f32::INFINITY, 0.0);
+            }
+            vehicle.x - start
+        };
+
+        let (at_60, at_120) = (stopping(60.0), stopping(120.0));
+        assert!(at_60 > VEHICLE_SPEED * 2.0, "stopped in {}", at_60);
+        assert!(
+            (at_60 - at_120).abs() < 1.0,
+            "{} at 60 Hz, {} at 120 Hz",
+            at_60,
+            at_120
+        );
+    }
+
+    #[test]
+    fn trucks_stay_until_fully_out_of_the_world() {
+        let mut simulation = TrafficSimulation::new(Config::default());
+        simulation.spawn_vehicle(Direction::Left);
+        let truck = &mut simulation.vehicles[0];
+        truck.kind = VehicleKind::Truck;
+        truck.length = VehicleKind::Truck.length(VEHICLE_SIZE as f32);
+        let (half_length, _) = truck.half_extents();
+        truck.route = Route::Straight;
+        assert!(half_length > VEHICLE_SIZE as f32 / 2.0);
+
+        // The tail is still showing at the left edge.
+        let world = World::default().bounds();
+        truck.x = 1.0 - half_length;
+        assert!(!truck.has_left(world));
+        assert!(truck.get_rect(1.0).right() > 0);
+        truck.x = -half_length - 1.0;
+        assert!(truck.has_left(world));
+        assert!(truck.get_rect(1.0).right() <= 0);
+        // Only the edge its route leaves by counts.
+        truck.x = WINDOW_WIDTH as f32 + half_length + 1.0;
+        assert!(!truck.has_left(world));
+        let wider = Rect::new(-100, 0, WINDOW_WIDTH + 100, WINDOW_HEIGHT);
+        truck.x = -half_length - 1.0;
+        assert!(!truck.has_left(wider));
+    }
+
+    #[test]
+    fn an_exit_closes_the_trip_then_fades_out() {
+        let mut simulation = TrafficSimulation::new(Config {
+            light_plan: lights::LightPlan::parse("up:100").unwrap(),
+            ..Config::default()
+        });
+        simulation
+            .spawn_vehicle_with(Direction::Up, VEHICLE_SPEED, Some(Route::Straight))
+            .unwrap();
+        let color = simulation.vehicles[0].color;
+        while !simulation.vehicles.is_empty() {
+            simulation.update();
+        }
+        assert_eq!(simulation.stats().exited, 1);
+        assert_eq!(
+            simulation.trips[Direction::Up.index()][Direction::Up.index()],
+            1
+        );
+        let (shortest, _) = simulation.crossing_ticks.unwrap();
+        assert_eq!(shortest, simulation.tick);
+
+        // Past the window, so only seen zoomed out.
+        let mut debug = DebugOverlay::default();
+        debug
+            .camera
+            .zoom_about(0.5, (CENTER_X as f32, CENTER_Y as f32));
+        let faded = |simulation: &TrafficSimulation| {
+            let mut recorder = RecordingRenderer::default();
+            simulation.render(&mut recorder, 1.0, &debug).unwrap();
+            recorder.calls.into_iter().find_map(|call| match call {
+                DrawCall::FillRect(rect, drawn) if drawn.rgb() == color.rgb() => {
+                    Some((rect.height(), drawn.a))
+                }
+                _ => None,
+            })
+        };
+        let (height, alpha) = faded(&simulation).unwrap();
+        simulation.run_ticks(FADE_TICKS as u64 / 2);
+        let (smaller, fainter) = faded(&simulation).unwrap();
+        assert!(smaller < height && fainter < alpha);
+        assert!(simulation.fades[0].vehicle.y < 0.0);
+
+        // A restore picks the fade up where it was.
+        let mut restored = TrafficSimulation::new(simulation.config.clone());
+        restored.restore(&simulation.snapshot()).unwrap();
+        assert_eq!(faded(&restored), faded(&simulation));
+        restored.run_ticks(FADE_TICKS as u64 / 2);
+        assert!(restored.fades.is_empty());
+        simulation.run_ticks(FADE_TICKS as u64 / 2);
+        assert!(simulation.fades.is_empty());
+        assert_eq!(faded(&simulation), None);
+    }
+
+    #[test]
+    fn busy_traffic_keeps_the_invariants() {
+        let config = Config {
+            yellow: 1.0,
+            all_red: 1.0,
+            truck_share: 0.3,
+            seed: Some(11),
+            check_invariants: true,
+            invariant_panic: true,
+            ..Config::default()
+        };
+        let mut simulation = TrafficSimulation::new(config);
+        for tick in 0..3000 {
+            if tick % 25 == 0 {
+                simulation.spawn_vehicle(Direction::ALL[tick / 25 % 4]);
+            }
+            simulation.update();
+        }
+    }
+
+    #[test]
+    fn broken_invariants_name_the_vehicles() {
+        let mut simulation = TrafficSimulation::new(Config::default());
+        simulation.spawn_vehicle(Direction::Down);
+        simulation.vehicles[0].y += 100.0;
+        simulation.spawn_vehicle(Direction::Down);
+        let copy = simulation.vehicles[0].clone();
+        simulation.vehicles[1].y = copy.y + 5.0;
+        simulation.vehicles[0].x += 12.0;
+
+        let violations = simulation.invariant_violations();
+        assert_eq!(
+            violations,
+            [
+                "vehicles 0 and 1 overlap in the same lane",
+                "vehicle 0 is 12.0px off its lane"
+            ]
+        );
+    }
+
+    #[test]
+    fn burst_fills_every_approach_without_overlaps() {
+        let config = Config {
+            burst_size: 3,
+            seed: Some(5),
+            ..Config::default()
+        };
+        let mut simulation = TrafficSimulation::new(config);
+        simulation.queue_burst();
+        assert_eq!(simulation.pending_burst, [3; 4]);
+
+        for _ in 0..2000 {
+            let first_new_id = simulation.next_id;
+            simulation.update();
+            for vehicle in simulation.vehicles.iter().filter(|v| v.id >= first_new_id) {
+                assert!(
+                    simulation
+                        .vehicles
+                        .iter()
+                        .all(|other| other.id == vehicle.id || !vehicle.overlaps(other))
+                );
+            }
+        }
+        assert_eq!(simulation.pending_burst, [0; 4]);
+        assert_eq!(simulation.next_id, 12);
+        assert_eq!(simulation.refused_spawns, [0; 4]);
+    }
+
+    #[test]
+    fn center_line_dashes_stop_at_the_intersection_box() {
+        let half_road = ROAD_WIDTH as i32 / 2;
+        let intersection = Rect::new(
+            CENTER_X - half_road,
+            CENTER_Y - half_road,
+            ROAD_WIDTH,
+            ROAD_WIDTH,
+        );
+        for layout in [Layout::Plus, Layout::parse("t-junction").unwrap()] {
+            let dashes = center_line_dashes(World::default(), layout);
+            for dash in &dashes {
+                let apart = dash.right() <= intersection.left()
+                    || dash.left() >= intersection.right()
+                    || dash.bottom() <= intersection.top()
+                    || dash.top() >= intersection.bottom();
+                assert!(apart, "{:?}", dash);
+            }
+            // Dashes above the box only when the layout has a top arm.
+            let above = dashes
+                .iter()
+                .filter(|dash| dash.bottom() <= intersection.top());
+            assert_eq!(above.count() > 0, layout.has_arm(Direction::Up));
+        }
+    }
+
+    #[test]
+    fn only_rects_touching_the_window_are_drawn() {
+        let camera = Camera::default();
+        assert!(on_screen(Rect::new(-39, 10, 40, 40), camera));
+        assert!(!on_screen(Rect::new(-40, 10, 40, 40), camera));
+        assert!(on_screen(
+            Rect::new(WINDOW_WIDTH as i32 - 1, WINDOW_HEIGHT as i32 - 1, 40, 40),
+            camera
+        ));
+        assert!(!on_screen(
+            Rect::new(10, WINDOW_HEIGHT as i32, 40, 40),
+            camera
+        ));
+
+        // Zoomed in on the middle, the corners of the window drop out.
+        let mut zoomed = camera;
+        zoomed.zoom_about(4.0, (CENTER_X as f32, CENTER_Y as f32));
+        assert!(!on_screen(Rect::new(10, 10, 40, 40), zoomed));
+        assert!(on_screen(Rect::new(CENTER_X, CENTER_Y, 40, 40), zoomed));
+    }
+
+    #[test]
+    fn coincident_spawns_are_delayed_a_tick() {
+        let mut simulation = TrafficSimulation::new(Config::default());
+        simulation.queue_burst();
+        for _ in 0..600 {
+            simulation.update();
+            for (index, vehicle) in simulation.vehicles.iter().enumerate() {
+                assert!(
+                    simulation.vehicles[index + 1..]
+                        .iter()
+                        .all(|other| (other.x, other.y) != (vehicle.x, vehicle.y))
+                );
+            }
+        }
+
+        // Force a twin past the spawn checks.
+        let mut simulation = TrafficSimulation::new(Config::default());
+        simulation.spawn_vehicle(Direction::Left);
+        let mut twin = simulation.vehicles[0].clone();
+        twin.id = 1;
+        simulation.vehicles.push(twin);
+        simulation.delay_coincident_spawns();
+        assert_eq!(simulation.vehicles.len(), 1);
+        assert_eq!(simulation.pending_burst[Direction::Left.index()], 1);
+        assert_eq!(simulation.despawned, [0, 0, 1]);
+    }
+
+    #[test]
+    fn phase_offset_starts_the_plan_part_way_through() {
+        let simulation = TrafficSimulation::new(Config {
+            phase_offset: 5.5,
+            ..Config::default()
+        });
+        assert_eq!(simulation.lights.phase(), 1);
+        assert_eq!(simulation.lights.elapsed(), 0.5);
+    }
+
+    #[test]
+    fn runs_can_start_in_any_phase() {
+        let simulation = TrafficSimulation::new(Config {
+            initial_phase: 1,
+            ..Config::default()
+        });
+        assert_eq!(simulation.lights.phase(), 1);
+        assert_eq!(simulation.lights.elapsed(), 0.0);
+
+        let start = |seed: u64| {
+            let simulation = TrafficSimulation::new(Config {
+                seed: Some(seed),
+                random_phase_offset: true,
+                ..Config::default()
+            });
+            (simulation.lights.phase(), simulation.lights.elapsed())
+        };
+        assert_eq!(start(3), start(3));
+        let phases: HashSet<usize> = (0..20).map(|seed| start(seed).0).collect();
+        assert_eq!(phases.len(), Config::default().light_plan.phases.len());
+    }
+
+    #[test]
+    fn despawns_are_counted_by_reason() {
+        let mut simulation = TrafficSimulation::new(Config {
+            collision_policy: CollisionPolicy::Remove,
+            ..Config::default()
+        });
+        simulation.spawn_vehicle(Direction::Up);
+        simulation.spawn_vehicle(Direction::Left);
+        // Park a third vehicle on top of the second.
+        let mut wreck = simulation.vehicles[1].clone();
+        wreck.id = 99;
+        wreck.x -= 10.0;
+        simulation.vehicles.push(wreck);
+
+        for _ in 0..2000 {
+            simulation.update();
+        }
+        assert!(simulation.vehicles.is_empty());
+        assert_eq!(simulation.despawned, [1, 2, 0]);
+        assert_eq!(simulation.exited.iter().sum::<usize>(), 1);
+    }
+
+    #[test]
+    fn vehicles_stop_at_the_drawn_stop_line() {
+        let mut simulation = TrafficSimulation::new(Config {
+            light_plan: lights::LightPlan::parse("none:100").unwrap(),
+            stop_lines: [100.0, 80.0, 150.0, 60.0],
+            ..Config::default()
+        });
+        for approach in Direction::ALL {
+            simulation.spawn_vehicle(approach);
+        }
+        for _ in 0..600 {
+            simulation.update();
+        }
+        for vehicle in &simulation.vehicles {
+            let approach = vehicle.initial_direction;
+            let line = stop_line_rect(
+                World::default(),
+                approach,
+                simulation.config.stop_lines[approach.index()],
+            );
+            let (half_width, half_height) = vehicle.half_extents();
+            let front = match approach {
+                Direction::Up => (vehicle.y - half_height) as i32 - line.top(),
+                Direction::Down => (vehicle.y + half_height) as i32 - line.bottom(),
+                Direction::Right => (vehicle.x + half_width) as i32 - line.right(),
+                Direction::Left => (vehicle.x - half_width) as i32 - line.left(),
+            };
+            assert_eq!(front, 0, "{}", approach);
+        }
+    }
+
+    fn record_frame(simulation: &TrafficSimulation) -> RecordingRenderer {
+        let mut recorder = RecordingRenderer::default();
+        simulation
+            .render(&mut recorder, 1.0, &DebugOverlay::default())
+            .unwrap();
+        recorder
+    }
+
+    #[test]
+    fn frame_draws_vehicles_and_light_states() {
+        let mut simulation = TrafficSimulation::new(Config {
+            seed: Some(1),
+            ..Config::default()
+        });
+        simulation
+            .spawn_vehicle_with(Direction::Up, VEHICLE_SPEED, Some(Route::Straight))
+            .unwrap();
+        simulation
+            .spawn_vehicle_with(Direction::Left, VEHICLE_SPEED, Some(Route::TurnLeft))
+            .unwrap();
+        for _ in 0..30 {
+            simulation.update();
+        }
+        let frame = record_frame(&simulation);
+
+        let straight = Color::RGB(200, 200, 200);
+        let left_turn = Color::RGB(100, 255, 100);
+        let vehicles: Vec<DrawCall> = frame
+            .calls
+            .iter()
+            .copied()
+            .filter(|call| {
+                matches!(call, DrawCall::FillRect(_, color) if *color == straight || *color == left_turn)
+            })
+            .collect();
+        assert_eq!(
+            vehicles,
+            [
+                DrawCall::FillRect(Rect::new(450, 720, 40, 40), straight),
+                DrawCall::FillRect(Rect::new(920, 350, 40, 40), left_turn),
+            ],
+            "{}",
+            frame.dump()
+        );
+
+        // Up and down start on green, the cross street on red; each lit lamp
+        // is a filled circle of 17 lines.
+        let lamp_lines = |lamp: Color| {
+            frame
+                .calls
+                .iter()
+                .filter(|call| matches!(call, DrawCall::Line(_, _, color) if *color == lamp))
+                .count()
+        };
+        assert_eq!(lamp_lines(Color::RGB(0, 255, 0)), 2 * 17);
+        assert_eq!(lamp_lines(Color::RGB(255, 0, 0)), 2 * 17);
+        assert_eq!(lamp_lines(Color::RGB(255, 200, 0)), 0);
+        assert_eq!(frame.calls.last(), Some(&DrawCall::Present));
+    }
+
+    #[test]
+    fn vehicles_are_drawn_as_turned_sprites_unless_asked_for_rectangles() {
+        let frame = |simple_render: bool| {
+            let mut simulation = TrafficSimulation::new(Config {
+                seed: Some(1),
+                simple_render,
+                ..Config::default()
+            });
+            simulation
+                .spawn_vehicle_with(Direction::Up, VEHICLE_SPEED, Some(Route::Straight))
+                .unwrap();
+            simulation
+                .spawn_kind(
+                    Direction::Left,
+                    VEHICLE_SPEED,
+                    Some(Route::TurnLeft),
+                    Some(VehicleKind::Truck),
+                )
+                .unwrap();
+            for _ in 0..30 {
+                simulation.update();
+            }
+            let mut recorder = RecordingRenderer::default();
+            recorder.sprites = true;
+            simulation
+                .render(&mut recorder, 1.0, &DebugOverlay::default())
+                .unwrap();
+            recorder
+        };
+
+        let sprites = frame(false);
+        let drawn: Vec<DrawCall> = sprites
+            .calls
+            .iter()
+            .copied()
+            .filter(|call| matches!(call, DrawCall::Sprite(..)))
+            .collect();
+        // The car heads up the screen, the truck comes in from the right
+        // heading left, each at its own size and tinted by route.
+        assert_eq!(
+            drawn,
+            [
+                DrawCall::Sprite(
+                    VehicleKind::Car,
+                    (470, 740),
+                    (40, 40),
+                    -90,
+                    Color::RGB(200, 200, 200)
+                ),
+                DrawCall::Sprite(
+                    VehicleKind::Truck,
+                    (952, 370),
+                    (64, 40),
+                    180,
+                    Color::RGB(100, 255, 100)
+                ),
+            ],
+            "{}",
+            sprites.dump()
+        );
+        assert!(!sprites.calls.contains(&DrawCall::FillRect(
+            Rect::new(450, 720, 40, 40),
+            Color::RGB(200, 200, 200)
+        )));
+
+        let rectangles = frame(true);
+        assert!(
+            !rectangles
+                .calls
+                .iter()
+                .any(|call| matches!(call, DrawCall::Sprite(..)))
+        );
+        assert!(rectangles.calls.contains(&DrawCall::FillRect(
+            Rect::new(450, 720, 40, 40),
+            Color::RGB(200, 200, 200)
+        )));
+    }
+
+    #[test]
+    fn bent_approaches_draw_vehicles_along_the_curve() {
+        let frame = |approach_bend: f32| {
+            let mut simulation = TrafficSimulation::new(Config {
+                seed: Some(1),
+                approach_bend,
+                ..Config::default()
+            });
+            simulation
+                .spawn_vehicle_with(Direction::Up, VEHICLE_SPEED, Some(Route::Straight))
+                .unwrap();
+            for _ in 0..30 {
+                simulation.update();
+            }
+            record_frame(&simulation)
+        };
+        let (straight, bent) = (frame(0.0), frame(100.0));
+        let body = |frame: &RecordingRenderer| -> Vec<Rect> {
+            frame
+                .calls
+                .iter()
+                .filter_map(|call| match call {
+                    DrawCall::FillRect(rect, color) if *color == Color::RGB(200, 200, 200) => {
+                        Some(*rect)
+                    }
+                    _ => None,
+                })
+                .collect()
+        };
+        assert_eq!(body(&straight), [Rect::new(450, 720, 40, 40)]);
+        // 340px out, 240px into a 300px curve: 0.8 squared of the way over,
+        // to the left on the bottom arm, in 4px slices.
+        let slices = body(&bent);
+        assert_eq!(slices.len(), 10);
+        assert!(
+            slices
+                .iter()
+                .all(|slice| slice.y() == 720 && slice.height() == 40)
+        );
+        assert_eq!(slices[0].x(), 450 - 64);
+        // The lights beside the box stay where they were.
+        let lamps = |frame: &RecordingRenderer| -> Vec<DrawCall> {
+            frame
+                .calls
+                .iter()
+                .filter(|call| matches!(call, DrawCall::Line(_, _, color) if *color == Color::RGB(0, 255, 0)))
+                .copied()
+                .collect()
+        };
+        assert_eq!(lamps(&straight), lamps(&bent));
+
+        let sharp = Config::from_args(["--approach-bend", "200"].into_iter().map(String::from));
+        assert!(sharp.is_err());
+    }
+
+    #[test]
+    fn debug_overlay_outlines_the_selected_vehicle() {
+        let mut simulation = TrafficSimulation::new(Config::default());
+        simulation.spawn_vehicle(Direction::Down);
+        simulation.update();
+        let plain = record_frame(&simulation);
+
+        let mut recorder = RecordingRenderer::default();
+        let debug = DebugOverlay {
+            selected: Some(0),
+            ..DebugOverlay::default()
+        };
+        simulation.render(&mut recorder, 1.0, &debug).unwrap();
+
+        let outline = DrawCall::DrawRect(
+            simulation.vehicles[0].get_rect(1.0),
+            Color::RGB(255, 255, 0),
+        );
+        assert!(!plain.calls.contains(&outline));
+        assert!(recorder.calls.contains(&outline), "{}", recorder.dump());
+    }
+
+    #[test]
+    fn banned_movements_are_never_assigned() {
+        let mut simulation = TrafficSimulation::new(Config {
+            banned_movements: vec![
+                Movement::parse("up-left").unwrap(),
+                Movement::parse("left-straight").unwrap(),
+            ],
+            seed: Some(5),
+            ..Config::default()
+        });
+        for _ in 0..200 {
+            for approach in [Direction::Up, Direction::Left] {
+                simulation.spawn_vehicle(approach);
+            }
+            for _ in 0..30 {
+                simulation.update();
+            }
+        }
+        for vehicle in &simulation.vehicles {
+            let movement = Movement {
+                approach: vehicle.initial_direction,
+                route: vehicle.route,
+            };
+            assert!(simulation.config.permits(movement), "{}", movement.name());
+        }
+        assert!(
+            simulation
+                .spawn_vehicle_with(Direction::Up, 2.0, Some(Route::TurnLeft))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn satellite_asphalt_spans_the_window_and_covers_the_box() {
+        let [horizontal, vertical] = asphalt_rects(World::default(), Layout::Plus);
+        assert_eq!(
+            horizontal,
+            Rect::new(0, CENTER_Y - 60, WINDOW_WIDTH, ROAD_WIDTH)
+        );
+        assert_eq!(
+            vertical,
+            Rect::new(CENTER_X - 60, 0, ROAD_WIDTH, WINDOW_HEIGHT)
+        );
+
+        // Without a top arm the vertical strip starts at the box, which the
+        // horizontal strip still covers, and a kerb closes it off.
+        let t_junction = Layout::parse("t-junction").unwrap();
+        let [_, vertical] = asphalt_rects(World::default(), t_junction);
+        assert_eq!(vertical.top(), CENTER_Y - 60);
+        let edges = arm_edges(World::default(), t_junction);
+        assert_eq!(edges.len(), 3 * 2 + 1);
+        assert!(edges.contains(&(
+            (CENTER_X - 60, CENTER_Y - 60),
+            (CENTER_X + 60, CENTER_Y - 60)
+        )));
+
+        let mut simulation = TrafficSimulation::new(Config {
+            road_style: RoadStyle::Satellite,
+            ..Config::default()
+        });
+        simulation.update();
+        let frame = record_frame(&simulation);
+        let asphalt = Color::RGB(72, 72, 76);
+        assert_eq!(frame.calls[0], DrawCall::Clear(Color::RGB(48, 82, 44)));
+        assert!(
+            frame
+                .calls
+                .contains(&DrawCall::FillRect(horizontal, asphalt))
+        );
+    }
+
+    #[test]
+    fn minimap_shows_the_world_and_a_dot_per_vehicle() {
+        let mut simulation = TrafficSimulation::new(Config::default());
+        simulation.spawn_vehicle(Direction::Right);
+        simulation.update();
+        let mut recorder = RecordingRenderer::default();
+        let debug = DebugOverlay {
+            minimap: true,
+            ..DebugOverlay::default()
+        };
+        simulation.render(&mut recorder, 1.0, &debug).unwrap();
+
+        let frame = to_minimap(
+            World::default(),
+            Rect::new(0, 0, WINDOW_WIDTH, WINDOW_HEIGHT),
+        );
+        assert_eq!(frame.right(), WINDOW_WIDTH as i32 - MINIMAP_MARGIN);
+        assert_eq!((frame.width(), frame.height()), (150, 120));
+        let vehicle = &simulation.vehicles[0];
+        let dot = to_minimap(
+            World::default(),
+            Rect::new(vehicle.x as i32, vehicle.y as i32, 1, 1),
+        );
+        let dot = DrawCall::FillRect(Rect::new(dot.x() - 1, dot.y() - 1, 3, 3), vehicle.color);
+        assert!(recorder.calls.contains(&dot), "{}", recorder.dump());
+        assert!(
+            recorder
+                .calls
+                .contains(&DrawCall::DrawRect(frame, Color::RGB(255, 255, 0)))
+        );
+        assert!(!record_frame(&simulation).calls.contains(&dot));
+    }
+
+    #[test]
+    fn the_hud_shows_counts_collisions_phase_time_and_frame_rate_when_toggled() {
+        let mut simulation = TrafficSimulation::new(Config::default());
+        simulation.spawn_vehicle(Direction::Up);
+        simulation.spawn_vehicle(Direction::Down);
+        let debug = DebugOverlay {
+            hud: true,
+            fps: 59.6,
+            ..DebugOverlay::default()
+        };
+        let mut recorder = RecordingRenderer::default();
+        simulation.render(&mut recorder, 1.0, &debug).unwrap();
+        let hud = |recorder: &RecordingRenderer, color: Color| {
+            recorder
+                .calls
+                .iter()
+                .filter(|call| {
+                    matches!(call, DrawCall::FillRect(rect, c)
+                        if *c == color && rect.x() < 100 && rect.y() > WINDOW_HEIGHT as i32 - 140)
+                })
+                .count()
+        };
+        // Five segments for the 2 vehicles, two each for the 1 up and 1
+        // down and six each for the empty approaches, six for no
+        // collisions, six for phase 0, six
+        // for 0 seconds so far and six each for 60 frames a second.
+        assert_eq!(hud(&recorder, Color::RGB(255, 255, 255)), 5);
+        assert_eq!(hud(&recorder, Color::RGB(160, 160, 160)), 2 + 2 + 6 + 6);
+        assert_eq!(hud(&recorder, Color::RGB(255, 60, 60)), 6);
+        assert_eq!(hud(&recorder, Color::RGB(0, 255, 0)), 6);
+        assert_eq!(hud(&recorder, Color::RGB(255, 0, 255)), 6);
+        assert_eq!(hud(&recorder, Color::RGB(0, 255, 255)), 6 + 6);
+        assert_eq!(hud(&record_frame(&simulation), Color::RGB(0, 255, 255)), 0);
+    }
+
+    #[test]
+    fn footprint_turns_with_the_vehicle() {
+        let mut simulation = TrafficSimulation::new(Config {
+            vehicle_length: 50.0,
+            vehicle_width: 30.0,
+            ..Config::default()
+        });
+        simulation
+            .spawn_vehicle_with(Direction::Right, VEHICLE_SPEED, Some(Route::TurnLeft))
+            .unwrap();
+        assert_eq!(simulation.vehicles[0].half_extents(), (25.0, 15.0));
+        let rect = simulation.vehicles[0].get_rect(1.0);
+        assert_eq!((rect.width(), rect.height()), (50, 30));
+
+        while simulation.vehicles[0].direction == Direction::Right {
+            simulation.update();
+        }
+        let vehicle = &simulation.vehicles[0];
+        assert_eq!(vehicle.direction, Direction::Up);
+        assert_eq!(vehicle.half_extents(), (15.0, 25.0));
+        let rect = vehicle.get_rect(1.0);
+        assert_eq!((rect.width(), rect.height()), (30, 50));
+    }
+
+    #[test]
+    fn tighter_turns_take_longer() {
+        let turn_ticks = |radius: f32| {
+            let mut simulation = TrafficSimulation::new(Config {
+                light_plan: lights::LightPlan::parse("right+left:100").unwrap(),
+                right_turn_radius: radius,
+                ..Config::default()
+            });
+            simulation
+                .spawn_vehicle_with(Direction::Right, VEHICLE_SPEED, Some(Route::TurnRight))
+                .unwrap();
+            let mut ticks = 0;
+            while let Some(vehicle) = simulation.vehicles.first() {
+                if vehicle.overlaps_intersection() {
+                    ticks += 1;
+                }
+                simulation.update();
+            }
+            ticks
+        };
+
+        let uncapped = turn_ticks(0.0);
+        let wide = turn_ticks(40.0);
+        let tight = turn_ticks(10.0);
+        let tighter = turn_ticks(2.5);
+        assert_eq!(wide, uncapped);
+        assert!(
+            tight > wide && tighter > tight,
+            "{} {} {}",
+            wide,
+            tight,
+            tighter
+        );
+        // Turn speed goes with the square root of the radius, so a quarter of
+        // the radius takes about twice as long at the lower speed.
+        let ratio = tighter as f32 / tight as f32;
+        assert!((1.7..2.2).contains(&ratio), "{}", ratio);
+    }
+
+    #[test]
+    fn requested_routes_are_honoured_unless_banned() {
+        let mut simulation = TrafficSimulation::new(Config {
+            banned_movements: vec![Movement::parse("down-right").unwrap()],
+            ..Config::default()
+        });
+        assert!(simulation.request_spawn(Direction::Up, Some(Route::TurnLeft), 0));
+        assert_eq!(simulation.vehicles[0].route, Route::TurnLeft);
+        assert!(!simulation.request_spawn(Direction::Down, Some(Route::TurnRight), 0));
+        assert!(simulation.request_spawn(Direction::Down, Some(Route::Straight), 0));
+        assert_eq!(simulation.vehicles[1].route, Route::Straight);
+    }
+
+    #[test]
+    fn drawn_heading_eases_round_a_turn() {
+        let heading_after_turn = |smooth_headings: bool| {
+            let mut simulation = TrafficSimulation::new(Config {
+                light_plan: lights::LightPlan::parse("right+left:100").unwrap(),
+                smooth_headings,
+                ..Config::default()
+            });
+            simulation
+                .spawn_vehicle_with(Direction::Right, VEHICLE_SPEED, Some(Route::TurnRight))
+                .unwrap();
+            while simulation.vehicles[0].direction == Direction::Right {
+                simulation.update();
+            }
+            let mut headings = vec![simulation.vehicles[0].render_heading];
+            for _ in 0..20 {
+                simulation.update();
+                headings.push(simulation.vehicles[0].render_heading);
+            }
+            headings
+        };
+
+        let down = Direction::Down.angle();
+        let eased = heading_after_turn(true);
+        assert!(eased[0] > 0.0 && eased[0] < down, "{:?}", eased);
+        assert!(eased.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(*eased.last().unwrap(), down);
+        assert!(
+            heading_after_turn(false)
+                .iter()
+                .all(|&heading| heading == down)
+        );
+    }
+
+    #[test]
+    fn soak_runs_clean() {
+        let mut simulation = TrafficSimulation::new(Config {
+            seed: Some(0),
+            ..Config::default()
+        });
+        assert_eq!(simulation.soak(120.0), Vec::<String>::new());
+        assert!(simulation.despawned[0] > 50, "{:?}", simulation.despawned);
+    }
+
+    #[test]
+    fn soak_runs_clean_with_the_tightest_gaps() {
+        for seed in [2, 4] {
+            let mut simulation = TrafficSimulation::new(Config {
+                seed: Some(seed),
+                base_gap: MIN_BASE_GAP,
+                gap_per_speed: 0.0,
+                ..Config::default()
+            });
+            assert_eq!(simulation.soak(120.0), Vec::<String>::new());
+        }
+    }
+
+    #[test]
+    fn checks_shared_among_threads_come_back_in_order() {
+        let square = |index: usize| index * index;
+        let serial: Vec<usize> = (0..1000).map(square).collect();
+        for threads in [1, 2, 3, 8, 64] {
+            assert_eq!(check_each(1000, threads, square), serial, "{}", threads);
+        }
+        assert_eq!(check_each(0, 4, square), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn a_resized_world_soaks_clean() {
+        let mut simulation = TrafficSimulation::new(Config {
+            seed: Some(3),
+            width: 1400,
+            height: 900,
+            road_width: 160,
+            stop_lines: [80.0; 4],
+            ..Config::default()
+        });
+        assert_eq!(simulation.soak(120.0), Vec::<String>::new());
+        assert!(simulation.despawned[0] > 50, "{:?}", simulation.despawned);
+        // Everyone still on the road was placed in this world.
+        assert!(
+            simulation
+                .vehicles
+                .iter()
+                .all(|vehicle| vehicle.world == simulation.config.world())
+        );
+    }
+
+    #[test]
+    fn wider_lanes_soak_clean_along_their_own_centres() {
+        let mut simulation = TrafficSimulation::new(Config {
+            seed: Some(3),
+            road_width: 160,
+            lane_width: 45,
+            stop_lines: [80.0; 4],
+            check_lanes: true,
+            ..Config::default()
+        });
+        assert_eq!(simulation.soak(120.0), Vec::<String>::new());
+        assert!(simulation.despawned[0] > 50, "{:?}", simulation.despawned);
+        let world = simulation.config.world();
+        assert_eq!(lane_centre(world, Direction::Up), CENTER_X as f32 - 45.0);
+        assert!(simulation.off_lane.is_empty(), "{:?}", simulation.off_lane);
+    }
+
+    #[test]
+    fn rain_slows_everyone_and_lengthens_their_braking() {
+        let mut simulation = TrafficSimulation::new(Config {
+            light_plan: lights::LightPlan::parse("up:100").unwrap(),
+            ..Config::default()
+        });
+        simulation.spawn_vehicle(Direction::Up);
+        for _ in 0..30 {
+            simulation.update();
+        }
+        let dry = simulation.vehicles[0].clone();
+        assert_eq!(dry.velocity, dry.cruise_speed);
+
+        // Everyone already on the road feels it, not only new arrivals.
+        simulation.set_weather(Weather::Rain);
+        assert_eq!(simulation.config.weather, Weather::Rain);
+        for _ in 0..60 {
+            simulation.update();
+        }
+        let wet = &simulation.vehicles[0];
+        let config = &simulation.config;
+        assert_eq!(wet.velocity, dry.cruise_speed * config.rain_speed_factor);
+        let dry_stop =
+            wet.velocity.powi(2) * config.tick_rate.powi(2) / (2.0 * wet.kind.deceleration());
+        assert_eq!(
+            wet.stopping_distance(),
+            dry_stop / config.rain_braking_factor
+        );
+        assert_eq!(simulation.stats().weather, "rain");
+
+        simulation.set_weather(Weather::Clear);
+        assert_eq!(simulation.vehicles[0].traction, Traction::DRY);
+    }
+
+    #[test]
+    fn rain_soaks_clean_and_gets_fewer_through() {
+        let soak = |weather| {
+            let mut simulation = TrafficSimulation::new(Config {
+                seed: Some(1),
+                weather,
+                ..Config::default()
+            });
+            assert_eq!(simulation.soak(120.0), Vec::<String>::new());
+            simulation.despawned[0]
+        };
+        let (clear, rain) = (soak(Weather::Clear), soak(Weather::Rain));
+        assert!(rain < clear, "{} {}", rain, clear);
+    }
+
+    #[test]
+    fn night_darkens_the_frame_and_rain_streaks_it() {
+        let shade = |frame: &RecordingRenderer| {
+            frame.calls.iter().find_map(|call| match call {
+                DrawCall::FillRect(rect, color)
+                    if *rect == Rect::new(0, 0, WINDOW_WIDTH, WINDOW_HEIGHT) =>
+                {
+                    Some(color.a)
+                }
+                _ => None,
+            })
+        };
+        let streaks = |frame: &RecordingRenderer| {
+            frame
+                .calls
+                .iter()
+                .filter(|call| matches!(call, DrawCall::Line(_, _, color) if color.b == 230))
+                .count()
+        };
+        let noon = TrafficSimulation::new(Config::default());
+        assert_eq!(shade(&record_frame(&noon)), None);
+        assert_eq!(streaks(&record_frame(&noon)), 0);
+
+        // Half a day on from noon.
+        let mut simulation = TrafficSimulation::new(Config {
+            day_length: 2.0,
+            weather: Weather::Rain,
+            ..Config::default()
+        });
+        simulation.spawn_vehicle(Direction::Up);
+        simulation.run_ticks(60);
+        assert_eq!(simulation.hour_of_day(), 0.0);
+        let frame = record_frame(&simulation);
+        assert_eq!(shade(&frame), Some(170));
+        assert_eq!(streaks(&frame), 160);
+        // The lamps and the headlights come after the dark.
+        let dark = frame
+            .calls
+            .iter()
+            .position(
+                |call| matches!(call, DrawCall::FillRect(rect, _) if rect.width() == WINDOW_WIDTH),
+            )
+            .unwrap();
+        assert!(frame.calls[dark..].iter().any(|call| matches!(
+            call,
+            DrawCall::FillRect(_, color) if *color == Color::RGBA(255, 240, 190, 72)
+        )));
+
+        simulation.advance_clock(12.0);
+        assert_eq!(simulation.hour_of_day(), 12.0);
+        assert_eq!(shade(&record_frame(&simulation)), None);
+    }
+
+    #[test]
+    fn collisions_are_graded_by_closing_speed() {
+        let mut simulation = TrafficSimulation::new(Config::default());
+        // Crossing traffic meeting at full speed in the middle of the box.
+        let crossing = (CENTER_X as f32, CENTER_Y as f32);
+        for direction in [Direction::Up, Direction::Right] {
+            simulation.spawn_vehicle(direction);
+            let vehicle = simulation.vehicles.last_mut().unwrap();
+            (vehicle.x, vehicle.y) = crossing;
+        }
+        // A follower nudging into a slow leader.
+        simulation.spawn_vehicle(Direction::Left);
+        let mut leader = simulation.vehicles[2].clone();
+        leader.id = 99;
+        leader.x -= 30.0;
+        leader.velocity = 1.5;
+        simulation.vehicles.push(leader);
+
+        let pairs = simulation.detect_collisions();
+        simulation.handle_collisions(&pairs);
+        assert_eq!(simulation.collisions, 2);
+        assert_eq!(simulation.collision_severity, [1, 1]);
+        assert_eq!(simulation.flashes.len(), 2);
+
+        let frame = record_frame(&simulation);
+        let major = simulation
+            .flashes
+            .iter()
+            .find(|flash| flash.relative_speed >= MAJOR_IMPACT_SPEED)
+            .unwrap();
+        let flash = DrawCall::FillRect(flash_rect(major), Color::RGBA(255, 40, 0, 255));
+        assert!(frame.calls.contains(&flash), "{}", frame.dump());
+    }
+
+    #[test]
+    fn a_crossing_crash_at_cruise_speed_is_major_at_any_tick_rate() {
+        for tick_rate in [30.0, 60.0, 120.0] {
+            let mut simulation = TrafficSimulation::new(Config {
+                tick_rate,
+                ..Config::default()
+            });
+            for direction in [Direction::Up, Direction::Right] {
+                simulation.spawn_vehicle(direction);
+                let vehicle = simulation.vehicles.last_mut().unwrap();
+                (vehicle.x, vehicle.y) = (CENTER_X as f32, CENTER_Y as f32);
+            }
+            let pairs = simulation.detect_collisions();
+            simulation.handle_collisions(&pairs);
+            assert_eq!(simulation.collision_severity, [0, 1], "at {} Hz", tick_rate);
+            let size = flash_rect(&simulation.flashes[0]).width();
+            assert_eq!(size, 62, "at {} Hz", tick_rate);
+        }
+    }
+
+    #[test]
+    fn vehicles_can_leave_as_soon_as_they_clear_the_box() {
+        let mut simulation = TrafficSimulation::new(Config {
+            despawn_at_box_exit: true,
+            light_plan: lights::LightPlan::parse("up+down:100").unwrap(),
+            ..Config::default()
+        });
+        simulation
+            .spawn_vehicle_with(Direction::Up, VEHICLE_SPEED, Some(Route::TurnLeft))
+            .unwrap();
+        let mut last = simulation.vehicles[0].clone();
+        while let Some(vehicle) = simulation.vehicles.first() {
+            assert!(!vehicle.cleared_intersection());
+            last = vehicle.clone();
+            simulation.update();
+        }
+        // Gone one step past the box, far from the screen edge.
+        assert!(last.overlaps_intersection());
+        assert!(!last.has_left(World::default().bounds()) && last.x > 100.0);
+        assert_eq!(simulation.exited[Direction::Up.index()], 1);
+        assert_eq!(simulation.despawned, [1, 0, 0]);
+    }
+
+    #[test]
+    fn random_spawns_follow_the_approach_weights() {
+        let mut simulation = TrafficSimulation::new(Config {
+            seed: Some(2),
+            approach_weights: [0.0, 3.0, 1.0, 0.0],
+            ..Config::default()
+        });
+        let mut counts = [0; 4];
+        for index in 0..400 {
+            let before = simulation.vehicles.len();
+            assert!(simulation.request_random_spawn(index * 2000));
+            counts[simulation.vehicles[before].initial_direction.index()] += 1;
+            simulation.vehicles.clear();
+        }
+        assert_eq!(counts[0] + counts[3], 0);
+        assert!((250..350).contains(&counts[1]), "{:?}", counts);
+
+        let weights = |value: &str| {
+            Config::from_args(["--approach-weights", value].into_iter().map(String::from))
+        };
+        assert!(weights("up:-1").is_err());
+        assert!(weights("up:0,down:0,right:0,left:0").is_err());
+        assert_eq!(
+            weights("left:2").unwrap().approach_weights,
+            [1.0, 1.0, 1.0, 2.0]
+        );
+    }
+
+    #[test]
+    fn unsignalled_modes_run_without_collisions() {
+        for (layout, control_mode) in [
+            (Layout::Plus, ControlMode::AllWayStop),
+            (Layout::Plus, ControlMode::Uncontrolled),
+            (Layout::Roundabout, ControlMode::Lights),
+        ] {
+            let mut simulation = TrafficSimulation::new(Config {
+                layout,
+                control_mode,
+                seed: Some(4),
+                ..Config::default()
+            });
+            assert_eq!(
+                simulation.soak(120.0),
+                Vec::<String>::new(),
+                "{:?}",
+                (layout, control_mode)
+            );
+            assert!(simulation.despawned[0] > 20, "{:?}", simulation.despawned);
+        }
+    }
+
+    #[test]
+    fn roundabouts_give_way_to_the_left_and_are_drawn_round_the_island() {
+        // Up meets traffic heading right, which comes from its left.
+        let first_in = |layout: Layout| {
+            let mut simulation = TrafficSimulation::new(Config {
+                layout,
+                control_mode: ControlMode::Uncontrolled,
+                ..Config::default()
+            });
+            for direction in [Direction::Up, Direction::Right] {
+                simulation
+                    .spawn_vehicle_with(direction, VEHICLE_SPEED, Some(Route::Straight))
+                    .unwrap();
+            }
+            simulation.vehicles[0].y = CENTER_Y as f32 + 130.0;
+            simulation.vehicles[1].x = CENTER_X as f32 - 130.0;
+            loop {
+                simulation.update();
+                if let Some(vehicle) = simulation
+                    .vehicles
+                    .iter()
+                    .find(|vehicle| vehicle.stop_line_distance() < 0.0)
+                {
+                    return vehicle.initial_direction;
+                }
+            }
+        };
+        assert_eq!(first_in(Layout::Plus), Direction::Up);
+        assert_eq!(first_in(Layout::Roundabout), Direction::Right);
+
+        // Red for the cross street means nothing without lights.
+        let mut simulation = TrafficSimulation::new(Config {
+            layout: Layout::Roundabout,
+            ..Config::default()
+        });
+        simulation
+            .spawn_vehicle_with(Direction::Right, VEHICLE_SPEED, Some(Route::TurnRight))
+            .unwrap();
+        let mut angles = Vec::new();
+        let mut closest = f32::INFINITY;
+        while !simulation.vehicles.is_empty() {
+            simulation.update();
+            let Some(vehicle) = simulation.vehicles.first() else {
+                break;
+            };
+            if vehicle.in_intersection() {
+                let (x, y) = vehicle.drawn_position(1.0);
+                let (x, y) = (x - CENTER_X as f32, y - CENTER_Y as f32);
+                closest = closest.min(x.hypot(y));
+                angles.push(y.atan2(x));
+            }
+        }
+        assert!(
+            (closest - World::default().roundabout_radius()).abs() < 1.0,
+            "{} from the centre",
+            closest
+        );
+        // Anticlockwise on screen, from the left arm round to the bottom.
+        assert!(
+            angles.windows(2).all(|pair| pair[1] < pair[0] + 1e-3),
+            "{:?}",
+            angles
+        );
+        assert!(
+            angles[0] > 2.0 && angles[angles.len() - 1] < 2.0,
+            "{:?}",
+            angles
+        );
+    }
+
+    #[test]
+    fn all_way_stop_makes_every_vehicle_stop_first() {
+        let mut simulation = TrafficSimulation::new(Config {
+            control_mode: ControlMode::AllWayStop,
+            ..Config::default()
+        });
+        simulation
+            .spawn_vehicle_with(Direction::Right, VEHICLE_SPEED, Some(Route::Straight))
+            .unwrap();
+        while simulation.vehicles[0].stop_line_distance() >= 0.0 {
+            simulation.update();
+        }
+        assert!(simulation.vehicles[0].stopped_at_line.is_some());
+    }
+
+    #[test]
+    fn tie_breaks_have_their_own_stream() {
+        let draws = |seed: u64, tie_break_seed: u64, colors: ColorMode| {
+            let mut simulation = TrafficSimulation::new(Config {
+                seed: Some(seed),
+                tie_break_seed: Some(tie_break_seed),
+                color_mode: colors,
+                ..Config::default()
+            });
+            for direction in Direction::ALL {
+                simulation.spawn_vehicle(direction);
+            }
+            simulation
+                .vehicles
+                .iter()
+                .map(|vehicle| vehicle.tie_break)
+                .collect::<Vec<_>>()
+        };
+
+        // Random colours draw extra numbers from the spawn stream only.
+        let plain = draws(1, 5, ColorMode::ByRoute);
+        assert_eq!(plain, draws(1, 5, ColorMode::Random));
+        assert_eq!(plain, draws(2, 5, ColorMode::ByRoute));
+        assert_ne!(plain, draws(1, 6, ColorMode::ByRoute));
+    }
+
+    #[test]
+    fn a_drain_refuses_every_spawn_and_times_the_clearance() {
+        let mut simulation = TrafficSimulation::new(Config {
+            seed: Some(2),
+            burst_size: 2,
+            ..Config::default()
+        });
+        for direction in Direction::ALL {
+            assert!(simulation.spawn_vehicle(direction));
+        }
+        simulation.queue_burst();
+        simulation.start_drain();
+        assert!(!simulation.spawning_enabled());
+        assert_eq!(simulation.pending_burst, [0; 4]);
+        assert!(!simulation.spawn_vehicle(Direction::Up));
+        assert!(!simulation.request_spawn(Direction::Down, None, 0));
+        simulation.queue_burst();
+        assert_eq!(simulation.pending_burst, [0; 4]);
+        assert_eq!(simulation.drain_report(), None);
+
+        while !simulation.vehicles.is_empty() {
+            simulation.update();
+            assert!(simulation.tick < 10_000, "never drained");
+        }
+        assert_eq!(simulation.next_id, 4);
+        assert!(simulation.events().contains(&SimEvent::Drained));
+        let report = simulation.drain_report().unwrap();
+        assert!(report.starts_with("Drained 4 vehicle(s) in "), "{}", report);
+    }
+
+    #[test]
+    fn the_camera_follows_the_selected_vehicle_and_lets_go_when_it_leaves() {
+        let mut simulation = TrafficSimulation::new(Config::default());
+        simulation.spawn_vehicle(Direction::Up);
+        let mut debug = DebugOverlay {
+            selected: Some(0),
+            follow: true,
+            ..DebugOverlay::default()
+        };
+        for _ in 0..60 {
+            simulation.update();
+            simulation.track_camera(&mut debug, 1.0);
+        }
+        let vehicle = &simulation.vehicles[0];
+        assert!((debug.camera.y - vehicle.y).abs() < 2.0 * VEHICLE_SPEED / 0.15);
+        assert!((debug.camera.x - vehicle.x).abs() < 0.5);
+
+        // The world is drawn shifted so the vehicle sits mid-window, and
+        // twice the size when zoomed in.
+        for zoom in [1.0, 2.0] {
+            debug.camera.zoom = zoom;
+            let mut canvas = RecordingRenderer::default();
+            simulation.render(&mut canvas, 1.0, &debug).unwrap();
+            let rect = vehicle.get_rect(1.0);
+            let (x, y) = debug.camera.to_screen(rect.x() as f32, rect.y() as f32);
+            let size = |length: u32| (length as f32 * zoom) as u32;
+            let shifted = Rect::new(x, y, size(rect.width()), size(rect.height()));
+            assert!(
+                canvas
+                    .calls
+                    .contains(&DrawCall::FillRect(shifted, vehicle.color))
+            );
+        }
+        debug.camera.zoom = 1.0;
+
+        simulation.vehicles.clear();
+        simulation.track_camera(&mut debug, 1.0);
+        assert!(!debug.follow);
+        assert_eq!(debug.selected, None);
+        for _ in 0..100 {
+            simulation.track_camera(&mut debug, 1.0);
+        }
+        assert_eq!(debug.camera, Camera::default());
+    }
+
+    #[test]
+    fn retimed_lights_show_in_the_overlay_and_the_config() {
+        let mut simulation = TrafficSimulation::new(Config::default());
+        simulation.retime_lights(2.0, 0.5);
+        simulation.retime_lights(100.0, 0.0);
+        assert!(
+            simulation
+                .config
+                .light_plan
+                .phases
+                .iter()
+                .all(|phase| !phase.green.contains(&true) || phase.duration == 30.0)
+        );
+        assert_eq!(simulation.config.yellow, 0.5);
+
+        let debug = DebugOverlay {
+            enabled: true,
+            ..DebugOverlay::default()
+        };
+        let mut canvas = RecordingRenderer::default();
+        simulation.render(&mut canvas, 1.0, &debug).unwrap();
+        let yellow = Color::RGB(255, 200, 0);
+        // The two rows below the four counters.
+        let lit = |color| {
+            canvas.calls.iter().any(|call| {
+                matches!(call, DrawCall::FillRect(rect, c)
+                    if *c == color && rect.x() < 60 && (80..140).contains(&rect.y()))
+            })
+        };
+        assert!(lit(Color::RGB(0, 255, 0)));
+        assert!(lit(yellow));
+    }
+
+    #[test]
+    fn vehicles_off_every_lane_are_flagged_once() {
+        let mut simulation = TrafficSimulation::new(Config {
+            check_lanes: true,
+            seed: Some(5),
+            ..Config::default()
+        });
+        simulation.soak(60.0);
+        assert!(simulation.despawned[0] > 20);
+        assert!(simulation.off_lane.is_empty(), "{:?}", simulation.off_lane);
+
+        // Push one into the oncoming lane, as a bad turn exit might.
+        let mut simulation = TrafficSimulation::new(Config {
+            check_lanes: true,
+            ..Config::default()
+        });
+        assert!(simulation.spawn_vehicle(Direction::Up));
+        let vehicle = simulation.vehicles.last_mut().unwrap();
+        let id = vehicle.id;
+        vehicle.x = spawn_position(World::default(), Direction::Down).0;
+        simulation.update();
+        simulation.update();
+        assert!(simulation.off_lane.contains(&id));
+        assert_eq!(simulation.off_lane.len(), 1);
+    }
+
+    #[test]
+    fn a_soak_ends_on_the_first_stop_condition() {
+        let mut simulation = TrafficSimulation::new(Config {
+            seed: Some(1),
+            stop_when: vec![StopCondition::Collision, StopCondition::Exited(10)],
+            ..Config::default()
+        });
+        assert_eq!(simulation.soak(600.0), Vec::<String>::new());
+        assert_eq!(simulation.stopped_by, Some(StopCondition::Exited(10)));
+        assert_eq!(simulation.exited.iter().sum::<usize>(), 10);
+        assert!(simulation.simulated_seconds() < 600.0);
+
+        let mut simulation = TrafficSimulation::new(Config {
+            seed: Some(1),
+            stop_when: vec![StopCondition::Seconds(5.0)],
+            ..Config::default()
+        });
+        simulation.soak(600.0);
+        assert_eq!(simulation.stopped_by, Some(StopCondition::Seconds(5.0)));
+        assert_eq!(simulation.tick, 300);
+    }
+
+    #[test]
+    fn a_headless_run_lasts_its_ticks_at_any_tick_rate() {
+        for (args, ticks) in [
+            (["--headless", "450", "--tick-rate", "120"], 450),
+            (["--tick-rate", "30", "--headless", "95"], 95),
+        ] {
+            let config = Config::from_args(args.map(String::from).into_iter()).unwrap();
+            let mut simulation = TrafficSimulation::new(Config {
+                seed: Some(0),
+                ..config
+            });
+            let seconds = simulation.config.soak_seconds().unwrap();
+            assert_eq!(simulation.soak(seconds), Vec::<String>::new());
+            assert_eq!(simulation.tick, ticks);
+        }
+        let both = ["--headless", "60", "--soak", "1"].map(String::from);
+        assert!(Config::from_args(both.into_iter()).is_err());
+    }
+
+    #[test]
+    fn cost_weighs_delay_throughput_queues_and_collisions() {
+        let mut simulation = TrafficSimulation::new(Config {
+            cost_delay_weight: 2.0,
+            cost_throughput_weight: 0.5,
+            cost_queue_weight: 3.0,
+            cost_collision_weight: 10.0,
+            ..Config::default()
+        });
+        assert_eq!(simulation.cost(), 0.0);
+        simulation.tick = 3600;
+        simulation.exited = [4, 2, 0, 0];
+        simulation.delay_totals = [6.0, 3.0, 0.0, 0.0];
+        simulation.max_queue = [1, 5, 2, 0];
+        simulation.collisions = 1;
+        // 1.5s average delay, 6 exits a minute, a queue of 5, one collision.
+        let expected = 2.0 * 1.5 - 0.5 * 6.0 + 3.0 * 5.0 + 10.0;
+        assert!((simulation.cost() - expected).abs() < 1e-9);
+        assert_eq!(simulation.stats().cost, simulation.cost());
+    }
+
+    #[test]
+    fn speed_limits_follow_the_lane_a_vehicle_is_in() {
+        let mut simulation = TrafficSimulation::new(Config {
+            speed_limits: [60.0, MAX_SPEED_LIMIT, 30.0, MAX_SPEED_LIMIT],
+            ..Config::default()
+        });
+        let (limits, tick_rate) = (simulation.config.speed_limits, simulation.config.tick_rate);
+        let limit = |direction: Direction| limits[direction.index()] / tick_rate;
+        let (up, right) = (limit(Direction::Up), limit(Direction::Right));
+        simulation
+            .spawn_vehicle_with(Direction::Up, VEHICLE_SPEED, Some(Route::TurnLeft))
+            .unwrap();
+        assert_eq!(simulation.vehicles[0].velocity, up);
+
+        let mut turned = false;
+        for _ in 0..2000 {
+            simulation.update();
+            let Some(vehicle) = simulation.vehicles.first() else {
+                break;
+            };
+            let cap = limit(vehicle.direction);
+            assert!(vehicle.velocity <= cap.max(up) + 1e-4);
+            if vehicle.direction == Direction::Right && !vehicle.overlaps_intersection() {
+                turned = true;
+                assert!(vehicle.velocity <= right + 1e-4);
+            }
+        }
+        assert!(turned);
+
+        for bad in ["up:0", "left:-5", "down:601"] {
+            let args = ["--speed-limits", bad].into_iter().map(String::from);
+            assert!(Config::from_args(args).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn rollback_keeps_crossing_traffic_from_ever_overlapping() {
+        // Everyone green at once, so crossing straights meet in the box.
+        let mut simulation = TrafficSimulation::new(Config {
+            light_plan: lights::LightPlan::parse("up+down+right+left:100").unwrap(),
+            rollback_collisions: true,
+            ..Config::default()
+        });
+        for _ in 0..600 {
+            for direction in Direction::ALL {
+                simulation
+                    .spawn_vehicle_with(direction, VEHICLE_SPEED, Some(Route::Straight))
+                    .unwrap();
+            }
+            simulation.update();
+            assert!(simulation.detect_collisions().is_empty());
+        }
+        assert_eq!(simulation.collisions, 0);
+        assert!(simulation.rollbacks > 0);
+    }
+
+    #[test]
+    fn stepping_hands_the_lights_to_the_caller() {
+        let mut simulation = TrafficSimulation::new(Config {
+            signal_controller: lights::ControllerKind::External,
+            ..Config::default()
+        });
+        assert!(simulation.spawn_vehicle(Direction::Right));
+        // The plan would switch after 5s; outside control holds phase 0.
+        let mut reward = 0.0;
+        for _ in 0..600 {
+            let observation = simulation.step(PhaseCommand::Hold);
+            assert_eq!(observation.phase, 0);
+            assert!(observation.reward <= 0.0);
+            reward += observation.reward;
+        }
+        assert_eq!(simulation.step(PhaseCommand::Hold).queues, [0, 0, 1, 0]);
+        assert!(reward < -5.0, "{}", reward);
+
+        let observation = simulation.step(PhaseCommand::Switch(2));
+        assert!(observation.accepted);
+        let mut exited = 0;
+        for _ in 0..1200 {
+            exited += simulation.step(PhaseCommand::Hold).exited;
+        }
+        assert_eq!(simulation.lights.phase(), 2);
+        assert_eq!(exited, 1);
+    }
+
+    #[test]
+    fn protected_left_turners_never_wait_for_oncoming_traffic() {
+        // Left turns from up and straights from down, under a plan that
+        // lets them go together and under one that separates them. The
+        // all-red is long enough for the box to clear between phases.
+        let run = |plan: &str| {
+            let config = Config {
+                light_plan: lights::LightPlan::parse(plan).unwrap(),
+                all_red: 3.0,
+                seed: Some(3),
+                ..Config::default()
+            };
+            let mut simulation = TrafficSimulation::new(config);
+            let mut waits = 0;
+            for tick in 0..60 * 40 {
+                if tick % 60 == 0 {
+                    simulation
+                        .spawn_vehicle_with(Direction::Up, VEHICLE_SPEED, Some(Route::TurnLeft))
+                        .unwrap();
+                    simulation
+                        .spawn_vehicle_with(Direction::Down, VEHICLE_SPEED, Some(Route::Straight))
+                        .unwrap();
+                }
+                simulation.update();
+                let lights = &simulation.lights;
+                waits += simulation
+                    .vehicles
+                    .iter()
+                    .filter(|vehicle| {
+                        vehicle.route == Route::TurnLeft
+                            && vehicle.stop_line_distance() == 0.0
+                            && vehicle.is_waiting()
+                            && lights.is_green_for(vehicle.initial_direction, vehicle.route)
+                    })
+                    .count();
+                if lights.is_green_for(Direction::Up, Route::TurnLeft) && plan.contains("up.left") {
+                    assert!(!lights.is_green(Direction::Down));
+                    assert!(!lights.is_green_for(Direction::Up, Route::Straight));
+                }
+            }
+            assert_eq!(simulation.collisions, 0, "{}", plan);
+            assert!(simulation.exited[Direction::Up.index()] > 0, "{}", plan);
+            waits
+        };
+        assert!(run("up+down:8") > 0);
+        assert_eq!(run("up.left:8,down:8"), 0);
+    }
+
+    #[test]
+    fn jerk_limited_acceleration_ramps_between_ticks() {
+        let config = Config {
+            light_plan: lights::LightPlan::parse("none:6,up:100").unwrap(),
+            max_jerk: 600.0,
+            ..Config::default()
+        };
+        let bound = config.max_jerk / config.tick_rate.powi(3);
+        let mut simulation = TrafficSimulation::new(config);
+        let mut previous: std::collections::HashMap<u64, f32> = Default::default();
+        let mut stopped = false;
+        for tick in 0..60 * 14 {
+            // A short queue forms at the red light and launches on green.
+            if tick % 60 == 0 && tick < 240 {
+                simulation
+                    .spawn_vehicle_with(Direction::Up, VEHICLE_SPEED, Some(Route::Straight))
+                    .unwrap();
+            }
+            simulation.update();
+            for vehicle in &simulation.vehicles {
+                if let Some(&acceleration) = previous.get(&vehicle.id) {
+                    let change = (vehicle.acceleration - acceleration).abs();
+                    assert!(
+                        change <= bound * 1.001,
+                        "vehicle {} at tick {}: {} > {}",
+                        vehicle.id,
+                        tick,
+                        change,
+                        bound
+                    );
+                }
+                previous.insert(vehicle.id, vehicle.acceleration);
+                stopped |= vehicle.velocity == 0.0;
+                if !simulation.lights.is_green(Direction::Up) {
+                    assert!(vehicle.stop_line_distance() >= 0.0);
+                }
+            }
+        }
+        assert!(stopped);
+        assert_eq!(simulation.exited[Direction::Up.index()], 4);
+        assert_eq!(simulation.collisions, 0);
+    }
+
+    #[test]
+    fn trips_follow_the_routes_from_a_single_approach() {
+        let mut simulation = TrafficSimulation::new(Config {
+            seed: Some(4),
+            light_plan: lights::LightPlan::parse("up:100").unwrap(),
+            banned_movements: vec![Movement::parse("up-right").unwrap()],
+            ..Config::default()
+        });
+        let mut routes = [0; 3];
+        for tick in 0..3000 {
+            if tick % 90 == 0 && tick < 1800 && simulation.spawn_vehicle(Direction::Up) {
+                let route = simulation.vehicles.last().unwrap().route;
+                routes[Route::ALL.iter().position(|&r| r == route).unwrap()] += 1;
+            }
+            simulation.update();
+        }
+        assert!(simulation.vehicles.is_empty());
+        let [straight, left, right] = routes;
+        assert!(straight > 0 && left > 0);
+        assert_eq!(right, 0);
+        let from_up = simulation.trips[Direction::Up.index()];
+        assert_eq!(from_up[Direction::Up.index()], straight);
+        assert_eq!(
+            from_up[Route::TurnLeft.exit_direction(Direction::Up).index()],
+            left
+        );
+        assert_eq!(from_up[Direction::Left.index()], 0);
+        assert_eq!(from_up.iter().sum::<usize>(), straight + left);
+        for origin in [Direction::Down, Direction::Left, Direction::Right] {
+            assert_eq!(simulation.trips[origin.index()], [0; 4]);
+        }
+        assert_eq!(simulation.stats().trips, simulation.trips);
+    }
+
+    #[test]
+    fn a_dragged_vehicle_snaps_to_its_path_and_waits_for_release() {
+        let mut simulation = TrafficSimulation::new(Config {
+            seed: Some(2),
+            ..Config::default()
+        });
+        simulation
+            .spawn_vehicle_with(Direction::Up, VEHICLE_SPEED, Some(Route::TurnLeft))
+            .unwrap();
+        let id = simulation.vehicles[0].id;
+        let (lane_x, _) = spawn_position(World::default(), Direction::Up);
+
+        // Off to the side of the approach: pulled back onto the lane.
+        assert!(simulation.drag_vehicle(id, lane_x + 25.0, 600.0));
+        for _ in 0..20 {
+            simulation.update();
+        }
+        let vehicle = &simulation.vehicles[0];
+        assert_eq!((vehicle.x, vehicle.y), (lane_x, 600.0));
+        assert_eq!(vehicle.direction, Direction::Up);
+
+        // Out along the exit arm: onto the turn line, already turned.
+        let turn_y = vehicle.turn_coordinate().unwrap();
+        simulation.drag_vehicle(id, 800.0, turn_y - 40.0);
+        let vehicle = &simulation.vehicles[0];
+        assert_eq!((vehicle.x, vehicle.y), (800.0, turn_y));
+        assert_eq!(vehicle.direction, Direction::Right);
+        assert_eq!(vehicle.lane_offset(), 0.0);
+
+        simulation.release_vehicle(id);
+        for _ in 0..60 {
+            simulation.update();
+        }
+        let vehicle = &simulation.vehicles[0];
+        assert!(vehicle.x > 800.0);
+        assert_eq!(vehicle.y, turn_y);
+        assert!(!simulation.drag_vehicle(id + 1, 0.0, 0.0));
+    }
+
+    #[test]
+    fn aggressive_drivers_soak_clean_and_run_more_yellows() {
+        let soak = |aggressiveness| {
+            let mut simulation = TrafficSimulation::new(Config {
+                seed: Some(1),
+                yellow: 3.0,
+                aggressiveness,
+                ..Config::default()
+            });
+            assert_eq!(simulation.soak(300.0), Vec::<String>::new());
+            simulation.yellow_runs
+        };
+        let (careful, aggressive) = (soak(0.0), soak(1.0));
+        assert!(aggressive > careful, "{} {}", aggressive, careful);
+    }
+
+    #[test]
+    fn aggressive_drivers_run_yellows_from_further_back_and_follow_closer() {
+        let runs_yellow = |aggressiveness: f32| {
+            let mut simulation = TrafficSimulation::new(Config {
+                light_plan: lights::LightPlan::parse("up:2,right:2").unwrap(),
+                yellow: 2.0,
+                ..Config::default()
+            });
+            while simulation.lights.stage() != lights::Stage::Yellow {
+                simulation.update();
+            }
+            simulation
+                .spawn_vehicle_with(Direction::Up, VEHICLE_SPEED, Some(Route::Straight))
+                .unwrap();
+            let vehicle = &mut simulation.vehicles[0];
+            vehicle.aggressiveness = aggressiveness;
+            vehicle.y += 1.5 * vehicle.stopping_distance() - vehicle.stop_line_distance();
+            for _ in 0..120 {
+                simulation.update();
+            }
+            assert!(simulation.invariant_violations().is_empty());
+            (
+                simulation.vehicles[0].stop_line_distance() < 0.0,
+                simulation.yellow_runs,
+            )
+        };
+        assert_eq!(runs_yellow(0.0), (false, 0));
+        assert_eq!(runs_yellow(1.0), (true, 1));
+
+        let mut simulation = TrafficSimulation::new(Config {
+            aggressiveness: 0.8,
+            ..Config::default()
+        });
+        let cruising = VEHICLE_SPEED * simulation.config.tick_rate;
+        assert!(simulation.required_gap(cruising, 1.0) < simulation.required_gap(cruising, 0.0));
+        assert_eq!(
+            simulation.required_gap(0.0, 1.0),
+            simulation.required_gap(0.0, 0.0)
+        );
+        let drawn: Vec<f32> = (0..200)
+            .map(|tie_break| simulation.driver_aggressiveness(tie_break))
+            .collect();
+        assert!(drawn.iter().all(|drawn| (0.0..0.8).contains(drawn)));
+        assert!(drawn.iter().any(|&drawn| drawn < 0.2));
+        assert!(drawn.iter().any(|&drawn| drawn > 0.6));
+
+        // Only the bolder drivers are outlined.
+        simulation.spawn_vehicle(Direction::Up);
+        simulation.vehicles[0].aggressiveness = 0.7;
+        let outlined = |simulation: &TrafficSimulation| {
+            record_frame(simulation).calls.iter().any(|call| {
+                matches!(call, DrawCall::DrawRect(_, color) if *color == Color::RGB(255, 90, 0))
+            })
+        };
+        assert!(outlined(&simulation));
+        simulation.vehicles[0].aggressiveness = 0.3;
+        assert!(!outlined(&simulation));
+    }
+
+    #[test]
+    fn step_reports_a_scripted_vehicle_as_events() {
+        let mut simulation = TrafficSimulation::new(Config {
+            seed: Some(1),
+            light_plan: lights::LightPlan::parse("none:4,up:100").unwrap(),
+            ..Config::default()
+        });
+        simulation
+            .spawn_vehicle_with(Direction::Up, VEHICLE_SPEED, Some(Route::TurnLeft))
+            .unwrap();
+        let mut events = Vec::new();
+        let mut first = None;
+        for _ in 0..900 {
+            let observation = simulation.step(PhaseCommand::Hold);
+            first.get_or_insert(observation.events.clone());
+            assert_eq!(observation.events, simulation.events());
+            events.extend(observation.events);
+        }
+        let spawned = SimEvent::Spawned {
+            id: 0,
+            direction: Direction::Up,
+            route: Route::TurnLeft,
+        };
+        assert_eq!(first, Some(vec![spawned]));
+        assert_eq!(
+            events,
+            [
+                spawned,
+                SimEvent::Stopped { id: 0 },
+                SimEvent::Turned { id: 0 },
+                SimEvent::Despawned {
+                    id: 0,
+                    reason: DespawnReason::Exited
+                },
+            ]
+        );
+        simulation.update();
+        assert!(simulation.events().is_empty());
+    }
+
+    #[test]
+    fn close_calls_and_times_on_the_road_are_recorded() {
+        let mut simulation = TrafficSimulation::new(Config {
+            light_plan: lights::LightPlan::parse("up:100").unwrap(),
+            ..Config::default()
+        });
+        simulation
+            .spawn_vehicle_with(Direction::Up, VEHICLE_SPEED, Some(Route::Straight))
+            .unwrap();
+        simulation
+            .spawn_vehicle_with(Direction::Right, VEHICLE_SPEED, Some(Route::Straight))
+            .unwrap();
+        // The cross-street car parked just short of the up lane, another
+        // up car right behind the first.
+        simulation.vehicles[0].y -= 100.0;
+        let up = simulation.vehicles[0].clone();
+        let right = &mut simulation.vehicles[1];
+        right.x = up.x - up.width / 2.0 - right.length / 2.0 - 5.0;
+        right.y = up.y;
+        right.frozen = true;
+        simulation.spawn_vehicle(Direction::Up);
+        for _ in 0..1000 {
+            simulation.update();
+        }
+        // Each up car passes the parked one once; followers never count.
+        assert_eq!(simulation.close_calls, 2);
+        assert_eq!(simulation.collisions, 0);
+        let (shortest, longest) = simulation.crossing_ticks.unwrap();
+        assert!(shortest < longest, "{} {}", shortest, longest);
+        let (slowest, fastest) = simulation.trip_speeds.unwrap();
+        assert!(
+            0.0 < slowest && slowest < fastest,
+            "{} {}",
+            slowest,
+            fastest
+        );
+        let (least, most) = simulation.delays.unwrap();
+        assert!(0.0 <= least && least <= most, "{} {}", least, most);
+
+        // A U-turn ends up near where it started, but its speed counts the
+        // whole loop it drove.
+        let mut simulation = TrafficSimulation::new(Config {
+            u_turns: true,
+            light_plan: lights::LightPlan::parse("up:100").unwrap(),
+            ..Config::default()
+        });
+        simulation
+            .spawn_vehicle_with(Direction::Up, VEHICLE_SPEED, Some(Route::UTurn))
+            .unwrap();
+        while simulation.stats().exited == 0 {
+            simulation.update();
+            assert!(simulation.tick < 10_000, "never left");
+        }
+        let (speed, _) = simulation.trip_speeds.unwrap();
+        let cruise = simulation.config.cruise_speed;
+        assert!((speed - cruise).abs() < 1.0, "{} against {}", speed, cruise);
+    }
+
+    #[test]
+    fn vehicles_hold_at_the_line_while_their_exit_is_full() {
+        let mut simulation = TrafficSimulation::new(Config {
+            light_plan: lights::LightPlan::parse("up:100").unwrap(),
+            ..Config::default()
+        });
+        simulation
+            .spawn_vehicle_with(Direction::Up, VEHICLE_SPEED, Some(Route::Straight))
+            .unwrap();
+        // A broken-down car just past the box on the up lane, with space
+        // behind it for less than a car.
+        let stuck = &mut simulation.vehicles[0];
+        stuck.y = (CENTER_Y - ROAD_WIDTH as i32 / 2) as f32 - stuck.length / 2.0 - 20.0;
+        stuck.frozen = true;
+        simulation
+            .spawn_vehicle_with(Direction::Up, VEHICLE_SPEED, Some(Route::Straight))
+            .unwrap();
+        for _ in 0..600 {
+            simulation.update();
+        }
+        let waiting = &simulation.vehicles[1];
+        assert!(waiting.stop_line_distance() >= 0.0);
+        assert!(!waiting.overlaps_intersection());
+        assert_eq!(waiting.velocity, 0.0);
+
+        // Towed away, the exit clears and the car drives through.
+        simulation.vehicles[0].y -= 200.0;
+        for _ in 0..300 {
+            simulation.update();
+        }
+        assert!(simulation.vehicles[1].cleared_intersection());
+        assert_eq!(simulation.collisions, 0);
+    }
+
+    #[test]
+    fn toggling_the_controller_changes_how_the_next_phase_is_picked() {
+        let mut simulation = TrafficSimulation::new(Config {
+            light_plan: lights::LightPlan::parse("up:1,down:1,left:1").unwrap(),
+            ..Config::default()
+        });
+        let next_phase = |simulation: &mut TrafficSimulation| {
+            let phase = simulation.lights.phase();
+            while simulation.lights.phase() == phase {
+                simulation.lights.update(1.0 / 60.0, [0, 0, 0, 5]);
+            }
+            simulation.lights.phase()
+        };
+        assert_eq!(next_phase(&mut simulation), 1);
+        simulation.toggle_controller();
+        assert_eq!(
+            simulation.config.signal_controller,
+            ControllerKind::MaxPressure
+        );
+        assert_eq!(next_phase(&mut simulation), 2);
+        simulation.toggle_controller();
+        assert_eq!(simulation.config.signal_controller, ControllerKind::Fixed);
+        assert_eq!(next_phase(&mut simulation), 0);
+
+        simulation.config.signal_controller = ControllerKind::External;
+        simulation.toggle_controller();
+        assert_eq!(
+            simulation.config.signal_controller,
+            ControllerKind::External
+        );
+    }
+
+    #[test]
+    fn turning_vehicles_are_drawn_round_a_curve_unless_turns_are_square() {
+        let drawn_path = |curved_turns: bool| {
+            let mut simulation = TrafficSimulation::new(Config {
+                light_plan: lights::LightPlan::parse("up:100").unwrap(),
+                curved_turns,
+                ..Config::default()
+            });
+            simulation
+                .spawn_vehicle_with(Direction::Up, VEHICLE_SPEED, Some(Route::TurnLeft))
+                .unwrap();
+            let mut path = Vec::new();
+            while simulation.vehicles[0].overlaps_intersection()
+                || simulation.vehicles[0].stop_line_distance() >= 0.0
+            {
+                simulation.update();
+                path.push(simulation.vehicles[0].drawn_position(1.0));
+            }
+            path
+        };
+        let entry_x = lane_centre(World::default(), Direction::Up);
+        let exit_y = lane_centre(
+            World::default(),
+            Route::TurnLeft.exit_direction(Direction::Up),
+        );
+        let off_both_lanes =
+            |&(x, y): &(f32, f32)| (x - entry_x).abs() > 1.0 && (y - exit_y).abs() > 1.0;
+
+        let curved = drawn_path(true);
+        assert!(curved.iter().any(off_both_lanes));
+        let largest_step = curved
+            .windows(2)
+            .map(|pair| (pair[1].0 - pair[0].0).hypot(pair[1].1 - pair[0].1))
+            .fold(0.0, f32::max);
+        assert!(largest_step <= 2.0 * VEHICLE_SPEED, "{}", largest_step);
+        assert!(!drawn_path(false).iter().any(off_both_lanes));
+    }
+
+    #[test]
+    fn automatic_arrivals_come_at_the_rate_and_split_by_weight() {
+        let mut simulation = TrafficSimulation::new(Config {
+            auto_spawn: true,
+            arrival_rate: 1.0,
+            approach_weights: [3.0, 1.0, 0.0, 0.0],
+            seed: Some(11),
+            ..Config::default()
+        });
+        let mut spawned = [0; 4];
+        for tick in 0..2 * 3600 {
+            if tick == 3600 {
+                simulation.toggle_auto_spawn();
+            }
+            simulation.update();
+            for event in simulation.events() {
+                if let SimEvent::Spawned { direction, .. } = event {
+                    spawned[direction.index()] += 1;
+                }
+            }
+        }
+        // About 60 in the first simulated minute and none after.
+        let total: usize = spawned.iter().sum();
+        assert!((40..=80).contains(&total), "{:?}", spawned);
+        assert!(spawned[0] > spawned[1], "{:?}", spawned);
+        assert_eq!(spawned[2] + spawned[3], 0);
+    }
+
+    #[test]
+    fn pedestrians_cross_without_being_run_into() {
+        let mut simulation = TrafficSimulation::new(Config {
+            auto_spawn: true,
+            arrival_rate: 1.0,
+            pedestrian_rate: 0.2,
+            seed: Some(3),
+            ..Config::default()
+        });
+        for _ in 0..3 * 3600 {
+            simulation.update();
+            assert_eq!(simulation.invariant_violations(), Vec::<String>::new());
+        }
+        assert!(simulation.pedestrians_crossed > 20);
+        assert!(simulation.stats().exited > 20);
+        assert_eq!(simulation.collisions, 0);
+    }
+
+    #[test]
+    fn vehicles_hold_on_green_while_someone_is_on_the_crosswalk() {
+        let mut simulation = TrafficSimulation::new(Config {
+            seed: Some(0),
+            light_plan: lights::LightPlan::parse("up:100").unwrap(),
+            pedestrian_rate: 0.001,
+            ..Config::default()
+        });
+        let mut pedestrian = Pedestrian::new(0, Direction::Up, 1.0);
+        pedestrian.walking = true;
+        simulation.pedestrians.push(pedestrian);
+        simulation
+            .spawn_vehicle_with(Direction::Up, VEHICLE_SPEED, Some(Route::Straight))
+            .unwrap();
+        for _ in 0..300 {
+            simulation.pedestrians[0].walked = WALK_SPEED * simulation.config.tick_seconds();
+            simulation.update();
+        }
+        let waiting = &simulation.vehicles[0];
+        assert!(waiting.stop_line_distance() >= 0.0);
+        assert_eq!(waiting.velocity, 0.0);
+
+        // Once they are across, the car drives on.
+        for _ in 0..900 {
+            simulation.update();
+        }
+        assert!(
+            simulation
+                .pedestrians
+                .iter()
+                .all(|pedestrian| pedestrian.id != 0)
+        );
+        assert_eq!(simulation.stats().exited, 1);
+    }
+
+    #[test]
+    fn an_arrow_phase_lights_the_red_and_a_green_arrow() {
+        let mut simulation = TrafficSimulation::new(Config {
+            light_plan: lights::LightPlan::parse("up.left:5,down:5").unwrap(),
+            ..Config::default()
+        });
+        // Whether each line drawn in `color` on the head is slanted: the
+        // arrow's head has the only slanted strokes, as lamps are filled in
+        // level lines.
+        let lamp_lines = |simulation: &TrafficSimulation, approach: Direction, color: Color| {
+            let head = light_head_rect(World::default(), approach);
+            let mut recorder = RecordingRenderer::default();
+            simulation
+                .render(&mut recorder, 1.0, &DebugOverlay::default())
+                .unwrap();
+            recorder
+                .calls
+                .into_iter()
+                .filter_map(|call| match call {
+                    DrawCall::Line(start, end, drawn)
+                        if drawn == color && head.contains_point(start) =>
+                    {
+                        Some(start.0 != end.0 && start.1 != end.1)
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let (red, green) = (Color::RGB(255, 0, 0), Color::RGB(0, 255, 0));
+        let slanted = |lines: &[bool]| lines.contains(&true);
+        let up_green = lamp_lines(&simulation, Direction::Up, green);
+        assert!(slanted(&up_green));
+        assert!(!lamp_lines(&simulation, Direction::Up, red).is_empty());
+        assert!(lamp_lines(&simulation, Direction::Down, green).is_empty());
+
+        while simulation.lights.phase() == 0 {
+            simulation.update();
+        }
+        let down_green = lamp_lines(&simulation, Direction::Down, green);
+        assert!(!down_green.is_empty() && !slanted(&down_green));
+        assert!(lamp_lines(&simulation, Direction::Down, red).is_empty());
+    }
+
+    #[test]
+    fn clicking_a_light_holds_its_approach_green_until_clicked_again() {
+        let mut simulation = TrafficSimulation::new(Config {
+            light_plan: lights::LightPlan::parse("up:30,right:30").unwrap(),
+            ..Config::default()
+        });
+        let head = light_head_rect(World::default(), Direction::Right);
+        let (x, y) = (head.center().x() as f32, head.center().y() as f32);
+        assert_eq!(simulation.light_at(x, y), Some(Direction::Right));
+        assert_eq!(simulation.light_at(CENTER_X as f32, CENTER_Y as f32), None);
+
+        // The mouse over a head frames it; a held head is framed in white.
+        let hovered = |simulation: &TrafficSimulation| {
+            let mut recorder = RecordingRenderer::default();
+            let debug = DebugOverlay {
+                mouse: Some((x as i32, y as i32)),
+                ..DebugOverlay::default()
+            };
+            simulation.render(&mut recorder, 1.0, &debug).unwrap();
+            recorder.calls
+        };
+        let frame = |color| DrawCall::DrawRect(head, color);
+        assert!(hovered(&simulation).contains(&frame(Color::RGB(0, 255, 255))));
+        assert!(!hovered(&simulation).contains(&frame(Color::RGB(255, 255, 255))));
+
+        simulation.toggle_light_hold(Direction::Right);
+        assert!(hovered(&simulation).contains(&frame(Color::RGB(255, 255, 255))));
+        for _ in 0..3000 {
+            simulation.update();
+        }
+        assert_eq!(simulation.lights.preempted(), Some(Direction::Right));
+        assert!(simulation.lights.is_green(Direction::Right));
+        assert!(!simulation.lights.is_green(Direction::Up));
+
+        simulation.toggle_light_hold(Direction::Right);
+        for _ in 0..300 {
+            simulation.update();
+        }
+        assert_eq!(simulation.lights.preempted(), None);
+        assert!(simulation.lights.is_green(Direction::Up));
+
+        let unsignalled = TrafficSimulation::new(Config {
+            control_mode: ControlMode::Uncontrolled,
+            ..Config::default()
+        });
+        assert_eq!(unsignalled.light_at(x, y), None);
+    }
+
+    #[test]
+    fn an_emergency_vehicle_gets_green_and_others_hold_until_it_clears() {
+        let mut simulation = TrafficSimulation::new(Config {
+            light_plan: lights::LightPlan::parse("up:30,right:30").unwrap(),
+            approach_weights: [0.0, 0.0, 1.0, 0.0],
+            ..Config::default()
+        });
+        assert!(simulation.spawn_emergency());
+        assert_eq!(simulation.vehicles[0].kind, VehicleKind::Emergency);
+        assert_eq!(simulation.vehicles[0].initial_direction, Direction::Right);
+        simulation
+            .spawn_vehicle_with(Direction::Up, VEHICLE_SPEED, Some(Route::Straight))
+            .unwrap();
+        simulation.update();
+        assert_eq!(simulation.lights.preempted(), Some(Direction::Right));
+
+        let mut up_entered_first = false;
+        while simulation.vehicles.iter().any(|vehicle| {
+            vehicle.kind == VehicleKind::Emergency && !vehicle.cleared_intersection()
+        }) {
+            assert!(!simulation.lights.is_green(Direction::Up));
+            up_entered_first |= simulation.vehicles[1].stop_line_distance() < 0.0;
+            simulation.update();
+            assert!(simulation.tick < 1200);
+        }
+        assert!(!up_entered_first);
+
+        // The plan resumes from its first phase, and the waiting car goes.
+        for _ in 0..300 {
+            simulation.update();
+        }
+        assert_eq!(simulation.lights.preempted(), None);
+        assert!(simulation.lights.is_green(Direction::Up));
+        let waiting = simulation.vehicles.iter().find(|vehicle| vehicle.id == 1);
+        assert!(waiting.is_none_or(|vehicle| vehicle.stop_line_distance() < 0.0));
+        assert_eq!(simulation.collisions, 0);
+    }
+
+    #[test]
+    fn on_yellow_only_vehicles_too_close_to_stop_go_on() {
+        let crosses_from = |to_stop_line: f32| {
+            let mut simulation = TrafficSimulation::new(Config {
+                light_plan: lights::LightPlan::parse("up:2,right:2").unwrap(),
+                yellow: 2.0,
+                ..Config::default()
+            });
+            while simulation.lights.stage() != lights::Stage::Yellow {
+                simulation.update();
+            }
+            simulation
+                .spawn_vehicle_with(Direction::Up, VEHICLE_SPEED, Some(Route::Straight))
+                .unwrap();
+            let vehicle = &mut simulation.vehicles[0];
+            vehicle.y += to_stop_line - vehicle.stop_line_distance();
+            for _ in 0..120 {
+                simulation.update();
+            }
+            assert!(simulation.invariant_violations().is_empty());
+            simulation.vehicles[0].stop_line_distance() < 0.0
+        };
+        assert!(crosses_from(4.0));
+        assert!(!crosses_from(60.0));
+    }
+
+    #[test]
+    fn a_replayed_run_repeats_the_recorded_one_tick_for_tick() {
+        let config = Config {
+            auto_spawn: true,
+            arrival_rate: 0.5,
+            pedestrian_rate: 0.1,
+            truck_share: 0.3,
+            color_mode: ColorMode::Random,
+            tick_cooldown: true,
+            record: Some("unused".to_string()),
+            ..Config::default()
+        };
+        // Key presses tagged by tick, some of them refused by the cooldown.
+        let run = |simulation: &mut TrafficSimulation| {
+            let mut hashes = Vec::new();
+            for tick in 0..2400 {
+                match tick % 97 {
+                    0 => simulation.queue_burst(),
+                    13 => {
+                        simulation.spawn_emergency();
+                    }
+                    n if n % 5 == 0 => {
+                        simulation.request_random_spawn(0);
+                    }
+                    _ => {}
+                }
+                simulation.update();
+                hashes.push(simulation.state_hash());
+            }
+            hashes
+        };
+        let mut recorded = TrafficSimulation::new(config.clone());
+        let hashes = run(&mut recorded);
+        let recording = recorded.recording().unwrap().clone();
+        assert!(recording.spawns.len() > 100, "{}", recording.spawns.len());
+        let recording = Recording::parse(&recording.to_text()).unwrap();
+
+        let mut replayed = TrafficSimulation::replaying(
+            Config {
+                record: None,
+                ..config.clone()
+            },
+            recording.clone(),
+        );
+        assert_eq!(run(&mut replayed), hashes);
+        assert_eq!(replayed.stats(), recorded.stats());
+        assert_eq!(replayed.refused_spawns, recorded.refused_spawns);
+        assert_eq!(replayed.pedestrians_crossed, recorded.pedestrians_crossed);
+        assert!(replayed.recording().is_none());
+
+        // Another controller gets the same arrivals, as their spawn points
+        // clear.
+        let mut actuated = TrafficSimulation::replaying(
+            Config {
+                signal_controller: ControllerKind::MaxPressure,
+                record: None,
+                ..config
+            },
+            recording,
+        );
+        for _ in 0..2400 + 3600 {
+            actuated.update();
+        }
+        assert_eq!(actuated.next_id, recorded.next_id);
+        assert_ne!(actuated.state_hash(), recorded.state_hash());
+    }
+
+    #[test]
+    fn a_restored_snapshot_carries_on_exactly_as_the_run_it_was_taken_from() {
+        let config = Config {
+            auto_spawn: true,
+            arrival_rate: 0.6,
+            pedestrian_rate: 0.1,
+            truck_share: 0.3,
+            color_mode: ColorMode::Random,
+            seed: Some(4),
+            ..Config::default()
+        };
+        let mut original = TrafficSimulation::new(config.clone());
+        for _ in 0..40 * 60 {
+            original.update();
+        }
+        assert!(!original.vehicles.is_empty());
+        let snapshot = Snapshot::parse(&original.snapshot().to_text()).unwrap();
+
+        // Another seed and a busy road of its own, all replaced.
+        let mut restored = TrafficSimulation::new(Config {
+            seed: Some(99),
+            ..config.clone()
+        });
+        for _ in 0..10 * 60 {
+            restored.update();
+        }
+        restored.restore(&snapshot).unwrap();
+        assert_eq!(restored.tick, original.tick);
+        assert_eq!(restored.state_hash(), original.state_hash());
+        for _ in 0..40 * 60 {
+            original.update();
+            restored.update();
+            assert_eq!(restored.state_hash(), original.state_hash());
+        }
+        assert_eq!(restored.stats(), original.stats());
+        assert_eq!(restored.heatmap(), original.heatmap());
+        assert_eq!(restored.snapshot().to_text(), original.snapshot().to_text());
+
+        let mut crossing = TrafficSimulation::new(Config {
+            layout: Layout::Roundabout,
+            ..config.clone()
+        });
+        assert!(crossing.restore(&snapshot).is_err());
+        let mut recording = TrafficSimulation::new(Config {
+            record: Some("unused".to_string()),
+            ..config
+        });
+        assert!(recording.restore(&snapshot).is_err());
+    }
+
+    #[test]
+    fn metrics_are_sampled_per_approach_and_written_at_the_end() {
+        let path = std::env::temp_dir().join("road_intersection_metrics.csv");
+        let mut simulation = TrafficSimulation::new(Config {
+            auto_spawn: true,
+            arrival_rate: 0.5,
+            seed: Some(2),
+            metrics_out: Some(path.display().to_string()),
+            metrics_every: 30.0,
+            ..Config::default()
+        });
+        for _ in 0..100 * 60 {
+            simulation.update();
+        }
+        simulation.write_metrics().unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let rows: Vec<&str> = text.lines().skip(1).collect();
+        // Three samples on the half minutes, then the last at 100 seconds.
+        assert_eq!(rows.len(), 4 * 4, "{}", text);
+        assert!(rows[0].starts_with("30.000,up,"), "{}", text);
+        assert!(rows[15].starts_with("100.000,left,"), "{}", text);
+        let exited: usize = rows[12..]
+            .iter()
+            .map(|row| row.split(',').nth(2).unwrap().parse::<usize>().unwrap())
+            .sum();
+        assert_eq!(exited, simulation.stats().exited);
+        for sample in &simulation.metrics[12..] {
+            assert!(sample.p95_wait >= sample.mean_wait, "{:?}", sample);
+        }
+    }
+
+    #[test]
+    fn each_kind_has_its_own_size_speed_and_spacing() {
+        let stopped_gap = |leader: VehicleKind| {
+            let mut simulation = TrafficSimulation::new(Config {
+                light_plan: lights::LightPlan::parse("right:100").unwrap(),
+                ..Config::default()
+            });
+            let speed = simulation.spawn_speed();
+            for kind in [leader, VehicleKind::Car] {
+                simulation
+                    .spawn_kind(Direction::Up, speed, Some(Route::Straight), Some(kind))
+                    .unwrap();
+                for _ in 0..600 {
+                    simulation.update();
+                }
+            }
+            let (leader, follower) = (&simulation.vehicles[0], &simulation.vehicles[1]);
+            assert_eq!(follower.velocity, 0.0);
+            (leader.clone(), follower.gap_to(leader).unwrap())
+        };
+        let (car, car_gap) = stopped_gap(VehicleKind::Car);
+        let (truck, truck_gap) = stopped_gap(VehicleKind::Truck);
+        let (motorcycle, motorcycle_gap) = stopped_gap(VehicleKind::Motorcycle);
+
+        assert!(truck.length > car.length && truck.width == car.width);
+        assert!(motorcycle.length < car.length && motorcycle.width < car.width);
+        assert!(truck.cruise_speed < car.cruise_speed);
+        assert!(motorcycle.cruise_speed > car.cruise_speed);
+        assert!(truck_gap > car_gap * 1.4, "{} {}", truck_gap, car_gap);
+        assert!((motorcycle_gap - car_gap).abs() < 1.0);
+        // Every kind still stops with its front at the stop line.
+        for leader in [car, truck, motorcycle] {
+            assert!(leader.stop_line_distance().abs() < 1.0);
+        }
+
+        let config = Config {
+            truck_share: 0.2,
+            motorcycle_share: 0.3,
+            ..Config::default()
+        };
+        let mut rng = StdRng::seed_from_u64(8);
+        let mut counts = [0; 4];
+        for _ in 0..10_000 {
+            counts[drawn_kind(&config, &mut rng) as usize] += 1;
+        }
+        assert!((1800..2200).contains(&counts[VehicleKind::Truck as usize]));
+        assert!((2800..3200).contains(&counts[VehicleKind::Motorcycle as usize]));
+        assert_eq!(counts[VehicleKind::Emergency as usize], 0);
+    }
+}
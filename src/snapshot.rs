@@ -0,0 +1,467 @@
+use crate::direction::{Direction, Route};
+use crate::environment::Traction;
+use crate::geometry::{TICK_RATE, World};
+use crate::lights::Stage;
+use crate::pedestrian::Pedestrian;
+use crate::vehicle::{Vehicle, VehicleKind};
+use sdl2::pixels::Color;
+use std::fs;
+use std::path::Path;
+
+/// A value that a snapshot line holds as one space-free word. Floats are
+/// written in full, so they read back to the same bits.
+pub trait Field: Sized {
+    fn write(&self) -> String;
+    fn read(word: &str) -> Result<Self, String>;
+}
+
+macro_rules! parsed_fields {
+    ($($kind:ty),*) => {
+        $(impl Field for $kind {
+            fn write(&self) -> String {
+                format!("{:?}", self)
+            }
+
+            fn read(word: &str) -> Result<Self, String> {
+                word.parse()
+                    .map_err(|_| format!("Invalid {}: {}", stringify!($kind), word))
+            }
+        })*
+    };
+}
+
+parsed_fields!(u8, u32, u64, usize, f32, f64, bool);
+
+impl Field for u128 {
+    fn write(&self) -> String {
+        self.to_string()
+    }
+
+    fn read(word: &str) -> Result<Self, String> {
+        word.parse().map_err(|_| format!("Invalid u128: {}", word))
+    }
+}
+
+/// Only for words with no spaces in them, such as a layout's name.
+impl Field for String {
+    fn write(&self) -> String {
+        self.clone()
+    }
+
+    fn read(word: &str) -> Result<Self, String> {
+        Ok(word.to_string())
+    }
+}
+
+/// The two values joined by a comma.
+impl<A: Field, B: Field> Field for (A, B) {
+    fn write(&self) -> String {
+        format!("{},{}", self.0.write(), self.1.write())
+    }
+
+    fn read(word: &str) -> Result<Self, String> {
+        let (a, b) = word
+            .split_once(',')
+            .ok_or_else(|| format!("Expected a pair: {}", word))?;
+        Ok((A::read(a)?, B::read(b)?))
+    }
+}
+
+impl Field for Direction {
+    fn write(&self) -> String {
+        self.name().to_string()
+    }
+
+    fn read(word: &str) -> Result<Self, String> {
+        Direction::parse(word)
+    }
+}
+
+impl Field for Route {
+    fn write(&self) -> String {
+        self.name().to_string()
+    }
+
+    fn read(word: &str) -> Result<Self, String> {
+        Route::parse(word)
+    }
+}
+
+impl Field for VehicleKind {
+    fn write(&self) -> String {
+        self.name().to_string()
+    }
+
+    fn read(word: &str) -> Result<Self, String> {
+        VehicleKind::parse(word)
+    }
+}
+
+impl Field for Stage {
+    fn write(&self) -> String {
+        match self {
+            Stage::Phase => "phase",
+            Stage::Yellow => "yellow",
+            Stage::AllRed => "all-red",
+        }
+        .to_string()
+    }
+
+    fn read(word: &str) -> Result<Self, String> {
+        match word {
+            "phase" => Ok(Stage::Phase),
+            "yellow" => Ok(Stage::Yellow),
+            "all-red" => Ok(Stage::AllRed),
+            _ => Err(format!("Unknown stage: {}", word)),
+        }
+    }
+}
+
+impl Field for Color {
+    fn write(&self) -> String {
+        format!("{},{},{}", self.r, self.g, self.b)
+    }
+
+    fn read(word: &str) -> Result<Self, String> {
+        let parts: Vec<&str> = word.split(',').collect();
+        let [r, g, b] = parts[..] else {
+            return Err(format!("Invalid colour: {}", word));
+        };
+        Ok(Color::RGB(u8::read(r)?, u8::read(g)?, u8::read(b)?))
+    }
+}
+
+/// `-` for `None`.
+impl<T: Field> Field for Option<T> {
+    fn write(&self) -> String {
+        match self {
+            Some(value) => value.write(),
+            None => "-".to_string(),
+        }
+    }
+
+    fn read(word: &str) -> Result<Self, String> {
+        match word {
+            "-" => Ok(None),
+            _ => T::read(word).map(Some),
+        }
+    }
+}
+
+/// One line of a snapshot: a name, then its values.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Line {
+    pub name: String,
+    words: Vec<String>,
+}
+
+impl Line {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            words: Vec::new(),
+        }
+    }
+
+    pub fn put<T: Field>(mut self, value: T) -> Self {
+        self.words.push(value.write());
+        self
+    }
+
+    pub fn put_all<T: Field>(mut self, values: impl IntoIterator<Item = T>) -> Self {
+        self.words
+            .extend(values.into_iter().map(|value| value.write()));
+        self
+    }
+
+    /// Reads the values back in the order they were put.
+    pub fn fields(&self) -> Fields<'_> {
+        Fields {
+            line: self,
+            next: 0,
+        }
+    }
+}
+
+/// The values of a line, taken one at a time.
+pub struct Fields<'a> {
+    line: &'a Line,
+    next: usize,
+}
+
+impl Fields<'_> {
+    pub fn take<T: Field>(&mut self) -> Result<T, String> {
+        let word = self
+            .line
+            .words
+            .get(self.next)
+            .ok_or_else(|| format!("Too few values on the {} line", self.line.name))?;
+        self.next += 1;
+        T::read(word).map_err(|e| format!("{} line: {}", self.line.name, e))
+    }
+
+    pub fn take_array<T: Field, const N: usize>(&mut self) -> Result<[T; N], String> {
+        let values = (0..N)
+            .map(|_| self.take())
+            .collect::<Result<Vec<T>, String>>()?;
+        Ok(values
+            .try_into()
+            .unwrap_or_else(|_| unreachable!("took N values")))
+    }
+
+    /// The values not yet taken, for a line that ends in a list.
+    pub fn take_rest<T: Field>(&mut self) -> Result<Vec<T>, String> {
+        let mut values = Vec::new();
+        while self.next < self.line.words.len() {
+            values.push(self.take()?);
+        }
+        Ok(values)
+    }
+
+    /// Fails if any value was left over.
+    pub fn finish(self) -> Result<(), String> {
+        if self.next < self.line.words.len() {
+            return Err(format!("Too many values on the {} line", self.line.name));
+        }
+        Ok(())
+    }
+}
+
+/// Everything a run has been through, written by
+/// `TrafficSimulation::snapshot` and put back by `restore`, one line per
+/// value or record: the clock, the lights, every vehicle and pedestrian,
+/// the counters, and seeds the random streams go on from.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Snapshot {
+    pub lines: Vec<Line>,
+}
+
+impl Snapshot {
+    pub fn push(&mut self, line: Line) {
+        self.lines.push(line);
+    }
+
+    /// The one line called `name`.
+    pub fn line(&self, name: &str) -> Result<Fields<'_>, String> {
+        let mut found = self.lines.iter().filter(|line| line.name == name);
+        match (found.next(), found.next()) {
+            (Some(line), None) => Ok(line.fields()),
+            (None, _) => Err(format!("The snapshot has no {} line", name)),
+            (Some(_), Some(_)) => Err(format!("The snapshot has more than one {} line", name)),
+        }
+    }
+
+    /// Every line called `name`, in order.
+    pub fn all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = Fields<'a>> + 'a {
+        self.lines
+            .iter()
+            .filter(move |line| line.name == name)
+            .map(Line::fields)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = "# Simulation state saved by road_intersection; restore with --load or F9\n"
+            .to_string();
+        for line in &self.lines {
+            text.push_str(&line.name);
+            for word in &line.words {
+                text.push(' ');
+                text.push_str(word);
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Reads back what `to_text` wrote.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = Vec::new();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let name = words.next().unwrap_or_default().to_string();
+            lines.push(Line {
+                name,
+                words: words.map(str::to_string).collect(),
+            });
+        }
+        Ok(Self { lines })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        Self::parse(&contents).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.to_text())
+            .map_err(|e| format!("Could not write {}: {}", path.display(), e))
+    }
+}
+
+/// Every field of the vehicle, in declaration order, but its traction, world
+/// and tick rate, which come from the configuration the snapshot is restored
+/// into.
+pub fn vehicle_line(vehicle: &Vehicle) -> Line {
+    put_vehicle(Line::new("vehicle"), vehicle)
+}
+
+/// A vehicle fading out as it leaves: the ticks it has left, then the
+/// vehicle as `vehicle_line` writes it. `read_vehicle` reads the rest once
+/// the ticks are taken.
+pub fn fade_line(ticks_left: u32, vehicle: &Vehicle) -> Line {
+    put_vehicle(Line::new("fade").put(ticks_left), vehicle)
+}
+
+fn put_vehicle(line: Line, vehicle: &Vehicle) -> Line {
+    line.put(vehicle.id)
+        .put(vehicle.x)
+        .put(vehicle.y)
+        .put(vehicle.prev_x)
+        .put(vehicle.prev_y)
+        .put(vehicle.direction)
+        .put(vehicle.initial_direction)
+        .put(vehicle.route)
+        .put(vehicle.color)
+        .put(vehicle.frozen)
+        .put(vehicle.held)
+        .put(vehicle.delay)
+        .put(vehicle.cruise_speed)
+        .put(vehicle.velocity)
+        .put(vehicle.acceleration)
+        .put(vehicle.max_jerk)
+        .put_all(vehicle.speed_limits)
+        .put(vehicle.turn_speed)
+        .put(vehicle.drawn_turn_radius)
+        .put(vehicle.circulates)
+        .put(vehicle.kind)
+        .put(vehicle.render_heading)
+        .put(vehicle.length)
+        .put(vehicle.width)
+        .put(vehicle.stop_setback)
+        .put(vehicle.reaction_ticks)
+        .put(vehicle.reaction_timer)
+        .put(vehicle.stopped_at_line)
+        .put(vehicle.runs_yellow)
+        .put(vehicle.aggressiveness)
+        .put(vehicle.tie_break)
+        .put(vehicle.spawned_at)
+        .put(vehicle.driven)
+}
+
+pub fn read_vehicle(mut fields: Fields) -> Result<Vehicle, String> {
+    let vehicle = Vehicle {
+        id: fields.take()?,
+        x: fields.take()?,
+        y: fields.take()?,
+        prev_x: fields.take()?,
+        prev_y: fields.take()?,
+        direction: fields.take()?,
+        initial_direction: fields.take()?,
+        route: fields.take()?,
+        color: fields.take()?,
+        frozen: fields.take()?,
+        held: fields.take()?,
+        delay: fields.take()?,
+        cruise_speed: fields.take()?,
+        traction: Traction::DRY,
+        velocity: fields.take()?,
+        acceleration: fields.take()?,
+        max_jerk: fields.take()?,
+        speed_limits: fields.take_array()?,
+        turn_speed: fields.take()?,
+        drawn_turn_radius: fields.take()?,
+        circulates: fields.take()?,
+        kind: fields.take()?,
+        render_heading: fields.take()?,
+        length: fields.take()?,
+        width: fields.take()?,
+        stop_setback: fields.take()?,
+        world: World::default(),
+        tick_rate: TICK_RATE,
+        reaction_ticks: fields.take()?,
+        reaction_timer: fields.take()?,
+        stopped_at_line: fields.take()?,
+        runs_yellow: fields.take()?,
+        aggressiveness: fields.take()?,
+        tie_break: fields.take()?,
+        spawned_at: fields.take()?,
+        driven: fields.take()?,
+    };
+    fields.finish()?;
+    Ok(vehicle)
+}
+
+pub fn pedestrian_line(pedestrian: &Pedestrian) -> Line {
+    Line::new("pedestrian")
+        .put(pedestrian.id)
+        .put(pedestrian.arm)
+        .put(pedestrian.side)
+        .put(pedestrian.walked)
+        .put(pedestrian.walking)
+        .put(pedestrian.waited)
+}
+
+pub fn read_pedestrian(mut fields: Fields) -> Result<Pedestrian, String> {
+    let pedestrian = Pedestrian {
+        id: fields.take()?,
+        arm: fields.take()?,
+        side: fields.take()?,
+        walked: fields.take()?,
+        walking: fields.take()?,
+        waited: fields.take()?,
+    };
+    fields.finish()?;
+    Ok(pedestrian)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_read_back_to_the_same_values() {
+        let line = Line::new("sample")
+            .put(0.1f32 + 0.2)
+            .put(f32::INFINITY)
+            .put(Some(Direction::Left))
+            .put(None::<u64>)
+            .put(Color::RGB(1, 2, 3))
+            .put(Stage::AllRed)
+            .put_all([4usize, 5]);
+        let mut snapshot = Snapshot::default();
+        snapshot.push(line);
+        let text = snapshot.to_text();
+        assert!(text.ends_with("sample 0.3 inf left - 1,2,3 all-red 4 5\n"));
+
+        let read = Snapshot::parse(&text).unwrap();
+        assert_eq!(read, snapshot);
+        let mut fields = read.line("sample").unwrap();
+        assert_eq!(
+            fields.take::<f32>().unwrap().to_bits(),
+            (0.1f32 + 0.2).to_bits()
+        );
+        assert_eq!(fields.take::<f32>().unwrap(), f32::INFINITY);
+        assert_eq!(
+            fields.take::<Option<Direction>>().unwrap(),
+            Some(Direction::Left)
+        );
+        assert_eq!(fields.take::<Option<u64>>().unwrap(), None);
+        assert_eq!(fields.take::<Color>().unwrap(), Color::RGB(1, 2, 3));
+        assert_eq!(fields.take::<Stage>().unwrap(), Stage::AllRed);
+        assert_eq!(fields.take_rest::<usize>().unwrap(), [4, 5]);
+        fields.finish().unwrap();
+
+        assert!(read.line("missing").is_err());
+        let mut fields = read.line("sample").unwrap();
+        assert!(fields.take::<bool>().is_err());
+        assert_eq!(
+            read.line("sample").unwrap().finish(),
+            Err("Too many values on the sample line".to_string())
+        );
+    }
+}
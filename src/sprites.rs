@@ -0,0 +1,127 @@
+use crate::render::Renderer;
+use crate::vehicle::VehicleKind;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, Texture, TextureCreator};
+use sdl2::video::{Window, WindowContext};
+use std::path::Path;
+
+/// The picture each kind is drawn with. Emergency vehicles are cars,
+/// told apart by their flashing tint.
+#[cfg(feature = "sprites")]
+fn file_name(kind: VehicleKind) -> &'static str {
+    match kind {
+        VehicleKind::Car | VehicleKind::Emergency => "car.png",
+        VehicleKind::Truck => "truck.png",
+        VehicleKind::Motorcycle => "motorcycle.png",
+    }
+}
+
+/// A texture per vehicle kind. The pictures point right and are pale, so
+/// the colour a vehicle is tinted with shows through.
+pub struct SpriteSheet<'t> {
+    textures: Vec<(VehicleKind, Texture<'t>)>,
+    #[cfg(feature = "sprites")]
+    _context: sdl2::image::Sdl2ImageContext,
+}
+
+impl<'t> SpriteSheet<'t> {
+    /// Loads every kind's picture from `dir`, failing if any is missing.
+    #[cfg(feature = "sprites")]
+    pub fn load(creator: &'t TextureCreator<WindowContext>, dir: &Path) -> Result<Self, String> {
+        use sdl2::image::{InitFlag, LoadTexture};
+
+        let context = sdl2::image::init(InitFlag::PNG)
+            .map_err(|e| format!("Could not initialize SDL_image: {}", e))?;
+        let mut textures = Vec::new();
+        for kind in VehicleKind::ALL {
+            let path = dir.join(file_name(kind));
+            let texture = creator
+                .load_texture(&path)
+                .map_err(|e| format!("Could not load {}: {}", path.display(), e))?;
+            textures.push((kind, texture));
+        }
+        Ok(Self {
+            textures,
+            _context: context,
+        })
+    }
+
+    #[cfg(not(feature = "sprites"))]
+    pub fn load(_creator: &'t TextureCreator<WindowContext>, _dir: &Path) -> Result<Self, String> {
+        Err("Built without the sprites feature".to_string())
+    }
+
+    fn texture(&mut self, kind: VehicleKind) -> Option<&mut Texture<'t>> {
+        self.textures
+            .iter_mut()
+            .find(|(textured, _)| *textured == kind)
+            .map(|(_, texture)| texture)
+    }
+}
+
+/// The window, drawing vehicles from `sheet` when there is one.
+pub struct SpriteCanvas<'a, 't> {
+    canvas: &'a mut Canvas<Window>,
+    sheet: Option<&'a mut SpriteSheet<'t>>,
+}
+
+impl<'a, 't> SpriteCanvas<'a, 't> {
+    pub fn new(canvas: &'a mut Canvas<Window>, sheet: Option<&'a mut SpriteSheet<'t>>) -> Self {
+        Self { canvas, sheet }
+    }
+}
+
+impl Renderer for SpriteCanvas<'_, '_> {
+    fn set_draw_color(&mut self, color: Color) {
+        Renderer::set_draw_color(self.canvas, color);
+    }
+
+    fn clear(&mut self) {
+        Renderer::clear(self.canvas);
+    }
+
+    fn fill_rect(&mut self, rect: Rect) -> Result<(), String> {
+        Renderer::fill_rect(self.canvas, rect)
+    }
+
+    fn draw_rect(&mut self, rect: Rect) -> Result<(), String> {
+        Renderer::draw_rect(self.canvas, rect)
+    }
+
+    fn draw_line(&mut self, start: (i32, i32), end: (i32, i32)) -> Result<(), String> {
+        Renderer::draw_line(self.canvas, start, end)
+    }
+
+    fn present(&mut self) {
+        Renderer::present(self.canvas);
+    }
+
+    fn draw_sprite(
+        &mut self,
+        kind: VehicleKind,
+        centre: (i32, i32),
+        (length, width): (u32, u32),
+        angle: f32,
+        tint: Color,
+    ) -> Result<bool, String> {
+        let Some(texture) = self.sheet.as_mut().and_then(|sheet| sheet.texture(kind)) else {
+            return Ok(false);
+        };
+        texture.set_color_mod(tint.r, tint.g, tint.b);
+        // Vehicles fading out have a translucent tint.
+        texture.set_alpha_mod(tint.a);
+        // Turned about its centre, so the rectangle is laid out unturned.
+        let rect = Rect::from_center(centre, length, width);
+        self.canvas.copy_ex(
+            texture,
+            None,
+            rect,
+            angle.to_degrees() as f64,
+            None,
+            false,
+            false,
+        )?;
+        Ok(true)
+    }
+}
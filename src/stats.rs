@@ -0,0 +1,316 @@
+use crate::direction::Direction;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// How long a metrics client may take to send its request line before it
+/// is dropped, so one that never does cannot hold up the scrapers behind it.
+const READ_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A snapshot of the simulation's counters, taken once per frame.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Stats {
+    pub tick: u64,
+    pub simulated_seconds: f64,
+    pub vehicles: usize,
+    /// Vehicles yet to reach the stop line, indexed by `Direction::index`.
+    pub queues: [usize; 4],
+    pub spawned: u64,
+    pub exited: usize,
+    pub collisions: usize,
+    /// Collisions by severity, minor then major.
+    pub collision_severity: [usize; 2],
+    /// `TrafficSimulation::cost` so far.
+    pub cost: f64,
+    /// Exits per simulated minute since the start.
+    pub throughput: f64,
+    /// What decides entry, as `Config::control_name` gives it, so runs
+    /// under different control can be told apart.
+    pub control: &'static str,
+    /// The weather, as `Weather::name` gives it, so runs in the rain can be
+    /// told from clear ones.
+    pub weather: &'static str,
+    /// Exits by origin approach, then by the direction they drove off in;
+    /// both indexed by `Direction::index`.
+    pub trips: [[usize; 4]; 4],
+}
+
+impl Stats {
+    /// The Prometheus text exposition format, one gauge or counter per
+    /// metric and one sample per approach for the queues.
+    pub fn prometheus(&self) -> String {
+        let mut text = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, String)]| {
+            text.push_str(&format!("# HELP {} {}\n", name, help));
+            text.push_str(&format!("# TYPE {} {}\n", name, kind));
+            for (labels, value) in samples {
+                text.push_str(&format!("{}{} {}\n", name, labels, value));
+            }
+        };
+        let plain = |value: String| [(String::new(), value)];
+
+        metric(
+            "intersection_ticks_total",
+            "counter",
+            "Simulation ticks run.",
+            &plain(self.tick.to_string()),
+        );
+        metric(
+            "intersection_simulated_seconds",
+            "gauge",
+            "Simulated time since the start.",
+            &plain(self.simulated_seconds.to_string()),
+        );
+        metric(
+            "intersection_vehicles",
+            "gauge",
+            "Vehicles currently on the road.",
+            &plain(self.vehicles.to_string()),
+        );
+        let queues: Vec<(String, String)> = Direction::ALL
+            .into_iter()
+            .map(|approach| {
+                (
+                    format!("{{approach=\"{}\"}}", approach),
+                    self.queues[approach.index()].to_string(),
+                )
+            })
+            .collect();
+        metric(
+            "intersection_queue_length",
+            "gauge",
+            "Vehicles yet to reach the stop line.",
+            &queues,
+        );
+        metric(
+            "intersection_spawns_total",
+            "counter",
+            "Vehicles spawned.",
+            &plain(self.spawned.to_string()),
+        );
+        metric(
+            "intersection_exits_total",
+            "counter",
+            "Vehicles that drove off the screen.",
+            &plain(self.exited.to_string()),
+        );
+        metric(
+            "intersection_collisions_total",
+            "counter",
+            "Distinct pairs of vehicles that collided.",
+            &plain(self.collisions.to_string()),
+        );
+        let severity: Vec<(String, String)> = ["minor", "major"]
+            .into_iter()
+            .zip(self.collision_severity)
+            .map(|(name, count)| (format!("{{severity=\"{}\"}}", name), count.to_string()))
+            .collect();
+        metric(
+            "intersection_collisions_by_severity_total",
+            "counter",
+            "Collisions by how fast the vehicles closed.",
+            &severity,
+        );
+        let trips: Vec<(String, String)> = Direction::ALL
+            .into_iter()
+            .flat_map(|origin| Direction::ALL.into_iter().map(move |exit| (origin, exit)))
+            .map(|(origin, exit)| {
+                (
+                    format!("{{origin=\"{}\",exit=\"{}\"}}", origin, exit),
+                    self.trips[origin.index()][exit.index()].to_string(),
+                )
+            })
+            .collect();
+        metric(
+            "intersection_trips_total",
+            "counter",
+            "Exits by origin approach and the direction they drove off in.",
+            &trips,
+        );
+        metric(
+            "intersection_cost",
+            "gauge",
+            "Weighted cost of the run so far; lower is better.",
+            &plain(self.cost.to_string()),
+        );
+        metric(
+            "intersection_throughput_per_minute",
+            "gauge",
+            "Exits per simulated minute since the start.",
+            &plain(self.throughput.to_string()),
+        );
+        metric(
+            "intersection_control_info",
+            "gauge",
+            "Always 1; the label names what decides who enters.",
+            &[(format!("{{control=\"{}\"}}", self.control), "1".to_string())],
+        );
+        metric(
+            "intersection_weather_info",
+            "gauge",
+            "Always 1; the label names the weather.",
+            &[(format!("{{weather=\"{}\"}}", self.weather), "1".to_string())],
+        );
+        text
+    }
+
+    /// One JSON object with a field per counter, the queues and trips keyed
+    /// by approach.
+    pub fn json(&self) -> String {
+        let by_approach = |values: [usize; 4]| {
+            let fields: Vec<String> = Direction::ALL
+                .into_iter()
+                .map(|approach| format!("\"{}\": {}", approach, values[approach.index()]))
+                .collect();
+            format!("{{{}}}", fields.join(", "))
+        };
+        let trips: Vec<String> = Direction::ALL
+            .into_iter()
+            .map(|origin| {
+                format!(
+                    "\"{}\": {}",
+                    origin,
+                    by_approach(self.trips[origin.index()])
+                )
+            })
+            .collect();
+        format!(
+            "{{\"tick\": {}, \"simulated_seconds\": {:.3}, \"vehicles\": {}, \"queues\": {}, \"spawned\": {}, \"exited\": {}, \"collisions\": {}, \"minor_collisions\": {}, \"major_collisions\": {}, \"cost\": {:.3}, \"throughput\": {:.3}, \"control\": \"{}\", \"weather\": \"{}\", \"trips\": {{{}}}}}",
+            self.tick,
+            self.simulated_seconds,
+            self.vehicles,
+            by_approach(self.queues),
+            self.spawned,
+            self.exited,
+            self.collisions,
+            self.collision_severity[0],
+            self.collision_severity[1],
+            self.cost,
+            self.throughput,
+            self.control,
+            self.weather,
+            trips.join(", ")
+        )
+    }
+}
+
+/// Answers `GET /metrics` on `address` from a background thread with the
+/// latest snapshot in `stats`; every other path gets a 404. A bare port
+/// listens on localhost only, as `Remote::listen` does. Returns where it
+/// listens, with the port picked if 0 was asked for.
+pub fn serve(address: &str, stats: Arc<Mutex<Stats>>) -> Result<SocketAddr, String> {
+    let address = if address.parse::<u16>().is_ok() {
+        format!("127.0.0.1:{}", address)
+    } else {
+        address.to_string()
+    };
+    let listener = TcpListener::bind(&address)
+        .map_err(|e| format!("Could not listen on {}: {}", address, e))?;
+    let bound = listener
+        .local_addr()
+        .map_err(|e| format!("Could not listen on {}: {}", address, e))?;
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else {
+                continue;
+            };
+            if stream.set_read_timeout(Some(READ_TIMEOUT)).is_err() {
+                continue;
+            }
+            let mut request_line = String::new();
+            if BufReader::new(&stream)
+                .read_line(&mut request_line)
+                .is_err()
+            {
+                continue;
+            }
+            let path = request_line.split_whitespace().nth(1).unwrap_or("");
+            let (status, body) = if path == "/metrics" {
+                let body = stats.lock().map(|stats| stats.prometheus());
+                ("200 OK", body.unwrap_or_default())
+            } else {
+                ("404 Not Found", String::new())
+            };
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            if let Err(e) = stream.write_all(response.as_bytes()) {
+                warn!("Could not answer a metrics request: {}", e);
+            }
+        }
+    });
+    info!("Serving metrics on {}", bound);
+    Ok(bound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prometheus_text_has_a_sample_per_metric_and_approach() {
+        let stats = Stats {
+            tick: 120,
+            simulated_seconds: 2.0,
+            vehicles: 3,
+            queues: [2, 0, 1, 0],
+            spawned: 7,
+            exited: 4,
+            collisions: 1,
+            collision_severity: [0, 1],
+            cost: -2.5,
+            throughput: 12.5,
+            control: "roundabout",
+            weather: "rain",
+            trips: [[1, 0, 2, 0], [0; 4], [0, 0, 0, 1], [0; 4]],
+        };
+        let text = stats.prometheus();
+        assert!(text.contains("# TYPE intersection_vehicles gauge\nintersection_vehicles 3\n"));
+        assert!(text.contains("intersection_queue_length{approach=\"up\"} 2\n"));
+        assert!(text.contains("intersection_queue_length{approach=\"right\"} 1\n"));
+        assert!(text.contains("# TYPE intersection_spawns_total counter\n"));
+        assert!(text.contains("intersection_exits_total 4\n"));
+        let samples = text.lines().filter(|line| !line.starts_with('#')).count();
+        assert!(text.contains("intersection_collisions_by_severity_total{severity=\"major\"} 1\n"));
+        assert!(text.contains("intersection_cost -2.5\n"));
+        assert!(text.contains("intersection_trips_total{origin=\"up\",exit=\"right\"} 2\n"));
+        assert!(text.contains("intersection_throughput_per_minute 12.5\n"));
+        assert!(text.contains("intersection_control_info{control=\"roundabout\"} 1\n"));
+        assert!(text.contains("intersection_weather_info{weather=\"rain\"} 1\n"));
+        assert_eq!(samples, 7 + 4 + 2 + 16 + 3);
+        assert!(text.ends_with('\n'));
+
+        let json = stats.json();
+        assert!(json.starts_with("{\"tick\": 120, \"simulated_seconds\": 2.000, \"vehicles\": 3, \"queues\": {\"up\": 2, \"down\": 0, \"right\": 1, \"left\": 0},"));
+        assert!(json.contains("\"major_collisions\": 1, \"cost\": -2.500,"));
+        assert!(json.contains("\"control\": \"roundabout\", \"weather\": \"rain\","));
+        assert!(json.ends_with("\"left\": {\"up\": 0, \"down\": 0, \"right\": 0, \"left\": 0}}}"));
+    }
+
+    #[test]
+    fn a_silent_client_does_not_hold_up_the_next_scrape() {
+        use std::io::Read;
+        use std::net::TcpStream;
+
+        let shared = Arc::new(Mutex::new(Stats {
+            tick: 42,
+            ..Stats::default()
+        }));
+        let address = serve("0", Arc::clone(&shared)).unwrap();
+        assert!(address.ip().is_loopback());
+
+        let _silent = TcpStream::connect(address).unwrap();
+        let mut scraper = TcpStream::connect(address).unwrap();
+        scraper.set_read_timeout(Some(READ_TIMEOUT * 5)).unwrap();
+        scraper.write_all(b"GET /metrics HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = String::new();
+        scraper.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        assert!(response.contains("42"), "{}", response);
+    }
+}
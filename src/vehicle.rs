@@ -0,0 +1,1029 @@
+use crate::config::ColorMode;
+use crate::conflicts::Movement;
+use crate::direction::{Direction, Route};
+use crate::environment::Traction;
+use crate::geometry::{TICK_RATE, VEHICLE_SIZE, VEHICLE_SPEED, World};
+use rand::Rng;
+use rand::rngs::StdRng;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+
+const GLYPH_SIZE: u32 = 10;
+
+/// Pixels of free road a stopped vehicle needs ahead before it moves off.
+const PULL_AWAY_ROOM: f32 = VEHICLE_SPEED;
+
+/// Fraction of the remaining turn the drawn heading catches up each tick.
+const HEADING_EASE: f32 = 0.35;
+
+/// Pixels per second squared a vehicle may pull sideways in a turn, which
+/// caps its speed through tight ones.
+const LATERAL_ACCELERATION: f32 = 360.0;
+
+/// Fastest speed, in pixels per second, through a turn of `radius` pixels;
+/// 0 leaves turns uncapped.
+pub fn turn_speed(radius: f32) -> f32 {
+    if radius > 0.0 {
+        (LATERAL_ACCELERATION * radius).sqrt()
+    } else {
+        f32::INFINITY
+    }
+}
+
+/// Why a vehicle left the simulation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DespawnReason {
+    /// Drove off the screen: throughput.
+    Exited,
+    /// Removed by the `remove` collision policy: a loss.
+    Collided,
+    /// Taken back after landing on another vehicle's spawn point, to spawn
+    /// again later.
+    Respawned,
+}
+
+impl DespawnReason {
+    pub const ALL: [DespawnReason; 3] = [
+        DespawnReason::Exited,
+        DespawnReason::Collided,
+        DespawnReason::Respawned,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DespawnReason::Exited => "exited",
+            DespawnReason::Collided => "collided",
+            DespawnReason::Respawned => "respawned",
+        }
+    }
+}
+
+/// What sort of vehicle it is, which sets its size, how fast it cruises and
+/// how hard it can speed up and brake.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum VehicleKind {
+    #[default]
+    Car,
+    /// Long and slow, and kept well clear of.
+    Truck,
+    /// Car-sized, and given the right of way over everything else.
+    Emergency,
+    /// Small, nippy and a little faster than a car.
+    Motorcycle,
+}
+
+impl VehicleKind {
+    pub const ALL: [VehicleKind; 4] = [
+        VehicleKind::Car,
+        VehicleKind::Truck,
+        VehicleKind::Emergency,
+        VehicleKind::Motorcycle,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            VehicleKind::Car => "car",
+            VehicleKind::Truck => "truck",
+            VehicleKind::Emergency => "emergency",
+            VehicleKind::Motorcycle => "motorcycle",
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Self, String> {
+        VehicleKind::ALL
+            .into_iter()
+            .find(|kind| kind.name() == value)
+            .ok_or_else(|| format!("Unknown vehicle kind: {}", value))
+    }
+
+    /// Pixels per second gained per second.
+    pub fn acceleration(self) -> f32 {
+        match self {
+            VehicleKind::Car | VehicleKind::Emergency => 252.0,
+            VehicleKind::Truck => 108.0,
+            VehicleKind::Motorcycle => 432.0,
+        }
+    }
+
+    /// Bumper-to-bumper length in pixels, given a car's.
+    pub fn length(self, car_length: f32) -> f32 {
+        match self {
+            VehicleKind::Car | VehicleKind::Emergency => car_length,
+            VehicleKind::Truck => car_length * 1.6,
+            VehicleKind::Motorcycle => car_length * 0.55,
+        }
+    }
+
+    /// Width in pixels, given a car's.
+    pub fn width(self, car_width: f32) -> f32 {
+        match self {
+            VehicleKind::Motorcycle => car_width * 0.5,
+            _ => car_width,
+        }
+    }
+
+    /// Cruise speed as a share of a car's.
+    pub fn speed_factor(self) -> f32 {
+        match self {
+            VehicleKind::Car | VehicleKind::Emergency => 1.0,
+            VehicleKind::Truck => 0.8,
+            VehicleKind::Motorcycle => 1.2,
+        }
+    }
+
+    /// How much longer than the configured following gap is kept between
+    /// this kind and a vehicle it follows or is followed by.
+    pub fn gap_factor(self) -> f32 {
+        match self {
+            VehicleKind::Truck => 1.5,
+            _ => 1.0,
+        }
+    }
+
+    /// Pixels per second shed per second under normal braking.
+    pub fn deceleration(self) -> f32 {
+        match self {
+            VehicleKind::Car | VehicleKind::Emergency => 540.0,
+            VehicleKind::Truck => 216.0,
+            VehicleKind::Motorcycle => 720.0,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Vehicle {
+    pub id: u64,
+    pub x: f32,
+    pub y: f32,
+    pub prev_x: f32,
+    pub prev_y: f32,
+    pub direction: Direction,
+    pub initial_direction: Direction,
+    pub route: Route,
+    pub color: Color,
+    pub frozen: bool,
+    /// Held in place by a mouse drag; skips its updates until let go.
+    pub held: bool,
+    pub delay: f32,
+    /// Pixels per tick the vehicle drives at when nothing holds it back.
+    pub cruise_speed: f32,
+    /// What the weather leaves it of its top speed and braking.
+    pub traction: Traction,
+    /// Pixels per tick moved on the last tick.
+    pub velocity: f32,
+    /// Change in `velocity` over the last tick.
+    pub acceleration: f32,
+    /// Pixels per tick cubed by which `acceleration` may change each tick;
+    /// infinite when unlimited.
+    pub max_jerk: f32,
+    /// Pixels per tick allowed in the lane for each direction of travel,
+    /// indexed by `Direction::index`; the one for `direction` applies, so a
+    /// turn can change it.
+    pub speed_limits: [f32; 4],
+    /// Pixels per tick the vehicle slows to for its turn, from its stop
+    /// line until it has turned out of the box; infinite when uncapped.
+    pub turn_speed: f32,
+    /// Radius in pixels of the arc the vehicle is drawn along through its
+    /// turn; 0 draws it turning on the spot.
+    pub drawn_turn_radius: f32,
+    /// Drawn going round a roundabout's ring while in the box, instead of
+    /// along its lanes.
+    pub circulates: bool,
+    pub kind: VehicleKind,
+    /// The heading the vehicle is drawn with, in radians as for
+    /// `Direction::angle`. It eases towards `direction` after a turn
+    /// instead of snapping.
+    pub render_heading: f32,
+    /// Bumper to bumper, along the direction of travel.
+    pub length: f32,
+    /// Across the direction of travel.
+    pub width: f32,
+    /// Distance from the centre to this vehicle's stop line.
+    pub stop_setback: f32,
+    /// The world the vehicle drives across, which places its lanes, its
+    /// stop line and the box.
+    pub world: World,
+    /// Ticks per second of the run it drives in, which turns its kind's
+    /// rates per second into steps per tick.
+    pub tick_rate: f32,
+    /// Ticks the vehicle waits, once stopped and free to move again, before
+    /// it pulls away; `reaction_timer` counts them off.
+    pub reaction_ticks: u32,
+    pub reaction_timer: u32,
+    /// Tick on which the vehicle came to a halt at its stop line, which
+    /// sets its turn at an all-way stop.
+    pub stopped_at_line: Option<u64>,
+    /// Decided when its yellow came on: whether it was too close to its
+    /// line to stop, and so goes on through.
+    pub runs_yellow: Option<bool>,
+    /// From 0 for a careful driver to 1 for one who goes on through a
+    /// yellow from twice its stopping distance and leaves half the gap
+    /// that grows with speed.
+    pub aggressiveness: f32,
+    /// Drawn from the tie-break stream at spawn; the lower value goes first
+    /// when two vehicles have an equal claim.
+    pub tie_break: u64,
+    /// Tick the vehicle was put on the road.
+    pub spawned_at: u64,
+    /// Pixels it has driven since, along every turn it took.
+    pub driven: f32,
+}
+
+/// Half the width and height of a `length` by `width` footprint heading
+/// `direction`; the length runs along the direction of travel.
+pub fn half_extents(length: f32, width: f32, direction: Direction) -> (f32, f32) {
+    match direction {
+        Direction::Up | Direction::Down => (width / 2.0, length / 2.0),
+        Direction::Right | Direction::Left => (length / 2.0, width / 2.0),
+    }
+}
+
+/// A bright colour whose hue steps round the wheel by the golden ratio per
+/// id, so vehicles close in id still look clearly different.
+fn color_for_id(id: u64) -> Color {
+    let hue = (id as f64 * 0.618_033_988_75).fract() * 6.0;
+    let sector = hue as u8;
+    let rising = ((hue - sector as f64) * 191.0) as u8 + 64;
+    let falling = 255 - rising + 64;
+    let (r, g, b) = match sector {
+        0 => (255, rising, 64),
+        1 => (falling, 255, 64),
+        2 => (64, 255, rising),
+        3 => (64, falling, 255),
+        4 => (rising, 64, 255),
+        _ => (255, 64, falling),
+    };
+    Color::RGB(r, g, b)
+}
+
+/// Where vehicles heading `direction` enter `world`.
+pub fn spawn_position(world: World, direction: Direction) -> (f32, f32) {
+    let lane = lane_centre(world, direction);
+    let (width, height) = (world.width as f32, world.height as f32);
+    match direction {
+        Direction::Up => (lane, height),
+        Direction::Down => (lane, 0.0),
+        Direction::Right => (0.0, lane),
+        Direction::Left => (width, lane),
+    }
+}
+
+/// The line, across `direction` (x for up and down, y otherwise), that
+/// every vehicle heading that way drives along, whether it entered that way
+/// or turned into it.
+pub fn lane_centre(world: World, direction: Direction) -> f32 {
+    let (center_x, center_y) = world.centre();
+    let offset = world.lane_width as f32;
+    match direction {
+        Direction::Up => center_x - offset,
+        Direction::Down => center_x + offset,
+        Direction::Right => center_y + offset,
+        Direction::Left => center_y - offset,
+    }
+}
+
+// Where the lane for `heading` crosses the edge of the box: on the arm it
+// comes in from if `entering`, otherwise on the arm it leaves by.
+fn lane_at_box_edge(world: World, heading: Direction, entering: bool) -> (f32, f32) {
+    let half_road = world.half_road();
+    let (center_x, center_y) = world.centre();
+    let (dx, dy) = heading.delta();
+    let side = if entering { -half_road } else { half_road };
+    match heading {
+        Direction::Up | Direction::Down => {
+            (lane_centre(world, heading), center_y + dy as f32 * side)
+        }
+        Direction::Right | Direction::Left => {
+            (center_x + dx as f32 * side, lane_centre(world, heading))
+        }
+    }
+}
+
+// Where the lane heading `from` meets the one heading `to`, which is where
+// a vehicle turns from one into the other.
+fn corner(world: World, from: Direction, to: Direction) -> (f32, f32) {
+    match from {
+        Direction::Up | Direction::Down => (lane_centre(world, from), lane_centre(world, to)),
+        Direction::Right | Direction::Left => (lane_centre(world, to), lane_centre(world, from)),
+    }
+}
+
+/// The way a vehicle that entered heading `initial` on `route` should be
+/// heading at `(x, y)`: `initial` until it reaches its turn line, the
+/// direction it turns into from then on. A U-turn's second leg takes it
+/// back across its first turn line, so the last line reached decides.
+pub fn next_direction(world: World, initial: Direction, route: Route, x: f32, y: f32) -> Direction {
+    let headings = route.headings(initial);
+    for leg in headings.windows(2).rev() {
+        let (heading, turned) = (leg[0], leg[1]);
+        let turn = lane_centre(world, turned);
+        let reached = match heading {
+            Direction::Up => y <= turn,
+            Direction::Down => y >= turn,
+            Direction::Right => x >= turn,
+            Direction::Left => x <= turn,
+        };
+        if reached {
+            return turned;
+        }
+    }
+    initial
+}
+
+// Where along its approach a vehicle on `route` first turns in `world`: on
+// the lane it turns into.
+fn turn_coordinate_in(world: World, approach: Direction, route: Route) -> Option<f32> {
+    let turned = *route.headings(approach).get(1)?;
+    Some(lane_centre(world, turned))
+}
+
+/// Checks that a vehicle entering `world` from `approach` can drive
+/// `route`: it must cover at least its own length
+/// before it turns, so it neither turns on the spawn point nor starts past
+/// its turn, and the turn must lie beyond the stop line `setback` from the
+/// centre.
+pub fn check_route_geometry(
+    approach: Direction,
+    route: Route,
+    setback: f32,
+    world: World,
+) -> Result<(), String> {
+    let center = world.centre();
+    let Some(turn) = turn_coordinate_in(world, approach, route) else {
+        return Ok(());
+    };
+    let (x, y) = spawn_position(world, approach);
+    let (to_turn, turn_from_center) = match approach {
+        Direction::Up => (y - turn, center.1 - turn),
+        Direction::Down => (turn - y, turn - center.1),
+        Direction::Right => (turn - x, turn - center.0),
+        Direction::Left => (x - turn, center.0 - turn),
+    };
+    let movement = Movement { approach, route }.name();
+    if to_turn < VEHICLE_SIZE as f32 {
+        return Err(format!(
+            "A {}x{} world leaves no room before the {} turn",
+            world.width, world.height, movement
+        ));
+    }
+    if turn_from_center < -setback {
+        return Err(format!(
+            "The {} turn would come before its stop line",
+            movement
+        ));
+    }
+    Ok(())
+}
+
+impl Vehicle {
+    pub fn new(
+        id: u64,
+        direction: Direction,
+        route: Route,
+        color_mode: ColorMode,
+        rng: &mut StdRng,
+    ) -> Self {
+        let world = World::default();
+        let (x, y) = spawn_position(world, direction);
+
+        let color = match color_mode {
+            ColorMode::ByRoute => match route {
+                Route::Straight => Color::RGB(200, 200, 200),
+                Route::TurnLeft => Color::RGB(100, 255, 100),
+                Route::TurnRight => Color::RGB(100, 100, 255),
+                Route::UTurn => Color::RGB(255, 120, 80),
+            },
+            ColorMode::ByDirection => match direction {
+                Direction::Up => Color::RGB(255, 165, 0),
+                Direction::Down => Color::RGB(255, 100, 255),
+                Direction::Right => Color::RGB(100, 255, 255),
+                Direction::Left => Color::RGB(255, 255, 100),
+            },
+            ColorMode::ById => color_for_id(id),
+            ColorMode::Random => Color::RGB(
+                rng.random_range(64..=255),
+                rng.random_range(64..=255),
+                rng.random_range(64..=255),
+            ),
+        };
+
+        Self {
+            id,
+            x,
+            y,
+            prev_x: x,
+            prev_y: y,
+            direction,
+            initial_direction: direction,
+            route,
+            color,
+            frozen: false,
+            held: false,
+            delay: 0.0,
+            cruise_speed: VEHICLE_SPEED,
+            traction: Traction::DRY,
+            velocity: VEHICLE_SPEED,
+            acceleration: 0.0,
+            max_jerk: f32::INFINITY,
+            speed_limits: [f32::INFINITY; 4],
+            turn_speed: f32::INFINITY,
+            drawn_turn_radius: 0.0,
+            circulates: false,
+            kind: VehicleKind::Car,
+            render_heading: direction.angle(),
+            length: VEHICLE_SIZE as f32,
+            width: VEHICLE_SIZE as f32,
+            stop_setback: world.default_stop_setback(),
+            world,
+            tick_rate: TICK_RATE,
+            reaction_ticks: 0,
+            reaction_timer: 0,
+            stopped_at_line: None,
+            runs_yellow: None,
+            aggressiveness: 0.0,
+            tie_break: 0,
+            spawned_at: 0,
+            driven: 0.0,
+        }
+    }
+
+    /// Moves one tick. `max_step` caps the advance, e.g. to keep a gap to the
+    /// vehicle ahead; `brake_room` is how far ahead the vehicle may need to
+    /// have stopped, which decides when it starts braking.
+    pub fn update(&mut self, green: bool, max_step: f32, brake_room: f32) {
+        self.prev_x = self.x;
+        self.prev_y = self.y;
+
+        if self.frozen || self.held {
+            self.velocity = 0.0;
+            self.acceleration = 0.0;
+            return;
+        }
+
+        let direction = next_direction(
+            self.world,
+            self.initial_direction,
+            self.route,
+            self.x,
+            self.y,
+        );
+        if direction != self.direction {
+            trace!("Vehicle {} turned {}", self.id, direction);
+            self.direction = direction;
+            // Turning about the centre would swing a long vehicle's tail back
+            // across the lane it turns into, so bring the tail round only as
+            // far as the side it had there.
+            let (dx, dy) = self.direction.delta();
+            let pivot = (self.length - self.width) / 2.0;
+            self.x += dx as f32 * pivot;
+            self.y += dy as f32 * pivot;
+        }
+
+        let mut limit = max_step;
+        let mut brake_room = brake_room;
+        let to_stop_line = self.stop_line_distance();
+        if !green && to_stop_line >= 0.0 {
+            limit = limit.min(to_stop_line);
+            brake_room = brake_room.min(to_stop_line);
+        }
+
+        // Aim for the fastest speed from which the vehicle can still stop
+        // within `brake_room` at its normal deceleration, so slow brakers start
+        // braking earlier. The limit itself is never overrun, even if that
+        // takes harder braking than normal.
+        let was_stopped = self.velocity == 0.0;
+        let previous = self.velocity;
+        let deceleration = self.deceleration();
+        let jerk_limited = self.max_jerk.is_finite();
+        brake_room -= self.braking_lag();
+        let mut target = self
+            .top_speed()
+            .min((2.0 * deceleration * brake_room.max(0.0)).sqrt())
+            .min(self.turn_target());
+        // Without the room to pull away into, a jerk-limited vehicle comes
+        // to a halt rather than creeping up to the limit.
+        if jerk_limited && (brake_room < PULL_AWAY_ROOM || limit < PULL_AWAY_ROOM) {
+            target = 0.0;
+        }
+        let mut change = (target - self.velocity).clamp(-deceleration, self.max_acceleration());
+        if jerk_limited {
+            // Ease off early enough to reach the target with no acceleration
+            // left, and brake no harder than can be eased off in steps of
+            // `jerk` before the vehicle halts; below `jerk` it may stop
+            // outright. Then ramp from the last tick's acceleration.
+            let jerk = self.max_jerk;
+            let easing = (2.0 * jerk * (target - self.velocity).abs()).sqrt();
+            let stopping =
+                (((8.0 * jerk * self.velocity).sqrt() - jerk) / 2.0).max(self.velocity.min(jerk));
+            change = change
+                .clamp(-easing.min(stopping), easing)
+                .clamp(self.acceleration - jerk, self.acceleration + jerk);
+        }
+        self.velocity = (self.velocity + change).max(0.0);
+        let step = self.velocity.min(limit);
+        self.velocity = step;
+        self.acceleration = self.velocity - previous;
+
+        // A stopped vehicle ignores room too small to pull away into, and
+        // reacts to its leader leaving or its light turning green only after
+        // a delay, so a queue launches in a wave instead of creeping.
+        if was_stopped && step > 0.0 {
+            if limit < PULL_AWAY_ROOM {
+                self.velocity = 0.0;
+                self.acceleration = 0.0;
+                self.reaction_timer = 0;
+                return;
+            }
+            if self.reaction_timer < self.reaction_ticks {
+                self.reaction_timer += 1;
+                self.velocity = 0.0;
+                self.acceleration = 0.0;
+                return;
+            }
+        }
+        self.reaction_timer = 0;
+
+        match self.direction {
+            Direction::Up => self.y -= step,
+            Direction::Down => self.y += step,
+            Direction::Right => self.x += step,
+            Direction::Left => self.x -= step,
+        }
+        self.driven += step;
+    }
+
+    /// Pixels covered, beyond what braking at full deceleration at once
+    /// would take, while a jerk-limited vehicle builds up its braking from
+    /// the acceleration it has now.
+    pub fn braking_lag(&self) -> f32 {
+        if self.max_jerk.is_finite() {
+            let ramp = (self.deceleration() + self.acceleration).max(0.0);
+            self.velocity * ramp / (2.0 * self.max_jerk)
+        } else {
+            0.0
+        }
+    }
+
+    /// Pixels the vehicle needs to stop in under normal braking.
+    pub fn stopping_distance(&self) -> f32 {
+        self.velocity.powi(2) / (2.0 * self.deceleration()) + self.braking_lag()
+    }
+
+    /// Pixels per tick shed per tick under normal braking, in this weather.
+    pub fn deceleration(&self) -> f32 {
+        self.kind.deceleration() * self.traction.braking / self.tick_rate.powi(2)
+    }
+
+    /// Pixels per tick gained per tick at most.
+    pub fn max_acceleration(&self) -> f32 {
+        self.kind.acceleration() / self.tick_rate.powi(2)
+    }
+
+    /// Pixels per tick the vehicle drives at in its lane when nothing holds
+    /// it back: its cruise speed, slowed by the weather, up to the limit.
+    pub fn top_speed(&self) -> f32 {
+        (self.cruise_speed * self.traction.speed).min(self.speed_limits[self.direction.index()])
+    }
+
+    /// Turns the drawn heading part of the way towards `direction`, the
+    /// short way round, or all of it when `smooth` is off.
+    pub fn ease_heading(&mut self, smooth: bool) {
+        use std::f32::consts::{PI, TAU};
+        let target = self.direction.angle();
+        let remaining = (target - self.render_heading + PI).rem_euclid(TAU) - PI;
+        if !smooth || remaining.abs() < 0.01 {
+            self.render_heading = target;
+        } else {
+            self.render_heading += remaining * HEADING_EASE;
+        }
+    }
+
+    /// A line from the centre towards the front along `drawn_heading`, so
+    /// a turn shows as the nose swinging round.
+    pub fn nose(&self, alpha: f32) -> ((i32, i32), (i32, i32)) {
+        let (x, y) = self.drawn_position(alpha);
+        let reach = self.length / 2.0 - 4.0;
+        let heading = self.drawn_heading(alpha);
+        let (dx, dy) = (heading.cos(), heading.sin());
+        (
+            (x as i32, y as i32),
+            ((x + dx * reach) as i32, (y + dy * reach) as i32),
+        )
+    }
+
+    // The turn speed while turning, and ahead of the stop line the fastest
+    // speed from which the vehicle can still slow to it by the line.
+    fn turn_target(&self) -> f32 {
+        let turned_out = self.direction == self.exit_direction() && !self.overlaps_intersection();
+        if self.turn_speed.is_infinite() || turned_out {
+            return f32::INFINITY;
+        }
+        let to_stop_line = self.stop_line_distance().max(0.0);
+        (self.turn_speed.powi(2) + 2.0 * self.deceleration() * to_stop_line).sqrt()
+    }
+
+    /// Where along its approach (y for up and down, x otherwise) the vehicle
+    /// first turns; `None` on a straight route.
+    pub fn turn_coordinate(&self) -> Option<f32> {
+        turn_coordinate_in(self.world, self.initial_direction, self.route)
+    }
+
+    /// How far, across its direction of travel, the vehicle is from
+    /// `lane_centre` for the way it is heading.
+    pub fn lane_offset(&self) -> f32 {
+        let cross = match self.direction {
+            Direction::Up | Direction::Down => self.x,
+            Direction::Right | Direction::Left => self.y,
+        };
+        (cross - lane_centre(self.world, self.direction)).abs()
+    }
+
+    // Distance the front of the vehicle can travel before reaching its stop line;
+    // negative once it has entered the intersection.
+    pub fn stop_line_distance(&self) -> f32 {
+        self.stop_line_distance_at(self.x, self.y)
+    }
+
+    /// Whether the front passed the stop line during the last tick.
+    pub fn crossed_stop_line(&self) -> bool {
+        self.stop_line_distance_at(self.prev_x, self.prev_y) >= 0.0
+            && self.stop_line_distance() < 0.0
+    }
+
+    fn stop_line_distance_at(&self, x: f32, y: f32) -> f32 {
+        // Heading back out from a U-turn, its way out is measured as if
+        // mirrored about the first turn, so the distance keeps falling.
+        let (mut x, mut y) = (x, y);
+        if self.direction == self.initial_direction.opposite()
+            && let Some(turn) = self.turn_coordinate()
+        {
+            match self.initial_direction {
+                Direction::Up | Direction::Down => y = 2.0 * turn - y,
+                Direction::Right | Direction::Left => x = 2.0 * turn - x,
+            }
+        }
+        let setback = self.stop_setback + self.length / 2.0;
+        let (center_x, center_y) = self.world.centre();
+        match self.initial_direction {
+            Direction::Up => y - (center_y + setback),
+            Direction::Down => (center_y - setback) - y,
+            Direction::Right => (center_x - setback) - x,
+            Direction::Left => x - (center_x + setback),
+        }
+    }
+
+    pub fn in_intersection(&self) -> bool {
+        self.world.in_intersection_box(self.x, self.y)
+    }
+
+    /// Whether the vehicle has driven through the box and is now wholly on
+    /// its exit arm.
+    pub fn cleared_intersection(&self) -> bool {
+        self.stop_line_distance() < 0.0
+            && self.direction == self.exit_direction()
+            && !self.entered()
+    }
+
+    /// Whether the vehicle has crossed its stop line and is in the box, or
+    /// still short of it behind a set-back line.
+    pub fn entered(&self) -> bool {
+        let to_stop_line = self.stop_line_distance();
+        to_stop_line < 0.0
+            && (self.overlaps_intersection()
+                || to_stop_line >= self.world.half_road() - self.stop_setback)
+    }
+
+    /// Whether any part of the vehicle is over the intersection box.
+    pub fn overlaps_intersection(&self) -> bool {
+        let half_road = self.world.half_road();
+        let (center_x, center_y) = self.world.centre();
+        let (half_width, half_height) = self.half_extents();
+        (self.x - center_x).abs() < half_road + half_width
+            && (self.y - center_y).abs() < half_road + half_height
+    }
+
+    pub fn half_extents(&self) -> (f32, f32) {
+        half_extents(self.length, self.width, self.direction)
+    }
+
+    pub fn speed(&self, dt: f32) -> f32 {
+        ((self.x - self.prev_x).powi(2) + (self.y - self.prev_y).powi(2)).sqrt() / dt
+    }
+
+    /// How fast this vehicle and `other` are closing, in pixels per second,
+    /// from the difference of their velocity vectors: crossing at speed is
+    /// worse than nudging a slower leader.
+    pub fn relative_speed(&self, other: &Vehicle) -> f32 {
+        let velocity = |vehicle: &Vehicle| {
+            let (dx, dy) = vehicle.direction.delta();
+            (dx as f32 * vehicle.velocity, dy as f32 * vehicle.velocity)
+        };
+        let ((ax, ay), (bx, by)) = (velocity(self), velocity(other));
+        ((ax - bx).powi(2) + (ay - by).powi(2)).sqrt() * self.tick_rate
+    }
+
+    pub fn is_waiting(&self) -> bool {
+        self.x == self.prev_x && self.y == self.prev_y && self.stop_line_distance() >= 0.0
+    }
+
+    /// True once the whole footprint is past the edge of `world` the
+    /// vehicle's route leaves by, so a longer vehicle goes further out
+    /// before it counts as gone. The other edges don't count: a vehicle
+    /// still coming in from beyond one is not taken for one that has left.
+    pub fn has_left(&self, world: Rect) -> bool {
+        let (half_width, half_height) = self.half_extents();
+        match self.exit_direction() {
+            Direction::Up => self.y + half_height < world.top() as f32,
+            Direction::Down => self.y - half_height > world.bottom() as f32,
+            Direction::Right => self.x - half_width > world.right() as f32,
+            Direction::Left => self.x + half_width < world.left() as f32,
+        }
+    }
+
+    pub fn overlaps(&self, other: &Vehicle) -> bool {
+        let (half_width, half_height) = other.half_extents();
+        self.overlaps_footprint(other.x, other.y, half_width, half_height)
+    }
+
+    /// Bumper-to-bumper distance to `other` if it is ahead in the same lane
+    /// and travelling the same way, or came down this lane and has turned
+    /// off it but not yet cleared it.
+    pub fn gap_to(&self, other: &Vehicle) -> Option<f32> {
+        if other.direction != self.direction && other.initial_direction != self.initial_direction {
+            return None;
+        }
+
+        let (ahead, across) = match self.direction {
+            Direction::Up => (self.y - other.y, self.x - other.x),
+            Direction::Down => (other.y - self.y, self.x - other.x),
+            Direction::Right => (other.x - self.x, self.y - other.y),
+            Direction::Left => (self.x - other.x, self.y - other.y),
+        };
+        let (own_width, own_height) = self.half_extents();
+        let (other_width, other_height) = other.half_extents();
+        let (along, side) = match self.direction {
+            Direction::Up | Direction::Down => (own_height + other_height, own_width + other_width),
+            Direction::Right | Direction::Left => {
+                (own_width + other_width, own_height + other_height)
+            }
+        };
+        if ahead <= 0.0 || across.abs() >= side {
+            return None;
+        }
+        Some(ahead - along)
+    }
+
+    /// Whether a footprint with the given half extents, centered on the
+    /// point, would overlap this vehicle.
+    pub fn overlaps_footprint(&self, x: f32, y: f32, half_width: f32, half_height: f32) -> bool {
+        let (own_width, own_height) = self.half_extents();
+        (self.x - x).abs() < own_width + half_width && (self.y - y).abs() < own_height + half_height
+    }
+
+    /// Whether the point lies on the vehicle's footprint.
+    pub fn contains_point(&self, x: f32, y: f32) -> bool {
+        self.overlaps_footprint(x, y, 0.0, 0.0)
+    }
+
+    /// The vehicle as it would be on each tick from now until it leaves the
+    /// world, if nothing held it up, including a drag. A vehicle with no
+    /// speed to drive at has no path, and any other is cut off after twice
+    /// the ticks its top speed takes to go round the edge of the world.
+    pub fn projected_path(&self) -> Vec<Vehicle> {
+        let mut ghost = self.clone();
+        ghost.frozen = false;
+        ghost.held = false;
+        let mut path = Vec::new();
+        if ghost.top_speed() <= 0.0 {
+            return path;
+        }
+        let world = self.world.bounds();
+        let perimeter = 2 * (world.width() + world.height());
+        let most_ticks = 2.0 * perimeter as f32 / ghost.top_speed();
+        while !ghost.has_left(world) && (path.len() as f32) < most_ticks {
+            ghost.update(true, f32::INFINITY, f32::INFINITY);
+            path.push(ghost.clone());
+        }
+        path
+    }
+
+    /// Moves the vehicle, at a standstill, to the point of its own path
+    /// nearest `x`, `y`: its approach lane up to the turn, then each turn
+    /// line in turn out to the world's edge. Its direction follows the part
+    /// of the path it lands on, so it can be put back before its turn.
+    pub fn drag_to(&mut self, x: f32, y: f32) {
+        let world = self.world;
+        let (width, height) = (world.width as f32, world.height as f32);
+        let headings = self.route.headings(self.initial_direction);
+        let mut points = vec![spawn_position(world, self.initial_direction)];
+        points.extend(headings.windows(2).map(|leg| corner(world, leg[0], leg[1])));
+        let (last_x, last_y) = points[points.len() - 1];
+        points.push(match headings[headings.len() - 1] {
+            Direction::Up => (last_x, 0.0),
+            Direction::Down => (last_x, height),
+            Direction::Right => (width, last_y),
+            Direction::Left => (0.0, last_y),
+        });
+        // Every part runs along an axis, so the nearest point on each is the
+        // mouse clamped into its box. The earlier part wins a tie.
+        let nearest = |(ax, ay): (f32, f32), (bx, by): (f32, f32)| {
+            (
+                x.clamp(ax.min(bx), ax.max(bx)),
+                y.clamp(ay.min(by), ay.max(by)),
+            )
+        };
+        let distance = |(px, py): (f32, f32)| (px - x).hypot(py - y);
+        let (point, direction) = points
+            .windows(2)
+            .zip(headings)
+            .map(|(ends, heading)| (nearest(ends[0], ends[1]), heading))
+            .min_by(|a, b| distance(a.0).total_cmp(&distance(b.0)))
+            .expect("every route has a leg");
+
+        (self.x, self.y) = point;
+        (self.prev_x, self.prev_y) = point;
+        self.direction = direction;
+        self.render_heading = direction.angle();
+        self.velocity = 0.0;
+        self.acceleration = 0.0;
+        self.reaction_timer = 0;
+    }
+
+    /// The direction the vehicle will be travelling once its route is
+    /// complete.
+    pub fn exit_direction(&self) -> Direction {
+        self.route.exit_direction(self.initial_direction)
+    }
+
+    // A small marker on the side of the vehicle facing where it will exit, so
+    // the route stays readable when colors encode something else.
+    pub fn get_glyph_rect(&self, alpha: f32) -> Rect {
+        let (x, y) = self.drawn_position(alpha);
+        let (half_width, half_height) = self.half_extents();
+        let inset = (GLYPH_SIZE / 2) as f32;
+        let (dx, dy) = match self.exit_direction() {
+            Direction::Up => (0.0, inset - half_height),
+            Direction::Down => (0.0, half_height - inset),
+            Direction::Right => (half_width - inset, 0.0),
+            Direction::Left => (inset - half_width, 0.0),
+        };
+        Rect::new(
+            (x + dx) as i32 - (GLYPH_SIZE / 2) as i32,
+            (y + dy) as i32 - (GLYPH_SIZE / 2) as i32,
+            GLYPH_SIZE,
+            GLYPH_SIZE,
+        )
+    }
+
+    pub fn interpolated_position(&self, alpha: f32) -> (f32, f32) {
+        (
+            self.prev_x + (self.x - self.prev_x) * alpha,
+            self.prev_y + (self.y - self.prev_y) * alpha,
+        )
+    }
+
+    /// Where the vehicle is drawn: `interpolated_position`, except that
+    /// within `drawn_turn_radius` of a corner between two lanes it turns
+    /// between it follows a quarter circle tangent to both. The tail pivot
+    /// on turning is spread over the arc.
+    pub fn drawn_position(&self, alpha: f32) -> (f32, f32) {
+        let (x, y) = self.interpolated_position(alpha);
+        if let Some(progress) = self.ring_progress(x, y) {
+            return self.ring_point(progress);
+        }
+        if self.drawn_turn_radius <= 0.0 {
+            return (x, y);
+        }
+        // Only the corners at either end of the leg it is on, since a
+        // U-turn's last leg passes back by its first corner.
+        self.route
+            .headings(self.initial_direction)
+            .windows(2)
+            .filter(|leg| leg.contains(&self.direction))
+            .find_map(|leg| self.rounded_corner(x, y, leg[0], leg[1]))
+            .unwrap_or((x, y))
+    }
+
+    // The point on the arc round the corner from the lane heading `entry`
+    // into the one heading `exit` for a vehicle centred at `(x, y)`, if it
+    // is on the arc's stretch of the two lanes.
+    fn rounded_corner(
+        &self,
+        x: f32,
+        y: f32,
+        entry: Direction,
+        exit: Direction,
+    ) -> Option<(f32, f32)> {
+        let radius = self.drawn_turn_radius;
+        let corner = corner(self.world, entry, exit);
+        let unit = |direction: Direction| {
+            let (dx, dy) = direction.delta();
+            (dx as f32, dy as f32)
+        };
+        let ((in_x, in_y), (out_x, out_y)) = (unit(entry), unit(exit));
+        // Distance driven past the corner: along the entry lane up to it,
+        // then along the exit lane, which the pivot jumps ahead.
+        let (from_x, from_y) = (x - corner.0, y - corner.1);
+        let along = from_x * (in_x + out_x) + from_y * (in_y + out_y);
+        let pivot = (self.length - self.width) / 2.0;
+        let t = (along + radius) / (2.0 * radius + pivot);
+        if !(0.0..=1.0).contains(&t) {
+            return None;
+        }
+        let angle = t * std::f32::consts::FRAC_PI_2;
+        let (centre_x, centre_y) = (
+            corner.0 + radius * (out_x - in_x),
+            corner.1 + radius * (out_y - in_y),
+        );
+        let (cos, sin) = (angle.cos(), angle.sin());
+        Some((
+            centre_x + radius * (in_x * sin - out_x * cos) + pivot * t * out_x,
+            centre_y + radius * (in_y * sin - out_y * cos) + pivot * t * out_y,
+        ))
+    }
+
+    /// The heading the vehicle is drawn with: `render_heading`, or along
+    /// the ring while it circulates.
+    pub fn drawn_heading(&self, alpha: f32) -> f32 {
+        let (x, y) = self.interpolated_position(alpha);
+        match self.ring_progress(x, y) {
+            Some(progress) => {
+                let (from_x, from_y) = self.ring_point(progress);
+                let (to_x, to_y) = self.ring_point(progress + 0.01);
+                (to_y - from_y).atan2(to_x - from_x)
+            }
+            None => self.render_heading,
+        }
+    }
+
+    // How far a circulating vehicle centred at `(x, y)` is through the box,
+    // from 0 where its entry lane meets the box edge to 1 where its exit
+    // lane leaves it; `None` outside the box or when it doesn't circulate.
+    // Every way across the box is a box width long, plus the tail pivot on
+    // each turn.
+    fn ring_progress(&self, x: f32, y: f32) -> Option<f32> {
+        if !self.circulates {
+            return None;
+        }
+        let (entry_x, entry_y) = lane_at_box_edge(self.world, self.initial_direction, true);
+        let (dx, dy) = self.initial_direction.delta();
+        if (x - entry_x) * dx as f32 + (y - entry_y) * (dy as f32) < 0.0 {
+            return None;
+        }
+        // Along the legs already driven, corner to corner, then on from the
+        // last corner.
+        let headings = self.route.headings(self.initial_direction);
+        let leg = headings
+            .iter()
+            .position(|&heading| heading == self.direction)
+            .unwrap_or(0);
+        let mut from = (entry_x, entry_y);
+        let mut driven = 0.0;
+        for turn in headings.windows(2).take(leg) {
+            let to = corner(self.world, turn[0], turn[1]);
+            driven += (to.0 - from.0).abs() + (to.1 - from.1).abs();
+            from = to;
+        }
+        driven += (x - from.0).abs() + (y - from.1).abs();
+        let turns = (headings.len() - 1) as f32;
+        let length = self.world.road_width as f32 + turns * (self.length - self.width) / 2.0;
+        let progress = driven / length;
+        (progress <= 1.0).then_some(progress)
+    }
+
+    // The point `progress` of the way round the ring, anticlockwise on
+    // screen from the entry lane to the exit lane, a whole lap further for
+    // a U-turn. It spirals in from the box edge onto the roundabout radius
+    // and back out again.
+    fn ring_point(&self, progress: f32) -> (f32, f32) {
+        let (center_x, center_y) = self.world.centre();
+        let (entry_x, entry_y) = lane_at_box_edge(self.world, self.initial_direction, true);
+        let (exit_x, exit_y) = lane_at_box_edge(self.world, self.exit_direction(), false);
+        let (entry_x, entry_y) = (entry_x - center_x, entry_y - center_y);
+        let (exit_x, exit_y) = (exit_x - center_x, exit_y - center_y);
+        let start = entry_y.atan2(entry_x);
+        let mut sweep = (start - exit_y.atan2(exit_x)).rem_euclid(std::f32::consts::TAU);
+        if self.route == Route::UTurn {
+            sweep += std::f32::consts::TAU;
+        }
+        let angle = start - progress * sweep;
+        let edge = entry_x.hypot(entry_y) * (1.0 - progress) + exit_x.hypot(exit_y) * progress;
+        let ring = self.world.roundabout_radius();
+        let radius = ring + (edge - ring) * (2.0 * progress - 1.0).powi(2);
+        (
+            center_x + radius * angle.cos(),
+            center_y + radius * angle.sin(),
+        )
+    }
+
+    pub fn get_rect(&self, alpha: f32) -> Rect {
+        let (x, y) = self.drawn_position(alpha);
+        let (half_width, half_height) = self.half_extents();
+        Rect::new(
+            (x - half_width) as i32,
+            (y - half_height) as i32,
+            (half_width * 2.0) as u32,
+            (half_height * 2.0) as u32,
+        )
+    }
+}
@@ -0,0 +1,107 @@
+//! Whole runs driven through `Harness`: scripted and random traffic under
+//! each kind of control, checked for overlaps, red-light running and
+//! vehicles that never get out.
+
+use road_intersection::config::{Config, ControlMode};
+use road_intersection::direction::{Direction, Route};
+use road_intersection::geometry::Layout;
+use road_intersection::harness::Harness;
+
+/// Ticks a run is given to empty once its spawns are made; a minute and a
+/// half at the default tick rate.
+const CLEAR_LIMIT: u64 = 60 * 90;
+
+/// Every approach sends one of each route at once, twice over, then the
+/// same again staggered a second apart.
+fn busy_script(config: Config) -> Harness {
+    let mut harness = Harness::new(config);
+    for round in 0..2 {
+        for approach in Direction::ALL {
+            for route in [Route::Straight, Route::TurnLeft, Route::TurnRight] {
+                harness = harness.spawn_at(round * 600, approach, route);
+            }
+        }
+    }
+    for (index, approach) in Direction::ALL.into_iter().enumerate() {
+        harness = harness.spawn_at(1200 + 60 * index as u64, approach, Route::TurnLeft);
+    }
+    harness
+}
+
+fn assert_clean(harness: &Harness) {
+    assert!(harness.failures().is_empty(), "{:#?}", harness.failures());
+    assert!(
+        harness.unfinished().is_empty(),
+        "still on the road: {:?}",
+        harness.unfinished()
+    );
+}
+
+#[test]
+fn scripted_traffic_gets_through_the_lights_without_running_a_red() {
+    let mut harness = busy_script(Config {
+        seed: Some(1),
+        ..Config::default()
+    });
+    assert!(harness.run_until_clear(2000 + CLEAR_LIMIT).unwrap());
+    assert_eq!(harness.spawned().len(), 2 * 4 * 3 + 4);
+    assert_eq!(harness.simulation.stats().exited, harness.exited().len());
+    assert_clean(&harness);
+}
+
+#[test]
+fn scripted_traffic_gets_through_without_lights() {
+    for (control_mode, layout) in [
+        (ControlMode::Uncontrolled, Layout::Plus),
+        (ControlMode::AllWayStop, Layout::Plus),
+        (ControlMode::Lights, Layout::Roundabout),
+    ] {
+        let mut harness = busy_script(Config {
+            seed: Some(2),
+            control_mode,
+            layout,
+            ..Config::default()
+        });
+        assert!(
+            harness.run_until_clear(2000 + CLEAR_LIMIT).unwrap(),
+            "{:?} {:?}",
+            control_mode,
+            layout
+        );
+        assert_clean(&harness);
+    }
+}
+
+#[test]
+fn random_arrivals_all_leave_once_spawning_stops() {
+    for seed in [3, 4] {
+        let mut harness = Harness::new(Config {
+            seed: Some(seed),
+            auto_spawn: true,
+            ..Config::default()
+        });
+        harness.step(60 * 120).unwrap();
+        assert!(!harness.spawned().is_empty());
+        harness.simulation.start_drain();
+        assert!(
+            harness.run_until_clear(CLEAR_LIMIT).unwrap(),
+            "seed {}",
+            seed
+        );
+        assert_clean(&harness);
+    }
+}
+
+#[test]
+fn a_vehicle_can_be_followed_tick_by_tick() {
+    let mut harness = Harness::new(Config::default()).spawn_at(0, Direction::Up, Route::Straight);
+    harness.step(1).unwrap();
+    let id = *harness.spawned().first().unwrap();
+    let (x, start) = harness.position(id).unwrap();
+    // Up and down start on green, so it drives straight up its lane.
+    harness.step(30).unwrap();
+    let (still_x, y) = harness.position(id).unwrap();
+    assert_eq!(still_x, x);
+    assert!(y < start);
+    assert!(harness.simulation.lights().is_green(Direction::Up));
+}